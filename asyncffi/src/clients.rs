@@ -4,12 +4,12 @@ use rustls::{SignatureScheme, client::danger::{HandshakeSignatureValid, ServerCe
 use tokio_rustls::{TlsConnector, rustls::ClientConfig};
 use tokio::{io::{ReadHalf, WriteHalf}, net::{TcpStream, ToSocketAddrs}};
 
-use crate::{DynStream, PROVIDER};
+use crate::{DynStream, PROVIDER, transport::QuicTransport};
 
 
 // use crate::DynStream;
 
-pub type DynHttpRequest = PolyHttpRequest<ReadHalf<DynStream>, WriteHalf<DynStream>>;
+pub type DynHttpRequest = PolyHttpRequest<ReadHalf<DynStream>, WriteHalf<DynStream>, QuicTransport>;
 
 pub async fn tcp_connect<A: ToSocketAddrs>(addr: A) -> io::Result<TcpStream> {
     // not alot going on
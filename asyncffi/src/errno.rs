@@ -1,9 +1,16 @@
-use http::shared::LibError;
+use http::{http2::core::Http2ErrorCode, shared::LibError};
 
 pub const NO_ERR: i32 = -1;
 pub const TYPE_ERR: i32 = 1;
 pub const ERROR: i32 = 0x100;
 pub const IO_ERROR: i32 = 0x200;
+// http2 error codes (rfc9113 7), http3 error codes (rfc9114 8) and grpc status codes are carried in the upper half, so errno & 0xffff still identifies the error
+pub const H2_CODE_SHIFT: i32 = 16;
+
+pub fn h2_errno(base: i32, code: Http2ErrorCode) -> i32 {
+    let code: u32 = code.into();
+    base | (((code & 0x7fff) as i32) << H2_CODE_SHIFT)
+}
 
 
 pub trait Errno {
@@ -29,9 +36,19 @@ impl Errno for LibError {
             Self::InvalidString => 0x111,
 
             Self::NotAccepted => 0x112,
-            Self::ResetStream => 0x113,
-            Self::Goaway => 0x114,
+            Self::ResetStream(code) => h2_errno(0x113, *code),
+            Self::Goaway(info) => h2_errno(0x114, info.code),
             Self::ProtocolError => 0x115,
+            Self::Refused => h2_errno(0x116, Http2ErrorCode::RefusedStream),
+            Self::Grpc(err) => {
+                let status: u32 = err.status.into();
+                0x117 | (((status & 0x7fff) as i32) << H2_CODE_SHIFT)
+            },
+            Self::Qpack(_) => 0x118,
+            Self::Http3(code) => {
+                let code: u64 = (*code).into();
+                0x119 | (((code & 0x7fff) as i32) << H2_CODE_SHIFT)
+            },
         }
     }
 }
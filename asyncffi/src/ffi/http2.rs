@@ -1,7 +1,7 @@
 use core::slice;
 use std::{borrow::Cow, ptr, sync::Arc};
 
-use http::{http2::{client::Http2Request, core::{Http2Frame, Http2Settings}, server::Http2Socket, session::{Http2Session, Mode}}};
+use http::{http2::{client::Http2Request, core::{Http2Frame, Http2Priority, Http2Settings}, server::Http2Socket, session::{Http2Session, Mode}}};
 use httprs_core::ffi::{futures::FfiFuture, slice::{FfiSlice, ToFfiSlice}};
 use tokio::io::{BufReader, ReadHalf, WriteHalf};
 
@@ -146,6 +146,12 @@ pub extern "C" fn http2_open_stream(session: *const DynH2Sess) -> u32 {
         (*session).open_stream().unwrap_or(0)
     }
 }
+#[unsafe(no_mangle)]
+pub extern "C" fn http2_open_push_stream(session: *const DynH2Sess) -> u32 {
+    unsafe {
+        (*session).open_push_stream().unwrap_or(0)
+    }
+}
 
 
 #[unsafe(no_mangle)]
@@ -191,6 +197,19 @@ pub extern "C" fn http2_send_priority(fut: *const FfiFuture, session: *const Dyn
     }
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn http2_send_priority_update(fut: *const FfiFuture, session: *const DynH2Sess, stream_id: u32, urgency: u8, incremental: bool) {
+    unsafe {
+        let sess = &*session;
+        let fut = &*fut;
+
+        spawn_task_with(fut, async move{
+            sess.send_priority_update(stream_id, Http2Priority::new(urgency, incremental)).await?;
+            Ok(ptr::null_mut())
+        });
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn http2_send_rst_stream(fut: *const FfiFuture, session: *const DynH2Sess, stream_id: u32, code: u32) {
     unsafe {
@@ -198,7 +217,7 @@ pub extern "C" fn http2_send_rst_stream(fut: *const FfiFuture, session: *const D
         let fut = &*fut;
 
         spawn_task_with(fut, async move{
-            sess.send_rst_stream(stream_id, code).await?;
+            sess.send_rst_stream(stream_id, code.into()).await?;
             Ok(ptr::null_mut())
         });
     }
@@ -295,7 +314,7 @@ pub extern "C" fn http2_send_goaway(fut: *const FfiFuture, session: *const DynH2
         let fut = &*fut;
 
         spawn_task_with(fut, async move{
-            sess.send_goaway(stream_id, code, buf.as_bytes()).await?;
+            sess.send_goaway(stream_id, code.into(), buf.as_bytes()).await?;
             Ok(ptr::null_mut())
         });
     }
@@ -0,0 +1,107 @@
+use std::{ptr, sync::Arc};
+
+use http::http3::{client::Http3Request, server::Http3Socket, session::Http3Session};
+use httprs_core::ffi::futures::FfiFuture;
+
+use crate::{clients::DynHttpRequest, ffi::utils::{heap_ptr, heap_void_ptr}, servers::DynHttpSocket, spawn_task_with, transport::QuicTransport};
+
+pub type DynH3Sess = Http3Session<QuicTransport>;
+
+
+
+// the transport is taken over, it comes from whatever runs the quic connection
+#[unsafe(no_mangle)]
+pub extern "C" fn http3_new_client(transport: *mut QuicTransport) -> *const DynH3Sess {
+    unsafe {
+        let transport = *Box::from_raw(transport);
+        Arc::into_raw(Arc::new(Http3Session::new_client(transport)))
+    }
+}
+#[unsafe(no_mangle)]
+pub extern "C" fn http3_new_server(transport: *mut QuicTransport) -> *const DynH3Sess {
+    unsafe {
+        let transport = *Box::from_raw(transport);
+        Arc::into_raw(Arc::new(Http3Session::new_server(transport)))
+    }
+}
+#[unsafe(no_mangle)]
+pub extern "C" fn http3_free(session: *const DynH3Sess) {
+    unsafe {
+        drop(Arc::from_raw(session));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn http3_start(fut: *const FfiFuture, session: *const DynH3Sess) {
+    unsafe {
+        let sess = &*session;
+        let fut = &*fut;
+
+        spawn_task_with(fut, async move{
+            sess.start().await?;
+            Ok(ptr::null_mut())
+        });
+    }
+}
+#[unsafe(no_mangle)]
+pub extern "C" fn http3_next(fut: *const FfiFuture, session: *const DynH3Sess) {
+    unsafe {
+        let sess = &*session;
+        let fut = &*fut;
+
+        spawn_task_with(fut, async move{
+            if let Some(open) = sess.next().await? {
+                Ok(heap_void_ptr(open))
+            }
+            else {
+                Ok(ptr::null_mut())
+            }
+        });
+    }
+}
+#[unsafe(no_mangle)]
+pub extern "C" fn http3_send_goaway(fut: *const FfiFuture, session: *const DynH3Sess) {
+    unsafe {
+        let sess = &*session;
+        let fut = &*fut;
+
+        spawn_task_with(fut, async move{
+            sess.send_goaway().await?;
+            Ok(ptr::null_mut())
+        });
+    }
+}
+
+
+#[unsafe(no_mangle)]
+pub extern "C" fn http3_client_handler(fut: *const FfiFuture, session: *const DynH3Sess) {
+    unsafe {
+        let session = {
+            Arc::increment_strong_count(session);
+            Arc::from_raw(session)
+        };
+        let fut = &*fut;
+
+        spawn_task_with(fut, async move{
+            let req = Http3Request::open(session).await?;
+            Ok(heap_void_ptr(DynHttpRequest::Http3(req)))
+        });
+    }
+}
+#[unsafe(no_mangle)]
+pub extern "C" fn http3_server_handler(session: *const DynH3Sess, stream_id: u64) -> *mut DynHttpSocket {
+    unsafe {
+        let session = {
+            Arc::increment_strong_count(session);
+            Arc::from_raw(session)
+        };
+
+        if let Ok(req) = Http3Socket::new(stream_id, session) {
+            let req = DynHttpSocket::Http3(req);
+            heap_ptr(req)
+        }
+        else {
+            ptr::null_mut()
+        }
+    }
+}
@@ -5,3 +5,4 @@ pub mod websocket;
 pub mod tls_server;
 pub mod utils;
 pub mod http2;
+pub mod http3;
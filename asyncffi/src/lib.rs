@@ -16,6 +16,7 @@ pub mod ffi;
 pub mod httpcpp;
 pub mod errno;
 pub mod clients;
+pub mod transport;
 // pub mod auto_server;
 
 
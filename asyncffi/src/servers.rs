@@ -6,13 +6,13 @@ use rustls::{ServerConfig, server::{ClientHello, ResolvesServerCert}, sign::Cert
 use tokio::{io::{ReadHalf, WriteHalf}, net::{TcpListener, TcpStream}};
 use tokio_rustls::{TlsAcceptor, server::TlsStream};
 
-use crate::DynStream;
+use crate::{DynStream, transport::QuicTransport};
 
 
 
 pub async fn tcp_serve<F, Fut, O>(address: String, handler: F) -> std::io::Result<()>
 where 
-    F: Fn(SocketAddr, PolyHttpSocket<ReadHalf<TcpStream>, WriteHalf<TcpStream>>) -> Fut + Send + Clone + Copy + Sync + 'static,
+    F: Fn(SocketAddr, PolyHttpSocket<ReadHalf<TcpStream>, WriteHalf<TcpStream>, QuicTransport>) -> Fut + Send + Clone + Copy + Sync + 'static,
     Fut: Future<Output = O> + Send + 'static,
 {
     let listener = TcpListener::bind(address).await?;
@@ -28,7 +28,7 @@ where
     }
 }
 
-pub type DynHttpSocket = PolyHttpSocket<ReadHalf<DynStream>, WriteHalf<DynStream>>;
+pub type DynHttpSocket = PolyHttpSocket<ReadHalf<DynStream>, WriteHalf<DynStream>, QuicTransport>;
 
 // pub struct TcpServer{
 //     // cb: Arc<dyn Fn(SocketAddr, PolyHttpSocket<ReadHalf<TcpStream>, WriteHalf<TcpStream>>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> + Send + Sync + 'static>,
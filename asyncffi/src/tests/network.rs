@@ -326,4 +326,77 @@ async fn http2_test(){
     // tokio::time::sleep(Duration::from_millis(1000)).await;
 
     join.await.unwrap();
-}
\ No newline at end of file
+}
+
+// the ffi http/3 socket over quic streams, the test moves the frames in place of the packets
+#[tokio::test]
+async fn http3_over_quic_streams(){
+    use http::{http3::client::Http3Request, shared::{HttpMethod, HttpRequest}};
+    use quic::{core::Side, params::TransportParameters, stream::Streams};
+    use crate::{ffi::{http3::{http3_free, http3_new_client, http3_new_server, http3_server_handler}, server::http_get_type, utils::heap_ptr}, transport::QuicTransport};
+
+    let params = TransportParameters {
+        initial_max_data: Some(1 << 20),
+        initial_max_stream_data_bidi_local: Some(1 << 16),
+        initial_max_stream_data_bidi_remote: Some(1 << 16),
+        initial_max_stream_data_uni: Some(1 << 16),
+        initial_max_streams_bidi: Some(16),
+        initial_max_streams_uni: Some(16),
+        ..TransportParameters::empty()
+    };
+    let (cstreams, sstreams) = (Streams::new(Side::Client, &params, &params), Streams::new(Side::Server, &params, &params));
+    let (a, b) = (cstreams.clone(), sstreams.clone());
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = a.frames() => {},
+                _ = b.frames() => {},
+            }
+            let (ab, ba) = (a.write_frames(1200), b.write_frames(1200));
+            for frame in ab { b.on_frame(frame).unwrap() }
+            for frame in ba { a.on_frame(frame).unwrap() }
+        }
+    });
+
+    let client = http3_new_client(heap_ptr(QuicTransport::new(cstreams)));
+    let server = http3_new_server(heap_ptr(QuicTransport::new(sstreams)));
+    let (client, server) = unsafe {
+        Arc::increment_strong_count(client);
+        Arc::increment_strong_count(server);
+        (Arc::from_raw(client), Arc::from_raw(server))
+    };
+    client.start().await.unwrap();
+    server.start().await.unwrap();
+
+    let driver = client.clone();
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+    let driver = server.clone();
+    tokio::spawn(async move {
+        while let Ok(id) = driver.next().await {
+            if let Some(id) = id {
+                let socket = http3_server_handler(Arc::as_ptr(&driver), id);
+                assert_eq!(http_get_type(socket), 3);
+
+                let mut socket = unsafe { Box::from_raw(socket) };
+                tokio::spawn(async move {
+                    let received = socket.read_until_complete().await.unwrap();
+                    let body = [received.path.as_bytes(), b" ", &received.body].concat();
+                    socket.set_status(200, String::new());
+                    socket.close(&body).await.unwrap();
+                });
+            }
+        }
+    });
+    client.wait_settings().await.unwrap();
+
+    let mut request = Http3Request::open(client.clone()).await.unwrap();
+    HttpRequest::set_method(&mut request, HttpMethod::Post);
+    HttpRequest::set_path(&mut request, "/echo".to_owned());
+    HttpRequest::set_host(&mut request, "example.com".to_owned());
+    HttpRequest::send(&mut request, b"over quic").await.unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(2), request.read_until_complete()).await.unwrap().unwrap();
+    assert_eq!((response.code, response.body.as_slice()), (200, &b"/echo over quic"[..]));
+
+    http3_free(Arc::into_raw(client));
+    http3_free(Arc::into_raw(server));
+}
@@ -0,0 +1,102 @@
+use std::{io, pin::Pin, sync::{Arc, Mutex as SyncMutex}, task::{Context, Poll}};
+
+use http::{http3::{core::Http3ErrorCode, transport::{Http3RecvStream, Http3SendStream, Http3Transport}}, shared::{LibError, LibResult}};
+use quic::{core::QuicErrorCode, stream::{QuicStream, Streams}};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+
+// http/3 over the streams of a quic connection, whoever moves the packets drives `streams`
+// clones share the same connection
+#[derive(Debug, Clone)]
+pub struct QuicTransport {
+    pub streams: Streams,
+    // the application code of the CONNECTION_CLOSE, for whoever sends the packets
+    pub closed: Arc<SyncMutex<Option<u64>>>,
+}
+impl QuicTransport {
+    pub fn new(streams: Streams) -> Self {
+        Self { streams, closed: Arc::new(SyncMutex::new(None)) }
+    }
+    pub fn close_code(&self) -> Option<u64> {
+        *self.closed.lock().unwrap()
+    }
+
+    // a connection we closed fails with our code, one closed any other way just as closed
+    fn error(&self, _: QuicErrorCode) -> LibError {
+        match self.close_code() {
+            Some(code) => LibError::Http3(Http3ErrorCode::from(code)),
+            None => LibError::ConnectionClosed,
+        }
+    }
+}
+
+// one direction of a quic stream, both halves of a bidirectional stream share it
+// the stream is finished and stopped once both are dropped
+#[derive(Debug, Clone)]
+pub struct QuicHalf {
+    pub id: u64,
+    pub stream: Arc<SyncMutex<QuicStream>>,
+}
+impl QuicHalf {
+    fn new(stream: QuicStream) -> Self {
+        Self { id: stream.id(), stream: Arc::new(SyncMutex::new(stream)) }
+    }
+}
+impl AsyncRead for QuicHalf {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.stream.lock().unwrap()).poll_read(cx, buf)
+    }
+}
+impl AsyncWrite for QuicHalf {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.stream.lock().unwrap()).poll_write(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.stream.lock().unwrap()).poll_flush(cx)
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.stream.lock().unwrap()).poll_shutdown(cx)
+    }
+}
+impl Http3RecvStream for QuicHalf {
+    fn stream_id(&self) -> u64 {
+        self.id
+    }
+    fn stop(&mut self, code: u64) {
+        self.stream.lock().unwrap().stop(code)
+    }
+}
+impl Http3SendStream for QuicHalf {
+    fn stream_id(&self) -> u64 {
+        self.id
+    }
+    fn reset(&mut self, code: u64) {
+        self.stream.lock().unwrap().reset(code)
+    }
+}
+
+impl Http3Transport for QuicTransport {
+    type Recv = QuicHalf;
+    type Send = QuicHalf;
+
+    async fn open_bi(&self) -> LibResult<(QuicHalf, QuicHalf)> {
+        let half = QuicHalf::new(self.streams.open(true).await.map_err(|e| self.error(e))?);
+        Ok((half.clone(), half))
+    }
+    async fn open_uni(&self) -> LibResult<QuicHalf> {
+        Ok(QuicHalf::new(self.streams.open(false).await.map_err(|e| self.error(e))?))
+    }
+    async fn accept_bi(&self) -> LibResult<(QuicHalf, QuicHalf)> {
+        let half = QuicHalf::new(self.streams.accept(true).await.map_err(|e| self.error(e))?);
+        Ok((half.clone(), half))
+    }
+    async fn accept_uni(&self) -> LibResult<QuicHalf> {
+        Ok(QuicHalf::new(self.streams.accept(false).await.map_err(|e| self.error(e))?))
+    }
+
+    // the streams fail right away, the code goes out with the CONNECTION_CLOSE
+    fn close(&self, code: u64, _reason: &[u8]) {
+        self.closed.lock().unwrap().get_or_insert(code);
+        self.streams.close(QuicErrorCode::ApplicationError);
+    }
+}
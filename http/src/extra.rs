@@ -1,17 +1,39 @@
+use std::collections::HashMap;
+
 use tokio::io::BufReader;
 
-use crate::{http1::{client::Http1Request, server::Http1Socket}, http2::{client::Http2Request, server::Http2Socket}, shared::{HttpClient, HttpMethod, HttpRequest, HttpResponse, HttpSocket, HttpType, LibError, ReadStream, WriteStream}};
+use crate::{http1::{client::Http1Request, server::Http1Socket}, http2::{client::Http2Request, server::Http2Socket}, http3::{client::Http3Request, server::Http3Socket, transport::Http3Transport}, shared::{HttpClient, HttpMethod, HttpRequest, HttpResponse, HttpSocket, HttpType, LibError, ReadStream, WriteStream}};
 
-pub enum PolyHttpSocket<R: ReadStream, W: WriteStream>{
+pub enum PolyHttpSocket<R: ReadStream, W: WriteStream, T: Http3Transport>{
     Http1(Http1Socket<R, W>),
     Http2(Http2Socket<BufReader<R>, W>),
+    Http3(Http3Socket<T>),
+}
+
+impl<R: ReadStream, W: WriteStream, T: Http3Transport> PolyHttpSocket<R, W, T>{
+    // http/1 trailers aren't supported
+    pub fn get_trailers(&self) -> Option<&HashMap<String, Vec<String>>> {
+        match self {
+            Self::Http1(_) => None,
+            Self::Http2(h) => Some(&h.trailers),
+            Self::Http3(h) => Some(&h.trailers),
+        }
+    }
+    pub async fn send_trailers(&mut self, trailers: &[(&str, &str)]) -> Result<(), LibError> {
+        match self {
+            Self::Http1(_) => Err(LibError::Invalid),
+            Self::Http2(h) => h.send_trailers(trailers).await,
+            Self::Http3(h) => h.send_trailers(trailers).await,
+        }
+    }
 }
 
-impl<R: ReadStream, W: WriteStream> HttpSocket for PolyHttpSocket<R, W>{
+impl<R: ReadStream, W: WriteStream, T: Http3Transport> HttpSocket for PolyHttpSocket<R, W, T>{
     fn get_type(&self) -> HttpType {
         match self {
             Self::Http1(_) => HttpType::Http1,
             Self::Http2(_) => HttpType::Http2,
+            Self::Http3(_) => HttpType::Http3,
         }
     }
 
@@ -19,24 +41,28 @@ impl<R: ReadStream, W: WriteStream> HttpSocket for PolyHttpSocket<R, W>{
         match self{
             Self::Http1(h) => &h.client,
             Self::Http2(h) => &h.client,
+            Self::Http3(h) => &h.client,
         }
     }
     async fn read_client(&'_ mut self) -> Result<&'_ HttpClient, LibError> {
         match self{
             Self::Http1(h) => h.read_client().await,
             Self::Http2(h) => h.read_client().await,
+            Self::Http3(h) => h.read_client().await,
         }
     }
     async fn read_until_complete(&'_ mut self) -> Result<&'_ HttpClient, LibError> {
         match self{
             Self::Http1(h) => h.read_until_complete().await,
             Self::Http2(h) => h.read_until_complete().await,
+            Self::Http3(h) => h.read_until_complete().await,
         }
     }
     async fn read_until_head_complete(&'_ mut self) -> Result<&'_ HttpClient, LibError> {
         match self{
             Self::Http1(h) => h.read_until_head_complete().await,
             Self::Http2(h) => h.read_until_head_complete().await,
+            Self::Http3(h) => h.read_until_head_complete().await,
         }
     }
 
@@ -44,18 +70,21 @@ impl<R: ReadStream, W: WriteStream> HttpSocket for PolyHttpSocket<R, W>{
         match self {
             Self::Http1(h) => h.add_header(header, value),
             Self::Http2(h) => h.add_header(&header.to_lowercase(), value),
+            Self::Http3(h) => h.add_header(&header.to_lowercase(), value),
         }
     }
     fn set_header(&mut self, header: &str, value: &str){ 
         match self {
             Self::Http1(h) => h.set_header(header, value),
             Self::Http2(h) => h.set_header(&header.to_lowercase(), value),
+            Self::Http3(h) => h.set_header(&header.to_lowercase(), value),
         } 
     }
     fn del_header(&mut self, header: &str) -> Option<Vec<String>>{ 
         match self {
             Self::Http1(h) => h.del_header(header),
             Self::Http2(h) => h.del_header(&header.to_lowercase()),
+            Self::Http3(h) => h.del_header(&header.to_lowercase()),
         }
     }
 
@@ -66,45 +95,68 @@ impl<R: ReadStream, W: WriteStream> HttpSocket for PolyHttpSocket<R, W>{
                 h.status = message;
             },
             Self::Http2(h) => h.status = code,
+            Self::Http3(h) => h.status = code,
         }
     }
     async fn write<'a>(&'a mut self, body: &'a [u8]) -> Result<(), LibError> {
         match self{
             Self::Http1(h) => h.write(body).await,
             Self::Http2(h) => h.write(body).await,
+            Self::Http3(h) => h.write(body).await,
         }
     }
     async fn close<'a>(&'a mut self, body: &'a [u8]) -> Result<(), LibError> {
         match self{
             Self::Http1(h) => h.close(body).await,
             Self::Http2(h) => h.close(body).await,
+            Self::Http3(h) => h.close(body).await,
         }
     }
     async fn flush<'a>(&'a mut self) -> Result<(), LibError> {
         match self{
             Self::Http1(h) => h.flush().await,
             Self::Http2(h) => h.flush().await,
+            Self::Http3(h) => h.flush().await,
         }
     }
 }
 
-impl<R: ReadStream, W: WriteStream> From<Http1Socket<R, W>> for PolyHttpSocket<R, W> {
+impl<R: ReadStream, W: WriteStream, T: Http3Transport> From<Http1Socket<R, W>> for PolyHttpSocket<R, W, T> {
     fn from(value: Http1Socket<R, W>) -> Self {
         Self::Http1(value)
     }
 }
 
 
-pub enum PolyHttpRequest<R: ReadStream, W: WriteStream>{
+pub enum PolyHttpRequest<R: ReadStream, W: WriteStream, T: Http3Transport>{
     Http1(Http1Request<R, W>),
     Http2(Http2Request<BufReader<R>, W>),
+    Http3(Http3Request<T>),
+}
+
+impl<R: ReadStream, W: WriteStream, T: Http3Transport> PolyHttpRequest<R, W, T>{
+    pub fn get_trailers(&self) -> Option<&HashMap<String, Vec<String>>> {
+        match self {
+            Self::Http1(_) => None,
+            Self::Http2(h) => Some(&h.trailers),
+            Self::Http3(h) => Some(&h.trailers),
+        }
+    }
+    pub async fn send_trailers(&mut self, trailers: &[(&str, &str)]) -> Result<(), LibError> {
+        match self {
+            Self::Http1(_) => Err(LibError::Invalid),
+            Self::Http2(h) => h.send_trailers(trailers).await,
+            Self::Http3(h) => h.send_trailers(trailers).await,
+        }
+    }
 }
 
-impl<R: ReadStream, W: WriteStream> HttpRequest for PolyHttpRequest<R, W>{
+impl<R: ReadStream, W: WriteStream, T: Http3Transport> HttpRequest for PolyHttpRequest<R, W, T>{
     fn get_type(&self) -> HttpType {
         match self {
             Self::Http1(_) => HttpType::Http1,
             Self::Http2(_) => HttpType::Http2,
+            Self::Http3(_) => HttpType::Http3,
         }
     }
 
@@ -112,18 +164,21 @@ impl<R: ReadStream, W: WriteStream> HttpRequest for PolyHttpRequest<R, W>{
         match self {
             Self::Http1(h) => h.add_header(header, value),
             Self::Http2(h) => h.add_header(&header.to_lowercase(), value),
+            Self::Http3(h) => h.add_header(&header.to_lowercase(), value),
         }
     }
     fn set_header(&mut self, header: &str, value: &str){ 
         match self {
             Self::Http1(h) => h.set_header(header, value),
             Self::Http2(h) => h.set_header(&header.to_lowercase(), value),
+            Self::Http3(h) => h.set_header(&header.to_lowercase(), value),
         } 
     }
     fn del_header(&mut self, header: &str) -> Option<Vec<String>>{ 
         match self {
             Self::Http1(h) => h.del_header(header),
             Self::Http2(h) => h.del_header(&header.to_lowercase()),
+            Self::Http3(h) => h.del_header(&header.to_lowercase()),
         }
     }
     
@@ -131,24 +186,28 @@ impl<R: ReadStream, W: WriteStream> HttpRequest for PolyHttpRequest<R, W>{
         match self {
             Self::Http1(h) => h.set_method(method),
             Self::Http2(h) => h.set_method(method),
+            Self::Http3(h) => h.set_method(method),
         }
     }
     fn set_scheme(&mut self, scheme: String) {
         match self {
             Self::Http1(h) => h.set_scheme(scheme),
             Self::Http2(h) => h.set_scheme(scheme),
+            Self::Http3(h) => h.set_scheme(scheme),
         }
     }
     fn set_path(&mut self, method: String){
         match self {
             Self::Http1(h) => h.set_path(method),
             Self::Http2(h) => h.set_path(method),
+            Self::Http3(h) => h.set_path(method),
         }
     }
     fn set_host(&mut self, host: String) {
         match self {
             Self::Http1(h) => h.set_host(host),
             Self::Http2(h) => h.set_host(host),
+            Self::Http3(h) => h.set_host(host),
         }
     }
 
@@ -156,18 +215,21 @@ impl<R: ReadStream, W: WriteStream> HttpRequest for PolyHttpRequest<R, W>{
         match self {
             Self::Http1(h) => h.write(body).await,
             Self::Http2(h) => h.write(body).await,
+            Self::Http3(h) => h.write(body).await,
         }
     }
     async fn send<'a>(&'a mut self, body: &'a [u8]) -> Result<(), LibError> {
         match self {
             Self::Http1(h) => h.send(body).await,
             Self::Http2(h) => h.send(body).await,
+            Self::Http3(h) => h.send(body).await,
         }
     }
     async fn flush<'a>(&'a mut self) -> Result<(), LibError> {
         match self {
             Self::Http1(h) => h.flush().await,
             Self::Http2(h) => h.flush().await,
+            Self::Http3(h) => h.flush().await,
         }
     }
 
@@ -175,29 +237,33 @@ impl<R: ReadStream, W: WriteStream> HttpRequest for PolyHttpRequest<R, W>{
         match self {
             Self::Http1(h) => h.get_response(),
             Self::Http2(h) => h.get_response(),
+            Self::Http3(h) => h.get_response(),
         }
     }
     async fn read_response<'_a>(&'_a mut self) -> Result<&'_a HttpResponse, LibError> {
         match self {
             Self::Http1(h) => h.read_response().await,
             Self::Http2(h) => h.read_response().await,
+            Self::Http3(h) => h.read_response().await,
         }
     }
     async fn read_until_complete<'_a>(&'_a mut self) -> Result<&'_a HttpResponse, LibError> {
         match self {
             Self::Http1(h) => h.read_until_complete().await,
             Self::Http2(h) => h.read_until_complete().await,
+            Self::Http3(h) => h.read_until_complete().await,
         }
     }
     async fn read_until_head_complete<'_a>(&'_a mut self) -> Result<&'_a HttpResponse, LibError> {
         match self {
             Self::Http1(h) => h.read_until_head_complete().await,
             Self::Http2(h) => h.read_until_head_complete().await,
+            Self::Http3(h) => h.read_until_head_complete().await,
         }
     }
 }
 
-impl<R: ReadStream, W: WriteStream> From<Http1Request<R, W>> for PolyHttpRequest<R, W>{
+impl<R: ReadStream, W: WriteStream, T: Http3Transport> From<Http1Request<R, W>> for PolyHttpRequest<R, W, T>{
     fn from(value: Http1Request<R, W>) -> Self {
         Self::Http1(value)
     }
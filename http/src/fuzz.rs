@@ -0,0 +1,97 @@
+use std::borrow::Cow;
+
+use tokio::io::AsyncWriteExt;
+
+use crate::{http1::server::Http1Socket, http2::{core::{Http2AltSvc, Http2Frame, Http2FrameType, Http2Goaway, Http2Priority, Http2Settings, origins_from}, hpack::{decoder::Decoder, encoder::Encoder, huffman::Huffman}}, websocket::core::WebSocketFrame};
+
+// one entry point per wire parser, each takes arbitrary bytes and panics when an invariant breaks
+// tests.rs runs them over the seed corpora in http/fuzz/corpus/<name> and mutations of those
+// only built for tests, a coverage guided fuzzer would call them the same way from its own crate
+
+
+// anything accepted has its ranges in bounds and comes out the same when written again
+pub fn http2_frame(data: &[u8]) {
+    let Some(frame) = Http2Frame::from(Cow::Borrowed(data)) else { return };
+
+    assert!(frame.padding.end <= data.len() && frame.payload.end <= frame.padding.start);
+    let payload = frame.get_payload();
+
+    match frame.ftype {
+        Http2FrameType::Settings => http2_settings(payload),
+        Http2FrameType::Goaway => if let Some(goaway) = Http2Goaway::from(payload) {
+            assert_eq!(Http2Goaway::from(&goaway.to_vec()).as_ref(), Some(&goaway));
+        },
+        Http2FrameType::AltSvc => if let Some(alt) = Http2AltSvc::from(payload) {
+            assert_eq!(Http2AltSvc::from(&alt.to_vec()).as_ref(), Some(&alt));
+        },
+        Http2FrameType::Origin => { let _ = origins_from(payload); },
+        Http2FrameType::PriorityUpdate => { let _ = Http2Priority::from_update(payload); },
+        _ => (),
+    }
+
+    let priority = (frame.flags & 0x20 != 0).then(|| &data[frame.priority.clone()]);
+    let padding = (frame.flags & 0x08 != 0).then(|| &data[frame.padding.clone()]);
+    let again = Http2Frame::create(frame.type_byte, frame.flags & !0x28, frame.stream_id, priority, Some(payload), padding);
+    let reparsed = Http2Frame::from_owned(again).expect("created frame does not parse");
+
+    assert_eq!((reparsed.type_byte, reparsed.flags, reparsed.stream_id, reparsed.length), (frame.type_byte, frame.flags, frame.stream_id, frame.length));
+    assert_eq!(reparsed.get_payload(), payload);
+}
+
+// settings survive a round trip, later duplicates replace earlier ones on the way
+pub fn http2_settings(data: &[u8]) {
+    let sett = Http2Settings::from(data);
+    let raw = sett.to_vec();
+    assert_eq!(Http2Settings::from(&raw).to_vec(), raw);
+}
+
+pub fn websocket_frame(data: &[u8]) {
+    let Some(frame) = WebSocketFrame::from_owned(data.to_vec()) else { return };
+
+    assert!(frame.mask.end <= frame.payload.start && frame.payload.end <= data.len());
+    let unmasked = frame.get_unmasked();
+    assert_eq!(unmasked.len(), frame.payload.len());
+
+    let mask = frame.masked.then(|| &data[frame.mask.clone()]);
+    let again = WebSocketFrame::from_owned(WebSocketFrame::create(frame.fin, frame.rsv, frame.opcode_byte, mask, &unmasked)).expect("created frame does not parse");
+    assert_eq!((again.fin, again.rsv, again.opcode_byte, again.masked), (frame.fin, frame.rsv, frame.opcode_byte, frame.masked));
+    assert_eq!(again.get_unmasked(), unmasked);
+}
+
+// a decoded block encodes into one that decodes to the same list
+pub fn hpack_block(data: &[u8]) {
+    let mut decoder: Decoder<'static> = Decoder::new(4096);
+    decoder.max_header_list_size = 65536;
+    let Ok(headers) = decoder.decode_all(data) else { return };
+
+    let list: Vec<(&[u8], &[u8])> = headers.iter().map(|(h, v)| (h.as_slice(), v.as_slice())).collect();
+    let mut block = Vec::new();
+    Encoder::new(4096).encode_block(&mut block, &list).unwrap();
+
+    assert_eq!(Decoder::new(4096).decode_all(&block).unwrap(), headers);
+}
+
+pub fn huffman(data: &[u8]) {
+    let huffman = Huffman::shared();
+
+    if let Ok(decoded) = huffman.decode(data) {
+        assert_eq!(huffman.decode(&huffman.encode(&decoded)).unwrap(), decoded);
+    }
+    let encoded = huffman.encode(data);
+    assert_eq!(encoded.len(), huffman.encoded_len(data));
+    assert_eq!(huffman.decode(&encoded).unwrap(), data);
+}
+
+// a request read from a closed connection either fails or ends, a declared body is read in full
+pub async fn http1_request(data: &[u8]) {
+    let (mut peer, net) = tokio::io::duplex(data.len().max(1));
+    peer.write_all(data).await.unwrap();
+    peer.shutdown().await.unwrap();
+
+    let mut socket = Http1Socket::new(net, 8192);
+    let Ok(client) = socket.read_until_complete().await else { return };
+
+    if client.valid && let Some(len) = client.headers.get("content-length").and_then(|cl| cl[0].parse::<usize>().ok()) && !client.headers.contains_key("transfer-encoding") {
+        assert_eq!(client.body.len(), len);
+    }
+}
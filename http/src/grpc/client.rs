@@ -0,0 +1,143 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::{grpc::core::{CONTENT_TYPE, GrpcCompression, GrpcError, GrpcMessageReader, GrpcStatus, decode_grpc_message, encode_message, format_timeout}, http2::{client::Http2Request, core::Http2ErrorCode, session::Http2Session}, shared::{LibError, LibResult, ReadStream, WriteStream}};
+
+
+#[derive(Debug)]
+pub struct GrpcRequest<R: ReadStream, W: WriteStream> {
+    pub request: Http2Request<R, W>,
+    pub reader: GrpcMessageReader,
+
+    pub deadline: Option<Instant>,
+    pub compression: Option<Box<dyn GrpcCompression>>,
+    pub compress: bool,
+
+    pub status: Option<GrpcError>,
+}
+impl<R: ReadStream, W: WriteStream> GrpcRequest<R, W> {
+    // opens a stream and sends the call headers, metadata is sent as is
+    // scheme is "https" when the connection runs over tls
+    pub async fn open(session: Arc<Http2Session<R, W>>, scheme: &str, authority: &str, path: &str, metadata: &[(&str, &str)], timeout: Option<Duration>) -> LibResult<Self> {
+        Self::with_compression(session, scheme, authority, path, metadata, timeout, None).await
+    }
+    pub async fn with_compression(session: Arc<Http2Session<R, W>>, scheme: &str, authority: &str, path: &str, metadata: &[(&str, &str)], timeout: Option<Duration>, compression: Option<Box<dyn GrpcCompression>>) -> LibResult<Self> {
+        let stream_id = session.open_stream().ok_or(LibError::Refused)?;
+        let timeout_val = timeout.map(format_timeout);
+        let accept = compression.as_ref().map(|comp| format!("identity,{}", comp.name()));
+
+        let mut head: Vec<(&[u8], &[u8])> = vec![
+            (b":method", b"POST"),
+            (b":scheme", scheme.as_bytes()),
+            (b":authority", authority.as_bytes()),
+            (b":path", path.as_bytes()),
+            (b"content-type", CONTENT_TYPE.as_bytes()),
+            (b"te", b"trailers"),
+        ];
+        if let Some(t) = &timeout_val { head.push((b"grpc-timeout", t.as_bytes())) }
+        if let Some(comp) = &compression {
+            head.push((b"grpc-encoding", comp.name().as_bytes()));
+        }
+        if let Some(accept) = &accept { head.push((b"grpc-accept-encoding", accept.as_bytes())) }
+        for (h, v) in metadata { head.push((h.as_bytes(), v.as_bytes())) }
+
+        session.send_headers(stream_id, false, &head).await?;
+
+        let mut request = Http2Request::new(stream_id, session)?;
+        request.sent_head = true;
+        request.path = path.to_owned();
+        request.scheme = scheme.to_owned();
+        request.authority = authority.to_owned();
+
+        Ok(Self {
+            request,
+            reader: GrpcMessageReader::new(),
+            deadline: timeout.map(|t| Instant::now() + t),
+            compression,
+            compress: false,
+            status: None,
+        })
+    }
+
+    pub async fn send_message(&mut self, msg: &[u8]) -> LibResult<()> {
+        let buf = match &self.compression {
+            Some(comp) if self.compress => encode_message(true, &comp.compress(msg)?),
+            _ => encode_message(false, msg),
+        };
+        self.request.write(&buf).await
+    }
+    // half closes the stream, no more messages can be sent
+    pub async fn close_send(&mut self) -> LibResult<()> {
+        self.request.session.send_data(self.request.stream_id, true, b"").await
+    }
+
+    async fn read_response(&mut self) -> LibResult<()> {
+        match self.deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, self.request.read_response()).await {
+                Ok(res) => res.map(|_| ()),
+                Err(_) => {
+                    // the server might not notice the deadline, cancel the stream ourselves
+                    let _ = self.request.session.send_rst_stream(self.request.stream_id, Http2ErrorCode::Cancel).await;
+                    Err(GrpcError::new(GrpcStatus::DeadlineExceeded, "deadline exceeded").into())
+                },
+            },
+            None => self.request.read_response().await.map(|_| ()),
+        }
+    }
+
+    // None after the trailers arrived with status OK, any other status is returned as an error
+    pub async fn read_message(&mut self) -> LibResult<Option<Vec<u8>>> {
+        loop {
+            if let Some((compressed, msg)) = self.reader.next_message()? {
+                return Ok(Some(match (compressed, &self.compression) {
+                    (false, _) => msg,
+                    (true, Some(comp)) => comp.decompress(&msg)?,
+                    (true, None) => return Err(GrpcError::new(GrpcStatus::Internal, "compressed message without grpc-encoding").into()),
+                }))
+            }
+            else if let Some(code) = self.request.is_reset {
+                return Err(LibError::ResetStream(code))
+            }
+            else if self.request.response.body_complete {
+                let status = self.read_status();
+                let err = status.clone();
+                self.status = Some(status);
+
+                return if !self.reader.buffer.is_empty() { Err(GrpcError::new(GrpcStatus::Internal, "incomplete message").into()) }
+                else if err.status.is_ok() { Ok(None) }
+                else { Err(err.into()) }
+            }
+
+            self.read_response().await?;
+            self.reader.push(&mut self.request.response.body);
+        }
+    }
+    // trailers, or the headers of a trailers-only response
+    fn read_status(&self) -> GrpcError {
+        let get = |name: &str| self.request.trailers.get(name).or(self.request.response.headers.get(name)).and_then(|v| v.first());
+
+        if self.request.response.code != 200 {
+            return GrpcError::new(GrpcStatus::Unknown, format!("http status {}", self.request.response.code))
+        }
+
+        match get("grpc-status").and_then(|s| s.parse::<u32>().ok()) {
+            Some(code) => GrpcError::new(code.into(), get("grpc-message").map(|m| decode_grpc_message(m)).unwrap_or_default()),
+            None => GrpcError::new(GrpcStatus::Internal, "missing grpc-status"),
+        }
+    }
+
+    pub async fn unary(&mut self, msg: &[u8]) -> LibResult<Vec<u8>> {
+        self.send_message(msg).await?;
+        self.close_send().await?;
+
+        let res = self.read_message().await?.ok_or(GrpcError::new(GrpcStatus::Internal, "missing response message"))?;
+        
+        if self.read_message().await?.is_some() {
+            Err(GrpcError::new(GrpcStatus::Internal, "more than one response message").into())
+        }
+        else {
+            Ok(res)
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::{fmt::Debug, io, time::Duration};
+
+// https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+
+pub const CONTENT_TYPE: &str = "application/grpc";
+pub const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrpcStatus {
+    Ok,
+    Cancelled,
+    Unknown,
+    InvalidArgument,
+    DeadlineExceeded,
+    NotFound,
+    AlreadyExists,
+    PermissionDenied,
+    ResourceExhausted,
+    FailedPrecondition,
+    Aborted,
+    OutOfRange,
+    Unimplemented,
+    Internal,
+    Unavailable,
+    DataLoss,
+    Unauthenticated,
+
+    Other(u32),
+}
+impl GrpcStatus {
+    pub fn is_ok(&self) -> bool { if let Self::Ok = self { true } else { false } }
+}
+impl From<u32> for GrpcStatus {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::Ok,
+            1 => Self::Cancelled,
+            2 => Self::Unknown,
+            3 => Self::InvalidArgument,
+            4 => Self::DeadlineExceeded,
+            5 => Self::NotFound,
+            6 => Self::AlreadyExists,
+            7 => Self::PermissionDenied,
+            8 => Self::ResourceExhausted,
+            9 => Self::FailedPrecondition,
+            10 => Self::Aborted,
+            11 => Self::OutOfRange,
+            12 => Self::Unimplemented,
+            13 => Self::Internal,
+            14 => Self::Unavailable,
+            15 => Self::DataLoss,
+            16 => Self::Unauthenticated,
+
+            v => Self::Other(v),
+        }
+    }
+}
+impl Into<u32> for GrpcStatus {
+    fn into(self) -> u32 {
+        match self {
+            Self::Ok => 0,
+            Self::Cancelled => 1,
+            Self::Unknown => 2,
+            Self::InvalidArgument => 3,
+            Self::DeadlineExceeded => 4,
+            Self::NotFound => 5,
+            Self::AlreadyExists => 6,
+            Self::PermissionDenied => 7,
+            Self::ResourceExhausted => 8,
+            Self::FailedPrecondition => 9,
+            Self::Aborted => 10,
+            Self::OutOfRange => 11,
+            Self::Unimplemented => 12,
+            Self::Internal => 13,
+            Self::Unavailable => 14,
+            Self::DataLoss => 15,
+            Self::Unauthenticated => 16,
+
+            Self::Other(v) => v,
+        }
+    }
+}
+impl std::fmt::Display for GrpcStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok => write!(f, "OK"),
+            Self::Cancelled => write!(f, "CANCELLED"),
+            Self::Unknown => write!(f, "UNKNOWN"),
+            Self::InvalidArgument => write!(f, "INVALID_ARGUMENT"),
+            Self::DeadlineExceeded => write!(f, "DEADLINE_EXCEEDED"),
+            Self::NotFound => write!(f, "NOT_FOUND"),
+            Self::AlreadyExists => write!(f, "ALREADY_EXISTS"),
+            Self::PermissionDenied => write!(f, "PERMISSION_DENIED"),
+            Self::ResourceExhausted => write!(f, "RESOURCE_EXHAUSTED"),
+            Self::FailedPrecondition => write!(f, "FAILED_PRECONDITION"),
+            Self::Aborted => write!(f, "ABORTED"),
+            Self::OutOfRange => write!(f, "OUT_OF_RANGE"),
+            Self::Unimplemented => write!(f, "UNIMPLEMENTED"),
+            Self::Internal => write!(f, "INTERNAL"),
+            Self::Unavailable => write!(f, "UNAVAILABLE"),
+            Self::DataLoss => write!(f, "DATA_LOSS"),
+            Self::Unauthenticated => write!(f, "UNAUTHENTICATED"),
+
+            Self::Other(v) => write!(f, "STATUS({v})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrpcError {
+    pub status: GrpcStatus,
+    pub message: String,
+}
+impl GrpcError {
+    pub fn new(status: GrpcStatus, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+}
+
+
+// per message compression, the name is what goes into grpc-encoding
+pub trait GrpcCompression: Debug + Send + Sync {
+    fn name(&self) -> &str;
+    fn compress(&self, msg: &[u8]) -> io::Result<Vec<u8>>;
+    fn decompress(&self, msg: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+
+// Length-Prefixed-Message, compressed flag followed by a 4 byte big endian length
+pub fn encode_message(compressed: bool, msg: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(5 + msg.len());
+    buf.push(compressed as u8);
+    buf.extend_from_slice(&u32::to_be_bytes(msg.len() as u32));
+    buf.extend_from_slice(msg);
+    buf
+}
+
+#[derive(Debug)]
+pub struct GrpcMessageReader {
+    pub buffer: Vec<u8>,
+    pub max_message_size: usize,
+}
+impl GrpcMessageReader {
+    pub fn new() -> Self {
+        Self { buffer: Vec::new(), max_message_size: MAX_MESSAGE_SIZE }
+    }
+
+    pub fn push(&mut self, data: &mut Vec<u8>) {
+        self.buffer.append(data);
+    }
+    // yields (compressed, message) once a whole message is buffered
+    pub fn next_message(&mut self) -> Result<Option<(bool, Vec<u8>)>, GrpcError> {
+        if self.buffer.len() < 5 { return Ok(None) }
+
+        let flag = self.buffer[0];
+        let len = u32::from_be_bytes([self.buffer[1], self.buffer[2], self.buffer[3], self.buffer[4]]) as usize;
+
+        if flag > 1 {
+            Err(GrpcError::new(GrpcStatus::Internal, "invalid compressed flag"))
+        }
+        else if len > self.max_message_size {
+            Err(GrpcError::new(GrpcStatus::ResourceExhausted, format!("message larger than {} bytes", self.max_message_size)))
+        }
+        else if self.buffer.len() < 5 + len {
+            Ok(None)
+        }
+        else {
+            let msg = self.buffer[5..5 + len].to_vec();
+            self.buffer.drain(..5 + len);
+            Ok(Some((flag == 1, msg)))
+        }
+    }
+}
+impl Default for GrpcMessageReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+// grpc-timeout, at most 8 digits followed by a unit
+pub fn parse_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 { return None }
+
+    let (num, unit) = value.split_at(value.len() - 1);
+    if !num.bytes().all(|b| b.is_ascii_digit()) { return None }
+    let num: u64 = num.parse().ok()?;
+
+    match unit {
+        "H" => Some(Duration::from_secs(num * 3600)),
+        "M" => Some(Duration::from_secs(num * 60)),
+        "S" => Some(Duration::from_secs(num)),
+        "m" => Some(Duration::from_millis(num)),
+        "u" => Some(Duration::from_micros(num)),
+        "n" => Some(Duration::from_nanos(num)),
+        _ => None,
+    }
+}
+pub fn format_timeout(timeout: Duration) -> String {
+    const MAX: u128 = 99_999_999;
+    let nanos = timeout.as_nanos();
+
+    // smallest unit that still fits, rounding up so the deadline is never shorter
+    for (unit, div) in [("n", 1u128), ("u", 1_000), ("m", 1_000_000), ("S", 1_000_000_000), ("M", 60_000_000_000), ("H", 3_600_000_000_000)] {
+        let val = nanos.div_ceil(div);
+        if val <= MAX {
+            return format!("{val}{unit}")
+        }
+    }
+    format!("{MAX}H")
+}
+
+
+// grpc-message is percent encoded, everything outside printable ascii and % itself
+pub fn encode_grpc_message(message: &str) -> String {
+    let mut out = String::with_capacity(message.len());
+    for b in message.bytes() {
+        if (0x20..0x7f).contains(&b) && b != b'%' { out.push(b as char) }
+        else { out.push_str(&format!("%{b:02X}")) }
+    }
+    out
+}
+pub fn decode_grpc_message(message: &str) -> String {
+    let bytes = message.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && let Some(hex) = bytes.get(i + 1..i + 3) && let Ok(hex) = std::str::from_utf8(hex) && let Ok(b) = u8::from_str_radix(hex, 16) {
+            out.push(b);
+            i += 3;
+        }
+        else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
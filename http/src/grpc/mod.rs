@@ -0,0 +1,3 @@
+pub mod core;
+pub mod server;
+pub mod client;
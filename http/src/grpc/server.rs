@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{grpc::core::{CONTENT_TYPE, GrpcCompression, GrpcError, GrpcMessageReader, GrpcStatus, encode_grpc_message, encode_message, parse_timeout}, http2::server::Http2Socket, shared::{LibError, LibResult, ReadStream, WriteStream}};
+
+
+#[derive(Debug)]
+pub struct GrpcSocket<R: ReadStream, W: WriteStream> {
+    pub socket: Http2Socket<R, W>,
+    pub reader: GrpcMessageReader,
+
+    pub deadline: Option<Instant>,
+    pub compression: Option<Box<dyn GrpcCompression>>,
+    pub compress: bool,
+
+    pub finished: bool,
+}
+impl<R: ReadStream, W: WriteStream> GrpcSocket<R, W> {
+    pub fn new(socket: Http2Socket<R, W>) -> Self {
+        Self {
+            socket,
+            reader: GrpcMessageReader::new(),
+            deadline: None,
+            compression: None,
+            compress: false,
+            finished: false,
+        }
+    }
+    pub fn with_compression(socket: Http2Socket<R, W>, compression: impl GrpcCompression + 'static) -> Self {
+        let mut grpc = Self::new(socket);
+        grpc.compression = Some(Box::new(compression));
+        grpc
+    }
+
+    // validates the request and picks up grpc-timeout, should be called before anything else
+    pub async fn read_head(&mut self) -> LibResult<()> {
+        let client = self.socket.read_until_head_complete().await?;
+        let header = |name: &str| client.headers.get(name).and_then(|v| v.first()).cloned();
+        let (content_type, timeout, encoding) = (header("content-type"), header("grpc-timeout"), header("grpc-encoding"));
+
+        // not a grpc client, answered in plain http
+        if !content_type.map(|ct| ct.starts_with(CONTENT_TYPE)).unwrap_or(false) {
+            self.finished = true;
+            self.socket.status = 415;
+            self.socket.send_head(true).await?;
+            return Err(GrpcError::new(GrpcStatus::Internal, "not a grpc request").into())
+        }
+
+        self.deadline = timeout.as_deref().and_then(parse_timeout).map(|t| Instant::now() + t);
+
+        let supported = self.compression.as_ref().map(|c| c.name().to_owned());
+        match encoding {
+            None => (),
+            Some(enc) if enc == "identity" || Some(&enc) == supported.as_ref() => (),
+            Some(enc) => {
+                let accept = supported.map(|name| format!("identity,{name}")).unwrap_or("identity".to_owned());
+                self.socket.set_header("grpc-accept-encoding", &accept);
+                return Err(GrpcError::new(GrpcStatus::Unimplemented, format!("unsupported grpc-encoding {enc}")).into())
+            }
+        }
+
+        Ok(())
+    }
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.socket.client.path
+    }
+    pub fn time_left(&self) -> Option<Duration> {
+        self.deadline.map(|d| d.saturating_duration_since(Instant::now()))
+    }
+
+    // None once the client half closed the stream
+    pub async fn read_message(&mut self) -> LibResult<Option<Vec<u8>>> {
+        loop {
+            if let Some((compressed, msg)) = self.reader.next_message()? {
+                return Ok(Some(self.decompress(compressed, msg)?))
+            }
+            else if self.socket.client.body_complete || self.socket.is_reset.is_some() {
+                return if self.reader.buffer.is_empty() { Ok(None) }
+                else { Err(GrpcError::new(GrpcStatus::Internal, "incomplete message").into()) }
+            }
+
+            match self.deadline {
+                Some(deadline) => {
+                    tokio::time::timeout_at(deadline, self.socket.read_client()).await
+                        .map_err(|_| GrpcError::new(GrpcStatus::DeadlineExceeded, "deadline exceeded"))??;
+                },
+                None => { self.socket.read_client().await?; },
+            }
+            self.reader.push(&mut self.socket.client.body);
+        }
+    }
+    fn decompress(&self, compressed: bool, msg: Vec<u8>) -> LibResult<Vec<u8>> {
+        match (compressed, &self.compression) {
+            (false, _) => Ok(msg),
+            (true, Some(comp)) => Ok(comp.decompress(&msg)?),
+            (true, None) => Err(GrpcError::new(GrpcStatus::Internal, "compressed message without grpc-encoding").into()),
+        }
+    }
+
+    async fn send_grpc_head(&mut self) -> LibResult<()> {
+        if !self.socket.sent_head {
+            self.socket.status = 200;
+            self.socket.set_header("content-type", CONTENT_TYPE);
+            if let Some(comp) = &self.compression {
+                let name = comp.name().to_owned();
+                self.socket.set_header("grpc-encoding", &name);
+            }
+            self.socket.send_head(false).await?;
+        }
+        Ok(())
+    }
+    pub async fn send_message(&mut self, msg: &[u8]) -> LibResult<()> {
+        self.send_grpc_head().await?;
+
+        let buf = match &self.compression {
+            Some(comp) if self.compress => encode_message(true, &comp.compress(msg)?),
+            _ => encode_message(false, msg),
+        };
+        self.socket.write(&buf).await
+    }
+
+    // ends the call, without any messages sent this becomes a trailers-only response
+    pub async fn finish(&mut self, status: GrpcStatus, message: &str) -> LibResult<()> {
+        if self.finished { return Err(LibError::StreamClosed) }
+        self.finished = true;
+
+        let code: u32 = status.into();
+        let code = code.to_string();
+        let message = encode_grpc_message(message);
+
+        if !self.socket.sent_head {
+            self.socket.status = 200;
+            self.socket.set_header("content-type", CONTENT_TYPE);
+            self.socket.set_header("grpc-status", &code);
+            if !message.is_empty() { self.socket.set_header("grpc-message", &message); }
+            self.socket.send_head(true).await
+        }
+        else if message.is_empty() {
+            self.socket.send_trailers(&[("grpc-status", &code)]).await
+        }
+        else {
+            self.socket.send_trailers(&[("grpc-status", &code), ("grpc-message", &message)]).await
+        }
+    }
+    #[inline]
+    pub async fn finish_err(&mut self, err: &GrpcError) -> LibResult<()> {
+        self.finish(err.status, &err.message).await
+    }
+
+    pub async fn unary<F: AsyncFnOnce(Vec<u8>) -> Result<Vec<u8>, GrpcError>>(&mut self, handler: F) -> LibResult<()> {
+        let req = match self.read_message().await {
+            Ok(Some(req)) => req,
+            Ok(None) => return self.finish(GrpcStatus::Internal, "missing request message").await,
+            Err(LibError::Grpc(err)) => return self.finish_err(&err).await,
+            Err(e) => return Err(e),
+        };
+
+        match handler(req).await {
+            Ok(res) => {
+                self.send_message(&res).await?;
+                self.finish(GrpcStatus::Ok, "").await
+            },
+            Err(err) => self.finish_err(&err).await,
+        }
+    }
+}
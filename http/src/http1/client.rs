@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use rand::Rng;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
 
-use crate::{http1::get_chunk, http2::{PREFACE, core::Http2Settings, session::Http2Session}, shared::{HttpMethod, HttpRequest, HttpResponse, HttpType, HttpVersion, LibError, LibResult, ReadStream, Stream, WriteStream}, websocket::socket::{MAGIC, WebSocket}};
+use crate::{http1::{get_chunk, read_body}, http2::{PREFACE, core::Http2Settings, session::Http2Session}, shared::{HttpMethod, HttpRequest, HttpResponse, HttpType, HttpVersion, LibError, LibResult, ReadStream, Stream, WriteStream}, websocket::socket::{MAGIC, WebSocket}};
 
 use base64::{Engine, engine::general_purpose::STANDARD as b64std};
 
@@ -189,15 +189,12 @@ impl<R: ReadStream, W: WriteStream> Http1Request<R, W>{
                     self.response.body_complete = true;
                 }
                 else{
-                    let ol = self.response.body.len();
-                    self.response.body.resize(self.response.body.len() + len, 0);
-                    self.netr.read_exact(&mut self.response.body[ol..]).await?;
+                    read_body(&mut self.netr, &mut self.response.body, len).await?;
                     self.netr.read_until(b'\n', &mut self.line_buf).await?;
                 }
             }
             else if let Some(cl) = self.response.headers.get("content-length") && let Ok(len) = cl[0].parse::<usize>(){
-                self.response.body.resize(len, 0);
-                self.netr.read_exact(&mut self.response.body).await?;
+                read_body(&mut self.netr, &mut self.response.body, len).await?;
                 self.response.body_complete = true;
             }
             else if self.response.version == HttpVersion::Http10 || self.response.version == HttpVersion::Http09 {
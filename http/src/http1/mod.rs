@@ -1,3 +1,7 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
 pub mod server;
 pub mod client;
 
@@ -12,3 +16,9 @@ fn get_chunk(buf: &[u8]) -> Vec<u8>{
 
     v
 }
+
+// appends len bytes to body, it only grows with what actually arrives so a bogus length can not exhaust memory
+async fn read_body<R: AsyncRead + Unpin>(netr: &mut R, body: &mut Vec<u8>, len: usize) -> io::Result<()> {
+    let read = netr.take(len as u64).read_to_end(body).await?;
+    if read < len { Err(io::ErrorKind::UnexpectedEof.into()) } else { Ok(()) }
+}
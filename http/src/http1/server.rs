@@ -5,7 +5,7 @@ use base64::Engine;
 use base64::engine::general_purpose::STANDARD as b64std;
 use sha1::{Digest, Sha1};
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
-use crate::http1::get_chunk;
+use crate::http1::{get_chunk, read_body};
 use crate::http2::core::Http2Settings;
 use crate::http2::session::{Http2Data, Http2Session};
 use crate::shared::{HttpMethod, LibError, LibResult};
@@ -139,15 +139,12 @@ impl<R: ReadStream, W: WriteStream> Http1Socket<R, W>{
                     self.client.body_complete = true;
                 }
                 else{
-                    let ol = self.client.body.len();
-                    self.client.body.resize(self.client.body.len() + len, 0);
-                    self.netr.read_exact(&mut self.client.body[ol..]).await?;
+                    read_body(&mut self.netr, &mut self.client.body, len).await?;
                     self.netr.read_until(b'\n', &mut self.line_buf).await?;
                 }
             }
             else if let Some(cl) = self.client.headers.get("content-length") && let Ok(len) = cl[0].parse::<usize>(){
-                self.client.body.resize(len, 0);
-                self.netr.read_exact(&mut self.client.body).await?;
+                read_body(&mut self.netr, &mut self.client.body, len).await?;
                 self.client.body_complete = true;
             }
             else if self.client.version == HttpVersion::Http10 {
@@ -337,7 +334,7 @@ impl<R: ReadStream, W: WriteStream> Http1Socket<R, W>{
 
         curr.end_head = true;
         curr.end_body = true;
-        curr.body = client.body;
+        curr.body = client.body.into();
 
         if let Some(host) = client.host {
             curr.headers.push((b":authority".to_vec(), host.into_bytes()));
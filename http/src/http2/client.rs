@@ -1,6 +1,6 @@
 use std::{collections::HashMap, sync::Arc};
 
-use crate::{http2::session::Http2Session, shared::{HttpMethod, HttpRequest, HttpResponse, HttpType, LibError, LibResult, ReadStream, WriteStream, string_from_owned_utf8}};
+use crate::{http2::{core::Http2ErrorCode, session::Http2Session, stream::{Http2Stream, Http2StreamReader, Http2StreamWriter}}, shared::{HttpMethod, HttpRequest, HttpResponse, HttpType, LibError, LibResult, ReadStream, WriteStream, string_from_owned_utf8}, websocket::socket::WebSocket};
 
 
 #[derive(Debug)]
@@ -12,6 +12,8 @@ pub struct Http2Request<R: ReadStream, W: WriteStream> {
     pub method: HttpMethod,
     pub authority: String,
     pub scheme: String,
+    // sent as :protocol, turns a CONNECT into an extended CONNECT
+    pub protocol: Option<String>,
     
     pub headers: HashMap<String, Vec<String>>,
     
@@ -19,7 +21,10 @@ pub struct Http2Request<R: ReadStream, W: WriteStream> {
     pub sent: bool,
     
     pub response: HttpResponse,
-    pub is_reset: bool,
+    pub is_reset: Option<Http2ErrorCode>,
+    pub trailers: HashMap<String, Vec<String>>,
+    // 1xx responses ahead of the final one, in order
+    pub interim: Vec<(u16, HashMap<String, Vec<String>>)>,
 }
 impl<R: ReadStream, W: WriteStream> Http2Request<R, W> {
     pub fn new(stream_id: u32, session: Arc<Http2Session<R, W>>) -> LibResult<Self> {
@@ -30,11 +35,14 @@ impl<R: ReadStream, W: WriteStream> Http2Request<R, W> {
                 method: HttpMethod::Get,
                 authority: String::new(),
                 scheme: String::new(),
+                protocol: None,
                 headers: HashMap::new(),
                 sent_head: false,
                 sent: false,
                 response: HttpResponse::default_h2(),
-                is_reset: false,
+                is_reset: None,
+                trailers: HashMap::new(),
+                interim: Vec::new(),
             })
         }
         else {
@@ -53,7 +61,12 @@ impl<R: ReadStream, W: WriteStream> Http2Request<R, W> {
         self.headers.remove(header)
     }
 
+    #[inline]
     pub async fn send_head(&mut self, end: bool) -> LibResult<()> {
+        self.write_head(end, true).await
+    }
+    // a head followed by body or trailers is left queued for them to take out
+    async fn write_head(&mut self, end: bool, flush: bool) -> LibResult<()> {
         if !self.sent_head {
             self.sent_head = true;
             let mut headers = Vec::new();
@@ -65,6 +78,9 @@ impl<R: ReadStream, W: WriteStream> Http2Request<R, W> {
             headers.push((b":scheme".to_vec(), self.scheme.as_bytes().to_vec()));
             headers.push((b":authority".to_vec(), self.authority.as_bytes().to_vec()));
             headers.push((b":path".to_vec(), self.path.as_bytes().to_vec()));
+            if let Some(protocol) = &self.protocol {
+                headers.push((b":protocol".to_vec(), protocol.as_bytes().to_vec()));
+            }
 
             for (header, values) in self.headers.drain(){
                 for value in values {
@@ -73,7 +89,8 @@ impl<R: ReadStream, W: WriteStream> Http2Request<R, W> {
             }
 
             let head = headers.iter().map(|(h, v)| (h.as_slice(), v.as_slice())).collect::<Vec<(&[u8], &[u8])>>();
-            self.session.send_headers(self.stream_id, end, &head).await?;
+            if flush { self.session.send_headers(self.stream_id, end, &head).await?; }
+            else { self.session.send_headers_buffered(self.stream_id, end, &head).await?; }
             Ok(())
         }
         else {
@@ -82,31 +99,102 @@ impl<R: ReadStream, W: WriteStream> Http2Request<R, W> {
     }
     pub async fn write(&mut self, buf: &[u8]) -> LibResult<()> {
         if !self.sent_head {
-            self.send_head(false).await?;
+            self.write_head(false, false).await?;
         }
         self.session.send_data(self.stream_id, false, buf).await
     }
     pub async fn send(&mut self, buf: &[u8]) -> LibResult<()> {
         if !self.sent_head {
             self.set_header("content-length", &buf.len().to_string());
-            self.send_head(false).await?;
+            self.write_head(false, false).await?;
         }
         self.session.send_data(self.stream_id, true, buf).await
     }
+    // ends the stream with a trailing header block instead of an empty DATA frame
+    pub async fn send_trailers(&mut self, trailers: &[(&str, &str)]) -> LibResult<()> {
+        if !self.sent_head {
+            self.write_head(false, false).await?;
+        }
+        let trailers = trailers.iter().map(|(h, v)| (h.as_bytes(), v.as_bytes())).collect::<Vec<(&[u8], &[u8])>>();
+        self.session.send_trailers(self.stream_id, &trailers).await
+    }
+
+    // streams the server promised in association with this request
+    #[inline]
+    pub fn promised(&self) -> Vec<u32> {
+        self.session.promised_streams(self.stream_id)
+    }
+    pub fn accept_push(&self, promised: u32) -> LibResult<Self> {
+        // the associated stream is the one that waits for the CONTINUATION of a promise
+        let complete = self.session.streams.get(&self.stream_id).is_some_and(|shard| shard.promising != Some(promised));
+        let push_headers = match self.session.streams.get(&promised) {
+            Some(shard) if complete && shard.ascociated == Some(self.stream_id) => shard.push_headers.clone(),
+            _ => return Err(LibError::InvalidStream),
+        };
+
+        let mut pushed = Self::new(promised, self.session.clone())?;
+        pushed.sent_head = true;
+        pushed.sent = true;
+
+        for (h, v) in push_headers {
+            let header = string_from_owned_utf8(h);
+            let value = string_from_owned_utf8(v);
+
+            match header.as_str() {
+                ":method" => pushed.method = value.into(),
+                ":scheme" => pushed.scheme = value,
+                ":authority" => pushed.authority = value,
+                ":path" => pushed.path = value,
+                _ => {
+                    if let Some(values) = pushed.headers.get_mut(&header) { values.push(value) }
+                    else { pushed.headers.insert(header, vec![value]); }
+                },
+            }
+        }
+
+        Ok(pushed)
+    }
+    pub async fn refuse_push(&self, promised: u32) -> LibResult<()> {
+        match self.session.streams.get_mut(&promised) {
+            Some(mut shard) if shard.ascociated == Some(self.stream_id) => {
+                shard.reset = true;
+                shard.reset_code = Some(Http2ErrorCode::Cancel);
+            },
+            _ => return Err(LibError::InvalidStream),
+        }
+        self.session.send_rst_stream(promised, Http2ErrorCode::Cancel).await?;
+        Ok(())
+    }
 
     pub async fn read_response(&mut self) -> LibResult<&HttpResponse> {
         let mut shard = self.session.streams.get_mut(&self.stream_id).unwrap();
 
-        if shard.reset {
-            self.is_reset = true;
+        if shard.refused {
+            return Err(LibError::Refused)
+        }
+        else if shard.reset {
+            self.is_reset = Some(shard.reset_code.unwrap_or(Http2ErrorCode::Cancel));
         }
         else if !self.response.head_complete {
             let mut shard = 
             if !shard.end_head {
+                // registered before the shard is let go, a reset or GOAWAY in between is not missed
                 let notif = shard.head_complete.clone();
+                let notified = notif.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
                 drop(shard);
-                notif.notified().await;
-                self.session.streams.get_mut(&self.stream_id).unwrap()
+                notified.await;
+
+                let shard = self.session.streams.get_mut(&self.stream_id).unwrap();
+                if shard.refused {
+                    return Err(LibError::Refused)
+                }
+                else if shard.reset && !shard.end_head {
+                    self.is_reset = Some(shard.reset_code.unwrap_or(Http2ErrorCode::Cancel));
+                    return Ok(&self.response)
+                }
+                shard
             }
             else { shard };
             
@@ -114,8 +202,22 @@ impl<R: ReadStream, W: WriteStream> Http2Request<R, W> {
 
             let mut headers = Vec::with_capacity(shard.headers.len());
             headers.append(&mut shard.headers);
+            let interim = std::mem::take(&mut shard.interim);
             drop(shard);
 
+            for block in interim {
+                let mut code = 0;
+                let mut fields: HashMap<String, Vec<String>> = HashMap::new();
+                for (h, v) in block {
+                    let header = string_from_owned_utf8(h);
+                    let value = string_from_owned_utf8(v);
+
+                    if header == ":status" { code = value.parse().unwrap_or(0) }
+                    else { fields.entry(header).or_default().push(value) }
+                }
+                self.interim.push((code, fields));
+            }
+
             for (h, v) in headers {
                 let header = string_from_owned_utf8(h);
                 let value = string_from_owned_utf8(v);
@@ -135,7 +237,7 @@ impl<R: ReadStream, W: WriteStream> Http2Request<R, W> {
         else if !self.response.body_complete {
 
             let avail = shard.body.len();
-            self.response.body.append(&mut shard.body);
+            shard.body.append_to(&mut self.response.body);
             self.response.body_complete = shard.end_body;
             
             if !shard.end_body && avail == 0 {
@@ -143,23 +245,114 @@ impl<R: ReadStream, W: WriteStream> Http2Request<R, W> {
                 drop(shard);
                 notif.notified().await;
             }
+            else if shard.end_body {
+                for (h, v) in shard.trailers.drain(..) {
+                    let header = string_from_owned_utf8(h);
+                    let value = string_from_owned_utf8(v);
+
+                    if let Some(values) = self.trailers.get_mut(&header) { values.push(value) }
+                    else { self.trailers.insert(header, vec![value]); }
+                }
+            }
         }
 
         Ok(&self.response)
     }
     pub async fn read_until_complete(&mut self) -> LibResult<&HttpResponse> {
-        while !self.response.body_complete && !self.is_reset {
+        while !self.response.body_complete && self.is_reset.is_none() {
             self.read_response().await?;
         }
         Ok(&self.response)
     }
     pub async fn read_until_head_complete(&mut self) -> LibResult<&HttpResponse> {
-        while !self.response.head_complete && !self.is_reset {
+        while !self.response.head_complete && self.is_reset.is_none() {
             self.read_response().await?;
         }
         Ok(&self.response)
     }
 }
+impl<R: ReadStream + 'static, W: WriteStream + 'static> Http2Request<R, W> {
+    // rfc8441, the peer has to have sent SETTINGS_ENABLE_CONNECT_PROTOCOL first
+    pub async fn open_extended(session: Arc<Http2Session<R, W>>, protocol: &str, scheme: &str, authority: &str, path: &str, headers: &[(&str, &str)]) -> LibResult<Self> {
+        if !session.connect_protocol_enabled() {
+            return Err(LibError::NotAccepted)
+        }
+        let stream_id = session.open_stream().ok_or(LibError::Refused)?;
+
+        let mut head: Vec<(&[u8], &[u8])> = vec![
+            (b":method", b"CONNECT"),
+            (b":protocol", protocol.as_bytes()),
+            (b":scheme", scheme.as_bytes()),
+            (b":authority", authority.as_bytes()),
+            (b":path", path.as_bytes()),
+        ];
+        for (h, v) in headers { head.push((h.as_bytes(), v.as_bytes())) }
+
+        session.send_headers(stream_id, false, &head).await?;
+
+        let mut request = Self::new(stream_id, session)?;
+        request.sent_head = true;
+        request.method = HttpMethod::Connect;
+        request.protocol = Some(protocol.to_owned());
+        request.scheme = scheme.to_owned();
+        request.authority = authority.to_owned();
+        request.path = path.to_owned();
+
+        Ok(request)
+    }
+    // scheme is "https" for wss:// and "http" for ws://
+    pub async fn websocket(session: Arc<Http2Session<R, W>>, scheme: &str, authority: &str, path: &str, headers: &[(&str, &str)]) -> LibResult<WebSocket<Http2StreamReader<R, W>, Http2StreamWriter<R, W>>> {
+        let mut headers = headers.to_vec();
+        headers.push(("sec-websocket-version", "13"));
+        let mut request = Self::open_extended(session, "websocket", scheme, authority, path, &headers).await?;
+
+        let code = request.read_until_head_complete().await?.code;
+        if let Some(code) = request.is_reset {
+            Err(LibError::ResetStream(code))
+        }
+        else if code != 200 {
+            Err(LibError::InvalidUpgrade)
+        }
+        else {
+            request.websocket_direct()
+        }
+    }
+    pub fn websocket_direct(self) -> LibResult<WebSocket<Http2StreamReader<R, W>, Http2StreamWriter<R, W>>> {
+        let (netr, netw) = self.into_stream()?.into_split();
+        Ok(WebSocket::with_split(netr, netw))
+    }
+
+    // a classic CONNECT, only :method and :authority are sent
+    pub async fn connect(session: Arc<Http2Session<R, W>>, authority: &str, headers: &[(&str, &str)]) -> LibResult<Http2Stream<R, W>> {
+        let stream_id = session.open_stream().ok_or(LibError::Refused)?;
+
+        let mut head: Vec<(&[u8], &[u8])> = vec![(b":method", b"CONNECT"), (b":authority", authority.as_bytes())];
+        for (h, v) in headers { head.push((h.as_bytes(), v.as_bytes())) }
+
+        session.send_headers(stream_id, false, &head).await?;
+
+        let mut request = Self::new(stream_id, session)?;
+        request.sent_head = true;
+        request.method = HttpMethod::Connect;
+        request.authority = authority.to_owned();
+
+        let code = request.read_until_head_complete().await?.code;
+        if let Some(code) = request.is_reset {
+            Err(LibError::ResetStream(code))
+        }
+        else if !(200..300).contains(&code) {
+            Err(LibError::NotAccepted)
+        }
+        else {
+            request.into_stream()
+        }
+    }
+    // whatever was not read into response.body yet is read from the stream
+    #[inline]
+    pub fn into_stream(self) -> LibResult<Http2Stream<R, W>> {
+        Http2Stream::new(self.stream_id, self.session)
+    }
+}
 impl<R: ReadStream, W: WriteStream> HttpRequest for Http2Request<R, W> {
     #[inline]
     fn get_type(&self) -> HttpType {
@@ -1,8 +1,8 @@
-use std::{borrow::Cow, ops::Range};
+use std::{borrow::Cow, fmt::Debug, ops::Range, time::Duration};
 
 use tokio::io::AsyncReadExt;
 
-use crate::shared::ReadStream;
+use crate::shared::{LibResult, ReadStream};
 
 
 #[derive(Debug, Clone)]
@@ -74,11 +74,13 @@ impl<'a> Http2Frame<'a>{
         if flags & 0x08 != 0 {
             pad_len = *buf.get(pay_start)?;
             pay_start += 1;
-            pay_end -= pad_len as usize;
+            pay_end = pay_end.checked_sub(pad_len as usize)?;
         }
         if flags & 0x20 != 0 {
             pay_start += 5;
         }
+        // padding longer than the frame or a truncated source would panic in get_payload
+        if pay_start > pay_end || buf.len() < length as usize + 9 { return None }
 
         let priority = if flags & 0x08 != 0 { 10 } else { 9 }..pay_start;
         let payload = pay_start..pay_end;
@@ -99,6 +101,10 @@ impl<'a> Http2Frame<'a>{
             ftype,
         })
     }
+    #[inline]
+    pub fn from_owned(source: Vec<u8>) -> Option<Http2Frame<'static>> {
+        Http2Frame::from(Cow::Owned(source))
+    }
     pub async fn from_reader<R: ReadStream>(stream: &mut R) -> Result<Http2Frame<'static>, std::io::Error> {
         let mut source = vec![0; 9];
         stream.read_exact(&mut source).await?;
@@ -148,6 +154,12 @@ impl<'a> Http2Frame<'a>{
     }
 
     pub fn create(ftype: impl Into<u8>, flags: u8, stream_id: u32, priority: Option<&[u8]>, payload: Option<&[u8]>, padding: Option<&[u8]>) -> Vec<u8> {
+        let mut frame = Vec::new();
+        Self::create_into(&mut frame, ftype, flags, stream_id, priority, payload, padding);
+        frame
+    }
+    // appends to an existing buffer instead of allocating one per frame
+    pub fn create_into(frame: &mut Vec<u8>, ftype: impl Into<u8>, flags: u8, stream_id: u32, priority: Option<&[u8]>, payload: Option<&[u8]>, padding: Option<&[u8]>) {
         let mut priority = priority.filter(|s| s.len() == 5);
         let mut payload = payload.filter(|s| s.len() < 16777216);
         let mut padding = padding.filter(|s| s.len() < 256);
@@ -168,7 +180,9 @@ impl<'a> Http2Frame<'a>{
             length
         };
         
-        let mut frame = vec![0; 9 + length];
+        let base = frame.len();
+        frame.resize(base + 9 + length, 0);
+        let frame = &mut frame[base..];
 
         frame[0] = ((length & 0xff0000) >> 16) as u8;
         frame[1] = ((length & 0x00ff00) >> 8) as u8;
@@ -198,8 +212,6 @@ impl<'a> Http2Frame<'a>{
             let off = frame.len() - padding.len();
             frame[off..].copy_from_slice(padding);
         }
-
-        frame
     }
 
 
@@ -235,6 +247,9 @@ pub enum Http2FrameType{
     Goaway,
     WindowUpdate,
     Continuation,
+    AltSvc, // RFC 7838
+    Origin, // RFC 8336
+    PriorityUpdate, // RFC 9218
     
     Invalid(u8),
 }
@@ -251,6 +266,9 @@ impl From<u8> for Http2FrameType {
             7 => Self::Goaway,
             8 => Self::WindowUpdate,
             9 => Self::Continuation,
+            10 => Self::AltSvc,
+            12 => Self::Origin,
+            16 => Self::PriorityUpdate,
 
             v => Self::Invalid(v),
         }
@@ -269,12 +287,73 @@ impl Into<u8> for Http2FrameType {
             Self::Goaway => 7,
             Self::WindowUpdate => 8,
             Self::Continuation => 9,
+            Self::AltSvc => 10,
+            Self::Origin => 12,
+            Self::PriorityUpdate => 16,
 
             Self::Invalid(v) => v,
         }
     }
 }
 
+// unknown ids past this many are dropped and `truncated` is set,
+// extensions and Http2Session::peer_unknown_settings still see all of them
+pub const MAX_UNKNOWN_SETTINGS: usize = 8;
+
+// unknown settings stored inline, so Http2Settings stays Copy
+#[derive(Debug, Clone, Copy)]
+pub struct Http2UnknownSettings {
+    pub len: usize,
+    pub entries: [(u16, u32); MAX_UNKNOWN_SETTINGS],
+    // some were dropped, get_unknown can miss an id the peer did send
+    pub truncated: bool,
+}
+impl Http2UnknownSettings {
+    pub const fn new() -> Self {
+        Self { len: 0, entries: [(0, 0); MAX_UNKNOWN_SETTINGS], truncated: false }
+    }
+    #[inline]
+    pub fn as_slice(&self) -> &[(u16, u32)] {
+        &self.entries[..self.len]
+    }
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<'_, (u16, u32)> {
+        self.as_slice().iter()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    // false when full, the setting is not kept then and the list is marked truncated
+    pub fn push(&mut self, id: u16, val: u32) -> bool {
+        if self.len == MAX_UNKNOWN_SETTINGS {
+            self.truncated = true;
+            return false
+        }
+        self.entries[self.len] = (id, val);
+        self.len += 1;
+        true
+    }
+}
+impl Default for Http2UnknownSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl PartialEq for Http2UnknownSettings {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice() && self.truncated == other.truncated
+    }
+}
+impl<'a> IntoIterator for &'a Http2UnknownSettings {
+    type Item = &'a (u16, u32);
+    type IntoIter = std::slice::Iter<'a, (u16, u32)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Http2Settings {
     pub header_table_size: Option<u32>,       // 1
@@ -283,6 +362,9 @@ pub struct Http2Settings {
     pub initial_window_size: Option<u32>,     // 4
     pub max_frame_size: Option<u32>,          // 5
     pub max_header_list_size: Option<u32>,    // 6
+    pub enable_connect_protocol: Option<u32>, // 8, rfc8441
+    // every other id, in the order received
+    pub unknown: Http2UnknownSettings,
 }
 impl Http2Settings {
     pub const fn empty() -> Self {
@@ -293,6 +375,8 @@ impl Http2Settings {
             initial_window_size: None,
             max_frame_size: None,
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            unknown: Http2UnknownSettings::new(),
         }
     }
     pub const fn default() -> Self {
@@ -303,6 +387,8 @@ impl Http2Settings {
             initial_window_size: Some(65535),
             max_frame_size: Some(65535),
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            unknown: Http2UnknownSettings::new(),
         }
     }
     pub const fn default_no_push() -> Self {
@@ -313,6 +399,8 @@ impl Http2Settings {
             initial_window_size: Some(65535),
             max_frame_size: Some(65535),
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            unknown: Http2UnknownSettings::new(),
         }
     }
     pub const fn maximum() -> Self {
@@ -324,6 +412,8 @@ impl Http2Settings {
             initial_window_size: Some(2147483647),
             max_frame_size: Some(16777215),
             max_header_list_size: None,
+            enable_connect_protocol: None,
+            unknown: Http2UnknownSettings::new(),
         }
     }
 
@@ -353,12 +443,39 @@ impl Http2Settings {
                 else if id == 4 { sett.initial_window_size = Some(val) }
                 else if id == 5 { sett.max_frame_size = Some(val) }
                 else if id == 6 { sett.max_header_list_size = Some(val) }
+                else if id == 8 { sett.enable_connect_protocol = Some(val) }
+                else { sett.set_unknown(id, val) }
             }
         }
 
         sett
     }
 
+    // the values `other` carries replace ours, like a SETTINGS frame applied on top of the last one
+    pub fn merge(&mut self, other: &Http2Settings) {
+        if let Some(val) = other.header_table_size { self.header_table_size = Some(val) }
+        if let Some(val) = other.enable_push { self.enable_push = Some(val) }
+        if let Some(val) = other.max_concurrent_streams { self.max_concurrent_streams = Some(val) }
+        if let Some(val) = other.initial_window_size { self.initial_window_size = Some(val) }
+        if let Some(val) = other.max_frame_size { self.max_frame_size = Some(val) }
+        if let Some(val) = other.max_header_list_size { self.max_header_list_size = Some(val) }
+        if let Some(val) = other.enable_connect_protocol { self.enable_connect_protocol = Some(val) }
+        for (id, val) in &other.unknown { self.set_unknown(*id, *val) }
+        if other.unknown.truncated { self.unknown.truncated = true }
+    }
+
+    pub fn get_unknown(&self, id: u16) -> Option<u32> {
+        self.unknown.iter().find(|(i, _)| *i == id).map(|(_, v)| *v)
+    }
+    // a later value for the same id replaces the earlier one
+    pub fn set_unknown(&mut self, id: u16, val: u32) {
+        let len = self.unknown.len;
+        match self.unknown.entries[..len].iter_mut().find(|(i, _)| *i == id) {
+            Some(setting) => setting.1 = val,
+            None => { self.unknown.push(id, val); },
+        }
+    }
+
     pub fn to_vec(&self) -> Vec<u8> {
         let mut res = vec![];
 
@@ -368,6 +485,8 @@ impl Http2Settings {
         if let Some(val) = self.initial_window_size { res.extend_from_slice(&[0, 4]); res.extend_from_slice(&u32::to_be_bytes(val)); }
         if let Some(val) = self.max_frame_size { res.extend_from_slice(&[0, 5]); res.extend_from_slice(&u32::to_be_bytes(val)); }
         if let Some(val) = self.max_header_list_size { res.extend_from_slice(&[0, 6]); res.extend_from_slice(&u32::to_be_bytes(val)); }
+        if let Some(val) = self.enable_connect_protocol { res.extend_from_slice(&[0, 8]); res.extend_from_slice(&u32::to_be_bytes(val)); }
+        for (id, val) in &self.unknown { res.extend_from_slice(&id.to_be_bytes()); res.extend_from_slice(&u32::to_be_bytes(*val)); }
     
         res
     }
@@ -378,3 +497,333 @@ impl Default for Http2Settings {
         Self::default()
     }
 }
+
+// local limits against abusive peers, rates are counted per second
+#[derive(Debug, Clone, Copy)]
+pub struct Http2Limits {
+    pub max_header_block_size: u32, // lowered to our advertised max_header_list_size
+    pub max_header_list_size: u32, // decoded, counted like SETTINGS_MAX_HEADER_LIST_SIZE
+    pub max_resets_per_sec: u32,
+    pub max_control_per_sec: u32,
+    pub max_pending_pings: u32, // PING ACKs queued but not written yet
+}
+impl Http2Limits {
+    pub const fn default() -> Self {
+        Self {
+            max_header_block_size: 65536,
+            max_header_list_size: 262144,
+            max_resets_per_sec: 100,
+            max_control_per_sec: 1000,
+            max_pending_pings: 16,
+        }
+    }
+    pub const fn unlimited() -> Self {
+        Self {
+            max_header_block_size: u32::MAX,
+            max_header_list_size: u32::MAX,
+            max_resets_per_sec: u32::MAX,
+            max_control_per_sec: u32::MAX,
+            max_pending_pings: u32::MAX,
+        }
+    }
+}
+impl Default for Http2Limits {
+    #[inline]
+    fn default() -> Self {
+        Self::default()
+    }
+}
+
+// a PING goes out after `interval` without reading anything, no ACK within `timeout` tears the connection down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Http2Keepalive {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+impl Http2Keepalive {
+    pub const fn new(interval: Duration, timeout: Duration) -> Self {
+        Self { interval, timeout }
+    }
+    pub const fn default() -> Self {
+        Self::new(Duration::from_secs(30), Duration::from_secs(20))
+    }
+}
+impl Default for Http2Keepalive {
+    #[inline]
+    fn default() -> Self {
+        Self::default()
+    }
+}
+
+
+// rfc9113 6.1, padding hides how large DATA and HEADERS payloads really are
+// the pad length takes one more byte, and on DATA frames all of it counts against flow control
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Http2Padding {
+    None,
+    // frame payloads are rounded up to a multiple of the block size, at most 256
+    Block(usize),
+    // a random amount between 0 and this per frame
+    Random(u8),
+}
+impl Http2Padding {
+    pub const fn default() -> Self {
+        Self::None
+    }
+    // padding bytes for a payload of len, without the pad length byte
+    pub fn padding_for(&self, len: usize) -> usize {
+        match *self {
+            Self::None => 0,
+            Self::Block(size) => {
+                let size = size.clamp(1, 256);
+                (size - (len + 1) % size) % size
+            },
+            Self::Random(max) => rand::random_range(0..=max as usize),
+        }
+    }
+}
+impl Default for Http2Padding {
+    #[inline]
+    fn default() -> Self {
+        Self::default()
+    }
+}
+
+
+// rfc9113 7
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Http2ErrorCode {
+    NoError,
+    ProtocolError,
+    InternalError,
+    FlowControlError,
+    SettingsTimeout,
+    StreamClosed,
+    FrameSizeError,
+    RefusedStream,
+    Cancel,
+    CompressionError,
+    ConnectError,
+    EnhanceYourCalm,
+    InadequateSecurity,
+    Http11Required,
+
+    Unknown(u32),
+}
+impl From<u32> for Http2ErrorCode {
+    fn from(value: u32) -> Self {
+        match value {
+            0x0 => Self::NoError,
+            0x1 => Self::ProtocolError,
+            0x2 => Self::InternalError,
+            0x3 => Self::FlowControlError,
+            0x4 => Self::SettingsTimeout,
+            0x5 => Self::StreamClosed,
+            0x6 => Self::FrameSizeError,
+            0x7 => Self::RefusedStream,
+            0x8 => Self::Cancel,
+            0x9 => Self::CompressionError,
+            0xa => Self::ConnectError,
+            0xb => Self::EnhanceYourCalm,
+            0xc => Self::InadequateSecurity,
+            0xd => Self::Http11Required,
+
+            v => Self::Unknown(v),
+        }
+    }
+}
+impl Into<u32> for Http2ErrorCode {
+    fn into(self) -> u32 {
+        match self {
+            Self::NoError => 0x0,
+            Self::ProtocolError => 0x1,
+            Self::InternalError => 0x2,
+            Self::FlowControlError => 0x3,
+            Self::SettingsTimeout => 0x4,
+            Self::StreamClosed => 0x5,
+            Self::FrameSizeError => 0x6,
+            Self::RefusedStream => 0x7,
+            Self::Cancel => 0x8,
+            Self::CompressionError => 0x9,
+            Self::ConnectError => 0xa,
+            Self::EnhanceYourCalm => 0xb,
+            Self::InadequateSecurity => 0xc,
+            Self::Http11Required => 0xd,
+
+            Self::Unknown(v) => v,
+        }
+    }
+}
+impl std::fmt::Display for Http2ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoError => write!(f, "NO_ERROR"),
+            Self::ProtocolError => write!(f, "PROTOCOL_ERROR"),
+            Self::InternalError => write!(f, "INTERNAL_ERROR"),
+            Self::FlowControlError => write!(f, "FLOW_CONTROL_ERROR"),
+            Self::SettingsTimeout => write!(f, "SETTINGS_TIMEOUT"),
+            Self::StreamClosed => write!(f, "STREAM_CLOSED"),
+            Self::FrameSizeError => write!(f, "FRAME_SIZE_ERROR"),
+            Self::RefusedStream => write!(f, "REFUSED_STREAM"),
+            Self::Cancel => write!(f, "CANCEL"),
+            Self::CompressionError => write!(f, "COMPRESSION_ERROR"),
+            Self::ConnectError => write!(f, "CONNECT_ERROR"),
+            Self::EnhanceYourCalm => write!(f, "ENHANCE_YOUR_CALM"),
+            Self::InadequateSecurity => write!(f, "INADEQUATE_SECURITY"),
+            Self::Http11Required => write!(f, "HTTP_1_1_REQUIRED"),
+
+            Self::Unknown(v) => write!(f, "UNKNOWN(0x{v:x})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http2Goaway {
+    pub last_stream_id: u32,
+    pub code: Http2ErrorCode,
+    pub debug: Vec<u8>,
+}
+impl Http2Goaway {
+    pub fn from(payload: &[u8]) -> Option<Self> {
+        if payload.len() < 8 { return None }
+
+        Some(Self {
+            last_stream_id: u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) & 0x7fffffff,
+            code: u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]).into(),
+            debug: payload[8..].to_vec(),
+        })
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut pay = Vec::with_capacity(8 + self.debug.len());
+        
+        pay.extend_from_slice(&u32::to_be_bytes(self.last_stream_id));
+        pay.extend_from_slice(&u32::to_be_bytes(self.code.into()));
+        pay.extend_from_slice(&self.debug);
+
+        pay
+    }
+}
+
+
+// handlers for frame types and settings the session does not know itself
+// a frame is offered to every extension until one returns true, unhandled frames are dropped unless the session is strict
+pub trait Http2Extension: Debug + Send + Sync {
+    fn handle_frame(&self, _frame: &Http2Frame<'_>) -> LibResult<bool> { Ok(false) }
+    fn handle_setting(&self, _id: u16, _value: u32) {}
+}
+
+// https://datatracker.ietf.org/doc/html/rfc7838#section-4
+// on stream 0 the origin is required, on any other stream it has to be empty and the stream's origin applies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http2AltSvc {
+    pub origin: Vec<u8>,
+    pub value: Vec<u8>,
+}
+impl Http2AltSvc {
+    pub fn from(payload: &[u8]) -> Option<Self> {
+        if payload.len() < 2 { return None }
+        let len = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+        if payload.len() < 2 + len { return None }
+
+        Some(Self {
+            origin: payload[2..2 + len].to_vec(),
+            value: payload[2 + len..].to_vec(),
+        })
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut pay = Vec::with_capacity(2 + self.origin.len() + self.value.len());
+
+        pay.extend_from_slice(&(self.origin.len() as u16).to_be_bytes());
+        pay.extend_from_slice(&self.origin);
+        pay.extend_from_slice(&self.value);
+
+        pay
+    }
+}
+
+// https://datatracker.ietf.org/doc/html/rfc8336#section-2, a list of length prefixed ascii origins
+pub fn origins_from(payload: &[u8]) -> Option<Vec<String>> {
+    let mut origins = vec![];
+    let mut pos = 0;
+
+    while pos < payload.len() {
+        let len = u16::from_be_bytes([*payload.get(pos)?, *payload.get(pos + 1)?]) as usize;
+        let origin = payload.get(pos + 2..pos + 2 + len)?;
+        origins.push(String::from_utf8(origin.to_vec()).ok()?);
+        pos += 2 + len;
+    }
+
+    Some(origins)
+}
+pub fn origins_to_vec(origins: &[&str]) -> Vec<u8> {
+    let mut pay = vec![];
+    for origin in origins {
+        pay.extend_from_slice(&(origin.len() as u16).to_be_bytes());
+        pay.extend_from_slice(origin.as_bytes());
+    }
+    pay
+}
+
+// https://datatracker.ietf.org/doc/html/rfc9218
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Http2Priority {
+    pub urgency: u8, // 0 (highest) - 7
+    pub incremental: bool,
+}
+impl Http2Priority {
+    pub const fn default() -> Self {
+        Self { urgency: 3, incremental: false }
+    }
+    pub const fn new(urgency: u8, incremental: bool) -> Self {
+        Self { urgency: if urgency > 7 { 7 } else { urgency }, incremental }
+    }
+
+    // structured field dictionary, unknown keys and invalid values are ignored (section 4)
+    pub fn from(value: &[u8]) -> Self {
+        let mut prio = Self::default();
+
+        for member in value.split(|&b| b == b',') {
+            let member = member.trim_ascii();
+            let (key, val) = match member.iter().position(|&b| b == b'=') {
+                Some(i) => (&member[..i], Some(member[i + 1..].trim_ascii())),
+                None => (member, None),
+            };
+            // parameters after ; carry no meaning here
+            let val = val.map(|v| v.split(|&b| b == b';').next().unwrap_or(v));
+
+            match (key, val) {
+                (b"u", Some([d])) if d.is_ascii_digit() && *d <= b'7' => prio.urgency = d - b'0',
+                (b"i", None | Some(b"?1")) => prio.incremental = true,
+                (b"i", Some(b"?0")) => prio.incremental = false,
+                _ => (),
+            }
+        }
+
+        prio
+    }
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut val = format!("u={}", self.urgency).into_bytes();
+        if self.incremental { val.extend_from_slice(b", i") }
+        val
+    }
+
+    // PRIORITY_UPDATE payload, prioritized stream id followed by the field value
+    pub fn from_update(payload: &[u8]) -> Option<(u32, Self)> {
+        if payload.len() < 4 { return None }
+        let stream_id = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) & 0x7fffffff;
+        Some((stream_id, Self::from(&payload[4..])))
+    }
+    pub fn to_update(&self, stream_id: u32) -> Vec<u8> {
+        let mut pay = u32::to_be_bytes(stream_id).to_vec();
+        pay.append(&mut self.to_vec());
+        pay
+    }
+}
+impl Default for Http2Priority {
+    #[inline]
+    fn default() -> Self {
+        Self::default()
+    }
+}
@@ -1,11 +1,18 @@
-use crate::http2::hpack::{DynamicTable, HeaderType, STATIC_TABLE, StaticTable, huffman::Huffman};
+use crate::http2::hpack::{DynamicTable, HeaderType, HpackError, STATIC_TABLE, StaticTable, huffman::Huffman};
 
+// (representation, name, value), size updates come with an empty name and value
+pub type DecodedField = (HeaderType, Vec<u8>, Vec<u8>);
+pub type DecodedHeaders = Vec<(Vec<u8>, Vec<u8>)>;
 
 #[derive(Debug)]
 pub struct Decoder<'a> {
     pub static_table: StaticTable<'a>,
     pub dynamic_table: DynamicTable,
-    pub huffman: Huffman,
+    pub huffman: &'static Huffman,
+    // the SETTINGS_HEADER_TABLE_SIZE we advertised, size updates above it are rejected
+    pub max_table_size: usize,
+    // name + value + 32 for every field of a block, rfc9113 6.5.2
+    pub max_header_list_size: usize,
 }
 impl<'a> Decoder<'a> {
     pub fn new(table_size: usize) -> Self {
@@ -15,7 +22,9 @@ impl<'a> Decoder<'a> {
         Self { 
             static_table,
             dynamic_table: DynamicTable::new(table_size),
-            huffman: Huffman::new(),
+            huffman: Huffman::shared(),
+            max_table_size: table_size,
+            max_header_list_size: usize::MAX,
         }
     }
 
@@ -34,122 +43,120 @@ impl<'a> Decoder<'a> {
         }
     }
 
-    pub fn read_int(buf: &[u8], prefix: u8, pos: &mut usize) -> Option<usize> {
-        // if prefix == 0 || prefix > 8 { return None; }
-
+    pub fn read_int(buf: &[u8], prefix: u8, pos: &mut usize) -> Result<usize, HpackError> {
         let mask = ((1u16 << prefix as u16) - 1) as u8;
-        let mut value = (buf.get(*pos)? & mask) as usize;
+        let mut value = (buf.get(*pos).ok_or(HpackError::Truncated)? & mask) as usize;
         *pos += 1;
 
-        if value < mask as usize { return Some(value) }
+        if value < mask as usize { return Ok(value) }
 
         let mut m = 0;
 
         for &b in buf[*pos..].iter() {
             *pos += 1;
-            value += ((b & 0x7f) as usize) * (1 << m);
+            // rfc7541 5.1, bits shifted out or a sum past usize are an overflow, not a wrap
+            let part = ((b & 0x7f) as usize).checked_shl(m).filter(|p| p >> m == (b & 0x7f) as usize).ok_or(HpackError::IntegerOverflow)?;
+            value = value.checked_add(part).ok_or(HpackError::IntegerOverflow)?;
             m += 7;
 
             if b & 0x80 == 0 {
-                return Some(value);
+                return Ok(value);
             }
         }
 
-        None
+        Err(HpackError::Truncated)
     }
-    pub fn read_string(&self, buf: &[u8], pos: &mut usize) -> Option<Vec<u8>> {
-        let huff = (*buf.get(*pos)? & 0x80) != 0;
+    pub fn read_string(&self, buf: &[u8], pos: &mut usize) -> Result<Vec<u8>, HpackError> {
+        let huff = (*buf.get(*pos).ok_or(HpackError::Truncated)? & 0x80) != 0;
         let length = Self::read_int(buf, 7, pos)?;
 
-        if *pos + length > buf.len() {
-            None
+        if length > buf.len() - *pos {
+            Err(HpackError::Truncated)
         }
         else {
             let buff = &buf[*pos..*pos + length];
             *pos += length;
             
-            if huff { self.huffman.decode(buff).ok() }
-            else { Some(buff.to_vec()) }
+            if huff { Ok(self.huffman.decode(buff)?) }
+            else { Ok(buff.to_vec()) }
         }
     }
+    fn read_name(&self, buf: &[u8], index: usize, pos: &mut usize) -> Result<Vec<u8>, HpackError> {
+        if index == 0 { self.read_string(buf, pos) }
+        else { Ok(self.get(index).ok_or(HpackError::InvalidIndex(index))?.0.to_vec()) }
+    }
 
-    pub fn decode(&mut self, buf: &[u8], pos: &mut usize) -> Option<(HeaderType, Vec<u8>, Vec<u8>)> {
-        let first = *buf.get(*pos)?;
-        let mut output = None;
+    pub fn decode(&mut self, buf: &[u8], pos: &mut usize) -> Result<DecodedField, HpackError> {
+        let first = *buf.get(*pos).ok_or(HpackError::Truncated)?;
 
         if first & 0x80 != 0 {
-            // 6.1 Indexed Header Field
+            // 6.1 Indexed Header Field, index 0 is not used
             let index = Self::read_int(buf, 7, pos)?;
+            let (name, valu) = self.get(index).ok_or(HpackError::InvalidIndex(index))?;
 
-            let fromt = self.get(index)?;
-            let name = fromt.0.to_vec();
-            let valu = fromt.1.to_vec();
-
-            output = Some((HeaderType::Lookup, name, valu));
+            Ok((HeaderType::Lookup, name.to_vec(), valu.to_vec()))
         }
         // 6.2 Literal Header Field Representation
         else if first & 0xc0 == 0x40 {
             // 6.2.1 Literal Header Field with Incremental Indexing
             let index = Self::read_int(buf, 6, pos)?;
-
-            let name =
-            if index == 0 { self.read_string(buf, pos)? }
-            else { self.get(index)?.0.to_vec() };
-
+            let name = self.read_name(buf, index, pos)?;
             let valu = self.read_string(buf, pos)?;
 
             self.dynamic_table.add((name.clone(), valu.clone()));
-            output = Some((HeaderType::Indexed, name, valu));
+            Ok((HeaderType::Indexed, name, valu))
         }
         else if first & 0xf0 == 0x00 {
             // 6.2.2 Literal Header Field without Indexing
             let index = Self::read_int(buf, 4, pos)?;
-
-            let name =
-            if index == 0 { self.read_string(buf, pos)? }
-            else { self.get(index)?.0.to_vec() };
-
+            let name = self.read_name(buf, index, pos)?;
             let valu = self.read_string(buf, pos)?;
 
-            output = Some((HeaderType::NotIndexed, name, valu));
+            Ok((HeaderType::NotIndexed, name, valu))
         }
         else if first & 0xf0 == 0x10 {
             // 6.2.3 Literal Header Field Never Indexed
             let index = Self::read_int(buf, 4, pos)?;
-
-            let name =
-            if index == 0 { self.read_string(buf, pos)? }
-            else { self.get(index)?.0.to_vec() };
-
+            let name = self.read_name(buf, index, pos)?;
             let valu = self.read_string(buf, pos)?;
 
-            output = Some((HeaderType::NeverIndexed, name, valu));
+            Ok((HeaderType::NeverIndexed, name, valu))
         }
-        else if first & 0xe0 == 0x20 {
-            // 6.3 Dynamic Table Size Update
+        else {
+            // 6.3 Dynamic Table Size Update, the only pattern left is 001xxxxx
             let new_size = Self::read_int(buf, 5, pos)?;
+            if new_size > self.max_table_size { return Err(HpackError::TableSizeExceeded(new_size)) }
+
             self.dynamic_table.resize(new_size);
-            output = Some((HeaderType::TableSizeChange, vec![], vec![]));
-        }
-        else {
-            // None?
+            Ok((HeaderType::TableSizeChange, vec![], vec![]))
         }
-        
-        
-        output
     }
-    pub fn decode_all(&mut self, buf: &[u8]) -> Option<Vec<(Vec<u8>, Vec<u8>)>> {
+    pub fn decode_all(&mut self, buf: &[u8]) -> Result<DecodedHeaders, HpackError> {
         let mut dec = Vec::new();
         let mut pos = 0;
+        let mut list_size: usize = 0;
 
         while pos < buf.len() {
             let (t, h, v) = self.decode(buf, &mut pos)?;
             
             if t != HeaderType::TableSizeChange {
-                dec.push((h, v))
+                list_size = list_size.saturating_add(h.len() + v.len() + 32);
+                // the rest is still decoded, the dynamic table has to see every insert
+                if list_size <= self.max_header_list_size { dec.push((h, v)) }
+                else { dec.clear() }
+            }
+            // 4.2, size updates have to come before the first field of a block
+            else if list_size > 0 {
+                return Err(HpackError::MisplacedTableSizeUpdate)
             }
         }
         
-        Some(dec)
+        if list_size > self.max_header_list_size { Err(HpackError::HeaderListTooLarge(list_size)) }
+        else { Ok(dec) }
+    }
+    // after SETTINGS_HEADER_TABLE_SIZE got acknowledged, the table is cut down right away if it is lowered
+    pub fn set_max_table_size(&mut self, size: usize) {
+        self.max_table_size = size;
+        if self.dynamic_table.table_size > size { self.dynamic_table.resize(size) }
     }
 }
@@ -1,12 +1,60 @@
-use std::io;
+use std::{fmt::Debug, io};
 
 use crate::http2::hpack::{DynamicTable, HeaderType, STATIC_TABLE, StaticTable, huffman::Huffman};
 
+pub const SENSITIVE_HEADERS: &[&[u8]] = &[b"authorization", b"proxy-authorization", b"cookie", b"set-cookie"];
+
+// decides how a field gets written, exact matches in the tables are always sent as an index
+pub trait IndexingPolicy: Debug + Send + Sync {
+    fn header_type(&self, name: &[u8], value: &[u8]) -> HeaderType;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DefaultIndexing {
+    pub max_value_size: usize,
+}
+impl DefaultIndexing {
+    pub const fn new() -> Self {
+        Self { max_value_size: 256 }
+    }
+}
+impl Default for DefaultIndexing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl IndexingPolicy for DefaultIndexing {
+    fn header_type(&self, name: &[u8], value: &[u8]) -> HeaderType {
+        if SENSITIVE_HEADERS.iter().any(|s| s.eq_ignore_ascii_case(name)) {
+            HeaderType::NeverIndexed
+        }
+        // large or one-off values would only push useful entries out of the table
+        else if value.len() > self.max_value_size || name == b":path" || name == b"content-length" {
+            HeaderType::NotIndexed
+        }
+        else {
+            HeaderType::Indexed
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoIndexing;
+impl IndexingPolicy for NoIndexing {
+    fn header_type(&self, name: &[u8], _value: &[u8]) -> HeaderType {
+        if SENSITIVE_HEADERS.iter().any(|s| s.eq_ignore_ascii_case(name)) { HeaderType::NeverIndexed }
+        else { HeaderType::NotIndexed }
+    }
+}
+
 #[derive(Debug)]
 pub struct Encoder<'a> {
     pub static_table: StaticTable<'a>,
     pub dynamic_table: DynamicTable,
-    pub huffman: Huffman,
+    pub huffman: &'static Huffman,
+    pub policy: Box<dyn IndexingPolicy>,
+    // (smallest, latest) size since the last header block, both have to be signaled if they differ
+    pub pending_size: Option<(usize, usize)>,
 }
 impl<'a> Encoder<'a> {
     pub fn new(table_size: usize) -> Self {
@@ -16,8 +64,32 @@ impl<'a> Encoder<'a> {
         Self { 
             static_table,
             dynamic_table: DynamicTable::new(table_size),
-            huffman: Huffman::new(),
+            huffman: Huffman::shared(),
+            policy: Box::new(DefaultIndexing::new()),
+            pending_size: None,
+        }
+    }
+    pub fn with_policy(table_size: usize, policy: impl IndexingPolicy + 'static) -> Self {
+        let mut enc = Self::new(table_size);
+        enc.policy = Box::new(policy);
+        enc
+    }
+
+    pub fn set_table_size(&mut self, new_size: usize) {
+        self.dynamic_table.resize(new_size);
+        self.pending_size = match self.pending_size {
+            Some((min, _)) if min < new_size => Some((min, new_size)),
+            _ => Some((new_size, new_size)),
+        };
+    }
+    pub fn write_pending_size<W: io::Write>(&mut self, writ: &mut W) -> io::Result<()> {
+        if let Some((min, last)) = self.pending_size.take() {
+            if min != last {
+                self.write_table_size(writ, min)?;
+            }
+            self.write_table_size(writ, last)?;
         }
+        Ok(())
     }
 
     pub fn find(&self, name: &[u8]) -> Option<usize> {
@@ -74,21 +146,15 @@ impl<'a> Encoder<'a> {
             Self::write_int(writ, huff.len(), 7, 0x80)?;
             writ.write_all(&huff)
         }
-        else if use_huff == Some(false) {
+        // without a preference huffman is only used when it is shorter
+        else if use_huff == Some(false) || self.huffman.encoded_len(value) > value.len() {
             Self::write_int(writ, value.len(), 7, 0x00)?;
             writ.write_all(&value)
         }
         else {
             let huff = self.huffman.encode(value);
-
-            if huff.len() > value.len() {
-                Self::write_int(writ, value.len(), 7, 0x00)?;
-                writ.write_all(&value)
-            }
-            else {
-                Self::write_int(writ, huff.len(), 7, 0x80)?;
-                writ.write_all(&huff)
-            }
+            Self::write_int(writ, huff.len(), 7, 0x80)?;
+            writ.write_all(&huff)
         }
     }
 
@@ -157,6 +223,17 @@ impl<'a> Encoder<'a> {
             }
         }
     }
+    // encodes a whole header block using the indexing policy
+    pub fn encode_block<W: io::Write>(&mut self, writ: &mut W, headers: &[(&[u8], &[u8])]) -> io::Result<()> {
+        self.write_pending_size(writ)?;
+
+        for &(nam, val) in headers {
+            let htype = self.policy.header_type(nam, val);
+            self.encode(writ, htype, nam, val, None)?;
+        }
+
+        Ok(())
+    }
     pub fn encode_all<'b, I: Iterator<Item = (&'b [u8], &'b [u8])>>(&mut self, headers: I) -> io::Result<Vec<u8>> {
         let mut buff = Vec::new();
 
@@ -1,6 +1,4 @@
-use std::{collections::HashMap, fmt::Display};
-
-use crate::http2::hpack::Biterator;
+use std::{fmt::Display, sync::LazyLock};
 
 // Appendix B
 pub const HUFFMAN_TABLE: &'static [(u32, u8); 257] = &[
@@ -311,130 +309,194 @@ impl Display for HuffmanError {
     }
 }
 
-#[derive(Debug, Clone)]
+// rfc7541 appendix B, the decoder walks the code tree 4 bits at a time through precomputed steps
+// every state is an inner node of the tree, with the shortest code at 5 bits one step completes at most one symbol
+pub const STEP_SYMBOL: u8 = 0x01;
+pub const STEP_FAIL: u8 = 0x02;
+
+static SHARED: LazyLock<Huffman> = LazyLock::new(Huffman::new);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HuffmanStep {
+    pub next: u16,
+    pub flags: u8,
+    pub symbol: u8,
+}
+
+#[derive(Clone, Copy)]
+enum Node {
+    Inner(usize),
+    Leaf(usize),
+}
+
+#[derive(Clone)]
 pub struct Huffman{
     pub code_from_symbol: [u32; 257],
     pub len_from_symbol: [u8; 257],
-    pub table: HashMap<u8, HashMap<u32, Symbol>>,
+    // 16 steps per state, indexed by state * 16 + nibble
+    pub steps: Vec<HuffmanStep>,
+    // why a string can not end in a state, None if the bits so far are valid padding
+    pub ends: Vec<Option<HuffmanError>>,
     pub eos: (u32, u8),
 }
 impl Huffman {
     pub fn new() -> Self {
         Self::from(HUFFMAN_TABLE).unwrap()
     }
+    // the tables are built once, for hpack and qpack alike
+    #[inline]
+    pub fn shared() -> &'static Self {
+        &SHARED
+    }
+    // codes have to form a complete prefix code of 4 to 32 bits, EOS needs at least 8 to pad with
     pub fn from(table: &[(u32, u8)]) -> Result<Self, HuffmanError> {
         if table.len() != 257 { return Err(HuffmanError::InvalidCodeTable); }
 
         let mut code_from_symbol = [0; 257];
         let mut len_from_symbol = [0; 257];
-        let mut htable: HashMap<u8, HashMap<u32, Symbol>> = HashMap::new();
-        let mut eos: Option<(u32, u8)> = None;
+        let eos = table[256];
+
+        if eos.1 < 8 { return Err(HuffmanError::InvalidCodeTable) }
+
+        // children of every inner node and (depth, whether the path is a prefix of EOS)
+        let mut tree: Vec<[Option<Node>; 2]> = vec![[None, None]];
+        let mut info: Vec<(u8, bool)> = vec![(0, true)];
 
         for (sym, &(code, len)) in table.iter().enumerate() {
+            if !(4..=32).contains(&len) || (len < 32 && code >> len != 0) { return Err(HuffmanError::InvalidCodeTable) }
             code_from_symbol[sym] = code;
             len_from_symbol[sym] = len;
-            let symb = sym.into();
 
-            if let Some(sub) = htable.get_mut(&len) {
-                sub.insert(code, symb);
-            }
-            else {
-                let mut sub = HashMap::new();
-                sub.insert(code, symb);
-                let _ = htable.insert(len, sub);
+            let mut node = 0;
+            for depth in 1..=len {
+                let bit = ((code >> (len - depth)) & 1) as usize;
+
+                match tree[node][bit] {
+                    Some(Node::Inner(next)) if depth < len => node = next,
+                    None if depth < len => {
+                        let eos_bit = depth <= eos.1 && (eos.0 >> (eos.1 - depth)) & 1 == bit as u32;
+                        tree.push([None, None]);
+                        info.push((depth, info[node].1 && eos_bit));
+                        tree[node][bit] = Some(Node::Inner(tree.len() - 1));
+                        node = tree.len() - 1;
+                    },
+                    None => tree[node][bit] = Some(Node::Leaf(sym)),
+                    // not a prefix code
+                    _ => return Err(HuffmanError::InvalidCodeTable),
+                }
             }
+        }
+
+        let mut steps = Vec::with_capacity(tree.len() * 16);
+        for state in 0..tree.len() {
+            for nibble in 0..16u8 {
+                let mut step = HuffmanStep::default();
+                let mut node = state;
+
+                for shift in (0..4).rev() {
+                    match tree[node][((nibble >> shift) & 1) as usize] {
+                        Some(Node::Inner(next)) => node = next,
+                        Some(Node::Leaf(256)) => { step.flags |= STEP_FAIL; break },
+                        Some(Node::Leaf(sym)) => {
+                            step.flags |= STEP_SYMBOL;
+                            step.symbol = sym as u8;
+                            node = 0;
+                        },
+                        // codes missing from the tree
+                        None => return Err(HuffmanError::InvalidCodeTable),
+                    }
+                }
 
-            if symb == Symbol::EndOfString {
-                eos = Some((code, len))
+                step.next = node as u16;
+                steps.push(step);
             }
         }
 
+        let ends = info.iter().map(|&(depth, eos_prefix)| {
+            if depth > 7 { Some(HuffmanError::PaddingTooLarge) }
+            else if !eos_prefix { Some(HuffmanError::InvalidPadding) }
+            else { None }
+        }).collect();
+
         Ok(Self {
             code_from_symbol,
             len_from_symbol,
-            table: htable,
-            eos: eos.ok_or(HuffmanError::InvalidCodeTable)?,
+            steps,
+            ends,
+            eos,
         })
     }
 
     pub fn decode(&self, buf: &[u8]) -> Result<Vec<u8>, HuffmanError> {
-        let mut cur = 0;
-        let mut len = 0;
-        let mut res = Vec::new();
+        let mut res = Vec::with_capacity(buf.len() * 8 / 5);
+        self.decode_into(buf, &mut res)?;
+        Ok(res)
+    }
+    pub fn decode_into(&self, buf: &[u8], res: &mut Vec<u8>) -> Result<(), HuffmanError> {
+        let mut state = 0;
 
-        for bit in Biterator::new(buf.iter()) {
-            len += 1;
-            cur <<= 1;
-            if bit { cur |= 1; }
+        for &byte in buf {
+            for nibble in [byte >> 4, byte & 0x0f] {
+                let step = self.steps[state * 16 + nibble as usize];
 
-            if 
-                let Some(ltable) = self.table.get(&len) && 
-                let Some(sym) = ltable.get(&cur)
-            {
-                res.push(match sym {
-                    Symbol::Symbol(sym) => *sym,
-                    Symbol::EndOfString => return Err(HuffmanError::EOSInString),
-                });
-                cur = 0;
-                len = 0;
+                if step.flags & STEP_FAIL != 0 { return Err(HuffmanError::EOSInString) }
+                if step.flags & STEP_SYMBOL != 0 { res.push(step.symbol) }
+                state = step.next as usize;
             }
         }
 
-        if len > 7 { return Err(HuffmanError::PaddingTooLarge); }
-
-
-        let right_align_current =
-        if len == 0 { 0 }
-        else { cur << (32 - len as u32) };
-
-        let right_align_eos = self.eos.0 << (32 - self.eos.1 as u32);
-
-        let mask =
-        if len == 0 { 0 }
-        else { ((1 << len as u32) - 1) << (32 - len as u32) };
-
-        let eos_mask = right_align_eos & mask;
-
-
-        if eos_mask != right_align_current {
-            Err(HuffmanError::InvalidPadding)
-        }
-        else {
-            Ok(res)
+        match self.ends[state] {
+            Some(err) => Err(err),
+            None => Ok(()),
         }
     }
+
+    pub fn encoded_len(&self, buf: &[u8]) -> usize {
+        buf.iter().map(|&sym| self.len_from_symbol[sym as usize] as usize).sum::<usize>().div_ceil(8)
+    }
     pub fn encode(&self, buf: &[u8]) -> Vec<u8> {
-        let mut res = Vec::new();
-        let mut acc = 0;
-        let mut alen: u8 = 0;
+        let mut res = Vec::with_capacity(self.encoded_len(buf));
+        self.encode_into(buf, &mut res);
+        res
+    }
+    pub fn encode_into(&self, buf: &[u8], res: &mut Vec<u8>) {
+        // less than 32 bits are left after every flush, so a code of up to 32 always fits
+        let mut acc: u64 = 0;
+        let mut bits: u8 = 0;
 
         for &sym in buf {
-            let code = self.code_from_symbol[sym as usize];
             let len = self.len_from_symbol[sym as usize];
 
-            acc <<= len;
-            acc |= code;
-            alen += len;
-            
-            while alen >= 8 {
-                let byte = acc >> (alen - 8);
-                let byte = byte as u8;
-                res.push(byte);
-                alen -= 8;
-    
-                if alen > 0 { acc &= (1 << alen) - 1; }
-                else { acc = 0; }
+            acc = (acc << len) | self.code_from_symbol[sym as usize] as u64;
+            bits += len;
+
+            if bits >= 32 {
+                bits -= 32;
+                res.extend_from_slice(&((acc >> bits) as u32).to_be_bytes());
             }
         }
 
-        if alen > 0 {
-            let bits = 8 - alen;
-            let pad = (acc << bits) | ((1 << bits) - 1);
-            let pad = pad as u8;
-            res.push(pad);
+        while bits >= 8 {
+            bits -= 8;
+            res.push((acc >> bits) as u8);
         }
 
-        res
+        // the last byte is padded with the most significant bits of EOS
+        if bits > 0 {
+            let pad = 8 - bits;
+            let eos = (self.eos.0 >> (self.eos.1 - pad)) as u64 & ((1 << pad) - 1);
+            res.push(((acc << pad) | eos) as u8);
+        }
+    }
+}
+impl Default for Huffman {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl std::fmt::Debug for Huffman {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Huffman").field("eos", &self.eos).field("states", &self.ends.len()).finish_non_exhaustive()
     }
 }
-
@@ -1,5 +1,7 @@
 use std::{collections::VecDeque, fmt::Display};
 
+use crate::http2::hpack::huffman::HuffmanError;
+
 pub mod huffman;
 pub mod encoder;
 pub mod decoder;
@@ -204,18 +206,50 @@ pub enum HeaderType{
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum HpackError{
     InvalidHeaderField,
+    // the block ends in the middle of a representation
+    Truncated,
+    // 5.1, an integer larger than usize
+    IntegerOverflow,
+    // 2.3.3, index 0 or past the end of both tables
+    InvalidIndex(usize),
+    Huffman(HuffmanError),
+    // 6.3, a size update above the table size we advertised
+    TableSizeExceeded(usize),
+    // 4.2, a size update after the first header field of a block
+    MisplacedTableSizeUpdate,
+    // the decoded block is larger than the decoder's max_header_list_size
+    HeaderListTooLarge(usize),
+}
+impl std::error::Error for HpackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Huffman(e) => Some(e),
+            _ => None,
+        }
+    }
 }
-impl std::error::Error for HpackError { }
 impl Display for HpackError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::InvalidHeaderField => write!(f, "Invalid header field"),
+            Self::Truncated => write!(f, "Header block is truncated"),
+            Self::IntegerOverflow => write!(f, "Integer overflow"),
+            Self::InvalidIndex(index) => write!(f, "Invalid table index {index}"),
+            Self::Huffman(err) => write!(f, "{err}"),
+            Self::TableSizeExceeded(size) => write!(f, "Table size update to {size} exceeds the advertised size"),
+            Self::MisplacedTableSizeUpdate => write!(f, "Table size update after a header field"),
+            Self::HeaderListTooLarge(size) => write!(f, "Header list of {size} bytes is too large"),
         }
     }
 }
+impl From<HuffmanError> for HpackError {
+    fn from(value: HuffmanError) -> Self {
+        Self::Huffman(value)
+    }
+}
 
 
 // no reason for public fields
@@ -3,5 +3,6 @@ pub mod core;
 pub mod session;
 pub mod client;
 pub mod server;
+pub mod stream;
 
 pub use session::PREFACE;
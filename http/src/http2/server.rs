@@ -1,6 +1,6 @@
 use std::{collections::HashMap, sync::Arc};
 
-use crate::{http2::session::Http2Session, shared::{HttpClient, HttpSocket, HttpType, LibError, LibResult, ReadStream, WriteStream, string_from_owned_utf8}};
+use crate::{http2::{core::Http2ErrorCode, session::Http2Session, stream::{Http2Stream, Http2StreamReader, Http2StreamWriter}}, shared::{HttpClient, HttpMethod, HttpSocket, HttpType, LibError, LibResult, ReadStream, WriteStream, string_from_owned_utf8}, websocket::socket::WebSocket};
 
 
 #[derive(Debug)]
@@ -9,13 +9,17 @@ pub struct Http2Socket<R: ReadStream, W: WriteStream> {
     pub session: Arc<Http2Session<R, W>>,
 
     pub client: HttpClient,
-    pub is_reset: bool,
+    pub is_reset: Option<Http2ErrorCode>,
+    // :protocol of an extended CONNECT
+    pub protocol: Option<String>,
     
     pub status: u16,
     pub headers: HashMap<String, Vec<String>>,
     
     pub sent_head: bool,
     pub closed: bool,
+
+    pub trailers: HashMap<String, Vec<String>>,
 }
 impl<R: ReadStream, W: WriteStream> Http2Socket<R, W> {
     pub fn new(stream_id: u32, session: Arc<Http2Session<R, W>>) -> LibResult<Self> {
@@ -23,11 +27,13 @@ impl<R: ReadStream, W: WriteStream> Http2Socket<R, W> {
             Ok(Self {
                 stream_id, session,
                 client: HttpClient::default_h2(),
-                is_reset: false,
+                is_reset: None,
+                protocol: None,
                 status: 200,
                 headers: HashMap::new(),
                 sent_head: false,
                 closed: false,
+                trailers: HashMap::new(),
             })
         }
         else {
@@ -38,8 +44,11 @@ impl<R: ReadStream, W: WriteStream> Http2Socket<R, W> {
     pub async fn read_client(&mut self) -> LibResult<&HttpClient> {
         let mut shard = self.session.streams.get_mut(&self.stream_id).unwrap();
 
-        if shard.reset {
-            self.is_reset = true;
+        if shard.refused {
+            return Err(LibError::Refused)
+        }
+        else if shard.reset {
+            self.is_reset = Some(shard.reset_code.unwrap_or(Http2ErrorCode::Cancel));
         }
         else if !self.client.head_complete {
             let mut shard = 
@@ -73,6 +82,9 @@ impl<R: ReadStream, W: WriteStream> Http2Socket<R, W> {
                 else if header == ":path" {
                     self.client.path = value
                 }
+                else if header == ":protocol" {
+                    self.protocol = Some(value)
+                }
 
                 else if let Some(values) = self.client.headers.get_mut(&header) {
                     values.push(value)
@@ -85,7 +97,7 @@ impl<R: ReadStream, W: WriteStream> Http2Socket<R, W> {
         else if !self.client.body_complete {
 
             let avail = shard.body.len();
-            self.client.body.append(&mut shard.body);
+            shard.body.append_to(&mut self.client.body);
             self.client.body_complete = shard.end_body;
             
             if !shard.end_body && avail == 0 {
@@ -93,18 +105,27 @@ impl<R: ReadStream, W: WriteStream> Http2Socket<R, W> {
                 drop(shard);
                 notif.notified().await;
             }
+            else if shard.end_body {
+                for (h, v) in shard.trailers.drain(..) {
+                    let header = string_from_owned_utf8(h);
+                    let value = string_from_owned_utf8(v);
+
+                    if let Some(values) = self.trailers.get_mut(&header) { values.push(value) }
+                    else { self.trailers.insert(header, vec![value]); }
+                }
+            }
         }
 
         Ok(&self.client)
     }
     pub async fn read_until_complete(&mut self) -> LibResult<&HttpClient> {
-        while !self.client.body_complete && !self.is_reset {
+        while !self.client.body_complete && self.is_reset.is_none() {
             self.read_client().await?;
         }
         Ok(&self.client)
     }
     pub async fn read_until_head_complete(&mut self) -> LibResult<&HttpClient> {
-        while !self.client.head_complete && !self.is_reset {
+        while !self.client.head_complete && self.is_reset.is_none() {
             self.read_client().await?;
         }
         Ok(&self.client)
@@ -121,7 +142,56 @@ impl<R: ReadStream, W: WriteStream> Http2Socket<R, W> {
         self.headers.remove(header)
     }
 
+    // promises a request to the client, the returned socket sends the pushed response
+    // rfc9113 8.4, only safe and cacheable requests can be pushed
+    pub async fn push(&mut self, method: HttpMethod, path: &str, headers: &[(&str, &str)]) -> LibResult<Self> {
+        if method != HttpMethod::Get && method != HttpMethod::Head {
+            return Err(LibError::Invalid)
+        }
+        if let Some(shard) = self.session.streams.get(&self.stream_id) && shard.self_end_body {
+            return Err(LibError::StreamClosed)
+        }
+        // a promised request needs an :authority, it comes from the request being answered
+        let authority = match &self.client.host {
+            Some(host) if !host.is_empty() => host.clone(),
+            _ => return Err(LibError::Invalid),
+        };
+        let promised = self.session.open_push_stream().ok_or(LibError::Refused)?;
+
+        let method_val = method.to_string();
+        let scheme = self.client.scheme.clone().unwrap_or("https".to_owned());
+
+        let mut head: Vec<(&[u8], &[u8])> = vec![
+            (b":method", method_val.as_bytes()),
+            (b":scheme", scheme.as_bytes()),
+            (b":authority", authority.as_bytes()),
+            (b":path", path.as_bytes()),
+        ];
+        for (h, v) in headers { head.push((h.as_bytes(), v.as_bytes())) }
+
+        self.session.send_push_promise(self.stream_id, promised, &head).await?;
+
+        let mut pushed = Self::new(promised, self.session.clone())?;
+        pushed.client.method = method;
+        pushed.client.path = path.to_owned();
+        pushed.client.scheme = Some(scheme);
+        pushed.client.host = Some(authority);
+        pushed.client.head_complete = true;
+        pushed.client.body_complete = true;
+        for (h, v) in headers {
+            if let Some(values) = pushed.client.headers.get_mut(*h) { values.push(v.to_string()) }
+            else { pushed.client.headers.insert(h.to_string(), vec![v.to_string()]); }
+        }
+
+        Ok(pushed)
+    }
+
+    #[inline]
     pub async fn send_head(&mut self, end: bool) -> LibResult<()> {
+        self.write_head(end, true).await
+    }
+    // a head followed by body or trailers is left queued for them to take out
+    async fn write_head(&mut self, end: bool, flush: bool) -> LibResult<()> {
         if !self.sent_head {
             self.sent_head = true;
             let mut headers = Vec::new();
@@ -134,7 +204,8 @@ impl<R: ReadStream, W: WriteStream> Http2Socket<R, W> {
             }
 
             let head = headers.iter().map(|(h, v)| (h.as_slice(), v.as_slice())).collect::<Vec<(&[u8], &[u8])>>();
-            self.session.send_headers(self.stream_id, end, &head).await?;
+            if flush { self.session.send_headers(self.stream_id, end, &head).await?; }
+            else { self.session.send_headers_buffered(self.stream_id, end, &head).await?; }
             Ok(())
         }
         else {
@@ -144,17 +215,79 @@ impl<R: ReadStream, W: WriteStream> Http2Socket<R, W> {
 
     pub async fn write(&mut self, buf: &[u8]) -> LibResult<()> {
         if !self.sent_head {
-            self.send_head(false).await?;
+            self.write_head(false, false).await?;
         }
         self.session.send_data(self.stream_id, false, buf).await
     }
     pub async fn close(&mut self, buf: &[u8]) -> LibResult<()> {
         if !self.sent_head {
             self.set_header("content-length", &buf.len().to_string());
-            self.send_head(false).await?;
+            self.write_head(false, false).await?;
         }
         self.session.send_data(self.stream_id, true, buf).await
     }
+    // 100 Continue, 103 Early Hints and the like, any number of them before send_head
+    pub async fn send_interim(&mut self, status: u16, headers: &[(&str, &str)]) -> LibResult<()> {
+        if self.sent_head { return Err(LibError::HeadersSent) }
+        if !(100..200).contains(&status) || status == 101 { return Err(LibError::Invalid) }
+
+        let status = status.to_string();
+        let mut head: Vec<(&[u8], &[u8])> = vec![(b":status", status.as_bytes())];
+        for (h, v) in headers { head.push((h.as_bytes(), v.as_bytes())) }
+
+        self.session.send_interim(self.stream_id, &head).await
+    }
+    // ends the stream with a trailing header block instead of an empty DATA frame
+    pub async fn send_trailers(&mut self, trailers: &[(&str, &str)]) -> LibResult<()> {
+        if !self.sent_head {
+            self.write_head(false, false).await?;
+        }
+        let trailers = trailers.iter().map(|(h, v)| (h.as_bytes(), v.as_bytes())).collect::<Vec<(&[u8], &[u8])>>();
+        self.session.send_trailers(self.stream_id, &trailers).await
+    }
+}
+impl<R: ReadStream + 'static, W: WriteStream + 'static> Http2Socket<R, W> {
+    // rfc8441, accepts an extended CONNECT with :protocol websocket
+    pub async fn websocket(mut self) -> LibResult<WebSocket<Http2StreamReader<R, W>, Http2StreamWriter<R, W>>> {
+        self.read_until_head_complete().await?;
+
+        if self.client.method != HttpMethod::Connect || self.protocol.as_deref() != Some("websocket") {
+            return Err(LibError::InvalidUpgrade)
+        }
+        // rfc6455 4.2.2, an unknown version is refused with the one we speak
+        if self.client.headers.get("sec-websocket-version").and_then(|v| v.first()).map(|v| v.as_str()) != Some("13") {
+            self.status = 400;
+            self.set_header("sec-websocket-version", "13");
+            self.send_head(true).await?;
+            return Err(LibError::InvalidUpgrade)
+        }
+
+        self.status = 200;
+        self.send_head(false).await?;
+        self.websocket_direct()
+    }
+    pub fn websocket_direct(self) -> LibResult<WebSocket<Http2StreamReader<R, W>, Http2StreamWriter<R, W>>> {
+        let (netr, netw) = self.into_stream()?.into_split();
+        Ok(WebSocket::with_split(netr, netw))
+    }
+
+    // accepts a CONNECT host:port, the tunnel is the stream itself
+    pub async fn tunnel(mut self) -> LibResult<Http2Stream<R, W>> {
+        self.read_until_head_complete().await?;
+
+        if self.client.method != HttpMethod::Connect || self.protocol.is_some() {
+            return Err(LibError::InvalidUpgrade)
+        }
+
+        self.status = 200;
+        self.send_head(false).await?;
+        self.into_stream()
+    }
+    // whatever was not read into client.body yet is read from the stream
+    #[inline]
+    pub fn into_stream(self) -> LibResult<Http2Stream<R, W>> {
+        Http2Stream::new(self.stream_id, self.session)
+    }
 }
 impl<R: ReadStream, W: WriteStream> HttpSocket for Http2Socket<R, W>{
     #[inline]
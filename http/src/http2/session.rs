@@ -1,11 +1,30 @@
-use std::{cmp::min, io, sync::{Arc, Mutex as SyncMutex, atomic::{AtomicBool, Ordering}}};
+use std::{borrow::Cow, cmp::{max, min}, collections::VecDeque, io, sync::{Arc, Mutex as SyncMutex, atomic::{AtomicBool, Ordering}}, time::Duration};
 
+use bytes::{Buf, Bytes, BytesMut};
 use dashmap::DashMap;
-use tokio::{io::{AsyncReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf}, sync::{Mutex as AsyncMutex, Notify}};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt, BufReader, ReadBuf, ReadHalf, WriteHalf}, sync::{Mutex as AsyncMutex, Notify}, time::Instant};
 
-use crate::{http2::{core::{Http2Frame, Http2FrameType, Http2Settings}, hpack::{HeaderType, HpackError, decoder::Decoder, encoder::Encoder}}, shared::{LibError, LibResult, ReadStream, Stream, WriteStream}};
+use crate::{http2::{core::{Http2AltSvc, Http2ErrorCode, Http2Extension, Http2Frame, Http2FrameType, Http2Goaway, Http2Keepalive, Http2Limits, Http2Padding, Http2Priority, Http2Settings, origins_from, origins_to_vec}, hpack::{HpackError, decoder::{DecodedHeaders, Decoder}, encoder::Encoder}}, shared::{LibError, LibResult, ReadStream, Stream, WriteStream}};
 
 pub const PREFACE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+pub const MAX_STREAM_ID: u32 = 0x7fffffff;
+pub const SHUTDOWN_PING: [u8; 8] = *b"shutdown";
+pub const MAX_WINDOW: u32 = 0x7fffffff;
+pub const WRITE_BUFFER_SIZE: usize = 65536;
+pub const READ_BUFFER_SIZE: usize = 16384 + 9;
+// padding is sent as zeroes, rfc9113 6.1
+const PADDING: [u8; 255] = [0; 255];
+// :status 431, a literal without indexing on the static :status name, it leaves the encoder's table alone
+const HEADER_LIST_TOO_LARGE: [u8; 5] = [0x08, 0x03, b'4', b'3', b'1'];
+
+// a header list over our limit is a stream error, anything else hpack reports ends the connection
+fn decoded(res: Result<DecodedHeaders, HpackError>) -> LibResult<Option<DecodedHeaders>> {
+    match res {
+        Ok(dec) => Ok(Some(dec)),
+        Err(HpackError::HeaderListTooLarge(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
 
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -29,13 +48,15 @@ pub struct Http2Data {
 
     pub stream_id: u32,
     pub reset: bool,
+    pub reset_code: Option<Http2ErrorCode>,
+    pub refused: bool,
 
     pub end_head: bool,
     pub end_body: bool,
     pub self_end_head: bool,
     pub self_end_body: bool,
     
-    pub body: Vec<u8>,
+    pub body: Http2Body,
     pub head: Vec<u8>,
     pub headers: Vec<(Vec<u8>, Vec<u8>)>,
 
@@ -48,7 +69,15 @@ pub struct Http2Data {
     pub promise: Vec<u8>,
     pub push_headers: Vec<(Vec<u8>, Vec<u8>)>,
 
+    pub trailing: bool,
+    pub trailers: Vec<(Vec<u8>, Vec<u8>)>,
+    // 1xx responses that came before the final one
+    pub interim: Vec<Vec<(Vec<u8>, Vec<u8>)>>,
+
     pub own_window: Option<SyncMutex<usize>>,
+    // stream WINDOW_UPDATEs are sent by whoever consumes the body, see Http2StreamReader
+    pub manual_window: bool,
+    pub priority: Http2Priority,
 }
 impl Http2Data {
     pub fn empty(stream_id: u32, sett: Http2Settings) -> Self {
@@ -58,11 +87,13 @@ impl Http2Data {
             notify: Arc::new(Notify::new()),
             stream_id,
             reset: false,
+            reset_code: None,
+            refused: false,
             end_head: false,
             end_body: false,
             self_end_head: false,
             self_end_body: false,
-            body: Vec::new(),
+            body: Http2Body::new(),
             head: Vec::new(),
             headers: Vec::new(),
             head_complete: Arc::new(Notify::new()),
@@ -72,34 +103,320 @@ impl Http2Data {
             promising: None,
             promise: Vec::new(),
             push_headers: Vec::new(),
+            trailing: false,
+            trailers: Vec::new(),
+            interim: Vec::new(),
             own_window: None,
+            manual_window: false,
+            priority: Http2Priority::default(),
+        }
+    }
+
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.reset || (self.end_body && self.self_end_body)
+    }
+
+    // rfc9113 8.1, a 1xx response is interim and the final one follows in another block, false for those
+    pub fn finish_head(&mut self, mut headers: Vec<(Vec<u8>, Vec<u8>)>) -> LibResult<bool> {
+        self.head.clear();
+
+        let interim = headers.iter().any(|(h, v)| h == b":status" && v.len() == 3 && v[0] == b'1');
+        if interim {
+            if self.end_body { return Err(LibError::ProtocolError) }
+            self.interim.push(headers);
+            return Ok(false)
+        }
+
+        self.headers.append(&mut headers);
+        self.apply_priority_header();
+        self.end_head = true;
+        self.head_complete.notify_waiters();
+        Ok(true)
+    }
+
+    pub fn finish_trailers(&mut self) {
+        self.trailing = false;
+        self.end_body = true;
+        self.head.clear();
+        self.body_received.notify_waiters();
+    }
+
+    pub fn apply_priority_header(&mut self) {
+        if let Some((_, val)) = self.headers.iter().find(|(h, _)| h == b"priority") {
+            self.priority = Http2Priority::from(val);
         }
     }
 }
 
+// DATA payloads as they arrived, slices of the session's read buffer until a consumer copies them out
+#[derive(Debug, Clone, Default)]
+pub struct Http2Body {
+    pub chunks: VecDeque<Bytes>,
+    pub len: usize,
+}
+impl Http2Body {
+    pub fn new() -> Self {
+        Self { chunks: VecDeque::new(), len: 0 }
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, chunk: Bytes) {
+        if !chunk.is_empty() {
+            self.len += chunk.len();
+            self.chunks.push_back(chunk);
+        }
+    }
+    #[inline]
+    pub fn extend_from_slice(&mut self, buf: &[u8]) {
+        self.push(Bytes::copy_from_slice(buf))
+    }
+    pub fn pop_chunk(&mut self) -> Option<Bytes> {
+        let chunk = self.chunks.pop_front()?;
+        self.len -= chunk.len();
+        Some(chunk)
+    }
+
+    // everything is moved into `out`, the one copy between the socket and the consumer
+    pub fn append_to(&mut self, out: &mut Vec<u8>) {
+        out.reserve(self.len);
+        for chunk in self.chunks.drain(..) { out.extend_from_slice(&chunk) }
+        self.len = 0;
+    }
+    pub fn read_into(&mut self, buf: &mut ReadBuf<'_>) -> usize {
+        let mut read = 0;
+        while buf.remaining() > 0 && let Some(chunk) = self.chunks.front_mut() {
+            let len = min(chunk.len(), buf.remaining());
+            buf.put_slice(&chunk[..len]);
+            chunk.advance(len);
+            read += len;
+
+            if chunk.is_empty() { self.chunks.pop_front(); }
+        }
+        self.len -= read;
+        read
+    }
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.len);
+        for chunk in &self.chunks { out.extend_from_slice(chunk) }
+        out
+    }
+}
+
+impl From<Vec<u8>> for Http2Body {
+    fn from(value: Vec<u8>) -> Self {
+        let mut body = Self::new();
+        body.push(value.into());
+        body
+    }
+}
+
+#[derive(Debug)]
+pub struct Http2Rates {
+    pub since: Instant,
+    pub resets: u32,
+    pub control: u32,
+}
+impl Http2Rates {
+    pub fn new() -> Self {
+        Self { since: Instant::now(), resets: 0, control: 0 }
+    }
+}
+impl Default for Http2Rates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// frames from every task are queued here in wire order, whoever gets netw next writes all of them at once
+// positions count bytes over the whole connection, a frame is out once flushed passed its end
+#[derive(Debug, Default)]
+pub struct Http2WriteBuffer {
+    pub buf: Vec<u8>,
+    pub spare: Vec<u8>,
+    pub queued: u64,
+    pub flushed: u64,
+}
+
+// the bytes a flush took out of Http2WriteBuffer, the unwritten rest is put back in front on drop
+// so a cancelled or failed flush never loses frames queued by other tasks
+struct Unflushed<'a> {
+    output: &'a SyncMutex<Http2WriteBuffer>,
+    buf: Vec<u8>,
+    written: usize,
+    max_spare: usize,
+}
+impl Drop for Unflushed<'_> {
+    fn drop(&mut self) {
+        let mut out = self.output.lock().unwrap();
+        let mut buf = std::mem::take(&mut self.buf);
+
+        if self.written < buf.len() {
+            buf.drain(..self.written);
+            buf.extend_from_slice(&out.buf);
+            buf = std::mem::replace(&mut out.buf, buf);
+        }
+        buf.clear();
+        // keeps the allocation around, unless a huge frame blew it up
+        if buf.capacity() <= self.max_spare { out.spare = buf }
+    }
+}
+
+// rfc6298 style smoothing over the rtt of our own PINGs
+#[derive(Debug, Default)]
+pub struct Http2Rtt {
+    pub latest: Option<Duration>,
+    pub smoothed: Option<Duration>,
+    pub variance: Duration,
+    pub min: Option<Duration>,
+
+    pub seq: u64,
+    pub outstanding: Option<([u8; 8], Instant)>,
+    // DATA bytes received while a PING was outstanding, one sample of the bandwidth delay product
+    pub received: usize,
+    pub bdp: usize,
+}
+impl Http2Rtt {
+    pub fn sample(&mut self, rtt: Duration) {
+        self.latest = Some(rtt);
+        self.min = Some(self.min.map(|m| m.min(rtt)).unwrap_or(rtt));
+
+        match self.smoothed {
+            None => {
+                self.smoothed = Some(rtt);
+                self.variance = rtt / 2;
+            },
+            Some(srtt) => {
+                self.variance = (self.variance * 3 + srtt.abs_diff(rtt)) / 4;
+                self.smoothed = Some((srtt * 7 + rtt) / 8);
+            },
+        }
+    }
+    // a new outstanding PING, None while the previous one is unanswered
+    pub fn start(&mut self) -> Option<[u8; 8]> {
+        if self.outstanding.is_some() { return None }
+
+        self.seq += 1;
+        let payload = self.seq.to_be_bytes();
+        self.outstanding = Some((payload, Instant::now()));
+        self.received = 0;
+        Some(payload)
+    }
+    pub fn ack(&mut self, payload: &[u8]) {
+        if let Some((sent, at)) = self.outstanding && payload == sent {
+            self.outstanding = None;
+            self.bdp = self.received;
+            self.sample(at.elapsed());
+        }
+    }
+}
+
+// DATA is written one frame per turn, the waiting stream with the best priority goes next
+#[derive(Debug, Default)]
+pub struct Http2Scheduler {
+    pub waiting: Vec<(u32, Http2Priority, u64)>,
+    pub active: Option<u32>,
+    pub seq: u64,
+}
+impl Http2Scheduler {
+    pub fn push(&mut self, stream_id: u32, priority: Http2Priority) {
+        self.seq += 1;
+        self.waiting.push((stream_id, priority, self.seq));
+    }
+    pub fn next(&self) -> Option<u32> {
+        // non incremental streams are served in id order, incremental ones take turns
+        self.waiting.iter()
+            .min_by_key(|(id, p, seq)| (p.urgency, p.incremental, if p.incremental { *seq } else { *id as u64 }))
+            .map(|w| w.0)
+    }
+    pub fn remove(&mut self, stream_id: u32) {
+        self.waiting.retain(|w| w.0 != stream_id);
+        if self.active == Some(stream_id) { self.active = None }
+    }
+}
+
+// releases the turn, also when the writing future gets dropped
+pub struct Http2WriteTurn<'a> {
+    scheduler: &'a SyncMutex<Http2Scheduler>,
+    scheduled: &'a Notify,
+    stream_id: u32,
+}
+impl Drop for Http2WriteTurn<'_> {
+    fn drop(&mut self) {
+        self.scheduler.lock().unwrap().remove(self.stream_id);
+        self.scheduled.notify_waiters();
+    }
+}
+
 #[derive(Debug)]
 pub struct Http2Session<R: ReadStream, W: WriteStream>{
     pub netr: AsyncMutex<R>,
+    // frames are read into this and split off, only locked while netr is held
+    pub input: AsyncMutex<BytesMut>,
     pub netw: AsyncMutex<W>,
+    pub output: SyncMutex<Http2WriteBuffer>,
+    // queued frames are written once this much piles up, or on the next flush
+    pub write_buffer_size: usize,
     pub mode: Mode,
     pub strict: bool,
+    // applies to outgoing DATA and HEADERS frames
+    pub padding: Http2Padding,
 
     pub decoder: AsyncMutex<Decoder<'static>>,
     pub encoder: AsyncMutex<Encoder<'static>>,
 
     pub max_stream_id: SyncMutex<u32>,
+    pub peer_stream_id: SyncMutex<u32>,
     pub streams: DashMap<u32, Http2Data>,
+    pub stream_closed: Notify,
 
     pub goaway: AtomicBool,
     pub goaway_frame: SyncMutex<Option<Http2Frame<'static>>>,
+    pub goaway_info: SyncMutex<Option<Http2Goaway>>,
+    pub goaway_sent: SyncMutex<Option<u32>>,
+    pub ping_ack: Notify,
+    // only the ack of the shutdown ping, a keepalive ack must not end that round trip
+    pub shutdown_ack: Notify,
+
+    pub keepalive: Option<Http2Keepalive>,
+    pub last_read: SyncMutex<Instant>,
+    pub rtt: SyncMutex<Http2Rtt>,
+    pub torn_down: AtomicBool,
+    pub teardown: Notify,
 
     pub window: SyncMutex<usize>,
     pub notify: Notify,
 
     pub settings: SyncMutex<Http2Settings>,
+    // every unknown setting the peer sent with its last value, the copy in settings keeps only a few
+    pub peer_unknown_settings: SyncMutex<Vec<(u16, u32)>>,
+
+    pub limits: Http2Limits,
+    pub rates: SyncMutex<Http2Rates>,
+    // where the PING ACKs still in the write buffer end, a peer that doesn't read can't make us queue forever
+    pub pending_pings: SyncMutex<VecDeque<u64>>,
+
+    pub scheduler: SyncMutex<Http2Scheduler>,
+    pub scheduled: Notify,
+
+    pub extensions: SyncMutex<Vec<Arc<dyn Http2Extension>>>,
+    // (stream_id, frame) of received ALTSVC frames, client side only
+    pub alt_svc: SyncMutex<Vec<(u32, Http2AltSvc)>>,
+    // the last ORIGIN set received, None means any origin the certificate covers
+    pub origins: SyncMutex<Option<Vec<String>>>,
 
     // TODO: force other side to respect settings & flow control
-    pub own_settings: Option<SyncMutex<Http2Settings>>,
+    // ours as far as the peer acknowledged them, None before the first ACK
+    pub own_settings: SyncMutex<Option<Http2Settings>>,
+    // sent and not acknowledged yet, ACKs come back in the same order, rfc9113 6.5.3
+    pub sent_settings: SyncMutex<VecDeque<Http2Settings>>,
     pub own_window: Option<SyncMutex<usize>>,
 }
 impl<S: Stream> Http2Session<ReadHalf<S>, WriteHalf<S>> {
@@ -137,16 +454,41 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
 
         Self {
             netr, netw, mode, strict,
+            padding: Http2Padding::default(),
+            input: AsyncMutex::new(BytesMut::with_capacity(READ_BUFFER_SIZE)),
+            output: SyncMutex::new(Http2WriteBuffer::default()),
+            write_buffer_size: WRITE_BUFFER_SIZE,
             decoder: AsyncMutex::new(Decoder::new(settings.header_table_size.unwrap_or(4096) as usize)),
             encoder: AsyncMutex::new(Encoder::new(settings.header_table_size.unwrap_or(4096) as usize)),
             max_stream_id: SyncMutex::new(0),
+            peer_stream_id: SyncMutex::new(0),
             streams: DashMap::new(),
+            stream_closed: Notify::new(),
             goaway: AtomicBool::new(false),
             goaway_frame: SyncMutex::new(None),
+            goaway_info: SyncMutex::new(None),
+            goaway_sent: SyncMutex::new(None),
+            ping_ack: Notify::new(),
+            shutdown_ack: Notify::new(),
+            keepalive: None,
+            last_read: SyncMutex::new(Instant::now()),
+            rtt: SyncMutex::new(Http2Rtt::default()),
+            torn_down: AtomicBool::new(false),
+            teardown: Notify::new(),
             window: SyncMutex::new(settings.initial_window_size.unwrap_or(65535) as usize),
             notify: Notify::new(),
             settings: SyncMutex::new(settings),
-            own_settings: None,
+            peer_unknown_settings: SyncMutex::new(Vec::new()),
+            limits: Http2Limits::default(),
+            rates: SyncMutex::new(Http2Rates::new()),
+            pending_pings: SyncMutex::new(VecDeque::new()),
+            scheduler: SyncMutex::new(Http2Scheduler::default()),
+            scheduled: Notify::new(),
+            extensions: SyncMutex::new(Vec::new()),
+            alt_svc: SyncMutex::new(Vec::new()),
+            origins: SyncMutex::new(None),
+            own_settings: SyncMutex::new(None),
+            sent_settings: SyncMutex::new(VecDeque::new()),
             own_window: None,
         }
     }
@@ -154,18 +496,42 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
     
 
     pub async fn send_preface(&self) -> io::Result<()> {
-        self.netw.lock().await.write_all(PREFACE).await
+        self.write_raw(PREFACE).await
     }
     pub async fn read_preface(&self) -> io::Result<bool> {
-        let mut pre = [0; PREFACE.len()];
-        self.netr.lock().await.read_exact(&mut pre).await?;
-        Ok(pre == PREFACE)
+        let mut netr = self.netr.lock().await;
+        let mut input = self.input.lock().await;
+
+        fill_buffer(&mut *netr, &mut input, PREFACE.len()).await?;
+        Ok(input.split_to(PREFACE.len()) == PREFACE)
     }
 
 
+    // one whole frame split off the input buffer, no copy is made
+    pub async fn read_raw_frame(&self) -> io::Result<Bytes> {
+        let torn_down = self.teardown.notified();
+        if self.torn_down.load(Ordering::SeqCst) { return Err(io::ErrorKind::ConnectionAborted.into()) }
+
+        let raw = tokio::select! {
+            raw = async {
+                let mut netr = self.netr.lock().await;
+                let mut input = self.input.lock().await;
+
+                fill_buffer(&mut *netr, &mut input, 9).await?;
+                let length = u32::from_be_bytes([0, input[0], input[1], input[2]]) as usize;
+                fill_buffer(&mut *netr, &mut input, 9 + length).await?;
+
+                io::Result::Ok(input.split_to(9 + length).freeze())
+            } => raw?,
+            _ = torn_down => return Err(io::ErrorKind::ConnectionAborted.into()),
+        };
+
+        *self.last_read.lock().unwrap() = Instant::now();
+        Ok(raw)
+    }
     pub async fn read_frame(&self) -> io::Result<Http2Frame<'static>> {
-        let mut reader = self.netr.lock().await;
-        Http2Frame::from_reader(&mut *reader).await
+        let raw = self.read_raw_frame().await?;
+        Http2Frame::from_owned(raw.into()).ok_or(io::ErrorKind::InvalidData.into())
     }
     pub async fn read_until(&self, frame_type: Http2FrameType) -> io::Result<Vec<Http2Frame<'static>>> {
         let mut frames = vec![];
@@ -197,22 +563,24 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
     }
 
     pub async fn next(&self) -> LibResult<Option<u32>> {
-        let frame = self.read_frame().await?;
+        let raw = self.read_raw_frame().await?;
+        let frame = Http2Frame::from(Cow::Borrowed(&raw)).ok_or(LibError::InvalidFrame)?;
 
         // println!("\x1b[36m{:?}\x1b[0m {:?}", frame.ftype, frame.source);
         
-        self.handle(frame).await
+        self.handle_shared(frame, Some(&raw)).await
     }
     pub async fn next_until(&self, frame_type: Http2FrameType) -> LibResult<Vec<u32>> {
         let mut opened = vec![];
         let mut done = false;
 
         while !done {
-            let frame = self.read_frame().await?;
+            let raw = self.read_raw_frame().await?;
+            let frame = Http2Frame::from(Cow::Borrowed(&raw)).ok_or(LibError::InvalidFrame)?;
             
             if frame.ftype == frame_type { done = true }
 
-            if let Some(open) = self.handle(frame).await? {
+            if let Some(open) = self.handle_shared(frame, Some(&raw)).await? {
                 opened.push(open);
             }
         }
@@ -224,11 +592,12 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
         let mut done = false;
 
         while !done {
-            let frame = self.read_frame().await?;
+            let raw = self.read_raw_frame().await?;
+            let frame = Http2Frame::from(Cow::Borrowed(&raw)).ok_or(LibError::InvalidFrame)?;
             
             if frame.ftype != frame_type { done = true }
 
-            if let Some(open) = self.handle(frame).await? {
+            if let Some(open) = self.handle_shared(frame, Some(&raw)).await? {
                 opened.push(open);
             }
         }
@@ -237,7 +606,12 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
     }
 
 
+    #[inline]
     pub async fn handle<'a>(&self, frame: Http2Frame<'a>) -> LibResult<Option<u32>> {
+        self.handle_shared(frame, None).await
+    }
+    // `raw` is the buffer the frame was parsed from, DATA payloads are kept as slices of it
+    pub async fn handle_shared<'a>(&self, frame: Http2Frame<'a>, raw: Option<&Bytes>) -> LibResult<Option<u32>> {
         // TODO: strict check wether frame fields are valid, e.g. allowed flags
 
         {
@@ -247,20 +621,42 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
             }
         }
 
+        if self.exceeds_limits(&frame) {
+            return Err(self.enhance_your_calm().await)
+        }
+
         match frame.ftype {
             Http2FrameType::Data => {                
                 if let Some(mut shard) = self.streams.get_mut(&frame.stream_id) {
                     // TODO: strict check wether closed
 
-                    shard.body.extend_from_slice(frame.get_payload());
+                    match raw {
+                        Some(raw) => shard.body.push(raw.slice(frame.payload.clone())),
+                        None => shard.body.extend_from_slice(frame.get_payload()),
+                    }
                     
-                    if frame.is_end_stream() { shard.end_body = true }
+                    if frame.is_end_stream() { 
+                        shard.end_body = true;
+                        self.stream_closed.notify_waiters();
+                    }
 
                     shard.body_received.notify_waiters();
 
+                    let manual = shard.manual_window;
                     drop(shard);
-                    self.send_window_update(0, frame.length).await?;
-                    self.send_window_update(frame.stream_id, frame.length).await?;
+                    {
+                        let mut rtt = self.rtt.lock().unwrap();
+                        if rtt.outstanding.is_some() { rtt.received += frame.length as usize }
+                    }
+                    // both updates go out in one write
+                    self.queue_frame(Http2FrameType::WindowUpdate, 0, 0, None, Some(&u32::to_be_bytes(frame.length)), None);
+                    if !manual { self.queue_frame(Http2FrameType::WindowUpdate, 0, frame.stream_id, None, Some(&u32::to_be_bytes(frame.length)), None); }
+                    else {
+                        // a manual reader only counts the data, the padding is handed back right away
+                        let padding = frame.length - frame.payload.len() as u32;
+                        if padding > 0 { self.queue_frame(Http2FrameType::WindowUpdate, 0, frame.stream_id, None, Some(&u32::to_be_bytes(padding)), None); }
+                    }
+                    self.flush().await?;
 
                     Ok(None)
                 }
@@ -270,38 +666,104 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
             },
             Http2FrameType::Headers => {
                 let mut decoder = self.decoder.lock().await;
+                decoder.max_header_list_size = self.header_list_limit();
                 match self.streams.get_mut(&frame.stream_id) {
-                    // TODO: allow leading or trailing non opening headers
+                    // a second block after the opening headers are trailers and has to end the stream
+                    Some(mut shard) if shard.end_head => {
+                        if !frame.is_end_stream() || shard.end_body {
+                            return Err(LibError::ProtocolError)
+                        }
+
+                        shard.head.extend_from_slice(frame.get_payload());
+                        shard.trailing = true;
+
+                        if frame.is_end_headers() {
+                            match decoded(decoder.decode_all(&shard.head))? {
+                                Some(mut dec) => {
+                                    shard.trailers.append(&mut dec);
+                                    shard.finish_trailers();
+                                    self.stream_closed.notify_waiters();
+                                },
+                                None => {
+                                    self.refuse_header_list(&mut shard);
+                                    drop(shard);
+                                    self.flush().await?;
+                                },
+                            }
+                        }
+                        Ok(None)
+                    },
                     // TODO: strict verify that this is the case
                     Some(mut shard) if self.mode.is_client() || self.mode.is_ambiguous() => {
 
                         shard.head.extend_from_slice(frame.get_payload());
 
+                        if frame.is_end_stream() { 
+                            shard.end_body = true;
+                            self.stream_closed.notify_waiters();
+                        }
                         if frame.is_end_headers() {
-                            let mut dec = decoder.decode_all(&shard.head).ok_or(HpackError::InvalidHeaderField)?;
-                            shard.headers.append(&mut dec);
-                            shard.end_head = true;
-                            shard.head.clear();
-                            shard.head_complete.notify_waiters();
+                            match decoded(decoder.decode_all(&shard.head))? {
+                                Some(dec) => { shard.finish_head(dec)?; },
+                                None => {
+                                    self.refuse_header_list(&mut shard);
+                                    drop(shard);
+                                    self.flush().await?;
+                                },
+                            }
                         }
-                        if frame.is_end_stream() { shard.end_body = true }
                         Ok(None)
                     },
                     None if self.mode.is_server() || self.mode.is_ambiguous() => {
                         let mut stream = Http2Data::empty(frame.stream_id, *self.settings.lock().unwrap());
 
+                        // after the final goaway anything above the advertised id is refused, 
+                        // the header block still has to be decoded to keep hpack state in sync
+                        let refuse = self.goaway_sent.lock().unwrap().map(|last| frame.stream_id > last).unwrap_or(false);
+                        if refuse {
+                            stream.reset = true;
+                            stream.reset_code = Some(Http2ErrorCode::RefusedStream);
+                            stream.refused = true;
+                        }
+                        else {
+                            let mut psid = self.peer_stream_id.lock().unwrap();
+                            if frame.stream_id > *psid { *psid = frame.stream_id }
+                        }
+
                         stream.head.extend_from_slice(frame.get_payload());
 
                         if frame.is_end_stream() { stream.end_body = true }
+                        let mut too_large = false;
                         if frame.is_end_headers() {
-                            stream.end_head = true;
-                            stream.headers.append(&mut decoder.decode_all(&stream.head).ok_or(HpackError::InvalidHeaderField)?);
-                            stream.head.clear();
+                            match decoded(decoder.decode_all(&stream.head))? {
+                                Some(mut dec) => {
+                                    stream.end_head = true;
+                                    stream.headers.append(&mut dec);
+                                    stream.head.clear();
+                                    stream.apply_priority_header();
+                                },
+                                // a refused stream is reset already, no 431 for it
+                                None if refuse => stream.head.clear(),
+                                None => {
+                                    self.refuse_header_list(&mut stream);
+                                    too_large = true;
+                                },
+                            }
                         }
                         
                         self.streams.insert(frame.stream_id, stream);
                         
-                        if frame.is_end_headers() {
+                        if refuse {
+                            drop(decoder);
+                            self.send_rst_stream(frame.stream_id, Http2ErrorCode::RefusedStream).await?;
+                            Ok(None)
+                        }
+                        else if too_large {
+                            drop(decoder);
+                            self.flush().await?;
+                            Ok(None)
+                        }
+                        else if frame.is_end_headers() {
                             Ok(Some(frame.stream_id))
                         }
                         else {
@@ -311,13 +773,30 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
                     _ => Err(LibError::ProtocolError),
                 }
             },
+            // RFC 7540 priorities are deprecated, RFC 9218 uses the priority header and PRIORITY_UPDATE
             Http2FrameType::Priority => {
                 Ok(None)
             },
+            Http2FrameType::PriorityUpdate => {
+                if frame.stream_id != 0 { return Err(LibError::ProtocolError) }
+                let (stream_id, priority) = Http2Priority::from_update(frame.get_payload()).ok_or(LibError::ProtocolError)?;
+                
+                if let Some(mut shard) = self.streams.get_mut(&stream_id) {
+                    shard.priority = priority;
+                }
+                Ok(None)
+            },
             Http2FrameType::RstStream => {
+                let pay = frame.get_payload();
+                if pay.len() != 4 { return Err(LibError::ProtocolError) }
+
                 if let Some(mut shard) = self.streams.get_mut(&frame.stream_id) {
                     shard.reset = true;
+                    shard.reset_code = Some(u32::from_be_bytes([pay[0], pay[1], pay[2], pay[3]]).into());
                     shard.notify.notify_waiters();
+                    shard.head_complete.notify_waiters();
+                    shard.body_received.notify_waiters();
+                    self.stream_closed.notify_waiters();
 
                     Ok(None)
                 }
@@ -326,22 +805,51 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
                 }
             },
             Http2FrameType::Settings => {
-                // TODO: strict only allow known settings (1 - 6) and stream_id == 0
+                // TODO: strict only allow stream_id == 0
                 if !frame.is_ack() {
-                    {
+                    let (table_size, unknown) = {
                         let sett = Http2Settings::from(frame.get_payload());
-                        let mut settings = self.settings.lock().unwrap();
-                        
-                        if let Some(val) = sett.header_table_size { settings.header_table_size = Some(val) }
-                        if let Some(val) = sett.enable_push { settings.enable_push = Some(val) }
-                        if let Some(val) = sett.max_concurrent_streams { settings.max_concurrent_streams = Some(val) }
-                        if let Some(val) = sett.initial_window_size { settings.initial_window_size = Some(val) }
-                        if let Some(val) = sett.max_frame_size { settings.max_frame_size = Some(val) }
-                        if let Some(val) = sett.max_header_list_size { settings.max_header_list_size = Some(val) }
+                        self.settings.lock().unwrap().merge(&sett);
+
+                        // taken from the frame itself, only a few are kept in the settings
+                        let unknown = Http2Settings::raw_from(frame.get_payload()).unwrap_or_default().into_iter()
+                            .filter(|(id, _)| !matches!(id, 1..=6 | 8))
+                            .collect::<Vec<_>>();
+                        (sett.header_table_size, unknown)
+                    };
+
+                    if !unknown.is_empty() {
+                        let mut all = self.peer_unknown_settings.lock().unwrap();
+                        for &(id, val) in &unknown {
+                            match all.iter_mut().find(|(i, _)| *i == id) {
+                                Some(setting) => setting.1 = val,
+                                None => all.push((id, val)),
+                            }
+                        }
+                        drop(all);
+
+                        let extensions = self.extensions.lock().unwrap().clone();
+                        for (id, val) in unknown {
+                            for ext in &extensions { ext.handle_setting(id, val) }
+                        }
+                    }
+
+                    if let Some(size) = table_size {
+                        self.encoder.lock().await.set_table_size(size as usize);
                     }
 
                     self.write_frame(Http2FrameType::Settings, 1, 0, None, None, None).await?;
                 }
+                else {
+                    let acked = self.sent_settings.lock().unwrap().pop_front();
+                    if let Some(sett) = acked {
+                        self.own_settings.lock().unwrap().get_or_insert(Http2Settings::empty()).merge(&sett);
+                        // rfc7541 4.2, only from here on the peer's size updates are held to the new size
+                        if let Some(size) = sett.header_table_size {
+                            self.decoder.lock().await.set_max_table_size(size as usize);
+                        }
+                    }
+                }
 
                 Ok(None)
             },
@@ -351,26 +859,51 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
                 // TODO: strict verify associated stream exists
                 if (self.mode.is_client() || self.mode.is_ambiguous()) && pay.len() >= 4 {
                     let mut decoder = self.decoder.lock().await;
+                    decoder.max_header_list_size = self.header_list_limit();
 
                     let promised = u32::from_be_bytes([pay[0], pay[1], pay[2], pay[3]]);
                     if self.streams.contains_key(&promised) {
                         Err(LibError::ProtocolError)
                     }
-                    else if let Some(mut shard) = self.streams.get_mut(&frame.stream_id) {
+                    else if self.streams.contains_key(&frame.stream_id) {
                         let mut stream = Http2Data::empty(promised, *self.settings.lock().unwrap());
 
+                        // reserved (remote), we never send on a pushed stream
+                        stream.ascociated = Some(frame.stream_id);
+                        stream.self_end_head = true;
+                        stream.self_end_body = true;
                         stream.promise.extend_from_slice(&pay[4..]);
 
+                        let mut too_large = false;
                         if frame.is_end_headers() {
-                            stream.push_headers.append(&mut decoder.decode_all(&stream.promise).ok_or(HpackError::InvalidHeaderField)?);
+                            match decoded(decoder.decode_all(&stream.promise))? {
+                                Some(mut dec) => stream.push_headers.append(&mut dec),
+                                None => {
+                                    self.refuse_header_list(&mut stream);
+                                    too_large = true;
+                                },
+                            }
                             stream.promise.clear();
                         }
                         if frame.is_end_stream() { stream.end_body = true }
 
+                        // no guard may be held while inserting, both ids can live in the same shard
                         self.streams.insert(promised, stream);
-                        shard.promising = Some(promised);
+                        if !frame.is_end_headers() && let Some(mut shard) = self.streams.get_mut(&frame.stream_id) {
+                            shard.promising = Some(promised);
+                        }
 
-                        if frame.is_end_headers() {
+                        {
+                            let mut psid = self.peer_stream_id.lock().unwrap();
+                            if promised > *psid { *psid = promised }
+                        }
+
+                        if too_large {
+                            drop(decoder);
+                            self.flush().await?;
+                            Ok(None)
+                        }
+                        else if frame.is_end_headers() {
                             Ok(Some(promised))
                         }
                         else {
@@ -386,11 +919,47 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
                 }
             },
             Http2FrameType::Ping => {
-                if !frame.is_ack() { self.send_ping(true, frame.get_payload()).await?; }
+                if !frame.is_ack() {
+                    let pos = {
+                        let flushed = self.output.lock().unwrap().flushed;
+                        let mut pending = self.pending_pings.lock().unwrap();
+                        while pending.front().is_some_and(|end| *end <= flushed) { pending.pop_front(); }
+
+                        if pending.len() >= self.limits.max_pending_pings as usize { None }
+                        else {
+                            let pos = self.queue_frame(Http2FrameType::Ping, 1, 0, None, Some(frame.get_payload()), None);
+                            pending.push_back(pos);
+                            Some(pos)
+                        }
+                    };
+                    let Some(pos) = pos else { return Err(self.enhance_your_calm().await) };
+                    self.flush_until(pos).await?;
+                }
+                else {
+                    self.rtt.lock().unwrap().ack(frame.get_payload());
+                    self.ping_ack.notify_waiters();
+                    if frame.get_payload() == SHUTDOWN_PING { self.shutdown_ack.notify_waiters() }
+                }
                 Ok(None)
             },
             Http2FrameType::Goaway => {
+                let info = Http2Goaway::from(frame.get_payload()).ok_or(LibError::ProtocolError)?;
+
+                // streams we opened above the last id were never processed by the peer, so they can be retried elsewhere
+                for mut shard in self.streams.iter_mut() {
+                    if shard.stream_id > info.last_stream_id && !shard.new && !shard.is_closed() {
+                        shard.reset = true;
+                        shard.reset_code = Some(Http2ErrorCode::RefusedStream);
+                        shard.refused = true;
+                        shard.notify.notify_waiters();
+                        shard.head_complete.notify_waiters();
+                        shard.body_received.notify_waiters();
+                    }
+                }
+
+                *self.goaway_info.lock().unwrap() = Some(info);
                 self.goaway.store(true, Ordering::SeqCst);
+                self.stream_closed.notify_waiters();
                 let mut goaway: std::sync::MutexGuard<'_, Option<Http2Frame<'static>>> = self.goaway_frame.lock().unwrap();
                 *goaway = Some(frame.into_owned());
                 self.notify.notify_waiters();
@@ -422,38 +991,83 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
             },
             Http2FrameType::Continuation => {
                 let mut decoder = self.decoder.lock().await;
+                decoder.max_header_list_size = self.header_list_limit();
                 // TODO: strict verify wether headers has opened
+                let promising = self.streams.get(&frame.stream_id).and_then(|s| s.promising);
+                
                 if let Some(mut shard) = self.streams.get_mut(&frame.stream_id) {
-                    if let Some(promising) = shard.promising && let Some(mut promised) = self.streams.get_mut(&promising) {
+                    if let Some(promising) = promising {
+                        drop(shard);
+                        let mut promised = self.streams.get_mut(&promising).ok_or(LibError::InvalidStream)?;
                         promised.promise.extend_from_slice(frame.get_payload());
 
                         if frame.is_end_headers() {
-                            let mut dec = decoder.decode_all(&promised.promise).ok_or(HpackError::InvalidHeaderField)?;
-                            promised.push_headers.append(&mut dec);
+                            let dec = decoded(decoder.decode_all(&promised.promise))?;
+                            match dec {
+                                Some(mut dec) => promised.push_headers.append(&mut dec),
+                                None => self.refuse_header_list(&mut promised),
+                            }
                             promised.promise.clear();
-                            shard.promising = None;
-                            Ok(Some(promising))
+                            let refused = promised.reset;
+                            drop(promised);
+
+                            if let Some(mut shard) = self.streams.get_mut(&frame.stream_id) { shard.promising = None }
+                            if refused {
+                                drop(decoder);
+                                self.flush().await?;
+                                Ok(None)
+                            }
+                            else {
+                                Ok(Some(promising))
+                            }
                         }
                         else {
                             Ok(None)
                         }
                     }
-                    else {
+                    else if shard.trailing {
                         shard.head.extend_from_slice(frame.get_payload());
 
-                        if frame.is_end_stream() { shard.end_body = true }
                         if frame.is_end_headers() {
-                            let mut dec = decoder.decode_all(&shard.head).ok_or(HpackError::InvalidHeaderField)?;
-                            shard.headers.append(&mut dec);
-                            shard.end_head = true;
-                            shard.head.clear();
-                            shard.head_complete.notify_waiters();
-
-                            if shard.new {
-                                Ok(Some(frame.stream_id))
+                            match decoded(decoder.decode_all(&shard.head))? {
+                                Some(mut dec) => {
+                                    shard.trailers.append(&mut dec);
+                                    shard.finish_trailers();
+                                    self.stream_closed.notify_waiters();
+                                },
+                                None => {
+                                    self.refuse_header_list(&mut shard);
+                                    drop(shard);
+                                    self.flush().await?;
+                                },
                             }
-                            else {
-                                Ok(None)
+                        }
+                        Ok(None)
+                    }
+                    else {
+                        shard.head.extend_from_slice(frame.get_payload());
+
+                        if frame.is_end_stream() { 
+                            shard.end_body = true;
+                            self.stream_closed.notify_waiters();
+                        }
+                        if frame.is_end_headers() {
+                            match decoded(decoder.decode_all(&shard.head))? {
+                                Some(dec) => {
+                                    if shard.finish_head(dec)? && shard.new && !shard.refused { Ok(Some(frame.stream_id)) }
+                                    else { Ok(None) }
+                                },
+                                // a refused stream is reset already
+                                None if shard.refused => {
+                                    shard.head.clear();
+                                    Ok(None)
+                                },
+                                None => {
+                                    self.refuse_header_list(&mut shard);
+                                    drop(shard);
+                                    self.flush().await?;
+                                    Ok(None)
+                                },
                             }
                         }
                         else {
@@ -465,7 +1079,30 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
                     Err(LibError::InvalidStream)
                 }
             },
+            // alternative services are only advertised by servers, RFC 7838 section 4
+            Http2FrameType::AltSvc => {
+                if self.mode.is_server() { return Ok(None) }
+
+                // frames with an origin on a stream or without one on stream 0 are ignored
+                if let Some(alt) = Http2AltSvc::from(frame.get_payload()) && (frame.stream_id == 0) != alt.origin.is_empty() {
+                    self.alt_svc.lock().unwrap().push((frame.stream_id, alt));
+                }
+                Ok(None)
+            },
+            Http2FrameType::Origin => {
+                if self.mode.is_server() || frame.stream_id != 0 { return Ok(None) }
+
+                if let Some(origins) = origins_from(frame.get_payload()) {
+                    *self.origins.lock().unwrap() = Some(origins);
+                }
+                Ok(None)
+            },
             Http2FrameType::Invalid(_) => {
+                let extensions = self.extensions.lock().unwrap().clone();
+                for ext in extensions {
+                    if ext.handle_frame(&frame)? { return Ok(None) }
+                }
+
                 if self.strict {
                     Err(LibError::ProtocolError)
                 }
@@ -476,7 +1113,139 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
         }
     }
 
+    // promised streams are always even, whatever mode the session is in
+    pub fn open_push_stream(&self) -> Option<u32> {
+        if self.goaway.load(Ordering::SeqCst) || self.mode.is_client() || self.settings.lock().unwrap().enable_push == Some(0) { return None }
+
+        let mut max_id = self.max_stream_id.lock().unwrap();
+        let stream_id = if max_id.is_multiple_of(2) { *max_id + 2 } else { *max_id + 1 };
+
+        if stream_id <= MAX_STREAM_ID {
+            *max_id = stream_id;
+            Some(stream_id)
+        }
+        else {
+            None
+        }
+    }
+    pub fn promised_streams(&self, associate_id: u32) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.streams.iter().filter(|s| s.ascociated == Some(associate_id)).map(|s| s.stream_id).collect();
+        ids.sort();
+        ids
+    }
+
+    // rfc8441 3, lets the peer open extended CONNECT streams, servers send it after their preface
+    pub async fn enable_connect_protocol(&self) -> io::Result<()> {
+        self.send_settings(Http2Settings { enable_connect_protocol: Some(1), ..Http2Settings::empty() }).await
+    }
+    // whether the peer allows extended CONNECT, needed before sending a :protocol
+    #[inline]
+    pub fn connect_protocol_enabled(&self) -> bool {
+        self.settings.lock().unwrap().enable_connect_protocol == Some(1)
+    }
+
+    // rfc9113 10.5.1, a header list over our limit only costs its stream
+    // requests get a 431, responses, trailers and pushes are cancelled
+    pub fn refuse_header_list(&self, shard: &mut Http2Data) {
+        shard.head.clear();
+        shard.promise.clear();
+        shard.trailing = false;
+        shard.reset = true;
+
+        if shard.new && !self.mode.is_client() && !shard.self_end_head {
+            shard.self_end_head = true;
+            shard.self_end_body = true;
+            shard.reset_code = Some(Http2ErrorCode::NoError);
+            self.queue_frame(Http2FrameType::Headers, 5, shard.stream_id, None, Some(&HEADER_LIST_TOO_LARGE), None);
+            // rfc9113 8.1, the rest of the request is not wanted
+            if !shard.end_body { self.queue_frame(Http2FrameType::RstStream, 0, shard.stream_id, None, Some(&u32::to_be_bytes(Http2ErrorCode::NoError.into())), None); }
+        }
+        else {
+            shard.reset_code = Some(Http2ErrorCode::Cancel);
+            self.queue_frame(Http2FrameType::RstStream, 0, shard.stream_id, None, Some(&u32::to_be_bytes(Http2ErrorCode::Cancel.into())), None);
+        }
+
+        shard.notify.notify_waiters();
+        shard.head_complete.notify_waiters();
+        shard.body_received.notify_waiters();
+        self.stream_closed.notify_waiters();
+    }
+
+    // the stream window we advertised, 65535 until our SETTINGS say otherwise
+    pub fn own_initial_window(&self) -> usize {
+        self.own_settings.lock().unwrap().and_then(|s| s.initial_window_size).unwrap_or(65535) as usize
+    }
+
+    pub fn header_block_limit(&self) -> usize {
+        let own = self.own_settings.lock().unwrap().and_then(|s| s.max_header_list_size);
+        own.map(|o| min(o, self.limits.max_header_block_size)).unwrap_or(self.limits.max_header_block_size) as usize
+    }
+    // the same for the decoded list, which can be a lot larger than the block
+    pub fn header_list_limit(&self) -> usize {
+        let own = self.own_settings.lock().unwrap().and_then(|s| s.max_header_list_size);
+        own.map(|o| min(o, self.limits.max_header_list_size)).unwrap_or(self.limits.max_header_list_size) as usize
+    }
+
+    // rapid reset (CVE-2023-44487), continuation flood and control frame floods
+    pub fn exceeds_limits(&self, frame: &Http2Frame<'_>) -> bool {
+        let pay = frame.get_payload();
+        
+        let (reset, control) = match frame.ftype {
+            Http2FrameType::RstStream => (true, false),
+            Http2FrameType::Ping | Http2FrameType::Settings => (false, !frame.is_ack()),
+            Http2FrameType::Priority | Http2FrameType::PriorityUpdate => (false, true),
+            Http2FrameType::AltSvc | Http2FrameType::Origin | Http2FrameType::Invalid(_) => (false, true),
+            Http2FrameType::Continuation => (false, pay.is_empty()),
+            Http2FrameType::Data => (false, pay.is_empty() && !frame.is_end_stream()),
+            _ => (false, false),
+        };
+
+        if reset || control {
+            let mut rates = self.rates.lock().unwrap();
+
+            if rates.since.elapsed() >= Duration::from_secs(1) {
+                *rates = Http2Rates::new();
+            }
+
+            if reset { rates.resets += 1 }
+            if control { rates.control += 1 }
+            
+            if rates.resets > self.limits.max_resets_per_sec || rates.control > self.limits.max_control_per_sec {
+                return true
+            }
+        }
+
+        let pending = match frame.ftype {
+            Http2FrameType::Headers => self.streams.get(&frame.stream_id).map(|s| s.head.len()).unwrap_or(0),
+            Http2FrameType::PushPromise => 0,
+            Http2FrameType::Continuation => {
+                match self.streams.get(&frame.stream_id) {
+                    Some(shard) => match shard.promising {
+                        Some(p) => { drop(shard); self.streams.get(&p).map(|s| s.promise.len()).unwrap_or(0) },
+                        None => shard.head.len(),
+                    },
+                    None => 0,
+                }
+            },
+            _ => return false,
+        };
+
+        pending + pay.len() > self.header_block_limit()
+    }
+    pub async fn enhance_your_calm(&self) -> LibError {
+        let last = *self.peer_stream_id.lock().unwrap();
+        let info = Http2Goaway { last_stream_id: last, code: Http2ErrorCode::EnhanceYourCalm, debug: Vec::new() };
+
+        *self.goaway_sent.lock().unwrap() = Some(last);
+        match self.send_goaway(last, info.code, b"").await {
+            Ok(_) => LibError::Goaway(info),
+            Err(e) => e.into(),
+        }
+    }
+
     pub fn open_stream(&self) -> Option<u32> {
+        if self.goaway.load(Ordering::SeqCst) { return None }
+
         let mut max_id = self.max_stream_id.lock().unwrap();
         let stream_id = 
         if self.mode.is_ambiguous() { 
@@ -502,15 +1271,74 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
 
 
 
+    pub fn queue_frame(&self, ftype: Http2FrameType, flags: u8, stream_id: u32, priority: Option<&[u8]>, payload: Option<&[u8]>, padding: Option<&[u8]>) -> u64 {
+        let mut out = self.output.lock().unwrap();
+        let len = out.buf.len();
+        Http2Frame::create_into(&mut out.buf, ftype, flags, stream_id, priority, payload, padding);
+        out.queued += (out.buf.len() - len) as u64;
+        out.queued
+    }
+    pub fn queue_raw(&self, bytes: &[u8]) -> u64 {
+        let mut out = self.output.lock().unwrap();
+        out.buf.extend_from_slice(bytes);
+        out.queued += bytes.len() as u64;
+        out.queued
+    }
+    // writes everything queued so far unless another task already got it out past `pos`
+    pub async fn flush_until(&self, pos: u64) -> io::Result<()> {
+        if self.output.lock().unwrap().flushed >= pos { return Ok(()) }
+        let mut netw = self.netw.lock().await;
+
+        let mut pending = {
+            let mut out = self.output.lock().unwrap();
+            if out.flushed >= pos { return Ok(()) }
+
+            let spare = std::mem::take(&mut out.spare);
+            Unflushed { output: &self.output, buf: std::mem::replace(&mut out.buf, spare), written: 0, max_spare: self.write_buffer_size * 4 }
+        };
+
+        // progress is counted per write, whatever is left goes back if this future errors or is dropped
+        while pending.written < pending.buf.len() {
+            let n = netw.write(&pending.buf[pending.written..]).await?;
+            if n == 0 { return Err(io::ErrorKind::WriteZero.into()) }
+
+            pending.written += n;
+            self.output.lock().unwrap().flushed += n as u64;
+        }
+        Ok(())
+    }
+    pub async fn flush(&self) -> io::Result<()> {
+        let pos = self.output.lock().unwrap().queued;
+        self.flush_until(pos).await
+    }
+    #[inline]
+    pub async fn write_raw(&self, bytes: &[u8]) -> io::Result<()> {
+        let pos = self.queue_raw(bytes);
+        self.flush_until(pos).await
+    }
+
     pub async fn write_frame(&self, ftype: Http2FrameType, flags: u8, stream_id: u32, priority: Option<&[u8]>, payload: Option<&[u8]>, padding: Option<&[u8]>) -> io::Result<()> {
-        self.netw.lock().await.write_all(&Http2Frame::create(ftype, flags, stream_id, priority, payload, padding)).await
+        let pos = self.queue_frame(ftype, flags, stream_id, priority, payload, padding);
+        self.flush_until(pos).await
+    }
+    // only written once the buffer is full or something else flushes
+    pub async fn write_frame_buffered(&self, ftype: Http2FrameType, flags: u8, stream_id: u32, priority: Option<&[u8]>, payload: Option<&[u8]>, padding: Option<&[u8]>) -> io::Result<()> {
+        let pos = self.queue_frame(ftype, flags, stream_id, priority, payload, padding);
+        let full = self.output.lock().unwrap().buf.len() >= self.write_buffer_size;
+        if full { self.flush_until(pos).await } else { Ok(()) }
     }
 
     pub async fn send_data(&self, stream_id: u32, end: bool, buf: &[u8]) -> LibResult<()> {
         // let mut stream = 
         let notify =
         if let Some(mut shard) = self.streams.get_mut(&stream_id) {
-            if shard.self_end_body || shard.reset {
+            if shard.refused {
+                return Err(LibError::Refused)
+            }
+            else if shard.reset {
+                return Err(LibError::ResetStream(shard.reset_code.unwrap_or(Http2ErrorCode::Cancel)))
+            }
+            else if shard.self_end_body {
                 return Err(LibError::StreamClosed)
             }
 
@@ -522,119 +1350,182 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
         };
 
         if buf.len() == 0 {
+            // also gets out a head queued by send_headers_buffered
             if end {
                 self.write_frame(Http2FrameType::Data, 1, stream_id, None, None, None).await?;
+                self.stream_closed.notify_waiters();
+            }
+            else {
+                self.flush().await?;
             }
             return Ok(());
         }
 
         let mut pos = 0;
-        let mut buff = vec![];
         let mfs = self.settings.lock().unwrap().max_frame_size.unwrap_or(16384) as usize;
-        // let mut window = self.window.lock().unwrap();
-        // let mut minim = min(mfs, min(*window, stream.window));
 
-        // while buf.len() - pos > minim {
         while buf.len() > pos {
-            let (max, ncws, nsws) =
+            let turn = self.write_turn(stream_id).await;
+
+            let (max, padding, ncws, nsws) =
             {
                 let mut window = self.window.lock().unwrap();
                 let mut stream = self.streams.get_mut(&stream_id).unwrap();
 
-                if stream.reset {
-                    return Err(LibError::ResetStream)
+                if stream.refused {
+                    return Err(LibError::Refused)
+                }
+                else if stream.reset {
+                    return Err(LibError::ResetStream(stream.reset_code.unwrap_or(Http2ErrorCode::Cancel)))
                 }
 
-                let max = min(buf.len() - pos, min(*window, stream.window));
-                *window -= max;
-                stream.window -= max;
-                // drop(window);
-                // drop(stream);
-                (max, *window, stream.window)
-            };
+                let avail = min(mfs, min(*window, stream.window));
+                // the pad length byte and the padding come out of the same windows as the data
+                let (max, padding) = match self.padding {
+                    Http2Padding::None => (min(buf.len() - pos, avail), None),
+                    _ if avail < 2 => (min(buf.len() - pos, avail), None),
+                    padding => {
+                        let max = min(buf.len() - pos, avail - 1);
+                        (max, Some(min(padding.padding_for(max), avail - 1 - max)))
+                    },
+                };
+                let used = max + padding.map(|p| p + 1).unwrap_or(0);
 
+                *window -= used;
+                stream.window -= used;
+                (max, padding, *window, stream.window)
+            };
 
             if max > 0 {
-                let chunks = max / mfs;
-                let rem = max % mfs;
+                let end_pos = pos + max;
+                let flags = if end && end_pos == buf.len() { 1 } else { 0 };
+                
+                // frames are only queued, the whole body goes out in as few writes as the buffer allows
+                let queued = self.queue_frame(Http2FrameType::Data, flags, stream_id, None, Some(&buf[pos..end_pos]), padding.map(|p| &PADDING[..p]));
+                pos = end_pos;
 
-                for _ in 0..chunks {
-                    let end_pos = pos + mfs;
-                    
-                    if end_pos == buf.len() {
-                        buff.append(&mut Http2Frame::create(Http2FrameType::Data, if end { 1 } else { 0 }, stream_id, None, Some(&buf[pos..end_pos]), None));
-                    }
-                    else {
-                        buff.append(&mut Http2Frame::create(Http2FrameType::Data, 0, stream_id, None, Some(&buf[pos..end_pos]), None));
-                    }
+                let full = self.output.lock().unwrap().buf.len() >= self.write_buffer_size;
+                if full { self.flush_until(queued).await?; }
+            }
 
-                    pos += mfs;
-                }
+            // the turn is given up between frames so other streams can interleave
+            // queueing never waits, so yield to let them ask for a turn
+            drop(turn);
+            if max > 0 && pos < buf.len() { tokio::task::yield_now().await; }
 
+            // whatever is queued has to reach the peer before it hands out more window
+            // the waiter is registered first, an update arriving during the flush is not missed
+            if max == 0 && (nsws == 0 || ncws == 0) {
+                let waiter = if nsws == 0 { notify.notified() } else { self.notify.notified() };
+                tokio::pin!(waiter);
+                waiter.as_mut().enable();
 
-                let end_pos = pos + rem;
+                self.flush().await?;
+                waiter.await;
+            }
+        }
 
-                if end_pos == buf.len() {
-                    buff.append(&mut Http2Frame::create(Http2FrameType::Data, if end { 1 } else { 0 }, stream_id, None, Some(&buf[pos..end_pos]), None));
-                }
-                else {
-                    buff.append(&mut Http2Frame::create(Http2FrameType::Data, 0, stream_id, None, Some(&buf[pos..end_pos]), None));
-                }
+        self.flush().await?;
+        if end { self.stream_closed.notify_waiters(); }
 
-                pos += rem;
-                
-                
-                self.netw.lock().await.write_all(&buff).await?;
-                buff.clear();
-            }
+        Ok(())
+    }
 
-            if nsws == 0 {
-                notify.notified().await;
-            }
-            else if ncws == 0 {
-                self.notify.notified().await;
+    pub async fn send_headers(&self, stream_id: u32, end: bool, headers: &[(&[u8], &[u8])]) -> LibResult<()> {
+        self.open_head(stream_id, end)?;
+        self.write_header_block(stream_id, end, headers, true).await?;
+
+        if end { self.stream_closed.notify_waiters(); }
+
+        Ok(())
+    }
+    // the head is only queued, the DATA that follows takes it out in the same write
+    pub async fn send_headers_buffered(&self, stream_id: u32, end: bool, headers: &[(&[u8], &[u8])]) -> LibResult<()> {
+        self.open_head(stream_id, end)?;
+        self.write_header_block(stream_id, end, headers, false).await?;
+
+        if end { self.stream_closed.notify_waiters(); }
+
+        Ok(())
+    }
+    fn open_head(&self, stream_id: u32, end: bool) -> LibResult<()> {
+        let mut shard = 
+        match self.streams.get_mut(&stream_id) {
+            // doing !self.mode.is_(oposite)() would be more optimized maybe
+            Some(s) if self.mode.is_server() || self.mode.is_ambiguous() => s,
+            None if self.goaway.load(Ordering::SeqCst) => {
+                let info = self.goaway_info.lock().unwrap().clone();
+                return Err(info.map(LibError::Goaway).unwrap_or(LibError::Refused))
+            },
+            None if self.mode.is_client() || self.mode.is_ambiguous() => {
+                let mut stream = Http2Data::empty(stream_id, *self.settings.lock().unwrap());
+                stream.new = false;
+
+                self.streams.insert(stream_id, stream);
+                self.streams.get_mut(&stream_id).unwrap()
             }
-            
+            _ => return Err(LibError::InvalidStream),
+        };
 
-            // window = self.window.lock().unwrap();
-            // stream = self.streams.get_mut(&stream_id).unwrap();
+        if shard.reset {
+            // e.g. a push the client already cancelled
+            return Err(LibError::ResetStream(shard.reset_code.unwrap_or(Http2ErrorCode::Cancel)))
+        }
+        else if shard.self_end_head || shard.self_end_body {
+            return Err(LibError::StreamClosed)
         }
 
+        shard.self_end_head = true;
+        shard.self_end_body = end;
+
         Ok(())
     }
+    // a 1xx response ahead of the final headers, rfc9113 8.1
+    pub async fn send_interim(&self, stream_id: u32, headers: &[(&[u8], &[u8])]) -> LibResult<()> {
+        {
+            let shard = self.streams.get(&stream_id).ok_or(LibError::InvalidStream)?;
 
-    pub async fn send_headers(&self, stream_id: u32, end: bool, headers: &[(&[u8], &[u8])]) -> LibResult<()> {
+            if shard.refused {
+                return Err(LibError::Refused)
+            }
+            else if shard.reset {
+                return Err(LibError::ResetStream(shard.reset_code.unwrap_or(Http2ErrorCode::Cancel)))
+            }
+            else if shard.self_end_head {
+                return Err(LibError::HeadersSent)
+            }
+        }
+
+        self.write_header_block(stream_id, false, headers, true).await
+    }
+    // trailing HEADERS always end the stream
+    pub async fn send_trailers(&self, stream_id: u32, trailers: &[(&[u8], &[u8])]) -> LibResult<()> {
         {
-            let mut shard = 
-            match self.streams.get_mut(&stream_id) {
-                // doing !self.mode.is_(oposite)() would be more optimized maybe
-                Some(s) if self.mode.is_server() || self.mode.is_ambiguous() => s,
-                None if self.mode.is_client() || self.mode.is_ambiguous() => {
-                    let mut stream = Http2Data::empty(stream_id, *self.settings.lock().unwrap());
-                    stream.new = false;
-
-                    self.streams.insert(stream_id, stream);
-                    self.streams.get_mut(&stream_id).unwrap()
-                }
-                _ => return Err(LibError::InvalidStream),
-            };
+            let mut shard = self.streams.get_mut(&stream_id).ok_or(LibError::InvalidStream)?;
 
-            if shard.self_end_head || shard.self_end_body {
+            if shard.refused {
+                return Err(LibError::Refused)
+            }
+            else if shard.reset {
+                return Err(LibError::ResetStream(shard.reset_code.unwrap_or(Http2ErrorCode::Cancel)))
+            }
+            else if !shard.self_end_head || shard.self_end_body {
                 return Err(LibError::StreamClosed)
             }
 
-            shard.self_end_head = true;
-            shard.self_end_body = end;
+            shard.self_end_body = true;
         }
 
+        self.write_header_block(stream_id, true, trailers, true).await?;
+        self.stream_closed.notify_waiters();
+
+        Ok(())
+    }
+    async fn write_header_block(&self, stream_id: u32, end: bool, headers: &[(&[u8], &[u8])], flush: bool) -> LibResult<()> {
         let mut hpacke = self.encoder.lock().await;
         let enc = {
             let mut buff = Vec::new();
-
-            for &(nam, val) in headers {
-                hpacke.encode(&mut buff, HeaderType::NotIndexed, nam, val, None)?;
-            }
-
+            hpacke.encode_block(&mut buff, headers)?;
             buff
         };
         let mfs = self.settings.lock().unwrap().max_frame_size.unwrap_or(16384) as usize;
@@ -644,10 +1535,15 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
 
         
         if enc.len() < mfs {
-            buff.append(&mut Http2Frame::create(Http2FrameType::Headers, if end { 5 } else { 4 }, stream_id, None, Some(&enc), None));
+            // only blocks that fit into a single frame get padded
+            let padding = match self.padding {
+                Http2Padding::None => None,
+                padding => Some(&PADDING[..min(padding.padding_for(enc.len()), mfs - 1 - enc.len())]),
+            };
+            Http2Frame::create_into(&mut buff, Http2FrameType::Headers, if end { 5 } else { 4 }, stream_id, None, Some(&enc), padding);
         }
         else {
-            buff.append(&mut Http2Frame::create(Http2FrameType::Headers, 0, stream_id, None, Some(&enc[pos..pos + mfs]), None));
+            Http2Frame::create_into(&mut buff, Http2FrameType::Headers, 0, stream_id, None, Some(&enc[pos..pos + mfs]), None);
             pos += mfs;
 
             let mut chunks = enc.len() / mfs;
@@ -658,16 +1554,20 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
             }
 
             for _ in 0..chunks {
-                buff.append(&mut Http2Frame::create(Http2FrameType::Continuation, 0, stream_id, None, Some(&enc[pos..pos + mfs]), None));
+                Http2Frame::create_into(&mut buff, Http2FrameType::Continuation, 0, stream_id, None, Some(&enc[pos..pos + mfs]), None);
                 pos += mfs;
             }
 
-            buff.append(&mut Http2Frame::create(Http2FrameType::Continuation, if end { 5 } else { 4 }, stream_id, None, Some(&enc[pos..]), None));
+            Http2Frame::create_into(&mut buff, Http2FrameType::Continuation, if end { 5 } else { 4 }, stream_id, None, Some(&enc[pos..]), None);
         }
 
-        self.netw.lock().await.write_all(&buff).await?;
+        // queued under the encoder lock, blocks reach the peer in the order they were encoded
+        let pos = self.queue_raw(&buff);
         drop(hpacke);
 
+        let full = self.output.lock().unwrap().buf.len() >= self.write_buffer_size;
+        if flush || full { self.flush_until(pos).await?; }
+
         Ok(())
     }
 
@@ -676,14 +1576,74 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
         self.write_frame(Http2FrameType::Priority, 0, stream_id, None, Some(&[(dependency >> 24) as u8, (dependency >> 16) as u8, (dependency >> 8) as u8, dependency as u8, weight]), None).await
     }
     
+    pub async fn send_priority_update(&self, stream_id: u32, priority: Http2Priority) -> io::Result<()> {
+        if let Some(mut shard) = self.streams.get_mut(&stream_id) {
+            shard.priority = priority;
+        }
+        self.write_frame(Http2FrameType::PriorityUpdate, 0, 0, None, Some(&priority.to_update(stream_id)), None).await
+    }
+
+    #[inline]
+    pub fn add_extension(&self, extension: impl Http2Extension + 'static) {
+        self.extensions.lock().unwrap().push(Arc::new(extension));
+    }
+    // frames of a type the session does not handle itself, mostly for extensions
+    pub async fn send_extension_frame(&self, ftype: u8, flags: u8, stream_id: u32, payload: &[u8]) -> io::Result<()> {
+        self.write_frame(Http2FrameType::Invalid(ftype), flags, stream_id, None, Some(payload), None).await
+    }
+    // an empty origin on a stream means the origin of that stream
+    pub async fn send_altsvc(&self, stream_id: u32, origin: &str, value: &str) -> io::Result<()> {
+        let alt = Http2AltSvc { origin: origin.as_bytes().to_vec(), value: value.as_bytes().to_vec() };
+        self.write_frame(Http2FrameType::AltSvc, 0, stream_id, None, Some(&alt.to_vec()), None).await
+    }
+    pub async fn send_origin(&self, origins: &[&str]) -> io::Result<()> {
+        self.write_frame(Http2FrameType::Origin, 0, 0, None, Some(&origins_to_vec(origins)), None).await
+    }
+    // whether a request for origin may be sent on this connection, per the ORIGIN frame if one was received
+    pub fn origin_allowed(&self, origin: &str) -> bool {
+        self.origins.lock().unwrap().as_ref().map(|o| o.iter().any(|x| x.eq_ignore_ascii_case(origin))).unwrap_or(true)
+    }
+    pub fn set_priority(&self, stream_id: u32, priority: Http2Priority) -> bool {
+        self.streams.get_mut(&stream_id).map(|mut s| s.priority = priority).is_some()
+    }
+    pub async fn write_turn(&self, stream_id: u32) -> Http2WriteTurn<'_> {
+        let priority = self.streams.get(&stream_id).map(|s| s.priority).unwrap_or_default();
+        self.scheduler.lock().unwrap().push(stream_id, priority);
+
+        let turn = Http2WriteTurn { scheduler: &self.scheduler, scheduled: &self.scheduled, stream_id };
+
+        loop {
+            let notified = self.scheduled.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let mut sched = self.scheduler.lock().unwrap();
+                if sched.active.is_none() && sched.next() == Some(stream_id) {
+                    sched.waiting.retain(|w| w.0 != stream_id);
+                    sched.active = Some(stream_id);
+                    return turn
+                }
+            }
+
+            notified.await;
+        }
+    }
+    
     #[inline]
-    pub async fn send_rst_stream(&self, stream_id: u32, code: u32) -> io::Result<()> { 
-        self.write_frame(Http2FrameType::RstStream, 0, stream_id, None, Some(&u32::to_be_bytes(code)), None).await
+    pub async fn send_rst_stream(&self, stream_id: u32, code: Http2ErrorCode) -> io::Result<()> { 
+        self.write_frame(Http2FrameType::RstStream, 0, stream_id, None, Some(&u32::to_be_bytes(code.into())), None).await
     }
 
     #[inline]
     pub async fn send_settings(&self, settings: Http2Settings) -> io::Result<()> { 
-        self.write_frame(Http2FrameType::Settings, 0, 0, None, Some(&settings.to_vec()), None).await
+        // queued under the same lock, so the order matches the one the ACKs come back in
+        let pos = {
+            let mut sent = self.sent_settings.lock().unwrap();
+            sent.push_back(settings);
+            self.queue_frame(Http2FrameType::Settings, 0, 0, None, Some(&settings.to_vec()), None)
+        };
+        self.flush_until(pos).await
     }
     
     pub async fn send_push_promise(&self, associate_id: u32, promise_id: u32, headers: &[(&[u8], &[u8])]) -> LibResult<()> {
@@ -699,7 +1659,10 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
                 Http2Data::empty(promise_id, *self.settings.lock().unwrap())
             };
 
+            // reserved (local), the peer never sends on a pushed stream
             stream.ascociated = Some(associate_id);
+            stream.end_head = true;
+            stream.end_body = true;
 
             self.streams.insert(promise_id, stream);
         }
@@ -707,11 +1670,7 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
         let mut hpacke = self.encoder.lock().await;
         let enc = {
             let mut buff = Vec::new();
-
-            for &(nam, val) in headers {
-                hpacke.encode(&mut buff, HeaderType::NotIndexed, nam, val, None)?;
-            }
-
+            hpacke.encode_block(&mut buff, headers)?;
             buff
         };
         let mfs = self.settings.lock().unwrap().max_frame_size.unwrap_or(16384) as usize;
@@ -725,14 +1684,14 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
             pay.extend_from_slice(&u32::to_be_bytes(promise_id));
             pay.extend_from_slice(&enc);
 
-            buff.append(&mut Http2Frame::create(Http2FrameType::PushPromise, 4, associate_id, None, Some(&pay), None));
+            Http2Frame::create_into(&mut buff, Http2FrameType::PushPromise, 4, associate_id, None, Some(&pay), None);
         }
         else {
             let mut pay = Vec::with_capacity(mfs);
             pay.extend_from_slice(&u32::to_be_bytes(promise_id));
             pay.extend_from_slice(&enc[pos..pos + mfs - 4]);
 
-            buff.append(&mut Http2Frame::create(Http2FrameType::PushPromise, 0, associate_id, None, Some(&pay), None));
+            Http2Frame::create_into(&mut buff, Http2FrameType::PushPromise, 0, associate_id, None, Some(&pay), None);
             pos += mfs - 4;
 
             let mut chunks = enc.len() / mfs;
@@ -743,14 +1702,14 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
             }
 
             for _ in 0..chunks {
-                buff.append(&mut Http2Frame::create(Http2FrameType::Continuation, 0, associate_id, None, Some(&enc[pos..pos + mfs]), None));
+                Http2Frame::create_into(&mut buff, Http2FrameType::Continuation, 0, associate_id, None, Some(&enc[pos..pos + mfs]), None);
                 pos += mfs;
             }
 
-            buff.append(&mut Http2Frame::create(Http2FrameType::Continuation, 4, associate_id, None, Some(&enc[pos..]), None));
+            Http2Frame::create_into(&mut buff, Http2FrameType::Continuation, 4, associate_id, None, Some(&enc[pos..]), None);
         }
 
-        self.netw.lock().await.write_all(&buff).await?;
+        self.write_raw(&buff).await?;
         drop(hpacke);
 
         Ok(())
@@ -761,11 +1720,11 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
         self.write_frame(Http2FrameType::Ping, if ack { 1 } else { 0 }, 0, None, Some(buf), None).await
     }
     
-    pub async fn send_goaway(&self, stream_id: u32, code: u32, buf: &[u8]) -> io::Result<()> {
+    pub async fn send_goaway(&self, stream_id: u32, code: Http2ErrorCode, buf: &[u8]) -> io::Result<()> {
         let mut pay = vec![];
         
         pay.extend_from_slice(&u32::to_be_bytes(stream_id));
-        pay.extend_from_slice(&u32::to_be_bytes(code));
+        pay.extend_from_slice(&u32::to_be_bytes(code.into()));
         pay.extend_from_slice(buf);
 
         self.write_frame(Http2FrameType::Goaway, 0, 0, None, Some(&pay), None).await
@@ -778,4 +1737,114 @@ impl<R: ReadStream, W: WriteStream> Http2Session<R, W> {
     
     // pub async fn send_continuation(&self, stream_id: u32) -> io::Result<()> { unimplemented!() } // no reason for this
 
-}
\ No newline at end of file
+
+    // false if the previous one is still unanswered, the ACK is only seen while something calls next()
+    pub async fn send_rtt_ping(&self) -> io::Result<bool> {
+        let payload = self.rtt.lock().unwrap().start();
+        match payload {
+            Some(payload) => self.send_ping(false, &payload).await.map(|_| true),
+            None => Ok(false),
+        }
+    }
+    #[inline]
+    pub fn smoothed_rtt(&self) -> Option<Duration> {
+        self.rtt.lock().unwrap().smoothed
+    }
+    // a receive window below the bandwidth delay product caps throughput, twice the last sample leaves room to grow
+    pub fn bdp_window(&self) -> Option<u32> {
+        let bdp = self.rtt.lock().unwrap().bdp;
+        if bdp == 0 { None }
+        else { Some(min(bdp.saturating_mul(2), MAX_WINDOW as usize).max(65535) as u32) }
+    }
+
+    // runs until the connection is torn down, next() has to keep running elsewhere for the ACKs to arrive
+    pub async fn keepalive(&self) -> LibResult<()> {
+        let Some(conf) = self.keepalive else { return Ok(()) };
+
+        loop {
+            if self.torn_down.load(Ordering::SeqCst) { return Err(LibError::ConnectionClosed) }
+
+            let idle = self.last_read.lock().unwrap().elapsed();
+            if idle < conf.interval {
+                tokio::time::sleep(conf.interval - idle).await;
+                continue;
+            }
+
+            let ack = self.ping_ack.notified();
+            self.send_rtt_ping().await?;
+            let sent = self.rtt.lock().unwrap().outstanding.map(|(_, at)| at).unwrap_or(Instant::now());
+
+            if tokio::time::timeout_at(sent + conf.timeout, ack).await.is_err() {
+                self.teardown(conf.timeout).await;
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "keepalive ping timed out").into())
+            }
+        }
+    }
+    // gives up on the connection, pending reads fail and every open stream is reset
+    pub async fn teardown(&self, timeout: Duration) {
+        self.goaway.store(true, Ordering::SeqCst);
+        self.torn_down.store(true, Ordering::SeqCst);
+        self.teardown.notify_waiters();
+
+        for mut shard in self.streams.iter_mut() {
+            if !shard.is_closed() {
+                shard.reset = true;
+                shard.reset_code = Some(Http2ErrorCode::Cancel);
+                shard.notify.notify_waiters();
+                shard.head_complete.notify_waiters();
+                shard.body_received.notify_waiters();
+            }
+        }
+        self.stream_closed.notify_waiters();
+
+        // a dead peer might never drain the socket, the goaway is best effort
+        let last = *self.peer_stream_id.lock().unwrap();
+        let _ = tokio::time::timeout(timeout, async {
+            self.send_goaway(last, Http2ErrorCode::NoError, b"").await?;
+            self.netw.lock().await.shutdown().await
+        }).await;
+    }
+
+    pub fn is_drained(&self) -> bool {
+        self.streams.iter().all(|shard| shard.is_closed())
+    }
+
+    // two phase goaway (rfc9113 6.8), requires something else to keep calling next() so the ping ack arrives
+    pub async fn shutdown(&self, code: Http2ErrorCode, timeout: Duration) -> LibResult<()> {
+        let deadline = Instant::now() + timeout;
+
+        *self.goaway_sent.lock().unwrap() = Some(MAX_STREAM_ID);
+        self.send_goaway(MAX_STREAM_ID, Http2ErrorCode::NoError, b"").await?;
+
+        let ack = self.shutdown_ack.notified();
+        self.send_ping(false, &SHUTDOWN_PING).await?;
+        let _ = tokio::time::timeout_at(deadline, ack).await;
+
+        // anything the peer opened before receiving the first goaway has arrived by now
+        let last = *self.peer_stream_id.lock().unwrap();
+        *self.goaway_sent.lock().unwrap() = Some(last);
+        self.send_goaway(last, code, b"").await?;
+
+        loop {
+            let closed = self.stream_closed.notified();
+            
+            if self.is_drained() { break }
+            if tokio::time::timeout_at(deadline, closed).await.is_err() { break }
+        }
+
+        self.netw.lock().await.shutdown().await?;
+        Ok(())
+    }
+
+}
+
+async fn fill_buffer<R: ReadStream>(netr: &mut R, input: &mut BytesMut, len: usize) -> io::Result<()> {
+    while input.len() < len {
+        // reclaims the allocation once every frame split off it was dropped
+        input.reserve(max(len - input.len(), READ_BUFFER_SIZE));
+        if netr.read_buf(input).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into())
+        }
+    }
+    Ok(())
+}
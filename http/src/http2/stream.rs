@@ -0,0 +1,230 @@
+use std::{cmp::min, io, pin::Pin, sync::{Arc, Mutex as SyncMutex}, task::{Context, Poll}};
+
+use tokio::{io::{AsyncRead, AsyncWrite, ReadBuf}, sync::futures::OwnedNotified};
+
+use crate::{http2::{core::Http2ErrorCode, session::Http2Session}, shared::{LibError, LibResult, ReadStream, WriteStream}};
+
+// keeps single writes within one default sized frame so the window applies per write
+pub const MAX_WRITE: usize = 16384;
+// consumed bytes before the reader hands the window back, capped at half the advertised window
+pub const WINDOW_THRESHOLD: usize = 32768;
+
+type WriteFuture = Pin<Box<dyn Future<Output = LibResult<usize>> + Send>>;
+type UpdateFuture = Pin<Box<dyn Future<Output = io::Result<()>> + Send>>;
+
+pub fn into_io(err: LibError) -> io::Error {
+    match err {
+        LibError::Io(e) => e,
+        LibError::ResetStream(code) => io::Error::new(io::ErrorKind::ConnectionReset, format!("stream reset ({code})")),
+        LibError::StreamClosed => io::Error::new(io::ErrorKind::BrokenPipe, "stream is closed"),
+        e => io::Error::other(e),
+    }
+}
+
+
+// the receiving half of a stream, DATA payloads are read until END_STREAM
+// someone still has to drive the session with next() for data to arrive
+// the stream window is only handed back once data is read, so a slow reader holds the sender back
+pub struct Http2StreamReader<R: ReadStream, W: WriteStream> {
+    pub stream_id: u32,
+    pub session: Arc<Http2Session<R, W>>,
+
+    waiter: Option<Pin<Box<OwnedNotified>>>,
+    consumed: usize,
+    update: Option<SyncMutex<UpdateFuture>>,
+}
+impl<R: ReadStream + 'static, W: WriteStream + 'static> Http2StreamReader<R, W> {
+    pub fn new(stream_id: u32, session: Arc<Http2Session<R, W>>) -> LibResult<Self> {
+        match session.streams.get_mut(&stream_id) {
+            Some(mut shard) => shard.manual_window = true,
+            None => return Err(LibError::InvalidStream),
+        }
+        Ok(Self { stream_id, session, waiter: None, consumed: 0, update: None })
+    }
+
+    fn poll_update(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        if let Some(fut) = &mut self.update && let Poll::Ready(res) = fut.get_mut().unwrap().as_mut().poll(cx) {
+            self.update = None;
+            res?;
+        }
+        // a small initial window would otherwise never be refilled before the sender stalls
+        let threshold = min(WINDOW_THRESHOLD, self.session.own_initial_window() / 2).max(1);
+        if self.update.is_none() && self.consumed >= threshold {
+            let session = self.session.clone();
+            let (stream_id, size) = (self.stream_id, self.consumed as u32);
+            self.consumed = 0;
+
+            let mut fut: UpdateFuture = Box::pin(async move { session.send_window_update(stream_id, size).await });
+            // the frame is usually written right away, otherwise the next read drives it
+            if let Poll::Ready(res) = fut.as_mut().poll(cx) { res?; }
+            else { self.update = Some(SyncMutex::new(fut)); }
+        }
+        Ok(())
+    }
+}
+impl<R: ReadStream + 'static, W: WriteStream + 'static> AsyncRead for Http2StreamReader<R, W> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.poll_update(cx)?;
+
+        loop {
+            {
+                let mut shard = match this.session.streams.get_mut(&this.stream_id) {
+                    Some(shard) => shard,
+                    None => return Poll::Ready(Err(into_io(LibError::InvalidStream))),
+                };
+
+                if !shard.body.is_empty() {
+                    let len = shard.body.read_into(buf);
+                    let end = shard.end_body;
+                    drop(shard);
+
+                    this.waiter = None;
+                    if !end { this.consumed += len; }
+                    return Poll::Ready(this.poll_update(cx))
+                }
+                else if shard.reset {
+                    return Poll::Ready(Err(into_io(LibError::ResetStream(shard.reset_code.unwrap_or(Http2ErrorCode::Cancel)))))
+                }
+                else if shard.end_body {
+                    return Poll::Ready(Ok(()))
+                }
+                else if this.waiter.is_none() {
+                    // registered before checking again, a notify in between is not lost
+                    let mut waiter = Box::pin(shard.body_received.clone().notified_owned());
+                    waiter.as_mut().enable();
+                    this.waiter = Some(waiter);
+                    continue;
+                }
+            }
+
+            match this.waiter.as_mut().map(|w| w.as_mut().poll(cx)) {
+                Some(Poll::Pending) => return Poll::Pending,
+                _ => this.waiter = None,
+            }
+        }
+    }
+}
+
+
+// the sending half of a stream, writes become DATA frames and shutdown sends END_STREAM
+pub struct Http2StreamWriter<R: ReadStream, W: WriteStream> {
+    pub stream_id: u32,
+    pub session: Arc<Http2Session<R, W>>,
+
+    // only ever touched through &mut, the mutex just makes the writer Sync
+    pending: Option<SyncMutex<WriteFuture>>,
+    closed: bool,
+}
+impl<R: ReadStream + 'static, W: WriteStream + 'static> Http2StreamWriter<R, W> {
+    pub fn new(stream_id: u32, session: Arc<Http2Session<R, W>>) -> LibResult<Self> {
+        if session.streams.contains_key(&stream_id) {
+            Ok(Self { stream_id, session, pending: None, closed: false })
+        }
+        else {
+            Err(LibError::InvalidStream)
+        }
+    }
+
+    fn start(&mut self, end: bool, buf: Vec<u8>) {
+        let session = self.session.clone();
+        let stream_id = self.stream_id;
+
+        self.pending = Some(SyncMutex::new(Box::pin(async move {
+            session.send_data(stream_id, end, &buf).await?;
+            Ok(buf.len())
+        })));
+    }
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<LibResult<usize>> {
+        let res = match &mut self.pending {
+            Some(fut) => match fut.get_mut().unwrap().as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(res) => res,
+            },
+            None => Ok(0),
+        };
+        self.pending = None;
+        Poll::Ready(res)
+    }
+}
+impl<R: ReadStream + 'static, W: WriteStream + 'static> AsyncWrite for Http2StreamWriter<R, W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if this.closed {
+            return Poll::Ready(Err(into_io(LibError::StreamClosed)))
+        }
+        // the previous write has to finish first, its error is reported here
+        if let Err(e) = std::task::ready!(this.poll_pending(cx)) {
+            return Poll::Ready(Err(into_io(e)))
+        }
+        if buf.is_empty() { return Poll::Ready(Ok(0)) }
+
+        // the bytes are copied, so they count as written even if the frame is still queued
+        // the caller may pass a different buffer next time, flush waits for this one
+        let len = buf.len().min(MAX_WRITE);
+        this.start(false, buf[..len].to_vec());
+        if let Poll::Ready(Err(e)) = this.poll_pending(cx) {
+            return Poll::Ready(Err(into_io(e)))
+        }
+        Poll::Ready(Ok(len))
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending(cx).map(|res| res.map(|_| ()).map_err(into_io))
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if !this.closed {
+            if let Err(e) = std::task::ready!(this.poll_pending(cx)) {
+                return Poll::Ready(Err(into_io(e)))
+            }
+            this.closed = true;
+            this.start(true, Vec::new());
+        }
+        this.poll_pending(cx).map(|res| res.map(|_| ()).map_err(into_io))
+    }
+}
+
+
+// both halves of a stream as one Stream, e.g. for CONNECT tunnels or HTTP/1 inside HTTP/2
+pub struct Http2Stream<R: ReadStream, W: WriteStream> {
+    pub reader: Http2StreamReader<R, W>,
+    pub writer: Http2StreamWriter<R, W>,
+}
+impl<R: ReadStream + 'static, W: WriteStream + 'static> Http2Stream<R, W> {
+    pub fn new(stream_id: u32, session: Arc<Http2Session<R, W>>) -> LibResult<Self> {
+        Ok(Self {
+            reader: Http2StreamReader::new(stream_id, session.clone())?,
+            writer: Http2StreamWriter::new(stream_id, session)?,
+        })
+    }
+    #[inline]
+    pub fn stream_id(&self) -> u32 {
+        self.reader.stream_id
+    }
+    #[inline]
+    pub fn into_split(self) -> (Http2StreamReader<R, W>, Http2StreamWriter<R, W>) {
+        (self.reader, self.writer)
+    }
+}
+impl<R: ReadStream + 'static, W: WriteStream + 'static> AsyncRead for Http2Stream<R, W> {
+    #[inline]
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().reader).poll_read(cx, buf)
+    }
+}
+impl<R: ReadStream + 'static, W: WriteStream + 'static> AsyncWrite for Http2Stream<R, W> {
+    #[inline]
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().writer).poll_write(cx, buf)
+    }
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().writer).poll_flush(cx)
+    }
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().writer).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,247 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::{http3::{core::{Http3Frame, Http3FrameType}, session::{Http3ReadBuffer, Http3Session}, transport::{Http3SendStream, Http3Transport}}, shared::{HttpMethod, HttpRequest, HttpResponse, HttpType, LibError, LibResult, string_from_owned_utf8}};
+
+
+// a request stream we opened, the session has to be driven by next() meanwhile
+pub struct Http3Request<T: Http3Transport> {
+    pub stream_id: u64,
+    pub session: Arc<Http3Session<T>>,
+
+    pub recv: T::Recv,
+    pub send: T::Send,
+    // what was read of the stream that was not handed out yet
+    pub input: Http3ReadBuffer,
+
+    pub path: String,
+    pub method: HttpMethod,
+    pub authority: String,
+    pub scheme: String,
+    // sent as :protocol, turns a CONNECT into an extended CONNECT
+    pub protocol: Option<String>,
+
+    pub headers: HashMap<String, Vec<String>>,
+
+    pub sent_head: bool,
+    pub sent: bool,
+
+    pub response: HttpResponse,
+    pub trailers: HashMap<String, Vec<String>>,
+}
+impl<T: Http3Transport> Http3Request<T> {
+    pub fn new(session: Arc<Http3Session<T>>, recv: T::Recv, send: T::Send) -> Self {
+        Self {
+            stream_id: send.stream_id(),
+            session, recv, send,
+            input: Http3ReadBuffer::new(),
+            path: "/".to_owned(),
+            method: HttpMethod::Get,
+            authority: String::new(),
+            scheme: "https".to_owned(),
+            protocol: None,
+            headers: HashMap::new(),
+            sent_head: false,
+            sent: false,
+            response: HttpResponse::default_h3(),
+            trailers: HashMap::new(),
+        }
+    }
+    // opens a new request stream, refused once the server sent GOAWAY
+    pub async fn open(session: Arc<Http3Session<T>>) -> LibResult<Self> {
+        let (recv, send) = session.open_request().await?;
+        Ok(Self::new(session, recv, send))
+    }
+
+    pub fn add_header(&mut self, header: &str, value: &str) {
+        if let Some(hs) = self.headers.get_mut(header) { hs.push(value.to_owned()); }
+        else { self.headers.insert(header.to_owned(), vec![ value.to_owned() ]); }
+    }
+    pub fn set_header(&mut self, header: &str, value: &str){
+        self.headers.insert(header.to_owned(), vec![ value.to_owned() ]);
+    }
+    pub fn del_header(&mut self, header: &str) -> Option<Vec<String>>{
+        self.headers.remove(header)
+    }
+
+    pub async fn send_head(&mut self, end: bool) -> LibResult<()> {
+        if !self.sent_head {
+            self.sent_head = true;
+            let mut headers = vec![
+                (b":method".to_vec(), match &self.method { HttpMethod::Unknown(Some(s)) => s.as_bytes().to_vec(), v => v.to_string().into_bytes()}),
+                (b":scheme".to_vec(), self.scheme.as_bytes().to_vec()),
+                (b":path".to_vec(), self.path.as_bytes().to_vec()),
+            ];
+            // 4.3.1, without a host there is no :authority rather than an empty one
+            if !self.authority.is_empty() {
+                headers.push((b":authority".to_vec(), self.authority.as_bytes().to_vec()));
+            }
+            if let Some(protocol) = &self.protocol {
+                headers.push((b":protocol".to_vec(), protocol.as_bytes().to_vec()));
+            }
+
+            for (header, values) in self.headers.drain(){
+                for value in values {
+                    headers.push((header.clone().into_bytes(), value.into_bytes()));
+                }
+            }
+
+            let head = headers.iter().map(|(h, v)| (h.as_slice(), v.as_slice())).collect::<Vec<(&[u8], &[u8])>>();
+            let frame = self.session.encode_headers(self.stream_id, &head).await?;
+            self.send.write_all(&frame).await?;
+            if end { self.finish().await }
+            else { Ok(()) }
+        }
+        else {
+            Err(LibError::HeadersSent)
+        }
+    }
+    pub async fn write(&mut self, buf: &[u8]) -> LibResult<()> {
+        if self.sent {
+            return Err(LibError::StreamClosed)
+        }
+        if !self.sent_head {
+            self.send_head(false).await?;
+        }
+        if !buf.is_empty() {
+            self.send.write_all(&Http3Frame::create_head(Http3FrameType::Data, buf.len())).await?;
+            self.send.write_all(buf).await?;
+        }
+        Ok(())
+    }
+    pub async fn send(&mut self, buf: &[u8]) -> LibResult<()> {
+        if !self.sent_head {
+            self.set_header("content-length", &buf.len().to_string());
+            self.send_head(false).await?;
+        }
+        self.write(buf).await?;
+        self.finish().await
+    }
+    // ends the stream with a trailing HEADERS frame
+    pub async fn send_trailers(&mut self, trailers: &[(&str, &str)]) -> LibResult<()> {
+        if self.sent {
+            return Err(LibError::StreamClosed)
+        }
+        if !self.sent_head {
+            self.send_head(false).await?;
+        }
+        let trailers = trailers.iter().map(|(h, v)| (h.as_bytes(), v.as_bytes())).collect::<Vec<(&[u8], &[u8])>>();
+        let frame = self.session.encode_headers(self.stream_id, &trailers).await?;
+        self.send.write_all(&frame).await?;
+        self.finish().await
+    }
+    async fn finish(&mut self) -> LibResult<()> {
+        self.sent = true;
+        Ok(self.send.shutdown().await?)
+    }
+    #[inline]
+    pub async fn flush(&mut self) -> LibResult<()> {
+        Ok(self.send.flush().await?)
+    }
+
+    pub async fn read_response(&mut self) -> LibResult<&HttpResponse> {
+        if !self.response.head_complete {
+            let headers = self.session.read_message_head(self.stream_id, &mut self.recv, &mut self.input).await?;
+
+            let mut response = HttpResponse::default_h3();
+            for (h, v) in headers {
+                let header = string_from_owned_utf8(h);
+                let value = string_from_owned_utf8(v);
+
+                if header == ":status" {
+                    response.code = value.parse().unwrap_or(0);
+                }
+
+                else if let Some(values) = response.headers.get_mut(&header) {
+                    values.push(value)
+                }
+                else {
+                    response.headers.insert(header, vec![value]);
+                }
+            }
+
+            // 4.1, interim responses come before the final one and are dropped
+            if !(100..200).contains(&response.code) {
+                response.head_complete = true;
+                self.response = response;
+            }
+        }
+        else if !self.response.body_complete {
+            match self.session.read_message_frame(&mut self.recv, &mut self.input).await? {
+                Some(frame) if frame.ftype == Http3FrameType::Data => self.response.body.extend_from_slice(&frame.payload),
+                // 4.1, trailers end the message, whatever follows them is not read
+                Some(frame) => {
+                    for (h, v) in self.session.decode_headers(self.stream_id, &frame.payload).await? {
+                        let header = string_from_owned_utf8(h);
+                        let value = string_from_owned_utf8(v);
+
+                        if let Some(values) = self.trailers.get_mut(&header) { values.push(value) }
+                        else { self.trailers.insert(header, vec![value]); }
+                    }
+                    self.response.body_complete = true;
+                },
+                None => self.response.body_complete = true,
+            }
+        }
+
+        Ok(&self.response)
+    }
+    pub async fn read_until_complete(&mut self) -> LibResult<&HttpResponse> {
+        while !self.response.body_complete {
+            self.read_response().await?;
+        }
+        Ok(&self.response)
+    }
+    pub async fn read_until_head_complete(&mut self) -> LibResult<&HttpResponse> {
+        while !self.response.head_complete {
+            self.read_response().await?;
+        }
+        Ok(&self.response)
+    }
+}
+impl<T: Http3Transport> HttpRequest for Http3Request<T> {
+    #[inline]
+    fn get_type(&self) -> HttpType {
+        HttpType::Http3
+    }
+
+    #[inline] fn add_header(&mut self, header: &str, value: &str) { self.add_header(&header.to_lowercase(), value) }
+    #[inline] fn set_header(&mut self, header: &str, value: &str) { self.set_header(&header.to_lowercase(), value) }
+    #[inline] fn del_header(&mut self, header: &str) -> Option<Vec<String>> { self.del_header(&header.to_lowercase()) }
+
+    #[inline] fn set_method(&mut self, method: HttpMethod) { self.method = method }
+    #[inline] fn set_scheme(&mut self, scheme: String) { self.scheme = scheme }
+    #[inline] fn set_path(&mut self, path: String) { self.path = path }
+    #[inline] fn set_host(&mut self, host: String) { self.authority = host }
+
+    #[inline]
+    fn write<'a>(&'a mut self, body: &'a [u8]) -> impl Future<Output = Result<(), LibError>> + Send + 'a {
+        self.write(body)
+    }
+    #[inline]
+    fn send<'a>(&'a mut self, body: &'a [u8]) -> impl Future<Output = Result<(), LibError>> + Send + 'a {
+        self.send(body)
+    }
+    #[inline]
+    fn flush<'a>(&'a mut self) -> impl Future<Output = Result<(), LibError>> + Send + 'a {
+        self.flush()
+    }
+
+    #[inline]
+    fn get_response(&self) -> &HttpResponse {
+        &self.response
+    }
+    #[inline]
+    fn read_response<'_a>(&'_a mut self) -> impl Future<Output = Result<&'_a HttpResponse, LibError>> + Send + '_a {
+        self.read_response()
+    }
+    #[inline]
+    fn read_until_complete<'_a>(&'_a mut self) -> impl Future<Output = Result<&'_a HttpResponse, LibError>> + Send + '_a {
+        self.read_until_complete()
+    }
+    #[inline]
+    fn read_until_head_complete<'_a>(&'_a mut self) -> impl Future<Output = Result<&'_a HttpResponse, LibError>> + Send + '_a {
+        self.read_until_head_complete()
+    }
+}
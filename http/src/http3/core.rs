@@ -0,0 +1,427 @@
+use std::{fmt::Display, io};
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::AsyncReadExt;
+
+use crate::shared::ReadStream;
+
+// https://datatracker.ietf.org/doc/html/rfc9114
+
+pub const MAX_VARINT: u64 = (1 << 62) - 1;
+
+
+// rfc9000 16, the two high bits of the first byte give the length
+pub fn varint_len(value: u64) -> usize {
+    if value < 1 << 6 { 1 }
+    else if value < 1 << 14 { 2 }
+    else if value < 1 << 30 { 4 }
+    else { 8 }
+}
+// values above MAX_VARINT can not be encoded, they are cut to 62 bits
+pub fn write_varint(buf: &mut Vec<u8>, value: u64) {
+    match varint_len(value) {
+        1 => buf.push(value as u8),
+        2 => buf.extend_from_slice(&(value as u16 | 0x4000).to_be_bytes()),
+        4 => buf.extend_from_slice(&(value as u32 | 0x8000_0000).to_be_bytes()),
+        _ => buf.extend_from_slice(&(value & MAX_VARINT | 0xc000_0000_0000_0000).to_be_bytes()),
+    }
+}
+pub fn read_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let first = *buf.get(*pos)?;
+    let len = 1 << (first >> 6);
+    let bytes = buf.get(*pos..*pos + len)?;
+
+    let mut value = (first & 0x3f) as u64;
+    for b in &bytes[1..] {
+        value = (value << 8) | *b as u64;
+    }
+
+    *pos += len;
+    Some(value)
+}
+pub async fn read_varint_from<R: ReadStream>(stream: &mut R) -> io::Result<u64> {
+    let first = stream.read_u8().await?;
+    let mut value = (first & 0x3f) as u64;
+
+    for _ in 1..1 << (first >> 6) {
+        value = (value << 8) | stream.read_u8().await? as u64;
+    }
+    Ok(value)
+}
+
+// 7.2.8, 7.2.4.1 and 6.2.3, reserved ids of the form 0x1f * N + 0x21 that have to be ignored
+pub fn is_grease(value: u64) -> bool {
+    value >= 0x21 && (value - 0x21).is_multiple_of(0x1f)
+}
+pub fn random_grease() -> u64 {
+    0x1f * rand::random_range(0..0x1000u64) + 0x21
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Http3FrameType {
+    Data,
+    Headers,
+    CancelPush,
+    Settings,
+    PushPromise,
+    Goaway,
+    MaxPushId,
+
+    // grease, extensions and the http/2 types 7.2.8 forbids
+    Unknown(u64),
+}
+impl Http3FrameType {
+    // 7.2.8, PRIORITY, PING, WINDOW_UPDATE and CONTINUATION have no meaning here and are an error
+    pub fn is_http2_only(&self) -> bool {
+        if let Self::Unknown(0x02 | 0x06 | 0x08 | 0x09) = self { true }
+        else { false }
+    }
+    pub fn is_unknown(&self) -> bool {
+        if let Self::Unknown(_) = self { true }
+        else { false }
+    }
+}
+impl From<u64> for Http3FrameType {
+    fn from(value: u64) -> Self {
+        match value {
+            0x00 => Self::Data,
+            0x01 => Self::Headers,
+            0x03 => Self::CancelPush,
+            0x04 => Self::Settings,
+            0x05 => Self::PushPromise,
+            0x07 => Self::Goaway,
+            0x0d => Self::MaxPushId,
+
+            v => Self::Unknown(v),
+        }
+    }
+}
+impl Into<u64> for Http3FrameType {
+    fn into(self) -> u64 {
+        match self {
+            Self::Data => 0x00,
+            Self::Headers => 0x01,
+            Self::CancelPush => 0x03,
+            Self::Settings => 0x04,
+            Self::PushPromise => 0x05,
+            Self::Goaway => 0x07,
+            Self::MaxPushId => 0x0d,
+
+            Self::Unknown(v) => v,
+        }
+    }
+}
+
+// 7.1, a type and a length as varints in front of the payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http3Frame {
+    pub ftype: Http3FrameType,
+    pub payload: Bytes,
+}
+impl Http3Frame {
+    // (frame, bytes used), None while the frame is not complete
+    pub fn from(buf: &[u8]) -> Option<(Self, usize)> {
+        let mut pos = 0;
+        let ftype = read_varint(buf, &mut pos)?.into();
+        let length = read_varint(buf, &mut pos)?;
+
+        if length > (buf.len() - pos) as u64 { return None }
+        let end = pos + length as usize;
+
+        Some((Self { ftype, payload: Bytes::copy_from_slice(&buf[pos..end]) }, end))
+    }
+    // splits a whole frame off the buffer without copying, frames with a payload over max_size are refused early
+    pub fn split_from(buf: &mut BytesMut, max_size: u64) -> Result<Option<Self>, Http3ErrorCode> {
+        let mut pos = 0;
+        let Some(ftype) = read_varint(buf, &mut pos) else { return Ok(None) };
+        let Some(length) = read_varint(buf, &mut pos) else { return Ok(None) };
+
+        if length > max_size { return Err(Http3ErrorCode::ExcessiveLoad) }
+        if length > (buf.len() - pos) as u64 { return Ok(None) }
+
+        buf.advance(pos);
+        Ok(Some(Self { ftype: ftype.into(), payload: buf.split_to(length as usize).freeze() }))
+    }
+    // takes the type and length of a DATA frame off the buffer and gives the length, its payload is read in pieces
+    pub fn split_data_head(buf: &mut BytesMut) -> Option<u64> {
+        let mut pos = 0;
+        let ftype = read_varint(buf, &mut pos)?;
+        let length = read_varint(buf, &mut pos)?;
+
+        if Http3FrameType::from(ftype) != Http3FrameType::Data { return None }
+
+        buf.advance(pos);
+        Some(length)
+    }
+
+    pub fn create(ftype: impl Into<u64>, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(payload.len() + 16);
+        Self::create_into(&mut frame, ftype, payload);
+        frame
+    }
+    pub fn create_into(frame: &mut Vec<u8>, ftype: impl Into<u64>, payload: &[u8]) {
+        write_varint(frame, ftype.into());
+        write_varint(frame, payload.len() as u64);
+        frame.extend_from_slice(payload);
+    }
+    // only type and length, for DATA written straight from the caller's buffer
+    pub fn create_head(ftype: impl Into<u64>, length: usize) -> Vec<u8> {
+        let mut head = Vec::with_capacity(16);
+        write_varint(&mut head, ftype.into());
+        write_varint(&mut head, length as u64);
+        head
+    }
+
+    // GOAWAY, MAX_PUSH_ID and CANCEL_PUSH carry a single varint
+    pub fn varint_payload(&self) -> Option<u64> {
+        let mut pos = 0;
+        let value = read_varint(&self.payload, &mut pos)?;
+        (pos == self.payload.len()).then_some(value)
+    }
+    pub fn create_varint(ftype: impl Into<u64>, value: u64) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(8);
+        write_varint(&mut payload, value);
+        Self::create(ftype, &payload)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http3Settings {
+    pub qpack_max_table_capacity: Option<u64>, // 0x01, rfc9204
+    pub max_field_section_size: Option<u64>,   // 0x06
+    pub qpack_blocked_streams: Option<u64>,    // 0x07, rfc9204
+    pub enable_connect_protocol: Option<u64>,  // 0x08, rfc9220
+    pub h3_datagram: Option<u64>,              // 0x33, rfc9297
+    // every other id, in the order received
+    pub unknown: Vec<(u64, u64)>,
+}
+impl Http3Settings {
+    pub const fn empty() -> Self {
+        Self {
+            qpack_max_table_capacity: None,
+            max_field_section_size: None,
+            qpack_blocked_streams: None,
+            enable_connect_protocol: None,
+            h3_datagram: None,
+            unknown: Vec::new(),
+        }
+    }
+    pub const fn default() -> Self {
+        Self {
+            qpack_max_table_capacity: Some(4096),
+            max_field_section_size: Some(262144),
+            qpack_blocked_streams: Some(16),
+            enable_connect_protocol: None,
+            h3_datagram: None,
+            unknown: Vec::new(),
+        }
+    }
+
+    // 7.2.4, repeated ids and the ones left over from http/2 are a H3_SETTINGS_ERROR
+    pub fn from(buf: &[u8]) -> Result<Self, Http3ErrorCode> {
+        let mut sett = Self::empty();
+        let mut seen = Vec::new();
+        let mut pos = 0;
+
+        while pos < buf.len() {
+            let id = read_varint(buf, &mut pos).ok_or(Http3ErrorCode::FrameError)?;
+            let val = read_varint(buf, &mut pos).ok_or(Http3ErrorCode::FrameError)?;
+
+            if seen.contains(&id) || matches!(id, 0x02..=0x05) { return Err(Http3ErrorCode::SettingsError) }
+            seen.push(id);
+
+            if id == 0x01 { sett.qpack_max_table_capacity = Some(val) }
+            else if id == 0x06 { sett.max_field_section_size = Some(val) }
+            else if id == 0x07 { sett.qpack_blocked_streams = Some(val) }
+            else if id == 0x08 { sett.enable_connect_protocol = Some(val) }
+            else if id == 0x33 { sett.h3_datagram = Some(val) }
+            else { sett.unknown.push((id, val)) }
+        }
+
+        Ok(sett)
+    }
+
+    pub fn get_unknown(&self, id: u64) -> Option<u64> {
+        self.unknown.iter().find(|(i, _)| *i == id).map(|(_, v)| *v)
+    }
+    pub fn set_unknown(&mut self, id: u64, val: u64) {
+        match self.unknown.iter_mut().find(|(i, _)| *i == id) {
+            Some(setting) => setting.1 = val,
+            None => self.unknown.push((id, val)),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut res = vec![];
+        let known = [
+            (0x01, self.qpack_max_table_capacity),
+            (0x06, self.max_field_section_size),
+            (0x07, self.qpack_blocked_streams),
+            (0x08, self.enable_connect_protocol),
+            (0x33, self.h3_datagram),
+        ];
+
+        for (id, val) in known.into_iter().filter_map(|(id, val)| Some((id, val?))).chain(self.unknown.iter().copied()) {
+            write_varint(&mut res, id);
+            write_varint(&mut res, val);
+        }
+
+        res
+    }
+}
+impl Default for Http3Settings {
+    #[inline]
+    fn default() -> Self {
+        Self::default()
+    }
+}
+
+
+// 6.2, the first varint of a unidirectional stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Http3StreamType {
+    Control,
+    Push,
+    QpackEncoder, // rfc9204 4.2
+    QpackDecoder,
+
+    Unknown(u64),
+}
+impl From<u64> for Http3StreamType {
+    fn from(value: u64) -> Self {
+        match value {
+            0x00 => Self::Control,
+            0x01 => Self::Push,
+            0x02 => Self::QpackEncoder,
+            0x03 => Self::QpackDecoder,
+
+            v => Self::Unknown(v),
+        }
+    }
+}
+impl Into<u64> for Http3StreamType {
+    fn into(self) -> u64 {
+        match self {
+            Self::Control => 0x00,
+            Self::Push => 0x01,
+            Self::QpackEncoder => 0x02,
+            Self::QpackDecoder => 0x03,
+
+            Self::Unknown(v) => v,
+        }
+    }
+}
+
+
+// 8.1 and rfc9204 6
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Http3ErrorCode {
+    NoError,
+    GeneralProtocolError,
+    InternalError,
+    StreamCreationError,
+    ClosedCriticalStream,
+    FrameUnexpected,
+    FrameError,
+    ExcessiveLoad,
+    IdError,
+    SettingsError,
+    MissingSettings,
+    RequestRejected,
+    RequestCancelled,
+    RequestIncomplete,
+    MessageError,
+    ConnectError,
+    VersionFallback,
+    QpackDecompressionFailed,
+    QpackEncoderStreamError,
+    QpackDecoderStreamError,
+
+    Unknown(u64),
+}
+impl From<u64> for Http3ErrorCode {
+    fn from(value: u64) -> Self {
+        match value {
+            0x100 => Self::NoError,
+            0x101 => Self::GeneralProtocolError,
+            0x102 => Self::InternalError,
+            0x103 => Self::StreamCreationError,
+            0x104 => Self::ClosedCriticalStream,
+            0x105 => Self::FrameUnexpected,
+            0x106 => Self::FrameError,
+            0x107 => Self::ExcessiveLoad,
+            0x108 => Self::IdError,
+            0x109 => Self::SettingsError,
+            0x10a => Self::MissingSettings,
+            0x10b => Self::RequestRejected,
+            0x10c => Self::RequestCancelled,
+            0x10d => Self::RequestIncomplete,
+            0x10e => Self::MessageError,
+            0x10f => Self::ConnectError,
+            0x110 => Self::VersionFallback,
+            0x200 => Self::QpackDecompressionFailed,
+            0x201 => Self::QpackEncoderStreamError,
+            0x202 => Self::QpackDecoderStreamError,
+
+            v => Self::Unknown(v),
+        }
+    }
+}
+impl Into<u64> for Http3ErrorCode {
+    fn into(self) -> u64 {
+        match self {
+            Self::NoError => 0x100,
+            Self::GeneralProtocolError => 0x101,
+            Self::InternalError => 0x102,
+            Self::StreamCreationError => 0x103,
+            Self::ClosedCriticalStream => 0x104,
+            Self::FrameUnexpected => 0x105,
+            Self::FrameError => 0x106,
+            Self::ExcessiveLoad => 0x107,
+            Self::IdError => 0x108,
+            Self::SettingsError => 0x109,
+            Self::MissingSettings => 0x10a,
+            Self::RequestRejected => 0x10b,
+            Self::RequestCancelled => 0x10c,
+            Self::RequestIncomplete => 0x10d,
+            Self::MessageError => 0x10e,
+            Self::ConnectError => 0x10f,
+            Self::VersionFallback => 0x110,
+            Self::QpackDecompressionFailed => 0x200,
+            Self::QpackEncoderStreamError => 0x201,
+            Self::QpackDecoderStreamError => 0x202,
+
+            Self::Unknown(v) => v,
+        }
+    }
+}
+impl Display for Http3ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoError => write!(f, "H3_NO_ERROR"),
+            Self::GeneralProtocolError => write!(f, "H3_GENERAL_PROTOCOL_ERROR"),
+            Self::InternalError => write!(f, "H3_INTERNAL_ERROR"),
+            Self::StreamCreationError => write!(f, "H3_STREAM_CREATION_ERROR"),
+            Self::ClosedCriticalStream => write!(f, "H3_CLOSED_CRITICAL_STREAM"),
+            Self::FrameUnexpected => write!(f, "H3_FRAME_UNEXPECTED"),
+            Self::FrameError => write!(f, "H3_FRAME_ERROR"),
+            Self::ExcessiveLoad => write!(f, "H3_EXCESSIVE_LOAD"),
+            Self::IdError => write!(f, "H3_ID_ERROR"),
+            Self::SettingsError => write!(f, "H3_SETTINGS_ERROR"),
+            Self::MissingSettings => write!(f, "H3_MISSING_SETTINGS"),
+            Self::RequestRejected => write!(f, "H3_REQUEST_REJECTED"),
+            Self::RequestCancelled => write!(f, "H3_REQUEST_CANCELLED"),
+            Self::RequestIncomplete => write!(f, "H3_REQUEST_INCOMPLETE"),
+            Self::MessageError => write!(f, "H3_MESSAGE_ERROR"),
+            Self::ConnectError => write!(f, "H3_CONNECT_ERROR"),
+            Self::VersionFallback => write!(f, "H3_VERSION_FALLBACK"),
+            Self::QpackDecompressionFailed => write!(f, "QPACK_DECOMPRESSION_FAILED"),
+            Self::QpackEncoderStreamError => write!(f, "QPACK_ENCODER_STREAM_ERROR"),
+            Self::QpackDecoderStreamError => write!(f, "QPACK_DECODER_STREAM_ERROR"),
+
+            Self::Unknown(v) => write!(f, "UNKNOWN(0x{v:x})"),
+        }
+    }
+}
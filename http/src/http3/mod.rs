@@ -1 +1,6 @@
+pub mod core;
+pub mod transport;
+pub mod session;
+pub mod client;
+pub mod server;
 pub mod qpack;
@@ -0,0 +1,212 @@
+use std::mem;
+
+use crate::{http2::hpack::{decoder::DecodedHeaders, huffman::Huffman}, http3::qpack::{DecoderInstruction, DynamicTable, EncoderInstruction, QpackError, STATIC_TABLE, decode_insert_count, read_int, read_string}};
+
+// errors from receive_instructions are QPACK_ENCODER_STREAM_ERROR, the ones from decoding a section QPACK_DECOMPRESSION_FAILED
+#[derive(Debug)]
+pub struct Decoder {
+    pub table: DynamicTable,
+    pub huffman: &'static Huffman,
+    // the SETTINGS_QPACK_BLOCKED_STREAMS we advertised
+    pub max_blocked_streams: usize,
+    // name + value + 32 for every field of a section, the SETTINGS_MAX_FIELD_SECTION_SIZE we advertised
+    pub max_field_section_size: usize,
+    // (stream id, required insert count, field section) waiting for insertions on the encoder stream
+    pub blocked: Vec<(u64, u64, Vec<u8>)>,
+    // the insert count the encoder knows we reached, through acknowledgments and increments
+    pub acknowledged: u64,
+    // encoder stream bytes that do not make a whole instruction yet
+    pub pending: Vec<u8>,
+    // decoder stream bytes waiting to be sent
+    pub instructions: Vec<u8>,
+}
+impl Decoder {
+    pub fn new(max_capacity: usize, max_blocked_streams: usize) -> Self {
+        Self {
+            table: DynamicTable::new(max_capacity),
+            huffman: Huffman::shared(),
+            max_blocked_streams,
+            max_field_section_size: usize::MAX,
+            blocked: Vec::new(),
+            acknowledged: 0,
+            pending: Vec::new(),
+            instructions: Vec::new(),
+        }
+    }
+
+    // static when stat is set, otherwise absolute, anything at or past the required insert count is invalid
+    fn get(&self, stat: bool, index: u64, required: u64) -> Result<(&[u8], &[u8]), QpackError> {
+        let field =
+        if stat { STATIC_TABLE.get(index as usize).copied() }
+        else if index < required { self.table.get(index) }
+        else { None };
+
+        field.ok_or(QpackError::InvalidIndex(index))
+    }
+
+    // whole instructions are applied, a partial one waits for the next call
+    pub fn receive_instructions(&mut self, data: &[u8]) -> Result<(), QpackError> {
+        self.pending.extend_from_slice(data);
+        let mut pos = 0;
+
+        while pos < self.pending.len() {
+            let start = pos;
+
+            match EncoderInstruction::from(self.huffman, &self.pending, &mut pos) {
+                Ok(instruction) => self.apply(instruction)?,
+                Err(QpackError::Truncated) => { pos = start; break },
+                Err(err) => return Err(err),
+            }
+        }
+        self.pending.drain(..pos);
+
+        // 4.4.3, let the encoder know about the new entries
+        if self.table.inserted > self.acknowledged {
+            DecoderInstruction::InsertCountIncrement(self.table.inserted - self.acknowledged).write(&mut self.instructions);
+            self.acknowledged = self.table.inserted;
+        }
+        Ok(())
+    }
+    fn apply(&mut self, instruction: EncoderInstruction) -> Result<(), QpackError> {
+        match instruction {
+            EncoderInstruction::SetCapacity(capacity) => {
+                self.table.set_capacity(usize::try_from(capacity).map_err(|_| QpackError::CapacityExceeded(usize::MAX))?)
+            },
+            EncoderInstruction::InsertWithNameRef(stat, index, value) => {
+                let name =
+                if stat { STATIC_TABLE.get(index as usize).map(|(h, _)| h.to_vec()) }
+                else { self.table.get_relative(index).map(|(h, _)| h.to_vec()) };
+
+                self.table.insert(name.ok_or(QpackError::InvalidIndex(index))?, value).map(|_| ())
+            },
+            EncoderInstruction::InsertWithLiteralName(name, value) => {
+                self.table.insert(name, value).map(|_| ())
+            },
+            EncoderInstruction::Duplicate(index) => {
+                let (name, value) = self.table.get_relative(index).map(|(h, v)| (h.to_vec(), v.to_vec())).ok_or(QpackError::InvalidIndex(index))?;
+                self.table.insert(name, value).map(|_| ())
+            },
+        }
+    }
+
+    // None when the section needs insertions that have not arrived, it is kept until decode_unblocked can finish it
+    pub fn decode_section(&mut self, stream_id: u64, block: &[u8]) -> Result<Option<DecodedHeaders>, QpackError> {
+        let mut pos = 0;
+
+        // 4.5.1
+        let encoded = read_int(block, 8, &mut pos)?;
+        let required = decode_insert_count(encoded, self.table.max_entries(), self.table.inserted)?;
+        let negative = *block.get(pos).ok_or(QpackError::Truncated)? & 0x80 != 0;
+        let delta = read_int(block, 7, &mut pos)?;
+
+        let base =
+        if negative { delta.checked_add(1).and_then(|d| required.checked_sub(d)).ok_or(QpackError::InvalidBase)? }
+        else { required.checked_add(delta).ok_or(QpackError::InvalidBase)? };
+
+        if required > self.table.inserted {
+            if self.blocked.len() >= self.max_blocked_streams { return Err(QpackError::TooManyBlockedStreams) }
+
+            self.blocked.push((stream_id, required, block.to_vec()));
+            return Ok(None);
+        }
+
+        let mut headers = Vec::new();
+        let mut size = 0usize;
+        let mut largest = None;
+
+        while pos < block.len() {
+            let first = block[pos];
+
+            // (static, index, literal value), dynamic indices are absolute from here on
+            let (stat, index, literal) =
+            if first & 0x80 != 0 {
+                let index = read_int(block, 6, &mut pos)?;
+                if first & 0x40 != 0 { (true, index, None) }
+                else { (false, relative(base, index)?, None) }
+            }
+            else if first & 0x40 != 0 {
+                let index = read_int(block, 4, &mut pos)?;
+                let index =
+                if first & 0x10 != 0 { index }
+                else { relative(base, index)? };
+
+                (first & 0x10 != 0, index, Some(read_string(self.huffman, block, 7, &mut pos)?))
+            }
+            else if first & 0x20 != 0 {
+                let name = read_string(self.huffman, block, 3, &mut pos)?;
+                let value = read_string(self.huffman, block, 7, &mut pos)?;
+
+                size = size.saturating_add(name.len() + value.len() + 32);
+                headers.push((name, value));
+                continue;
+            }
+            else if first & 0x10 != 0 {
+                (false, post_base(base, read_int(block, 4, &mut pos)?)?, None)
+            }
+            else {
+                let index = post_base(base, read_int(block, 3, &mut pos)?)?;
+                (false, index, Some(read_string(self.huffman, block, 7, &mut pos)?))
+            };
+
+            if !stat { largest = largest.max(Some(index)) }
+
+            let (name, value) = self.get(stat, index, required)?;
+            let value = literal.unwrap_or_else(|| value.to_vec());
+
+            size = size.saturating_add(name.len() + value.len() + 32);
+            if size > self.max_field_section_size { return Err(QpackError::FieldSectionTooLarge(size)) }
+
+            headers.push((name.to_vec(), value));
+        }
+
+        if size > self.max_field_section_size { return Err(QpackError::FieldSectionTooLarge(size)) }
+        // 2.2.3, a required insert count larger than the references is an error too
+        if required != largest.map_or(0, |l| l + 1) { return Err(QpackError::InvalidInsertCount(required)) }
+
+        // 4.4.1
+        if required > 0 {
+            DecoderInstruction::SectionAck(stream_id).write(&mut self.instructions);
+            self.acknowledged = self.acknowledged.max(required);
+        }
+        Ok(Some(headers))
+    }
+
+    // the blocked sections the received insertions made decodable, in the order they arrived
+    pub fn decode_unblocked(&mut self) -> Result<Vec<(u64, DecodedHeaders)>, QpackError> {
+        let mut res = Vec::new();
+        let mut i = 0;
+
+        while i < self.blocked.len() {
+            if self.blocked[i].1 > self.table.inserted { i += 1; continue }
+
+            let (stream_id, _, block) = self.blocked.remove(i);
+            if let Some(headers) = self.decode_section(stream_id, &block)? {
+                res.push((stream_id, headers));
+            }
+        }
+        Ok(res)
+    }
+
+    // 4.4.2, the stream was reset or its section abandoned
+    pub fn cancel_stream(&mut self, stream_id: u64) {
+        self.blocked.retain(|(id, _, _)| *id != stream_id);
+
+        // without a table there is nothing the encoder could be tracking
+        if self.table.max_capacity > 0 {
+            DecoderInstruction::StreamCancel(stream_id).write(&mut self.instructions);
+        }
+    }
+
+    pub fn take_instructions(&mut self) -> Vec<u8> {
+        mem::take(&mut self.instructions)
+    }
+}
+
+// 4.5.2 and 4.5.4, relative indices count down from base - 1, past zero they point nowhere
+fn relative(base: u64, index: u64) -> Result<u64, QpackError> {
+    index.checked_add(1).and_then(|i| base.checked_sub(i)).ok_or(QpackError::InvalidIndex(index))
+}
+// 4.5.3 and 4.5.5, post-base indices count up from base
+fn post_base(base: u64, index: u64) -> Result<u64, QpackError> {
+    base.checked_add(index).ok_or(QpackError::InvalidIndex(index))
+}
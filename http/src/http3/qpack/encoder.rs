@@ -0,0 +1,248 @@
+use std::{collections::VecDeque, mem};
+
+use crate::{http2::hpack::{HeaderType, encoder::{DefaultIndexing, IndexingPolicy}, huffman::Huffman}, http3::qpack::{DecoderInstruction, DynamicTable, EncoderInstruction, QpackError, STATIC_TABLE, encode_insert_count, write_int, write_string}};
+
+// one line of a field section before the base is known, dynamic indices are absolute
+#[derive(Debug)]
+enum FieldLine<'b> {
+    Static(u64),
+    Dynamic(u64),
+    // (index, never indexed, value)
+    StaticName(u64, bool, &'b [u8]),
+    DynamicName(u64, bool, &'b [u8]),
+    Literal(bool, &'b [u8], &'b [u8]),
+}
+
+// errors from receive_instructions are QPACK_DECODER_STREAM_ERROR
+#[derive(Debug)]
+pub struct Encoder {
+    pub table: DynamicTable,
+    pub huffman: &'static Huffman,
+    pub policy: Box<dyn IndexingPolicy>,
+    // the SETTINGS_QPACK_BLOCKED_STREAMS of the peer
+    pub max_blocked_streams: usize,
+    // how much of the table the peer offers we are willing to use
+    pub capacity_limit: usize,
+    // the insert count the decoder confirmed, entries below it can be referenced without blocking
+    pub known_received: u64,
+    // (stream id, required insert count, smallest absolute index referenced) of sections not acknowledged yet, oldest first
+    pub unacked: VecDeque<(u64, u64, u64)>,
+    // decoder stream bytes that do not make a whole instruction yet
+    pub pending: Vec<u8>,
+    // encoder stream bytes waiting to be sent
+    pub instructions: Vec<u8>,
+}
+impl Encoder {
+    // the table stays unused until the peer's settings allow one
+    pub fn new() -> Self {
+        Self::with_policy(DefaultIndexing::new())
+    }
+    pub fn with_policy(policy: impl IndexingPolicy + 'static) -> Self {
+        Self {
+            table: DynamicTable::new(0),
+            huffman: Huffman::shared(),
+            policy: Box::new(policy),
+            max_blocked_streams: 0,
+            capacity_limit: 65536,
+            known_received: 0,
+            unacked: VecDeque::new(),
+            pending: Vec::new(),
+            instructions: Vec::new(),
+        }
+    }
+
+    // SETTINGS_QPACK_MAX_TABLE_CAPACITY and SETTINGS_QPACK_BLOCKED_STREAMS from the peer, the capacity is raised as far as capacity_limit allows
+    pub fn apply_settings(&mut self, max_capacity: usize, max_blocked_streams: usize) -> Result<(), QpackError> {
+        self.table.max_capacity = max_capacity;
+        self.max_blocked_streams = max_blocked_streams;
+
+        let capacity = max_capacity.min(self.capacity_limit);
+        if capacity > 0 { self.set_capacity(capacity) }
+        else { Ok(()) }
+    }
+    // 4.3.1, refuses to drop entries that unacknowledged sections still reference
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<(), QpackError> {
+        if capacity > self.table.max_capacity { return Err(QpackError::CapacityExceeded(capacity)) }
+
+        let mut size = self.table.size;
+        let mut evicted = self.table.dropped();
+
+        for (h, v) in self.table.entries.iter() {
+            if size <= capacity { break }
+            size -= DynamicTable::entry_size(h, v);
+            evicted += 1;
+        }
+        if evicted > self.evictable_below() { return Err(QpackError::CapacityExceeded(capacity)) }
+
+        self.table.set_capacity(capacity)?;
+        EncoderInstruction::SetCapacity(capacity as u64).write(self.huffman, &mut self.instructions);
+        Ok(())
+    }
+
+    // 2.1.1, entries below this absolute index have no unacknowledged references
+    pub fn evictable_below(&self) -> u64 {
+        self.unacked.iter().map(|(_, _, smallest)| *smallest).min().unwrap_or(self.table.inserted)
+    }
+    // 2.1.2, streams whose sections wait for insertions the decoder may not have yet
+    pub fn blocked_streams(&self) -> usize {
+        let mut streams: Vec<u64> = self.unacked.iter().filter(|(_, required, _)| *required > self.known_received).map(|(id, _, _)| *id).collect();
+        streams.sort_unstable();
+        streams.dedup();
+        streams.len()
+    }
+    // entries from `keep` on have to stay as well
+    fn can_insert(&self, size: usize, keep: u64) -> bool {
+        match self.table.evictions_for(size) {
+            Some(count) => self.table.dropped() + count as u64 <= self.evictable_below().min(keep),
+            None => false,
+        }
+    }
+    // adds the field on the encoder stream, referring to a name already in one of the tables when possible
+    fn insert(&mut self, name: &[u8], value: &[u8]) -> Result<u64, QpackError> {
+        let instruction =
+        if let Some(index) = STATIC_TABLE.iter().position(|(h, _)| *h == name) {
+            EncoderInstruction::InsertWithNameRef(true, index as u64, value.to_vec())
+        }
+        else if let Some(index) = self.table.find(name) {
+            EncoderInstruction::InsertWithNameRef(false, self.table.inserted - 1 - index, value.to_vec())
+        }
+        else {
+            EncoderInstruction::InsertWithLiteralName(name.to_vec(), value.to_vec())
+        };
+
+        instruction.write(self.huffman, &mut self.instructions);
+        self.table.insert(name.to_vec(), value.to_vec())
+    }
+
+    pub fn encode_section(&mut self, stream_id: u64, headers: &[(&[u8], &[u8])]) -> Result<Vec<u8>, QpackError> {
+        // a stream that already waits on the decoder costs nothing more to block again
+        let may_block = self.unacked.iter().any(|(id, required, _)| *id == stream_id && *required > self.known_received)
+            || self.blocked_streams() < self.max_blocked_streams;
+        let usable = |known: u64, index: u64| index < known || may_block;
+
+        let mut lines = Vec::with_capacity(headers.len());
+        // smallest index this section refers to, later insertions must not evict it
+        let mut keep = u64::MAX;
+
+        for &(name, value) in headers {
+            let htype = self.policy.header_type(name, value);
+            let never = htype == HeaderType::NeverIndexed;
+
+            if let Some(index) = STATIC_TABLE.iter().position(|f| *f == (name, value)) {
+                lines.push(FieldLine::Static(index as u64));
+                continue;
+            }
+            let exact = self.table.find_exact(name, value);
+
+            if let Some(index) = exact && usable(self.known_received, index) {
+                keep = keep.min(index);
+                lines.push(FieldLine::Dynamic(index));
+                continue;
+            }
+            // an entry the decoder has not confirmed yet is not inserted a second time
+            if htype == HeaderType::Indexed && exact.is_none() && self.can_insert(DynamicTable::entry_size(name, value), keep) {
+                let index = self.insert(name, value)?;
+
+                if usable(self.known_received, index) {
+                    keep = keep.min(index);
+                    lines.push(FieldLine::Dynamic(index));
+                    continue;
+                }
+            }
+
+            if let Some(index) = STATIC_TABLE.iter().position(|(h, _)| *h == name) {
+                lines.push(FieldLine::StaticName(index as u64, never, value));
+            }
+            else if let Some(index) = self.table.find(name) && usable(self.known_received, index) {
+                keep = keep.min(index);
+                lines.push(FieldLine::DynamicName(index, never, value));
+            }
+            else {
+                lines.push(FieldLine::Literal(never, name, value));
+            }
+        }
+
+        let required = lines.iter().filter_map(|l| match l {
+            FieldLine::Dynamic(index) | FieldLine::DynamicName(index, _, _) => Some(*index + 1),
+            _ => None,
+        }).max().unwrap_or(0);
+
+        // every reference is below the base, so only the pre-base forms are needed
+        let base = if required > 0 { self.table.inserted } else { 0 };
+        let mut buf = Vec::new();
+
+        write_int(&mut buf, encode_insert_count(required, self.table.max_entries()), 8, 0x00);
+        write_int(&mut buf, base - required, 7, 0x00);
+
+        for line in lines {
+            match line {
+                FieldLine::Static(index) => write_int(&mut buf, index, 6, 0xc0),
+                FieldLine::Dynamic(index) => write_int(&mut buf, base - 1 - index, 6, 0x80),
+                FieldLine::StaticName(index, never, value) => {
+                    write_int(&mut buf, index, 4, if never { 0x70 } else { 0x50 });
+                    write_string(self.huffman, &mut buf, value, 7, 0x00);
+                },
+                FieldLine::DynamicName(index, never, value) => {
+                    write_int(&mut buf, base - 1 - index, 4, if never { 0x60 } else { 0x40 });
+                    write_string(self.huffman, &mut buf, value, 7, 0x00);
+                },
+                FieldLine::Literal(never, name, value) => {
+                    write_string(self.huffman, &mut buf, name, 3, if never { 0x30 } else { 0x20 });
+                    write_string(self.huffman, &mut buf, value, 7, 0x00);
+                },
+            }
+        }
+
+        if required > 0 {
+            self.unacked.push_back((stream_id, required, keep));
+        }
+        Ok(buf)
+    }
+
+    // whole instructions are applied, a partial one waits for the next call
+    pub fn receive_instructions(&mut self, data: &[u8]) -> Result<(), QpackError> {
+        self.pending.extend_from_slice(data);
+        let mut pos = 0;
+
+        while pos < self.pending.len() {
+            let start = pos;
+
+            match DecoderInstruction::from(&self.pending, &mut pos) {
+                Ok(instruction) => self.apply(instruction)?,
+                Err(QpackError::Truncated) => { pos = start; break },
+                Err(err) => return Err(err),
+            }
+        }
+        self.pending.drain(..pos);
+        Ok(())
+    }
+    fn apply(&mut self, instruction: DecoderInstruction) -> Result<(), QpackError> {
+        match instruction {
+            // 4.4.1, sections of a stream are acknowledged in the order they were sent
+            DecoderInstruction::SectionAck(stream_id) => {
+                let pos = self.unacked.iter().position(|(id, _, _)| *id == stream_id).ok_or(QpackError::UnknownStream(stream_id))?;
+                let (_, required, _) = self.unacked.remove(pos).unwrap();
+                self.known_received = self.known_received.max(required);
+            },
+            DecoderInstruction::StreamCancel(stream_id) => {
+                self.unacked.retain(|(id, _, _)| *id != stream_id);
+            },
+            DecoderInstruction::InsertCountIncrement(increment) => {
+                if increment == 0 || increment > self.table.inserted - self.known_received {
+                    return Err(QpackError::InvalidIncrement(increment));
+                }
+                self.known_received += increment;
+            },
+        }
+        Ok(())
+    }
+
+    pub fn take_instructions(&mut self) -> Vec<u8> {
+        mem::take(&mut self.instructions)
+    }
+}
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,423 @@
+use std::{collections::VecDeque, fmt::Display};
+
+use crate::http2::hpack::{HpackError, decoder::Decoder, encoder::Encoder, huffman::{Huffman, HuffmanError}};
+
+pub mod encoder;
+pub mod decoder;
+
+// https://datatracker.ietf.org/doc/html/rfc9204
+
+// 6, error codes for the connection close
+pub const QPACK_DECOMPRESSION_FAILED: u64 = 0x200;
+pub const QPACK_ENCODER_STREAM_ERROR: u64 = 0x201;
+pub const QPACK_DECODER_STREAM_ERROR: u64 = 0x202;
+
+// 4.2, unidirectional stream types
+pub const ENCODER_STREAM_TYPE: u64 = 0x02;
+pub const DECODER_STREAM_TYPE: u64 = 0x03;
+
+
+// Appendix A, indices start at 0
+pub const STATIC_TABLE: &'static [(&'static [u8], &'static [u8]); 99] = &[
+    (b":authority", b""),
+    (b":path", b"/"),
+    (b"age", b"0"),
+    (b"content-disposition", b""),
+    (b"content-length", b"0"),
+    (b"cookie", b""),
+    (b"date", b""),
+    (b"etag", b""),
+    (b"if-modified-since", b""),
+    (b"if-none-match", b""),
+    (b"last-modified", b""),
+    (b"link", b""),
+    (b"location", b""),
+    (b"referer", b""),
+    (b"set-cookie", b""),
+    (b":method", b"CONNECT"),
+    (b":method", b"DELETE"),
+    (b":method", b"GET"),
+    (b":method", b"HEAD"),
+    (b":method", b"OPTIONS"),
+    (b":method", b"POST"),
+    (b":method", b"PUT"),
+    (b":scheme", b"http"),
+    (b":scheme", b"https"),
+    (b":status", b"103"),
+    (b":status", b"200"),
+    (b":status", b"304"),
+    (b":status", b"404"),
+    (b":status", b"503"),
+    (b"accept", b"*/*"),
+    (b"accept", b"application/dns-message"),
+    (b"accept-encoding", b"gzip, deflate, br"),
+    (b"accept-ranges", b"bytes"),
+    (b"access-control-allow-headers", b"cache-control"),
+    (b"access-control-allow-headers", b"content-type"),
+    (b"access-control-allow-origin", b"*"),
+    (b"cache-control", b"max-age=0"),
+    (b"cache-control", b"max-age=2592000"),
+    (b"cache-control", b"max-age=604800"),
+    (b"cache-control", b"no-cache"),
+    (b"cache-control", b"no-store"),
+    (b"cache-control", b"public, max-age=31536000"),
+    (b"content-encoding", b"br"),
+    (b"content-encoding", b"gzip"),
+    (b"content-type", b"application/dns-message"),
+    (b"content-type", b"application/javascript"),
+    (b"content-type", b"application/json"),
+    (b"content-type", b"application/x-www-form-urlencoded"),
+    (b"content-type", b"image/gif"),
+    (b"content-type", b"image/jpeg"),
+    (b"content-type", b"image/png"),
+    (b"content-type", b"text/css"),
+    (b"content-type", b"text/html; charset=utf-8"),
+    (b"content-type", b"text/plain"),
+    (b"content-type", b"text/plain;charset=utf-8"),
+    (b"range", b"bytes=0-"),
+    (b"strict-transport-security", b"max-age=31536000"),
+    (b"strict-transport-security", b"max-age=31536000; includesubdomains"),
+    (b"strict-transport-security", b"max-age=31536000; includesubdomains; preload"),
+    (b"vary", b"accept-encoding"),
+    (b"vary", b"origin"),
+    (b"x-content-type-options", b"nosniff"),
+    (b"x-xss-protection", b"1; mode=block"),
+    (b":status", b"100"),
+    (b":status", b"204"),
+    (b":status", b"206"),
+    (b":status", b"302"),
+    (b":status", b"400"),
+    (b":status", b"403"),
+    (b":status", b"421"),
+    (b":status", b"425"),
+    (b":status", b"500"),
+    (b"accept-language", b""),
+    (b"access-control-allow-credentials", b"FALSE"),
+    (b"access-control-allow-credentials", b"TRUE"),
+    (b"access-control-allow-headers", b"*"),
+    (b"access-control-allow-methods", b"get"),
+    (b"access-control-allow-methods", b"get, post, options"),
+    (b"access-control-allow-methods", b"options"),
+    (b"access-control-expose-headers", b"content-length"),
+    (b"access-control-request-headers", b"content-type"),
+    (b"access-control-request-method", b"get"),
+    (b"access-control-request-method", b"post"),
+    (b"alt-svc", b"clear"),
+    (b"authorization", b""),
+    (b"content-security-policy", b"script-src 'none'; object-src 'none'; base-uri 'none'"),
+    (b"early-data", b"1"),
+    (b"expect-ct", b""),
+    (b"forwarded", b""),
+    (b"if-range", b""),
+    (b"origin", b""),
+    (b"purpose", b"prefetch"),
+    (b"server", b""),
+    (b"timing-allow-origin", b"*"),
+    (b"upgrade-insecure-requests", b"1"),
+    (b"user-agent", b""),
+    (b"x-forwarded-for", b""),
+    (b"x-frame-options", b"deny"),
+    (b"x-frame-options", b"sameorigin"),
+];
+
+// 3.2, entries are addressed by absolute index, the n-th insertion ever made has index n
+// the oldest entry sits at the front, so entries[i] has absolute index dropped() + i
+#[derive(Debug)]
+pub struct DynamicTable {
+    pub size: usize,
+    pub capacity: usize,
+    // SETTINGS_QPACK_MAX_TABLE_CAPACITY of the decoder side, capacity can not go above it
+    pub max_capacity: usize,
+    // total number of insertions, the Insert Count of 2.1.4
+    pub inserted: u64,
+    pub entries: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+impl DynamicTable {
+    pub fn new(max_capacity: usize) -> Self {
+        Self {
+            size: 0,
+            capacity: 0,
+            max_capacity,
+            inserted: 0,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn entry_size(name: &[u8], value: &[u8]) -> usize {
+        name.len() + value.len() + 32
+    }
+    // 3.2.2, the number of entries a table of max_capacity could hold at most
+    pub fn max_entries(&self) -> u64 {
+        self.max_capacity as u64 / 32
+    }
+    // absolute index of the oldest entry still in the table
+    pub fn dropped(&self) -> u64 {
+        self.inserted - self.entries.len() as u64
+    }
+
+    pub fn get(&self, index: u64) -> Option<(&[u8], &[u8])> {
+        let pos = index.checked_sub(self.dropped())?;
+        self.entries.get(pos as usize).map(|(h, v)| (h.as_slice(), v.as_slice()))
+    }
+    // 3.2.5, relative to the last insertion on the encoder stream
+    pub fn get_relative(&self, index: u64) -> Option<(&[u8], &[u8])> {
+        self.get(self.inserted.checked_sub(index.checked_add(1)?)?)
+    }
+
+    pub fn find_exact(&self, name: &[u8], value: &[u8]) -> Option<u64> {
+        self.entries.iter().rposition(|(h, v)| h == name && v == value).map(|p| self.dropped() + p as u64)
+    }
+    pub fn find(&self, name: &[u8]) -> Option<u64> {
+        self.entries.iter().rposition(|(h, _)| h == name).map(|p| self.dropped() + p as u64)
+    }
+
+    // how many of the oldest entries would have to go to fit `size` more bytes, None if it never fits
+    pub fn evictions_for(&self, size: usize) -> Option<usize> {
+        if size > self.capacity { return None }
+
+        let mut free = self.capacity - self.size;
+        let mut count = 0;
+
+        for (h, v) in self.entries.iter() {
+            if free >= size { break }
+            free += Self::entry_size(h, v);
+            count += 1;
+        }
+        Some(count)
+    }
+
+    pub fn insert(&mut self, name: Vec<u8>, value: Vec<u8>) -> Result<u64, QpackError> {
+        let size = Self::entry_size(&name, &value);
+        if size > self.capacity { return Err(QpackError::EntryTooLarge(size)) }
+
+        self.size += size;
+        self.entries.push_back((name, value));
+        self.inserted += 1;
+        self.evict();
+        Ok(self.inserted - 1)
+    }
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<(), QpackError> {
+        if capacity > self.max_capacity { return Err(QpackError::CapacityExceeded(capacity)) }
+
+        self.capacity = capacity;
+        self.evict();
+        Ok(())
+    }
+    fn evict(&mut self) {
+        while self.size > self.capacity {
+            let Some((name, value)) = self.entries.pop_front() else { break };
+            self.size -= Self::entry_size(&name, &value);
+        }
+    }
+}
+
+
+// 4.5.1.1, the Required Insert Count is sent modulo twice the possible number of entries
+pub fn encode_insert_count(required: u64, max_entries: u64) -> u64 {
+    if required == 0 { 0 }
+    else { required % (2 * max_entries) + 1 }
+}
+pub fn decode_insert_count(encoded: u64, max_entries: u64, total_inserts: u64) -> Result<u64, QpackError> {
+    if encoded == 0 { return Ok(0) }
+
+    let full_range = 2 * max_entries;
+    if encoded > full_range { return Err(QpackError::InvalidInsertCount(encoded)) }
+
+    let max_value = total_inserts + max_entries;
+    let max_wrapped = (max_value / full_range) * full_range;
+    let mut required = max_wrapped + encoded - 1;
+
+    if required > max_value {
+        if required <= full_range { return Err(QpackError::InvalidInsertCount(encoded)) }
+        required -= full_range;
+    }
+
+    if required == 0 { Err(QpackError::InvalidInsertCount(encoded)) }
+    else { Ok(required) }
+}
+
+// the hpack integers with whatever prefix a qpack representation uses, writing into a Vec can not fail
+pub fn write_int(buf: &mut Vec<u8>, value: u64, prefix: u8, first: u8) {
+    let _ = Encoder::write_int(buf, value as usize, prefix, first);
+}
+pub fn read_int(buf: &[u8], prefix: u8, pos: &mut usize) -> Result<u64, QpackError> {
+    Ok(Decoder::read_int(buf, prefix, pos)? as u64)
+}
+
+// 4.1.2, the huffman flag sits right above the length prefix
+pub fn write_string(huffman: &Huffman, buf: &mut Vec<u8>, value: &[u8], prefix: u8, first: u8) {
+    if huffman.encoded_len(value) < value.len() {
+        write_int(buf, huffman.encoded_len(value) as u64, prefix, first | 1 << prefix);
+        huffman.encode_into(value, buf);
+    }
+    else {
+        write_int(buf, value.len() as u64, prefix, first);
+        buf.extend_from_slice(value);
+    }
+}
+pub fn read_string(huffman: &Huffman, buf: &[u8], prefix: u8, pos: &mut usize) -> Result<Vec<u8>, QpackError> {
+    let huff = buf.get(*pos).ok_or(QpackError::Truncated)? & 1 << prefix != 0;
+    let length = read_int(buf, prefix, pos)?;
+
+    if length > (buf.len() - *pos) as u64 {
+        Err(QpackError::Truncated)
+    }
+    else {
+        let buff = &buf[*pos..*pos + length as usize];
+        *pos += length as usize;
+
+        if huff { Ok(huffman.decode(buff)?) }
+        else { Ok(buff.to_vec()) }
+    }
+}
+
+
+// 4.3, sent on the encoder stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncoderInstruction {
+    SetCapacity(u64),
+    // (static, index, value), a dynamic index is relative
+    InsertWithNameRef(bool, u64, Vec<u8>),
+    InsertWithLiteralName(Vec<u8>, Vec<u8>),
+    // relative index
+    Duplicate(u64),
+}
+impl EncoderInstruction {
+    // Truncated means the rest of the instruction has not arrived yet
+    pub fn from(huffman: &Huffman, buf: &[u8], pos: &mut usize) -> Result<Self, QpackError> {
+        let first = *buf.get(*pos).ok_or(QpackError::Truncated)?;
+
+        if first & 0x80 != 0 {
+            let index = read_int(buf, 6, pos)?;
+            Ok(Self::InsertWithNameRef(first & 0x40 != 0, index, read_string(huffman, buf, 7, pos)?))
+        }
+        else if first & 0x40 != 0 {
+            let name = read_string(huffman, buf, 5, pos)?;
+            Ok(Self::InsertWithLiteralName(name, read_string(huffman, buf, 7, pos)?))
+        }
+        else if first & 0x20 != 0 {
+            Ok(Self::SetCapacity(read_int(buf, 5, pos)?))
+        }
+        else {
+            Ok(Self::Duplicate(read_int(buf, 5, pos)?))
+        }
+    }
+    pub fn write(&self, huffman: &Huffman, buf: &mut Vec<u8>) {
+        match self {
+            Self::SetCapacity(capacity) => write_int(buf, *capacity, 5, 0x20),
+            Self::InsertWithNameRef(stat, index, value) => {
+                write_int(buf, *index, 6, if *stat { 0xc0 } else { 0x80 });
+                write_string(huffman, buf, value, 7, 0x00);
+            },
+            Self::InsertWithLiteralName(name, value) => {
+                write_string(huffman, buf, name, 5, 0x40);
+                write_string(huffman, buf, value, 7, 0x00);
+            },
+            Self::Duplicate(index) => write_int(buf, *index, 5, 0x00),
+        }
+    }
+}
+
+// 4.4, sent on the decoder stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecoderInstruction {
+    SectionAck(u64),
+    StreamCancel(u64),
+    InsertCountIncrement(u64),
+}
+impl DecoderInstruction {
+    pub fn from(buf: &[u8], pos: &mut usize) -> Result<Self, QpackError> {
+        let first = *buf.get(*pos).ok_or(QpackError::Truncated)?;
+
+        if first & 0x80 != 0 { Ok(Self::SectionAck(read_int(buf, 7, pos)?)) }
+        else if first & 0x40 != 0 { Ok(Self::StreamCancel(read_int(buf, 6, pos)?)) }
+        else { Ok(Self::InsertCountIncrement(read_int(buf, 6, pos)?)) }
+    }
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::SectionAck(stream_id) => write_int(buf, *stream_id, 7, 0x80),
+            Self::StreamCancel(stream_id) => write_int(buf, *stream_id, 6, 0x40),
+            Self::InsertCountIncrement(increment) => write_int(buf, *increment, 6, 0x00),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub enum QpackError {
+    // an instruction or field line ends early, on the instruction streams it just waits for more data
+    Truncated,
+    IntegerOverflow,
+    // an index outside the static table or not (any more) in the dynamic table
+    InvalidIndex(u64),
+    Huffman(HuffmanError),
+    // 4.3.1, a capacity above SETTINGS_QPACK_MAX_TABLE_CAPACITY
+    CapacityExceeded(usize),
+    // 3.2.2, an insertion larger than the table capacity
+    EntryTooLarge(usize),
+    // 4.5.1.1, a Required Insert Count that can not be decoded or did not match the references
+    InvalidInsertCount(u64),
+    // 4.5.1.2, a negative Base
+    InvalidBase,
+    // 2.1.2, a section blocked after SETTINGS_QPACK_BLOCKED_STREAMS were already waiting
+    TooManyBlockedStreams,
+    // the decoded section is larger than max_field_section_size
+    FieldSectionTooLarge(usize),
+    // 4.4.1, an acknowledgment for a stream without outstanding sections
+    UnknownStream(u64),
+    // 4.4.3, an increment of zero or past the insertions made
+    InvalidIncrement(u64),
+    // a representation hpack would reject, qpack itself has no use for it
+    InvalidFieldLine,
+}
+impl QpackError {
+    pub fn is_truncated(&self) -> bool {
+        if let Self::Truncated = self { true }
+        else { false }
+    }
+}
+impl std::error::Error for QpackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Huffman(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl Display for QpackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "Field section or instruction is truncated"),
+            Self::IntegerOverflow => write!(f, "Integer overflow"),
+            Self::InvalidIndex(index) => write!(f, "Invalid table index {index}"),
+            Self::Huffman(err) => write!(f, "{err}"),
+            Self::CapacityExceeded(capacity) => write!(f, "Table capacity {capacity} exceeds the advertised maximum"),
+            Self::EntryTooLarge(size) => write!(f, "Entry of {size} bytes does not fit the table"),
+            Self::InvalidInsertCount(count) => write!(f, "Invalid required insert count {count}"),
+            Self::InvalidBase => write!(f, "Invalid base"),
+            Self::TooManyBlockedStreams => write!(f, "Too many blocked streams"),
+            Self::FieldSectionTooLarge(size) => write!(f, "Field section of {size} bytes is too large"),
+            Self::UnknownStream(id) => write!(f, "No outstanding field section on stream {id}"),
+            Self::InvalidIncrement(increment) => write!(f, "Invalid insert count increment {increment}"),
+            Self::InvalidFieldLine => write!(f, "Invalid field line"),
+        }
+    }
+}
+impl From<HuffmanError> for QpackError {
+    fn from(value: HuffmanError) -> Self {
+        Self::Huffman(value)
+    }
+}
+impl From<HpackError> for QpackError {
+    fn from(value: HpackError) -> Self {
+        match value {
+            HpackError::IntegerOverflow => Self::IntegerOverflow,
+            HpackError::Huffman(err) => Self::Huffman(err),
+            HpackError::InvalidIndex(index) => Self::InvalidIndex(index as u64),
+            HpackError::Truncated => Self::Truncated,
+            HpackError::TableSizeExceeded(size) => Self::CapacityExceeded(size),
+            HpackError::HeaderListTooLarge(size) => Self::FieldSectionTooLarge(size),
+            HpackError::InvalidHeaderField | HpackError::MisplacedTableSizeUpdate => Self::InvalidFieldLine,
+        }
+    }
+}
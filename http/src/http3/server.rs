@@ -0,0 +1,239 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::{http3::{core::{Http3ErrorCode, Http3Frame, Http3FrameType}, session::{Http3ReadBuffer, Http3Session}, transport::{Http3RecvStream, Http3Transport}}, shared::{HttpClient, HttpSocket, HttpType, LibError, LibResult, string_from_owned_utf8}};
+
+
+// a request stream the peer opened, the session has to be driven by next() meanwhile
+pub struct Http3Socket<T: Http3Transport> {
+    pub stream_id: u64,
+    pub session: Arc<Http3Session<T>>,
+
+    pub recv: T::Recv,
+    pub send: T::Send,
+    // what was read of the stream that was not handed out yet
+    pub input: Http3ReadBuffer,
+
+    pub client: HttpClient,
+    // :protocol of an extended CONNECT
+    pub protocol: Option<String>,
+
+    pub status: u16,
+    pub headers: HashMap<String, Vec<String>>,
+
+    pub sent_head: bool,
+    pub closed: bool,
+
+    pub trailers: HashMap<String, Vec<String>>,
+}
+impl<T: Http3Transport> Http3Socket<T> {
+    pub fn new(stream_id: u64, session: Arc<Http3Session<T>>) -> LibResult<Self> {
+        let (recv, send) = session.take_request(stream_id).ok_or(LibError::InvalidStream)?;
+
+        Ok(Self {
+            stream_id, session, recv, send,
+            input: Http3ReadBuffer::new(),
+            client: HttpClient::default_h3(),
+            protocol: None,
+            status: 200,
+            headers: HashMap::new(),
+            sent_head: false,
+            closed: false,
+            trailers: HashMap::new(),
+        })
+    }
+
+    pub async fn read_client(&mut self) -> LibResult<&HttpClient> {
+        if !self.client.head_complete {
+            let headers = self.session.read_message_head(self.stream_id, &mut self.recv, &mut self.input).await?;
+            self.client.head_complete = true;
+
+            for (h, v) in headers {
+                let header = string_from_owned_utf8(h);
+                let value = string_from_owned_utf8(v);
+
+                if header == ":method" {
+                    self.client.method = value.into();
+                }
+                else if header == ":scheme" {
+                    self.client.scheme = Some(value);
+                }
+                else if header == ":authority" {
+                    self.client.host = Some(value)
+                }
+                else if header == ":path" {
+                    self.client.path = value
+                }
+                else if header == ":protocol" {
+                    self.protocol = Some(value)
+                }
+
+                else if let Some(values) = self.client.headers.get_mut(&header) {
+                    values.push(value)
+                }
+                else {
+                    self.client.headers.insert(header, vec![value]);
+                }
+            }
+        }
+        else if !self.client.body_complete {
+            match self.session.read_message_frame(&mut self.recv, &mut self.input).await? {
+                Some(frame) if frame.ftype == Http3FrameType::Data => self.client.body.extend_from_slice(&frame.payload),
+                // 4.1, trailers end the message, whatever follows them is not read
+                Some(frame) => {
+                    for (h, v) in self.session.decode_headers(self.stream_id, &frame.payload).await? {
+                        let header = string_from_owned_utf8(h);
+                        let value = string_from_owned_utf8(v);
+
+                        if let Some(values) = self.trailers.get_mut(&header) { values.push(value) }
+                        else { self.trailers.insert(header, vec![value]); }
+                    }
+                    self.client.body_complete = true;
+                },
+                None => self.client.body_complete = true,
+            }
+        }
+
+        Ok(&self.client)
+    }
+    pub async fn read_until_complete(&mut self) -> LibResult<&HttpClient> {
+        while !self.client.body_complete {
+            self.read_client().await?;
+        }
+        Ok(&self.client)
+    }
+    pub async fn read_until_head_complete(&mut self) -> LibResult<&HttpClient> {
+        while !self.client.head_complete {
+            self.read_client().await?;
+        }
+        Ok(&self.client)
+    }
+
+    pub fn add_header(&mut self, header: &str, value: &str) {
+        if let Some(hs) = self.headers.get_mut(header) { hs.push(value.to_owned()); }
+        else { self.headers.insert(header.to_owned(), vec![ value.to_owned() ]); }
+    }
+    pub fn set_header(&mut self, header: &str, value: &str){
+        self.headers.insert(header.to_owned(), vec![ value.to_owned() ]);
+    }
+    pub fn del_header(&mut self, header: &str) -> Option<Vec<String>>{
+        self.headers.remove(header)
+    }
+
+    pub async fn send_head(&mut self, end: bool) -> LibResult<()> {
+        if !self.sent_head {
+            self.sent_head = true;
+            let mut headers = Vec::new();
+            headers.push((b":status".to_vec(), self.status.to_string().into_bytes()));
+
+            for (header, values) in self.headers.drain(){
+                for value in values {
+                    headers.push((header.clone().into_bytes(), value.into_bytes()));
+                }
+            }
+
+            let head = headers.iter().map(|(h, v)| (h.as_slice(), v.as_slice())).collect::<Vec<(&[u8], &[u8])>>();
+            let frame = self.session.encode_headers(self.stream_id, &head).await?;
+            self.send.write_all(&frame).await?;
+            if end { self.finish().await }
+            else { Ok(()) }
+        }
+        else {
+            Err(LibError::HeadersSent)
+        }
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> LibResult<()> {
+        if self.closed {
+            return Err(LibError::StreamClosed)
+        }
+        if !self.sent_head {
+            self.send_head(false).await?;
+        }
+        if !buf.is_empty() {
+            self.send.write_all(&Http3Frame::create_head(Http3FrameType::Data, buf.len())).await?;
+            self.send.write_all(buf).await?;
+        }
+        Ok(())
+    }
+    pub async fn close(&mut self, buf: &[u8]) -> LibResult<()> {
+        if !self.sent_head {
+            self.set_header("content-length", &buf.len().to_string());
+            self.send_head(false).await?;
+        }
+        self.write(buf).await?;
+        self.finish().await
+    }
+    // ends the stream with a trailing HEADERS frame
+    pub async fn send_trailers(&mut self, trailers: &[(&str, &str)]) -> LibResult<()> {
+        if self.closed {
+            return Err(LibError::StreamClosed)
+        }
+        if !self.sent_head {
+            self.send_head(false).await?;
+        }
+        let trailers = trailers.iter().map(|(h, v)| (h.as_bytes(), v.as_bytes())).collect::<Vec<(&[u8], &[u8])>>();
+        let frame = self.session.encode_headers(self.stream_id, &trailers).await?;
+        self.send.write_all(&frame).await?;
+        self.finish().await
+    }
+    async fn finish(&mut self) -> LibResult<()> {
+        self.closed = true;
+        self.send.shutdown().await?;
+
+        // 4.1.2, a response may go out before the request ended, the rest of the request is not wanted
+        if !self.client.body_complete {
+            self.recv.stop(Http3ErrorCode::NoError.into());
+        }
+        Ok(())
+    }
+    #[inline]
+    pub async fn flush(&mut self) -> LibResult<()> {
+        Ok(self.send.flush().await?)
+    }
+}
+impl<T: Http3Transport> HttpSocket for Http3Socket<T> {
+    #[inline]
+    fn get_type(&self) -> HttpType {
+        HttpType::Http3
+    }
+
+    #[inline]
+    fn get_client(&self) -> &HttpClient {
+        &self.client
+    }
+    #[inline]
+    fn read_client(&'_ mut self) -> impl Future<Output = Result<&'_ HttpClient, LibError>> + Send + '_ {
+        self.read_client()
+    }
+    #[inline]
+    fn read_until_complete(&'_ mut self) -> impl Future<Output = Result<&'_ HttpClient, LibError>> + Send + '_ {
+        self.read_until_complete()
+    }
+    #[inline]
+    fn read_until_head_complete(&'_ mut self) -> impl Future<Output = Result<&'_ HttpClient, LibError>> + Send + '_ {
+        self.read_until_head_complete()
+    }
+
+    #[inline] fn add_header(&mut self, header: &str, value: &str) { self.add_header(&header.to_lowercase(), value) }
+    #[inline] fn set_header(&mut self, header: &str, value: &str){ self.set_header(&header.to_lowercase(), value) }
+    #[inline] fn del_header(&mut self, header: &str) -> Option<Vec<String>>{ self.del_header(&header.to_lowercase()) }
+
+    #[inline]
+    fn set_status(&mut self, code: u16, _message: String) {
+        self.status = code;
+    }
+    #[inline]
+    fn write<'a>(&'a mut self, body: &'a [u8] ) -> impl Future<Output = Result<(), LibError>> + Send + 'a {
+        self.write(body)
+    }
+    #[inline]
+    fn close<'a>(&'a mut self, body: &'a [u8] ) -> impl Future<Output = Result<(), LibError>> + Send + 'a {
+        self.close(body)
+    }
+    #[inline]
+    fn flush<'a>(&'a mut self) -> impl Future<Output = Result<(), LibError>> + Send + 'a {
+        self.flush()
+    }
+}
@@ -0,0 +1,532 @@
+use std::{io, pin::Pin, sync::Mutex as SyncMutex, task::{Context, Poll}};
+
+use bytes::BytesMut;
+use dashmap::DashMap;
+use tokio::{io::{AsyncReadExt, AsyncWriteExt, ReadBuf}, sync::{Mutex as AsyncMutex, Notify}};
+
+use crate::{http2::{hpack::decoder::DecodedHeaders, session::Mode}, http3::{core::{Http3ErrorCode, Http3Frame, Http3FrameType, Http3Settings, Http3StreamType, random_grease, read_varint, write_varint}, qpack::{QpackError, decoder::Decoder, encoder::Encoder}, transport::{Http3RecvStream, Http3SendStream, Http3Transport}}, shared::{LibError, LibResult, ReadStream}};
+
+pub const READ_BUFFER_SIZE: usize = 16384;
+pub const MAX_FRAME_SIZE: u64 = 1 << 20;
+
+
+// what was read of a request stream, with what the DATA frame being read still has to hand out
+#[derive(Debug, Default)]
+pub struct Http3ReadBuffer {
+    pub buf: BytesMut,
+    pub data_left: u64,
+}
+impl Http3ReadBuffer {
+    pub fn new() -> Self {
+        Self { buf: BytesMut::with_capacity(READ_BUFFER_SIZE), data_left: 0 }
+    }
+}
+
+// a unidirectional stream of the peer that stays open for the whole connection, with what was read of it
+pub type Http3CriticalStream<R> = AsyncMutex<Option<(R, BytesMut)>>;
+
+enum Http3Event<R, S> {
+    Uni(LibResult<R>),
+    UniType((R, u64)),
+    Bi(LibResult<(R, S)>),
+    Control(io::Result<usize>),
+    QpackEncoder(io::Result<usize>),
+    QpackDecoder(io::Result<usize>),
+}
+
+async fn read_critical<R: ReadStream>(slot: &Http3CriticalStream<R>) -> io::Result<usize> {
+    let mut slot = slot.lock().await;
+
+    match slot.as_mut() {
+        Some((recv, buf)) => recv.read_buf(buf).await,
+        None => std::future::pending().await,
+    }
+}
+
+// 6.2, the first uni stream of the peer to have sent its whole type, a stream that ends before is dropped
+// only the type is read off, whatever follows stays in the stream for the slot it goes to
+fn poll_uni_type<R: ReadStream>(pending: &mut Vec<(R, BytesMut)>, cx: &mut Context<'_>) -> Poll<(R, u64)> {
+    let mut i = 0;
+
+    while i < pending.len() {
+        let (recv, buf) = &mut pending[i];
+        let needed = buf.first().map_or(1, |first| 1 << (first >> 6));
+
+        if buf.len() == needed {
+            let (recv, buf) = pending.swap_remove(i);
+            return Poll::Ready((recv, read_varint(&buf, &mut 0).unwrap_or_default()));
+        }
+
+        let mut bytes = [0; 8];
+        let mut read = ReadBuf::new(&mut bytes[..needed - buf.len()]);
+        match Pin::new(recv).poll_read(cx, &mut read) {
+            Poll::Ready(Ok(())) if !read.filled().is_empty() => buf.extend_from_slice(read.filled()),
+            Poll::Ready(_) => { pending.swap_remove(i); },
+            Poll::Pending => i += 1,
+        }
+    }
+    Poll::Pending
+}
+
+// the connection level of http/3, driven by calling next() like the http/2 session
+pub struct Http3Session<T: Http3Transport> {
+    pub transport: T,
+    pub mode: Mode,
+    // what we announce, set before start()
+    pub settings: Http3Settings,
+    // largest frame payload read whole, DATA is handed out as it arrives instead
+    pub max_frame_size: u64,
+
+    pub encoder: SyncMutex<Encoder>,
+    pub decoder: SyncMutex<Decoder>,
+
+    pub control: AsyncMutex<Option<T::Send>>,
+    pub encoder_stream: AsyncMutex<Option<T::Send>>,
+    pub decoder_stream: AsyncMutex<Option<T::Send>>,
+
+    pub peer_control: Http3CriticalStream<T::Recv>,
+    pub peer_encoder: Http3CriticalStream<T::Recv>,
+    pub peer_decoder: Http3CriticalStream<T::Recv>,
+    // uni streams of the peer whose type is still being read, without holding up the rest of the connection
+    pub pending_uni: SyncMutex<Vec<(T::Recv, BytesMut)>>,
+
+    pub peer_settings: SyncMutex<Option<Http3Settings>>,
+    pub settings_received: Notify,
+
+    // sections the decoder held back until the encoder stream caught up
+    pub unblocked: DashMap<u64, DecodedHeaders>,
+    pub unblocked_notify: Notify,
+
+    // request streams the peer opened, until they are taken
+    pub requests: DashMap<u64, (T::Recv, T::Send)>,
+    pub peer_stream_id: SyncMutex<Option<u64>>,
+
+    pub goaway: SyncMutex<Option<u64>>,
+    pub goaway_sent: SyncMutex<Option<u64>>,
+    pub max_push_id: SyncMutex<Option<u64>>,
+
+    pub driver: AsyncMutex<()>,
+    pub error: SyncMutex<Option<Http3ErrorCode>>,
+}
+impl<T: Http3Transport> Http3Session<T> {
+    pub fn new(transport: T, mode: Mode, settings: Http3Settings) -> Self {
+        let mut decoder = Decoder::new(settings.qpack_max_table_capacity.unwrap_or(0) as usize, settings.qpack_blocked_streams.unwrap_or(0) as usize);
+        decoder.max_field_section_size = settings.max_field_section_size.map_or(usize::MAX, |s| s as usize);
+
+        Self {
+            transport, mode, settings,
+            max_frame_size: MAX_FRAME_SIZE,
+            encoder: SyncMutex::new(Encoder::new()),
+            decoder: SyncMutex::new(decoder),
+            control: AsyncMutex::new(None),
+            encoder_stream: AsyncMutex::new(None),
+            decoder_stream: AsyncMutex::new(None),
+            peer_control: AsyncMutex::new(None),
+            peer_encoder: AsyncMutex::new(None),
+            peer_decoder: AsyncMutex::new(None),
+            pending_uni: SyncMutex::new(Vec::new()),
+            peer_settings: SyncMutex::new(None),
+            settings_received: Notify::new(),
+            unblocked: DashMap::new(),
+            unblocked_notify: Notify::new(),
+            requests: DashMap::new(),
+            peer_stream_id: SyncMutex::new(None),
+            goaway: SyncMutex::new(None),
+            goaway_sent: SyncMutex::new(None),
+            max_push_id: SyncMutex::new(None),
+            driver: AsyncMutex::new(()),
+            error: SyncMutex::new(None),
+        }
+    }
+    pub fn new_client(transport: T) -> Self {
+        Self::new(transport, Mode::Client, Http3Settings::default())
+    }
+    pub fn new_server(transport: T) -> Self {
+        Self::new(transport, Mode::Server, Http3Settings::default())
+    }
+
+    // 6.2, opens the control stream with our SETTINGS and the two qpack streams
+    pub async fn start(&self) -> LibResult<()> {
+        let mut settings = self.settings.clone();
+        // 7.2.4.1, a reserved setting keeps peers from choking on ones they do not know
+        settings.set_unknown(random_grease(), rand::random_range(0..1 << 20));
+
+        let mut head = Vec::new();
+        write_varint(&mut head, Http3StreamType::Control.into());
+        Http3Frame::create_into(&mut head, Http3FrameType::Settings, &settings.to_vec());
+        *self.control.lock().await = Some(self.open_critical(&head).await?);
+
+        let mut head = Vec::new();
+        write_varint(&mut head, Http3StreamType::QpackEncoder.into());
+        *self.encoder_stream.lock().await = Some(self.open_critical(&head).await?);
+
+        let mut head = Vec::new();
+        write_varint(&mut head, Http3StreamType::QpackDecoder.into());
+        *self.decoder_stream.lock().await = Some(self.open_critical(&head).await?);
+
+        Ok(())
+    }
+    async fn open_critical(&self, head: &[u8]) -> LibResult<T::Send> {
+        let mut stream = self.transport.open_uni().await?;
+        stream.write_all(head).await?;
+        stream.flush().await?;
+        Ok(stream)
+    }
+
+    pub fn get_error(&self) -> Option<Http3ErrorCode> {
+        *self.error.lock().unwrap()
+    }
+    // closes the connection with a connection error, everything waiting on the session is woken
+    pub fn fail(&self, code: Http3ErrorCode) -> LibError {
+        let code = *self.error.lock().unwrap().get_or_insert(code);
+
+        self.transport.close(code.into(), b"");
+        self.settings_received.notify_waiters();
+        self.unblocked_notify.notify_waiters();
+        LibError::Http3(code)
+    }
+
+
+    // handles whatever arrives first, Some(stream id) when the peer opened a request stream
+    pub async fn next(&self) -> LibResult<Option<u64>> {
+        let _driver = self.driver.lock().await;
+        if let Some(code) = self.get_error() { return Err(LibError::Http3(code)) }
+
+        let event = tokio::select! {
+            uni = self.transport.accept_uni() => Http3Event::Uni(uni),
+            uni = std::future::poll_fn(|cx| poll_uni_type(&mut self.pending_uni.lock().unwrap(), cx)) => Http3Event::UniType(uni),
+            bi = self.transport.accept_bi() => Http3Event::Bi(bi),
+            read = read_critical(&self.peer_control) => Http3Event::Control(read),
+            read = read_critical(&self.peer_encoder) => Http3Event::QpackEncoder(read),
+            read = read_critical(&self.peer_decoder) => Http3Event::QpackDecoder(read),
+        };
+
+        let res = match event {
+            Http3Event::Uni(recv) => {
+                self.pending_uni.lock().unwrap().push((recv?, BytesMut::new()));
+                Ok(None)
+            },
+            Http3Event::UniType((recv, stype)) => self.handle_uni(recv, stype).await.map(|_| None),
+            Http3Event::Bi(bi) => self.handle_bi(bi?),
+            // 6.2.1, closing a critical stream closes the connection
+            Http3Event::Control(Ok(0) | Err(_)) | Http3Event::QpackEncoder(Ok(0) | Err(_)) | Http3Event::QpackDecoder(Ok(0) | Err(_)) => {
+                Err(LibError::Http3(Http3ErrorCode::ClosedCriticalStream))
+            },
+            Http3Event::Control(Ok(_)) => self.handle_control().await.map(|_| None),
+            Http3Event::QpackEncoder(Ok(_)) => self.handle_qpack_encoder().await.map(|_| None),
+            Http3Event::QpackDecoder(Ok(_)) => self.handle_qpack_decoder().await.map(|_| None),
+        };
+
+        match res {
+            Err(LibError::Http3(code)) => Err(self.fail(code)),
+            res => res,
+        }
+    }
+
+    async fn handle_uni(&self, mut recv: T::Recv, stype: u64) -> LibResult<()> {
+        let slot = match Http3StreamType::from(stype) {
+            Http3StreamType::Control => &self.peer_control,
+            Http3StreamType::QpackEncoder => &self.peer_encoder,
+            Http3StreamType::QpackDecoder => &self.peer_decoder,
+            // 4.6, we never send MAX_PUSH_ID, and only servers push
+            Http3StreamType::Push if self.mode.is_server() => return Err(LibError::Http3(Http3ErrorCode::StreamCreationError)),
+            Http3StreamType::Push => return Err(LibError::Http3(Http3ErrorCode::IdError)),
+            // 6.2.3, unknown and reserved types are not read
+            Http3StreamType::Unknown(_) => {
+                recv.stop(Http3ErrorCode::StreamCreationError.into());
+                return Ok(());
+            },
+        };
+
+        let mut slot = slot.lock().await;
+        if slot.is_some() { return Err(LibError::Http3(Http3ErrorCode::StreamCreationError)) }
+
+        *slot = Some((recv, BytesMut::with_capacity(READ_BUFFER_SIZE)));
+        Ok(())
+    }
+    fn handle_bi(&self, (mut recv, mut send): (T::Recv, T::Send)) -> LibResult<Option<u64>> {
+        // 6.1, servers do not open bidirectional streams
+        if self.mode.is_client() { return Err(LibError::Http3(Http3ErrorCode::StreamCreationError)) }
+
+        let stream_id = recv.stream_id();
+
+        // 5.2, requests past our GOAWAY were never seen
+        if let Some(last) = *self.goaway_sent.lock().unwrap() && stream_id >= last {
+            recv.stop(Http3ErrorCode::RequestRejected.into());
+            send.reset(Http3ErrorCode::RequestRejected.into());
+            return Ok(None);
+        }
+
+        let mut peer_stream_id = self.peer_stream_id.lock().unwrap();
+        *peer_stream_id = Some(peer_stream_id.map_or(stream_id, |id| id.max(stream_id)));
+
+        self.requests.insert(stream_id, (recv, send));
+        Ok(Some(stream_id))
+    }
+
+    async fn handle_control(&self) -> LibResult<()> {
+        let frames = {
+            let mut slot = self.peer_control.lock().await;
+            let Some((_, buf)) = slot.as_mut() else { return Ok(()) };
+
+            let mut frames = Vec::new();
+            while let Some(frame) = Http3Frame::split_from(buf, self.max_frame_size).map_err(LibError::Http3)? {
+                frames.push(frame);
+            }
+            frames
+        };
+
+        for frame in frames {
+            self.handle_control_frame(frame).await?;
+        }
+        Ok(())
+    }
+    async fn handle_control_frame(&self, frame: Http3Frame) -> LibResult<()> {
+        let first = self.peer_settings.lock().unwrap().is_none();
+
+        // 6.2.1, SETTINGS comes first and only once
+        if first && frame.ftype != Http3FrameType::Settings { return Err(LibError::Http3(Http3ErrorCode::MissingSettings)) }
+
+        match frame.ftype {
+            Http3FrameType::Settings => {
+                if !first { return Err(LibError::Http3(Http3ErrorCode::FrameUnexpected)) }
+                let settings = Http3Settings::from(&frame.payload).map_err(LibError::Http3)?;
+
+                let mut stream = self.encoder_stream.lock().await;
+                let instructions = {
+                    let mut encoder = self.encoder.lock().unwrap();
+                    encoder.apply_settings(settings.qpack_max_table_capacity.unwrap_or(0) as usize, settings.qpack_blocked_streams.unwrap_or(0) as usize)
+                        .map_err(|_| LibError::Http3(Http3ErrorCode::InternalError))?;
+                    if stream.is_some() { encoder.take_instructions() } else { Vec::new() }
+                };
+                if let Some(stream) = stream.as_mut() && !instructions.is_empty() {
+                    stream.write_all(&instructions).await?;
+                    stream.flush().await?;
+                }
+
+                *self.peer_settings.lock().unwrap() = Some(settings);
+                self.settings_received.notify_waiters();
+            },
+            // 5.2, the id may only go down, a server sends a client request stream id
+            Http3FrameType::Goaway => {
+                let id = frame.varint_payload().ok_or(LibError::Http3(Http3ErrorCode::FrameError))?;
+                if self.mode.is_client() && id % 4 != 0 { return Err(LibError::Http3(Http3ErrorCode::IdError)) }
+
+                let mut goaway = self.goaway.lock().unwrap();
+                if goaway.is_some_and(|last| id > last) { return Err(LibError::Http3(Http3ErrorCode::IdError)) }
+                *goaway = Some(id);
+            },
+            // 7.2.7, only clients send it and it may not go down
+            Http3FrameType::MaxPushId => {
+                if self.mode.is_client() { return Err(LibError::Http3(Http3ErrorCode::FrameUnexpected)) }
+                let id = frame.varint_payload().ok_or(LibError::Http3(Http3ErrorCode::FrameError))?;
+
+                let mut max_push_id = self.max_push_id.lock().unwrap();
+                if max_push_id.is_some_and(|last| id < last) { return Err(LibError::Http3(Http3ErrorCode::IdError)) }
+                *max_push_id = Some(id);
+            },
+            // nothing is ever pushed, so there is nothing to cancel
+            Http3FrameType::CancelPush => {
+                frame.varint_payload().ok_or(LibError::Http3(Http3ErrorCode::FrameError))?;
+            },
+            Http3FrameType::Data | Http3FrameType::Headers | Http3FrameType::PushPromise => {
+                return Err(LibError::Http3(Http3ErrorCode::FrameUnexpected));
+            },
+            ftype if ftype.is_http2_only() => return Err(LibError::Http3(Http3ErrorCode::FrameUnexpected)),
+            // 9, unknown frame types are ignored
+            Http3FrameType::Unknown(_) => (),
+        }
+        Ok(())
+    }
+
+    async fn handle_qpack_encoder(&self) -> LibResult<()> {
+        let data = {
+            let mut slot = self.peer_encoder.lock().await;
+            let Some((_, buf)) = slot.as_mut() else { return Ok(()) };
+            buf.split().freeze()
+        };
+
+        let unblocked = {
+            let mut decoder = self.decoder.lock().unwrap();
+            decoder.receive_instructions(&data).map_err(|_| LibError::Http3(Http3ErrorCode::QpackEncoderStreamError))?;
+            decoder.decode_unblocked().map_err(|_| LibError::Http3(Http3ErrorCode::QpackDecompressionFailed))?
+        };
+
+        if !unblocked.is_empty() {
+            for (stream_id, headers) in unblocked {
+                self.unblocked.insert(stream_id, headers);
+            }
+            self.unblocked_notify.notify_waiters();
+        }
+        self.write_decoder_instructions().await
+    }
+    async fn handle_qpack_decoder(&self) -> LibResult<()> {
+        let data = {
+            let mut slot = self.peer_decoder.lock().await;
+            let Some((_, buf)) = slot.as_mut() else { return Ok(()) };
+            buf.split().freeze()
+        };
+
+        self.encoder.lock().unwrap().receive_instructions(&data).map_err(|_| LibError::Http3(Http3ErrorCode::QpackDecoderStreamError))
+    }
+    // the stream is locked first so instructions go out in the order they were made
+    async fn write_decoder_instructions(&self) -> LibResult<()> {
+        let mut stream = self.decoder_stream.lock().await;
+        let Some(stream) = stream.as_mut() else { return Ok(()) };
+
+        let instructions = self.decoder.lock().unwrap().take_instructions();
+        if !instructions.is_empty() {
+            stream.write_all(&instructions).await?;
+            stream.flush().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn wait_settings(&self) -> LibResult<Http3Settings> {
+        loop {
+            let received = self.settings_received.notified();
+
+            if let Some(settings) = self.peer_settings.lock().unwrap().clone() { return Ok(settings) }
+            if let Some(code) = self.get_error() { return Err(LibError::Http3(code)) }
+
+            received.await;
+        }
+    }
+
+
+    // 4.1, a new request stream, refused once the server sent GOAWAY
+    pub async fn open_request(&self) -> LibResult<(T::Recv, T::Send)> {
+        if let Some(code) = self.get_error() { return Err(LibError::Http3(code)) }
+        if self.goaway.lock().unwrap().is_some() { return Err(LibError::Refused) }
+
+        self.transport.open_bi().await
+    }
+    pub fn take_request(&self, stream_id: u64) -> Option<(T::Recv, T::Send)> {
+        self.requests.remove(&stream_id).map(|(_, streams)| streams)
+    }
+
+    // a whole HEADERS frame, the encoder stream gets what the decoder needs for it first
+    pub async fn encode_headers(&self, stream_id: u64, headers: &[(&[u8], &[u8])]) -> LibResult<Vec<u8>> {
+        let mut stream = self.encoder_stream.lock().await;
+
+        let (block, instructions) = {
+            let mut encoder = self.encoder.lock().unwrap();
+            let block = encoder.encode_section(stream_id, headers)?;
+            (block, if stream.is_some() { encoder.take_instructions() } else { Vec::new() })
+        };
+        if let Some(stream) = stream.as_mut() && !instructions.is_empty() {
+            stream.write_all(&instructions).await?;
+            stream.flush().await?;
+        }
+
+        Ok(Http3Frame::create(Http3FrameType::Headers, &block))
+    }
+    // the payload of a HEADERS frame, a blocked section waits for next() to read the insertions it needs
+    pub async fn decode_headers(&self, stream_id: u64, block: &[u8]) -> LibResult<DecodedHeaders> {
+        let decoded = self.decoder.lock().unwrap().decode_section(stream_id, block);
+
+        match decoded {
+            Ok(Some(headers)) => {
+                self.write_decoder_instructions().await?;
+                Ok(headers)
+            },
+            Ok(None) => loop {
+                let unblocked = self.unblocked_notify.notified();
+
+                if let Some((_, headers)) = self.unblocked.remove(&stream_id) { return Ok(headers) }
+                if let Some(code) = self.get_error() { return Err(LibError::Http3(code)) }
+
+                unblocked.await;
+            },
+            // rfc9204 4.4.2, the section is dropped unread so the encoder has to hear it was cancelled
+            Err(QpackError::FieldSectionTooLarge(_)) => {
+                self.decoder.lock().unwrap().cancel_stream(stream_id);
+                self.write_decoder_instructions().await?;
+                Err(LibError::Http3(Http3ErrorCode::ExcessiveLoad))
+            },
+            Err(_) => Err(self.fail(Http3ErrorCode::QpackDecompressionFailed)),
+        }
+    }
+    // rfc9204 4.4.2, a request stream abandoned before its headers were decoded
+    pub async fn cancel_request(&self, stream_id: u64) -> LibResult<()> {
+        self.decoder.lock().unwrap().cancel_stream(stream_id);
+        self.unblocked.remove(&stream_id);
+        self.write_decoder_instructions().await
+    }
+
+    // the next frame of a request stream, None once the peer finished it
+    // DATA comes as pieces of its payload as they arrive, so max_frame_size only limits the other frames
+    pub async fn read_frame<R: ReadStream>(&self, recv: &mut R, input: &mut Http3ReadBuffer) -> LibResult<Option<Http3Frame>> {
+        loop {
+            if input.data_left > 0 {
+                if !input.buf.is_empty() {
+                    let len = input.data_left.min(input.buf.len() as u64);
+                    input.data_left -= len;
+                    return Ok(Some(Http3Frame { ftype: Http3FrameType::Data, payload: input.buf.split_to(len as usize).freeze() }));
+                }
+            }
+            else if let Some(length) = Http3Frame::split_data_head(&mut input.buf) {
+                input.data_left = length;
+                continue;
+            }
+            else if let Some(frame) = Http3Frame::split_from(&mut input.buf, self.max_frame_size).map_err(|code| self.fail(code))? {
+                return Ok(Some(frame));
+            }
+
+            if recv.read_buf(&mut input.buf).await? == 0 {
+                // 7.1, a frame cut off by the end of the stream
+                if input.buf.is_empty() && input.data_left == 0 { return Ok(None) }
+                else { return Err(self.fail(Http3ErrorCode::FrameError)) }
+            }
+        }
+    }
+    // the next DATA or HEADERS of a request stream, unknown frames are skipped
+    pub async fn read_message_frame<R: ReadStream>(&self, recv: &mut R, input: &mut Http3ReadBuffer) -> LibResult<Option<Http3Frame>> {
+        loop {
+            let frame = self.read_frame(recv, input).await?;
+
+            match frame.as_ref().map(|f| f.ftype) {
+                None | Some(Http3FrameType::Data | Http3FrameType::Headers) => return Ok(frame),
+                // 7.2.8, the http/2 leftovers are an error anywhere
+                Some(ftype) if ftype.is_unknown() && !ftype.is_http2_only() => (),
+                // 4.1, control frames and pushes we never allowed
+                Some(_) => return Err(self.fail(Http3ErrorCode::FrameUnexpected)),
+            }
+        }
+    }
+    // 4.1, the HEADERS a message starts with, DATA before it is an error
+    pub async fn read_message_head<R: ReadStream>(&self, stream_id: u64, recv: &mut R, input: &mut Http3ReadBuffer) -> LibResult<DecodedHeaders> {
+        match self.read_message_frame(recv, input).await? {
+            Some(frame) if frame.ftype == Http3FrameType::Headers => self.decode_headers(stream_id, &frame.payload).await,
+            Some(_) => Err(self.fail(Http3ErrorCode::FrameUnexpected)),
+            None => Err(LibError::StreamClosed),
+        }
+    }
+
+    pub async fn write_control(&self, frame: &[u8]) -> LibResult<()> {
+        let mut control = self.control.lock().await;
+        let control = control.as_mut().ok_or(LibError::NotConnected)?;
+
+        control.write_all(frame).await?;
+        control.flush().await?;
+        Ok(())
+    }
+    // 5.2, a server names the first request stream it will not handle, a client the first push id
+    pub async fn send_goaway(&self) -> LibResult<()> {
+        let id =
+        if self.mode.is_server() { self.peer_stream_id.lock().unwrap().map_or(0, |id| id + 4) }
+        else { 0 };
+
+        // a second GOAWAY may only lower the id
+        let id = {
+            let mut sent = self.goaway_sent.lock().unwrap();
+            let id = sent.map_or(id, |last| last.min(id));
+            *sent = Some(id);
+            id
+        };
+        self.write_control(&Http3Frame::create_varint(Http3FrameType::Goaway, id)).await
+    }
+    pub fn get_goaway(&self) -> Option<u64> {
+        *self.goaway.lock().unwrap()
+    }
+}
@@ -0,0 +1,221 @@
+use std::{future::Future, io, pin::Pin, sync::{Arc, Mutex as SyncMutex, atomic::{AtomicU64, Ordering}}, task::{Context, Poll}};
+
+use tokio::{io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf}, sync::{Mutex as AsyncMutex, Notify, mpsc}};
+
+use crate::{http3::core::Http3ErrorCode, shared::{LibError, LibResult, ReadStream, WriteStream}};
+
+// what http/3 needs from the connection below it, quic or anything else with ordered streams and stream ids
+
+// the receiving half of a stream
+pub trait Http3RecvStream: ReadStream {
+    fn stream_id(&self) -> u64;
+    // asks the peer to stop sending, STOP_SENDING on quic
+    fn stop(&mut self, code: u64);
+}
+// the sending half of a stream, shutdown finishes it
+pub trait Http3SendStream: WriteStream {
+    fn stream_id(&self) -> u64;
+    // abandons what was not delivered yet, RESET_STREAM on quic
+    fn reset(&mut self, code: u64);
+}
+
+// the accept futures are raced against each other and have to be cancel safe
+pub trait Http3Transport: Send + Sync {
+    type Recv: Http3RecvStream + 'static;
+    type Send: Http3SendStream + 'static;
+
+    fn open_bi(&self) -> impl Future<Output = LibResult<(Self::Recv, Self::Send)>> + Send + '_;
+    fn open_uni(&self) -> impl Future<Output = LibResult<Self::Send>> + Send + '_;
+    fn accept_bi(&self) -> impl Future<Output = LibResult<(Self::Recv, Self::Send)>> + Send + '_;
+    fn accept_uni(&self) -> impl Future<Output = LibResult<Self::Recv>> + Send + '_;
+
+    // closes the whole connection with an application error code
+    fn close(&self, code: u64, reason: &[u8]);
+}
+
+
+// reset and stop codes travel next to the bytes of one direction
+#[derive(Debug, Default)]
+struct MemorySignals {
+    reset: SyncMutex<Option<u64>>,
+    stopped: SyncMutex<Option<u64>>,
+}
+
+#[derive(Debug)]
+pub struct MemoryRecv {
+    id: u64,
+    inner: DuplexStream,
+    signals: Arc<MemorySignals>,
+}
+impl AsyncRead for MemoryRecv {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+
+        match Pin::new(&mut self.inner).poll_read(cx, buf) {
+            // the end of a reset stream is an error, not a clean finish
+            Poll::Ready(Ok(())) if buf.filled().len() == filled && self.signals.reset.lock().unwrap().is_some() => {
+                Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+            },
+            poll => poll,
+        }
+    }
+}
+impl Http3RecvStream for MemoryRecv {
+    fn stream_id(&self) -> u64 {
+        self.id
+    }
+    fn stop(&mut self, code: u64) {
+        *self.signals.stopped.lock().unwrap() = Some(code);
+    }
+}
+
+#[derive(Debug)]
+pub struct MemorySend {
+    id: u64,
+    inner: Option<DuplexStream>,
+    signals: Arc<MemorySignals>,
+}
+impl AsyncWrite for MemorySend {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if self.signals.stopped.lock().unwrap().is_some() { return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())) }
+
+        match self.inner.as_mut() {
+            Some(inner) => Pin::new(inner).poll_write(cx, buf),
+            None => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.inner.as_mut() {
+            Some(inner) => Pin::new(inner).poll_flush(cx),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.inner.as_mut() {
+            Some(inner) => Pin::new(inner).poll_shutdown(cx),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+impl Http3SendStream for MemorySend {
+    fn stream_id(&self) -> u64 {
+        self.id
+    }
+    fn reset(&mut self, code: u64) {
+        *self.signals.reset.lock().unwrap() = Some(code);
+        self.inner = None;
+    }
+}
+
+fn memory_stream(id: u64, buffer_size: usize) -> (MemorySend, MemoryRecv) {
+    let (writer, reader) = tokio::io::duplex(buffer_size);
+    let signals = Arc::new(MemorySignals::default());
+
+    (MemorySend { id, inner: Some(writer), signals: signals.clone() }, MemoryRecv { id, inner: reader, signals })
+}
+
+#[derive(Debug, Default)]
+struct MemoryShared {
+    closed: SyncMutex<Option<u64>>,
+    notify: Notify,
+}
+
+// one end of an in-memory connection, for tests and for running http/3 over something that is not quic
+// closing only stops new streams, the open ones keep working
+#[derive(Debug)]
+pub struct MemoryTransport {
+    pub buffer_size: usize,
+
+    next_bi: AtomicU64,
+    next_uni: AtomicU64,
+    bi_out: mpsc::UnboundedSender<(MemoryRecv, MemorySend)>,
+    bi_in: AsyncMutex<mpsc::UnboundedReceiver<(MemoryRecv, MemorySend)>>,
+    uni_out: mpsc::UnboundedSender<MemoryRecv>,
+    uni_in: AsyncMutex<mpsc::UnboundedReceiver<MemoryRecv>>,
+    shared: Arc<MemoryShared>,
+}
+impl MemoryTransport {
+    // (client, server), stream ids follow rfc9000 2.1
+    pub fn pair(buffer_size: usize) -> (Self, Self) {
+        let shared = Arc::new(MemoryShared::default());
+        let (cbi_out, sbi_in) = mpsc::unbounded_channel();
+        let (sbi_out, cbi_in) = mpsc::unbounded_channel();
+        let (cuni_out, suni_in) = mpsc::unbounded_channel();
+        let (suni_out, cuni_in) = mpsc::unbounded_channel();
+
+        let client = Self {
+            buffer_size,
+            next_bi: AtomicU64::new(0),
+            next_uni: AtomicU64::new(2),
+            bi_out: cbi_out,
+            bi_in: AsyncMutex::new(cbi_in),
+            uni_out: cuni_out,
+            uni_in: AsyncMutex::new(cuni_in),
+            shared: shared.clone(),
+        };
+        let server = Self {
+            buffer_size,
+            next_bi: AtomicU64::new(1),
+            next_uni: AtomicU64::new(3),
+            bi_out: sbi_out,
+            bi_in: AsyncMutex::new(sbi_in),
+            uni_out: suni_out,
+            uni_in: AsyncMutex::new(suni_in),
+            shared,
+        };
+
+        (client, server)
+    }
+
+    fn closed(&self) -> LibResult<()> {
+        match *self.shared.closed.lock().unwrap() {
+            Some(code) => Err(LibError::Http3(Http3ErrorCode::from(code))),
+            None => Ok(()),
+        }
+    }
+    async fn accept<V>(&self, channel: &AsyncMutex<mpsc::UnboundedReceiver<V>>) -> LibResult<V> {
+        let closed = self.shared.notify.notified();
+        self.closed()?;
+
+        tokio::select! {
+            stream = async { channel.lock().await.recv().await } => stream.ok_or(LibError::ConnectionClosed),
+            _ = closed => self.closed().and(Err(LibError::ConnectionClosed)),
+        }
+    }
+}
+impl Http3Transport for MemoryTransport {
+    type Recv = MemoryRecv;
+    type Send = MemorySend;
+
+    async fn open_bi(&self) -> LibResult<(MemoryRecv, MemorySend)> {
+        self.closed()?;
+        let id = self.next_bi.fetch_add(4, Ordering::SeqCst);
+
+        let (send, peer_recv) = memory_stream(id, self.buffer_size);
+        let (peer_send, recv) = memory_stream(id, self.buffer_size);
+
+        self.bi_out.send((peer_recv, peer_send)).map_err(|_| LibError::ConnectionClosed)?;
+        Ok((recv, send))
+    }
+    async fn open_uni(&self) -> LibResult<MemorySend> {
+        self.closed()?;
+        let id = self.next_uni.fetch_add(4, Ordering::SeqCst);
+
+        let (send, peer_recv) = memory_stream(id, self.buffer_size);
+
+        self.uni_out.send(peer_recv).map_err(|_| LibError::ConnectionClosed)?;
+        Ok(send)
+    }
+    async fn accept_bi(&self) -> LibResult<(MemoryRecv, MemorySend)> {
+        self.accept(&self.bi_in).await
+    }
+    async fn accept_uni(&self) -> LibResult<MemoryRecv> {
+        self.accept(&self.uni_in).await
+    }
+
+    fn close(&self, code: u64, _reason: &[u8]) {
+        self.shared.closed.lock().unwrap().get_or_insert(code);
+        self.shared.notify.notify_waiters();
+    }
+}
+
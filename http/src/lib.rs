@@ -7,5 +7,8 @@ pub mod http2;
 pub mod http3;
 
 pub mod websocket;
+pub mod grpc;
 
 pub mod extra;
+#[cfg(test)]
+pub mod fuzz;
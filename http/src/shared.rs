@@ -2,7 +2,7 @@ use std::{fmt::Display, collections::HashMap, pin::Pin};
 
 use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::http2::hpack::{HpackError, huffman::HuffmanError};
+use crate::{grpc::core::GrpcError, http2::{core::{Http2ErrorCode, Http2Goaway}, hpack::{HpackError, huffman::HuffmanError}}, http3::{core::Http3ErrorCode, qpack::QpackError}};
 
 
 
@@ -505,14 +505,24 @@ pub enum LibError {
     InvalidString,
 
     NotAccepted,
-    ResetStream,
-    Goaway,
+    ResetStream(Http2ErrorCode),
+    Goaway(Http2Goaway),
     ProtocolError,
+    Refused,
+    Grpc(GrpcError),
+    Qpack(QpackError),
+    // a connection error with the code it was closed with, rfc9114 8
+    Http3(Http3ErrorCode),
 }
 impl LibError {
     pub fn io(&self) -> Option<&std::io::Error> { if let Self::Io(io) = self { Some(io) } else { None } }
     pub fn huffman(&self) -> Option<&HuffmanError> { if let Self::Huffman(err) = self { Some(err) } else { None } }
     pub fn hpack(&self) -> Option<&HpackError> { if let Self::Hpack(err) = self { Some(err) } else { None } }
+    pub fn reset_code(&self) -> Option<Http2ErrorCode> { if let Self::ResetStream(code) = self { Some(*code) } else { None } }
+    pub fn goaway(&self) -> Option<&Http2Goaway> { if let Self::Goaway(info) = self { Some(info) } else { None } }
+    pub fn grpc(&self) -> Option<&GrpcError> { if let Self::Grpc(err) = self { Some(err) } else { None } }
+    pub fn qpack(&self) -> Option<&QpackError> { if let Self::Qpack(err) = self { Some(err) } else { None } }
+    pub fn h3_code(&self) -> Option<Http3ErrorCode> { if let Self::Http3(code) = self { Some(*code) } else { None } }
     
     pub fn is_not_connected(&self) -> bool { if let Self::NotConnected = self { true } else { false } }
     pub fn is_connection_closed(&self) -> bool { if let Self::ConnectionClosed = self { true } else { false } }
@@ -526,9 +536,10 @@ impl LibError {
     pub fn is_invalid_string(&self) -> bool { if let Self::InvalidString = self { true } else { false } }
     
     pub fn is_not_accepted(&self) -> bool { if let Self::NotAccepted = self { true } else { false } }
-    pub fn is_reset_stream(&self) -> bool { if let Self::ResetStream = self { true } else { false } }
-    pub fn is_goaway(&self) -> bool { if let Self::Goaway = self { true } else { false } }
+    pub fn is_reset_stream(&self) -> bool { if let Self::ResetStream(_) = self { true } else { false } }
+    pub fn is_goaway(&self) -> bool { if let Self::Goaway(_) = self { true } else { false } }
     pub fn is_protocol_error(&self) -> bool { if let Self::ProtocolError = self { true } else { false } }
+    pub fn is_refused(&self) -> bool { if let Self::Refused = self { true } else { false } }
 }
 impl From<std::io::Error> for LibError {
     fn from(value: std::io::Error) -> Self {
@@ -545,6 +556,16 @@ impl From<HpackError> for LibError {
         Self::Hpack(value)
     }
 }
+impl From<GrpcError> for LibError {
+    fn from(value: GrpcError) -> Self {
+        Self::Grpc(value)
+    }
+}
+impl From<QpackError> for LibError {
+    fn from(value: QpackError) -> Self {
+        Self::Qpack(value)
+    }
+}
 impl Display for LibError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -564,9 +585,13 @@ impl Display for LibError {
             Self::InvalidString => writeln!(f, "Invalid string"),
 
             Self::NotAccepted => writeln!(f, "Not accepted"),
-            Self::ResetStream => writeln!(f, "stream reset"),
-            Self::Goaway => writeln!(f, "Goaway received"),
+            Self::ResetStream(code) => writeln!(f, "stream reset ({code})"),
+            Self::Goaway(info) => writeln!(f, "Goaway ({}, last stream {})", info.code, info.last_stream_id),
             Self::ProtocolError => writeln!(f, "Protocol error"),
+            Self::Refused => writeln!(f, "Stream refused, safe to retry"),
+            Self::Grpc(err) => writeln!(f, "grpc {}: {}", err.status, err.message),
+            Self::Qpack(err) => writeln!(f, "{err}"),
+            Self::Http3(code) => writeln!(f, "Connection closed ({code})"),
         }
     }
 }
@@ -576,6 +601,7 @@ impl std::error::Error for LibError {
             Self::Io(e) => Some(e),
             Self::Huffman(e) => Some(e),
             Self::Hpack(e) => Some(e),
+            Self::Qpack(e) => Some(e),
             _ => None,
         }
     }
@@ -587,6 +613,7 @@ impl std::error::Error for LibError {
             Self::Io(e) => Some(e),
             Self::Huffman(e) => Some(e),
             Self::Hpack(e) => Some(e),
+            Self::Qpack(e) => Some(e),
             _ => None,
         }
     }
@@ -2,7 +2,7 @@
 
 use std::sync::atomic::Ordering;
 
-use crate::{http1::{client::Http1Request, server::Http1Socket}, http2::{core::{Http2Frame, Http2FrameType, Http2Settings}, hpack::{Biterator, HeaderType, decoder::Decoder, encoder::Encoder}, session::Http2Session}, websocket::core::WebSocketFrame};
+use crate::{grpc::{client::GrpcRequest, core::{GrpcError, GrpcMessageReader, GrpcStatus, decode_grpc_message, encode_grpc_message, encode_message, format_timeout, parse_timeout}, server::GrpcSocket}, http1::{client::Http1Request, server::Http1Socket}, http2::{client::Http2Request, server::Http2Socket, core::{Http2AltSvc, Http2ErrorCode, Http2Extension, Http2Frame, Http2FrameType, Http2Goaway, Http2Keepalive, Http2Limits, Http2Padding, Http2Priority, Http2Settings}, hpack::{Biterator, HeaderType, HpackError, decoder::Decoder, encoder::Encoder, huffman::{HUFFMAN_TABLE, Huffman, HuffmanError}}, session::{Http2Rtt, Http2Scheduler, Http2Session, MAX_STREAM_ID, Mode}}, shared::HttpMethod, http3::{core::{Http3ErrorCode, Http3Frame, Http3FrameType, Http3Settings, Http3StreamType, is_grease, random_grease, read_varint, varint_len, write_varint}, client::Http3Request, server::Http3Socket, session::{Http3ReadBuffer, Http3Session}, transport::{Http3SendStream, Http3Transport, MemoryTransport}, qpack::{DecoderInstruction, EncoderInstruction, QpackError, decode_insert_count, decoder::Decoder as QpackDecoder, encode_insert_count, encoder::Encoder as QpackEncoder}}, websocket::core::WebSocketFrame, extra::PolyHttpSocket, shared::{HttpRequest, HttpSocket, HttpType, HttpVersion}};
 
 #[test]
 fn two_is_two(){
@@ -126,6 +126,260 @@ fn biterator(){
     assert_eq!(&bits as &[bool], biter.as_slice());
 }
 
+#[test]
+fn hpack_huffman() {
+    let huffman = Huffman::shared();
+
+    // rfc7541 C.4.1 and C.6.1
+    let www = [0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff];
+    assert_eq!(huffman.encode(b"www.example.com"), www);
+    assert_eq!(huffman.decode(&www).unwrap(), b"www.example.com");
+    assert_eq!(huffman.encoded_len(b"www.example.com"), www.len());
+    let date = [0xd0, 0x7a, 0xbe, 0x94, 0x10, 0x54, 0xd4, 0x44, 0xa8, 0x20, 0x05, 0x95, 0x04, 0x0b, 0x81, 0x66, 0xe0, 0x82, 0xa6, 0x2d, 0x1b, 0xff];
+    assert_eq!(huffman.decode(&date).unwrap(), b"Mon, 21 Oct 2013 20:13:21 GMT");
+
+    let all: Vec<u8> = (0..=255u8).chain((0..=255u8).rev()).collect();
+    let encoded = huffman.encode(&all);
+    assert_eq!(encoded.len(), huffman.encoded_len(&all));
+    assert_eq!(huffman.decode(&encoded).unwrap(), all);
+    assert!(huffman.decode(b"").unwrap().is_empty());
+
+    // 'a' is 00011, padded with ones and then with zeroes
+    assert_eq!(huffman.decode(&[0x1f]).unwrap(), b"a");
+    assert!(matches!(huffman.decode(&[0x18]), Err(HuffmanError::InvalidPadding)));
+    assert!(matches!(huffman.decode(&[0x1f, 0xff]), Err(HuffmanError::PaddingTooLarge)));
+    assert!(matches!(huffman.decode(&[0xff, 0xff, 0xff, 0xfc]), Err(HuffmanError::EOSInString)));
+
+    assert!(Huffman::from(&HUFFMAN_TABLE[..256]).is_err());
+    assert!(Huffman::from(&[(0, 8); 257]).is_err());
+}
+
+#[test]
+fn hpack_decoder_limits() {
+    let mut decoder: Decoder<'static> = Decoder::new(4096);
+
+    let mut pos = 0;
+    assert!(matches!(Decoder::read_int(&[0x1f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01], 5, &mut pos), Err(HpackError::IntegerOverflow)));
+    let mut pos = 0;
+    assert!(matches!(Decoder::read_int(&[0x1f, 0xff], 5, &mut pos), Err(HpackError::Truncated)));
+    // a string longer than what is left of the block
+    assert!(matches!(decoder.decode_all(&[0x00, 0x85, 0x00]), Err(HpackError::Truncated)));
+
+    assert!(matches!(decoder.decode_all(&[0x80]), Err(HpackError::InvalidIndex(0))));
+    assert!(matches!(decoder.decode_all(&[0xff, 0x00]), Err(HpackError::InvalidIndex(127))));
+    assert!(matches!(decoder.decode_all(&[0x00, 0x81, 0x18, 0x80]), Err(HpackError::Huffman(_))));
+
+    // 4096 is what was advertised, one more is not
+    assert!(decoder.decode_all(&[0x3f, 0xe1, 0x1f]).unwrap().is_empty());
+    assert!(matches!(decoder.decode_all(&[0x3f, 0xe2, 0x1f]), Err(HpackError::TableSizeExceeded(4097))));
+    assert_eq!(decoder.decode_all(&[0x20, 0x82]).unwrap().len(), 1);
+    assert!(matches!(decoder.decode_all(&[0x82, 0x20]), Err(HpackError::MisplacedTableSizeUpdate)));
+
+    // :method GET is 7 + 3 + 32
+    decoder.max_header_list_size = 84;
+    assert_eq!(decoder.decode_all(&[0x82, 0x82]).unwrap().len(), 2);
+    assert!(matches!(decoder.decode_all(&[0x82, 0x82, 0x82]), Err(HpackError::HeaderListTooLarge(126))));
+    // the block is still decoded to the end, the insert past the limit lands in the table
+    assert!(matches!(decoder.decode_all(&[0x3f, 0xe1, 0x1f, 0x82, 0x82, 0x40, 0x01, b'a', 0x01, b'b']), Err(HpackError::HeaderListTooLarge(118))));
+    assert_eq!(decoder.get(62), Some((&b"a"[..], &b"b"[..])));
+}
+
+#[tokio::test]
+async fn http2_header_list_limit() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = Http2Session::new_client(client);
+    let mut server = Http2Session::new_server(server);
+    server.limits.max_header_list_size = 200;
+
+    let big = vec![b'x'; 300];
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, true, &[(b":method", b"GET"), (b":scheme", b"http"), (b":path", b"/"), (b"x-big", &big), (b"x-after", b"1")]).await.unwrap();
+
+    // only the stream is answered, with a 431
+    assert_eq!(server.next().await.unwrap(), None);
+    assert!(server.streams.get(&stream_id).unwrap().reset);
+    client.next().await.unwrap();
+    let status = client.streams.get(&stream_id).unwrap().headers.iter().find(|(h, _)| h == b":status").map(|(_, v)| v.clone());
+    assert_eq!(status.as_deref(), Some(&b"431"[..]));
+
+    // the connection and both tables are still fine
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, true, &[(b":method", b"GET"), (b":scheme", b"http"), (b":path", b"/"), (b"x-after", b"1")]).await.unwrap();
+    assert_eq!(server.next().await.unwrap(), Some(stream_id));
+    assert!(server.streams.get(&stream_id).unwrap().headers.iter().any(|(h, v)| h == b"x-after" && v == b"1"));
+
+    // a lowered table size only holds the peer once the SETTINGS are acknowledged
+    server.send_settings(Http2Settings { header_table_size: Some(0), ..Http2Settings::empty() }).await.unwrap();
+    assert_eq!(server.decoder.lock().await.max_table_size, 4096);
+    client.next().await.unwrap();
+    server.next().await.unwrap();
+    assert_eq!(server.decoder.lock().await.max_table_size, 0);
+    assert_eq!(server.own_settings.lock().unwrap().unwrap().header_table_size, Some(0));
+    assert!(server.sent_settings.lock().unwrap().is_empty());
+}
+
+#[test]
+fn qpack_rfc_examples() {
+    // rfc9204 B.1, a static name reference with nothing in the dynamic table
+    let mut decoder = QpackDecoder::new(0, 0);
+    let section = [0x00, 0x00, 0x51, 0x0b, 0x2f, 0x69, 0x6e, 0x64, 0x65, 0x78, 0x2e, 0x68, 0x74, 0x6d, 0x6c];
+    assert_eq!(decoder.decode_section(0, &section).unwrap().unwrap(), vec![(b":path".to_vec(), b"/index.html".to_vec())]);
+    assert!(decoder.take_instructions().is_empty());
+
+    // B.2, the section arrives before the insertions it needs
+    let mut decoder = QpackDecoder::new(220, 1);
+    let encoder_stream = [
+        0x3f, 0xbd, 0x01,
+        0xc0, 0x0f, 0x77, 0x77, 0x77, 0x2e, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x2e, 0x63, 0x6f, 0x6d,
+        0xc1, 0x0c, 0x2f, 0x73, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x2f, 0x70, 0x61, 0x74, 0x68,
+    ];
+    assert!(decoder.decode_section(4, &[0x03, 0x81, 0x10, 0x11]).unwrap().is_none());
+    assert!(matches!(decoder.decode_section(8, &[0x03, 0x81, 0x10, 0x11]), Err(QpackError::TooManyBlockedStreams)));
+
+    // split in the middle of an instruction
+    decoder.receive_instructions(&encoder_stream[..10]).unwrap();
+    assert_eq!(decoder.table.inserted, 0);
+    assert!(decoder.decode_unblocked().unwrap().is_empty());
+    decoder.receive_instructions(&encoder_stream[10..]).unwrap();
+    assert_eq!((decoder.table.inserted, decoder.table.size), (2, 106));
+
+    let unblocked = decoder.decode_unblocked().unwrap();
+    assert_eq!(unblocked, vec![(4, vec![(b":authority".to_vec(), b"www.example.com".to_vec()), (b":path".to_vec(), b"/sample/path".to_vec())])]);
+    assert_eq!(decoder.take_instructions(), vec![0x02, 0x84]);
+
+    // B.3, a literal name and the increment that acknowledges it
+    decoder.receive_instructions(&[0x4a, 0x63, 0x75, 0x73, 0x74, 0x6f, 0x6d, 0x2d, 0x6b, 0x65, 0x79, 0x0c, 0x63, 0x75, 0x73, 0x74, 0x6f, 0x6d, 0x2d, 0x76, 0x61, 0x6c, 0x75, 0x65]).unwrap();
+    assert_eq!(decoder.table.get(2).unwrap(), (&b"custom-key"[..], &b"custom-value"[..]));
+    assert_eq!(decoder.take_instructions(), vec![0x01]);
+
+    // B.4, a duplicate of the oldest entry, then a section using the post-base and relative forms
+    decoder.receive_instructions(&[0x02]).unwrap();
+    assert_eq!(decoder.table.get(3).unwrap(), (&b":authority"[..], &b"www.example.com"[..]));
+    let headers = decoder.decode_section(8, &[0x05, 0x00, 0x80, 0xc1, 0x81]).unwrap().unwrap();
+    assert_eq!(headers, vec![(b":authority".to_vec(), b"www.example.com".to_vec()), (b":path".to_vec(), b"/".to_vec()), (b"custom-key".to_vec(), b"custom-value".to_vec())]);
+    assert_eq!(decoder.take_instructions(), vec![0x01, 0x88]);
+
+    decoder.cancel_stream(8);
+    assert_eq!(decoder.take_instructions(), vec![0x48]);
+
+    let mut buf = Vec::new();
+    EncoderInstruction::InsertWithNameRef(false, 2, b"x".to_vec()).write(decoder.huffman, &mut buf);
+    DecoderInstruction::InsertCountIncrement(300).write(&mut buf);
+    let mut pos = 0;
+    assert_eq!(EncoderInstruction::from(decoder.huffman, &buf, &mut pos).unwrap(), EncoderInstruction::InsertWithNameRef(false, 2, b"x".to_vec()));
+    assert_eq!(DecoderInstruction::from(&buf, &mut pos).unwrap(), DecoderInstruction::InsertCountIncrement(300));
+    assert_eq!(pos, buf.len());
+}
+
+#[test]
+fn qpack_encoder_decoder() {
+    let headers: &[(&[u8], &[u8])] = &[(b":method", b"GET"), (b":path", b"/api/items"), (b"user-agent", b"crate-test"), (b"x-custom", b"one"), (b"authorization", b"secret")];
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = headers.iter().map(|(h, v)| (h.to_vec(), v.to_vec())).collect();
+
+    let mut encoder = QpackEncoder::new();
+    let mut decoder = QpackDecoder::new(4096, 1);
+    encoder.apply_settings(4096, 1).unwrap();
+
+    // the first section references entries the decoder does not have yet
+    let first = encoder.encode_section(0, headers).unwrap();
+    assert!(decoder.decode_section(0, &first).unwrap().is_none());
+    assert_eq!(encoder.blocked_streams(), 1);
+
+    // with the one blocked stream used up the next section may only use what was confirmed
+    let second = encoder.encode_section(4, headers).unwrap();
+    assert_eq!(second[0], 0x00);
+    assert_eq!(decoder.decode_section(4, &second).unwrap().unwrap(), expected);
+
+    decoder.receive_instructions(&encoder.take_instructions()).unwrap();
+    assert_eq!(decoder.decode_unblocked().unwrap(), vec![(0, expected.clone())]);
+    encoder.receive_instructions(&decoder.take_instructions()).unwrap();
+    assert_eq!((encoder.known_received, encoder.blocked_streams()), (encoder.table.inserted, 0));
+    assert!(encoder.unacked.is_empty());
+
+    // now everything is acknowledged, the same short references no longer block
+    let third = encoder.encode_section(8, headers).unwrap();
+    assert!(encoder.take_instructions().is_empty());
+    assert!(third.len() == first.len() && first.len() < second.len());
+    assert_eq!(decoder.decode_section(8, &third).unwrap().unwrap(), expected);
+    // the sensitive value never went into the table
+    assert!(decoder.table.find(b"authorization").is_none());
+
+    // an entry an unacknowledged section refers to can not be evicted
+    let mut encoder = QpackEncoder::new();
+    encoder.apply_settings(100, 10).unwrap();
+    let first = encoder.encode_section(0, &[(b"x-a", b"aaaaaaaaaaaaaaaaaaaaaaaaa")]).unwrap();
+    let second = encoder.encode_section(4, &[(b"x-b", b"bbbbbbbbbbbbbbbbbbbbbbbbb")]).unwrap();
+    assert_eq!((encoder.table.inserted, encoder.unacked.len()), (1, 1));
+    assert!(matches!(encoder.set_capacity(0), Err(QpackError::CapacityExceeded(0))));
+
+    let mut decoder = QpackDecoder::new(100, 10);
+    decoder.receive_instructions(&encoder.take_instructions()).unwrap();
+    assert_eq!(decoder.decode_section(0, &first).unwrap().unwrap()[0].1, b"aaaaaaaaaaaaaaaaaaaaaaaaa");
+    assert_eq!(decoder.decode_section(4, &second).unwrap().unwrap()[0].1, b"bbbbbbbbbbbbbbbbbbbbbbbbb");
+    encoder.receive_instructions(&decoder.take_instructions()).unwrap();
+
+    // once acknowledged the old entry makes room
+    encoder.encode_section(8, &[(b"x-b", b"bbbbbbbbbbbbbbbbbbbbbbbbb")]).unwrap();
+    assert_eq!((encoder.table.inserted, encoder.table.entries.len()), (2, 1));
+
+    // while section 8 is unacknowledged x-b stays, a cancelled stream releases its references too
+    assert!(encoder.encode_section(12, &[(b"x-c", b"ccccccccccccccccccccccccc")]).unwrap().len() > 4);
+    assert_eq!(encoder.table.inserted, 2);
+    encoder.receive_instructions(&[0x88]).unwrap();
+    encoder.encode_section(12, &[(b"x-c", b"ccccccccccccccccccccccccc")]).unwrap();
+    assert_eq!((encoder.table.inserted, encoder.unacked.len()), (3, 1));
+    encoder.receive_instructions(&[0x4c]).unwrap();
+    assert!(encoder.unacked.is_empty());
+}
+
+#[test]
+fn qpack_errors() {
+    for total in 0..100u64 {
+        for required in total.saturating_sub(5)..=total + 6 {
+            let encoded = encode_insert_count(required, 6);
+            assert_eq!(decode_insert_count(encoded, 6, total).unwrap(), required);
+        }
+    }
+    assert!(matches!(decode_insert_count(13, 6, 0), Err(QpackError::InvalidInsertCount(13))));
+
+    let mut decoder = QpackDecoder::new(220, 0);
+    assert!(matches!(decoder.receive_instructions(&[0x3f, 0xbe, 0x01]), Err(QpackError::CapacityExceeded(221))));
+    let mut decoder = QpackDecoder::new(220, 0);
+    assert!(matches!(decoder.receive_instructions(&[0x3f, 0xbd, 0x01, 0x00]), Err(QpackError::InvalidIndex(0))));
+
+    let mut decoder = QpackDecoder::new(220, 0);
+    decoder.receive_instructions(&[0x3f, 0xbd, 0x01, 0xc0, 0x01, 0x61]).unwrap();
+    assert!(matches!(decoder.receive_instructions(&[0x85, 0x00]), Err(QpackError::InvalidIndex(5))));
+    // references at or past the required insert count, and a required insert count nothing needs
+    assert!(matches!(decoder.decode_section(0, &[0x02, 0x00, 0x10]), Err(QpackError::InvalidIndex(1))));
+    assert!(matches!(decoder.decode_section(0, &[0x02, 0x00, 0xd1]), Err(QpackError::InvalidInsertCount(1))));
+    assert!(matches!(decoder.decode_section(0, &[0x02, 0x81, 0xd1]), Err(QpackError::InvalidBase)));
+    assert!(matches!(decoder.decode_section(0, &[0x00, 0x00, 0x51, 0x85]), Err(QpackError::Truncated)));
+    assert!(matches!(decoder.decode_section(0, &[0x00, 0x00, 0xff, 0x24]), Err(QpackError::InvalidIndex(99))));
+    // indices and deltas of u64::MAX, one more than them must not wrap
+    let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+    assert!(matches!(decoder.decode_section(0, &[0x00, 0xff, 0x80, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]), Err(QpackError::InvalidBase)));
+    assert!(matches!(decoder.decode_section(0, &[&[0x00, 0x00, 0xbf, 0xc0][..], &max].concat()), Err(QpackError::InvalidIndex(u64::MAX))));
+    assert!(matches!(decoder.decode_section(0, &[&[0x00, 0x7f, 0x80][..], &max, &[0x1f, 0x01]].concat()), Err(QpackError::InvalidIndex(16))));
+    assert!(matches!(decoder.decode_section(0, &[&[0x00, 0x7f, 0x80][..], &max, &[0x07, 0x09, 0x01, 0x61]].concat()), Err(QpackError::InvalidIndex(16))));
+    assert!(matches!(QpackDecoder::new(220, 0).receive_instructions(&[&[0xbf, 0xc0][..], &max, &[0x01, 0x61]].concat()), Err(QpackError::InvalidIndex(u64::MAX))));
+    assert!(matches!(QpackError::from(HpackError::HeaderListTooLarge(70)), QpackError::FieldSectionTooLarge(70)));
+    assert!(matches!(QpackError::from(HpackError::TableSizeExceeded(9)), QpackError::CapacityExceeded(9)));
+    assert_eq!(decoder.decode_section(0, &[0x02, 0x00, 0x80]).unwrap().unwrap(), vec![(b":authority".to_vec(), b"a".to_vec())]);
+
+    decoder.max_field_section_size = 60;
+    assert!(matches!(decoder.decode_section(0, &[0x00, 0x00, 0xd1, 0xd1]), Err(QpackError::FieldSectionTooLarge(84))));
+
+    let mut encoder = QpackEncoder::new();
+    assert!(matches!(encoder.receive_instructions(&[0x01]), Err(QpackError::InvalidIncrement(1))));
+    encoder.pending.clear();
+    assert!(matches!(encoder.receive_instructions(&[0x00]), Err(QpackError::InvalidIncrement(0))));
+    encoder.pending.clear();
+    assert!(matches!(encoder.receive_instructions(&[0x84]), Err(QpackError::UnknownStream(4))));
+    encoder.pending.clear();
+    assert!(matches!(encoder.apply_settings(100, 0).and_then(|_| encoder.set_capacity(101)), Err(QpackError::CapacityExceeded(101))));
+}
+
 #[test]
 fn hpack_decode(){
     let mut decoder: Decoder<'static> = Decoder::new(4096);
@@ -216,6 +470,37 @@ fn hpack_encode(){
 
 }
 
+#[test]
+fn hpack_indexing_policy() {
+    let mut encoder: Encoder<'static> = Encoder::new(4096);
+    let mut decoder: Decoder<'static> = Decoder::new(4096);
+    let headers: &[(&[u8], &[u8])] = &[(b":method", b"GET"), (b"user-agent", b"httplib"), (b"authorization", b"Bearer secret"), (b"cookie", b"a=b")];
+
+    let mut first = Vec::new();
+    encoder.encode_block(&mut first, headers).unwrap();
+    let mut second = Vec::new();
+    encoder.encode_block(&mut second, headers).unwrap();
+
+    assert!(second.len() < first.len());
+    assert_eq!(encoder.dynamic_table.table.len(), 1);
+    assert!(encoder.find_exact(b"authorization", b"Bearer secret").is_none());
+    assert_eq!(second[2], 0x10 | 15); // authorization never indexed, static name index 23
+
+    let expected: Vec<_> = headers.iter().map(|(h, v)| (h.to_vec(), v.to_vec())).collect();
+    assert_eq!(decoder.decode_all(&first).unwrap(), expected);
+    assert_eq!(decoder.decode_all(&second).unwrap(), expected);
+
+    encoder.set_table_size(0);
+    encoder.set_table_size(1024);
+    let mut third = Vec::new();
+    encoder.encode_block(&mut third, headers).unwrap();
+
+    assert_eq!(&third[..4], &[0x20, 0x3f, 0xe1, 0x07]);
+    assert_eq!(decoder.decode_all(&third).unwrap(), expected);
+    assert_eq!(decoder.dynamic_table.table_size, 1024);
+    assert_eq!(encoder.pending_size, None);
+}
+
 #[test]
 fn http2_frame() {
     let frame_raw = [
@@ -329,10 +614,1427 @@ async fn http2() {
     server.next().await.unwrap(); // window update
 
 
-    client.send_goaway(0, 0, b"shutdown").await.unwrap();
+    client.send_goaway(0, Http2ErrorCode::NoError, b"shutdown").await.unwrap();
     server.next().await.unwrap();
     assert_eq!(server.goaway.load(Ordering::SeqCst), true);
 
     drop(server);
     drop(client);
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn http2_goaway_refuses_unprocessed() {
+    let (client, _server) = tokio::io::duplex(64 * 1024);
+    let client = Http2Session::new_client(client);
+
+    let first = client.open_stream().unwrap();
+    client.send_headers(first, false, &[(b":method", b"GET"), (b":path", b"/")]).await.unwrap();
+    let second = client.open_stream().unwrap();
+    client.send_headers(second, false, &[(b":method", b"GET"), (b":path", b"/")]).await.unwrap();
+
+    let goaway = Http2Frame::create(Http2FrameType::Goaway, 0, 0, None, Some(&[0, 0, 0, 1, 0, 0, 0, 0]), None);
+    client.handle(Http2Frame::from_owned(goaway).unwrap()).await.unwrap();
+
+    assert_eq!(client.goaway.load(Ordering::SeqCst), true);
+    assert_eq!(client.streams.get(&first).unwrap().refused, false);
+    assert_eq!(client.streams.get(&second).unwrap().refused, true);
+    assert!(client.send_data(second, true, b"").await.unwrap_err().is_refused());
+    assert_eq!(client.open_stream(), None);
+
+    let mut req = Http2Request::new(second, std::sync::Arc::new(client)).unwrap();
+    assert!(req.read_response().await.unwrap_err().is_refused());
+}
+
+#[tokio::test]
+async fn http2_graceful_shutdown() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    client.send_preface().await.unwrap();
+    assert_eq!(server.read_preface().await.unwrap(), true);
+
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, false, &[
+        (b":method", b"POST"),
+        (b":scheme", b"https"),
+        (b":authority", b"localhost"),
+        (b":path", b"/"),
+    ]).await.unwrap();
+    assert_eq!(server.next().await.unwrap(), Some(stream_id));
+
+    let cdriver = client.clone();
+    let cdriver = tokio::spawn(async move { while cdriver.next().await.is_ok() {} });
+    let sdriver = server.clone();
+    let sdriver = tokio::spawn(async move { while sdriver.next().await.is_ok() {} });
+
+    let sclone = server.clone();
+    let shutdown = tokio::spawn(async move { sclone.shutdown(Http2ErrorCode::NoError, std::time::Duration::from_secs(5)).await });
+
+    // in flight request still completes after the goaway
+    client.send_data(stream_id, true, b"ping").await.unwrap();
+    server.send_headers(stream_id, false, &[(b":status", b"200")]).await.unwrap();
+    server.send_data(stream_id, true, b"pong").await.unwrap();
+
+    shutdown.await.unwrap().unwrap();
+    assert_eq!(*server.goaway_sent.lock().unwrap(), Some(stream_id));
+
+    cdriver.await.unwrap();
+    sdriver.abort();
+
+    assert_eq!(client.goaway.load(Ordering::SeqCst), true);
+    assert_eq!(client.streams.get(&stream_id).unwrap().refused, false);
+    assert_eq!(client.streams.get(&stream_id).unwrap().body.to_vec(), b"pong");
+}
+
+#[tokio::test]
+async fn http2_shutdown_refuses_continued_headers() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    client.send_preface().await.unwrap();
+    assert_eq!(server.read_preface().await.unwrap(), true);
+
+    // an open stream keeps the shutdown waiting for it to drain
+    client.write_frame(Http2FrameType::Headers, 0x4, 1, None, Some(&[0x82, 0x87, 0x84]), None).await.unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(server.handle(frame).await.unwrap(), Some(1));
+
+    let sclone = server.clone();
+    let shutdown = tokio::spawn(async move { sclone.shutdown(Http2ErrorCode::NoError, std::time::Duration::from_secs(5)).await });
+
+    let ping = loop {
+        let frame = client.read_frame().await.unwrap();
+        if frame.ftype == Http2FrameType::Ping { break frame }
+        client.handle(frame).await.unwrap();
+    };
+
+    // an ack for some other ping does not end the round trip
+    client.send_ping(true, b"keepaliv").await.unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.ftype, Http2FrameType::Ping);
+    server.handle(frame).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(*server.goaway_sent.lock().unwrap(), Some(MAX_STREAM_ID));
+
+    // the client answers the shutdown ping, then the server takes the ack
+    client.handle(ping).await.unwrap();
+    loop {
+        let frame = server.read_frame().await.unwrap();
+        let ping = frame.ftype == Http2FrameType::Ping;
+        server.handle(frame).await.unwrap();
+        if ping { break }
+    }
+    while *server.goaway_sent.lock().unwrap() != Some(1) { tokio::task::yield_now().await }
+
+    // a header block split over CONTINUATION is refused as a whole, it never opens a stream
+    client.write_frame(Http2FrameType::Headers, 0x1, 3, None, Some(&[0x82, 0x87]), None).await.unwrap();
+    client.write_frame(Http2FrameType::Continuation, 0x4, 3, None, Some(&[0x84]), None).await.unwrap();
+    for _ in 0..2 {
+        let frame = server.read_frame().await.unwrap();
+        assert_eq!(server.handle(frame).await.unwrap(), None);
+    }
+    assert!(server.streams.get(&3).unwrap().refused);
+    shutdown.abort();
+}
+
+#[test]
+fn http2_error_codes() {
+    for code in 0..=0xd {
+        let typed = Http2ErrorCode::from(code);
+        assert!(!matches!(typed, Http2ErrorCode::Unknown(_)));
+        assert_eq!(Into::<u32>::into(typed), code);
+    }
+    assert_eq!(Http2ErrorCode::from(0xe), Http2ErrorCode::Unknown(0xe));
+    assert_eq!(Http2ErrorCode::EnhanceYourCalm.to_string(), "ENHANCE_YOUR_CALM");
+
+    let info = Http2Goaway { last_stream_id: 7, code: Http2ErrorCode::EnhanceYourCalm, debug: b"calm down".to_vec() };
+    assert_eq!(Http2Goaway::from(&info.to_vec()), Some(info));
+    assert_eq!(Http2Goaway::from(&[0, 0, 0, 1]), None);
+}
+
+#[tokio::test]
+async fn http2_reset_details() {
+    let (client, _server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, false, &[(b":method", b"GET"), (b":path", b"/")]).await.unwrap();
+
+    let rst = Http2Frame::create(Http2FrameType::RstStream, 0, stream_id, None, Some(&u32::to_be_bytes(Http2ErrorCode::Cancel.into())), None);
+    client.handle(Http2Frame::from_owned(rst).unwrap()).await.unwrap();
+
+    let err = client.send_data(stream_id, false, b"body").await.unwrap_err();
+    assert_eq!(err.reset_code(), Some(Http2ErrorCode::Cancel));
+
+    let mut req = Http2Request::new(stream_id, client.clone()).unwrap();
+    req.read_until_complete().await.unwrap();
+    assert_eq!(req.is_reset, Some(Http2ErrorCode::Cancel));
+
+    let goaway = Http2Frame::create(Http2FrameType::Goaway, 0, 0, None, Some(b"\x00\x00\x00\x01\x00\x00\x00\x0bslow down"), None);
+    client.handle(Http2Frame::from_owned(goaway).unwrap()).await.unwrap();
+    
+    let err = client.send_headers(3, true, &[(b":method", b"GET"), (b":path", b"/")]).await.unwrap_err();
+    let info = err.goaway().unwrap();
+    assert_eq!(info.last_stream_id, 1);
+    assert_eq!(info.code, Http2ErrorCode::EnhanceYourCalm);
+    assert_eq!(info.debug, b"slow down");
+}
+
+#[tokio::test]
+async fn http2_flood_limits() {
+    let (server, _client) = tokio::io::duplex(1024 * 1024);
+    let mut server = Http2Session::new_server(server);
+    server.limits = Http2Limits { max_resets_per_sec: 10, ..Http2Limits::default() };
+
+    let mut err = None;
+    for id in (1..100).step_by(2) {
+        let head = Http2Frame::create(Http2FrameType::Headers, 0x4, id, None, Some(b"\x82\x84\x86"), None);
+        let rst = Http2Frame::create(Http2FrameType::RstStream, 0, id, None, Some(&u32::to_be_bytes(Http2ErrorCode::Cancel.into())), None);
+
+        if let Err(e) = server.handle(Http2Frame::from_owned(head).unwrap()).await { err = Some(e); break }
+        if let Err(e) = server.handle(Http2Frame::from_owned(rst).unwrap()).await { err = Some(e); break }
+    }
+    assert_eq!(err.unwrap().goaway().unwrap().code, Http2ErrorCode::EnhanceYourCalm);
+    assert!(server.goaway_sent.lock().unwrap().is_some());
+
+    let (server, _client) = tokio::io::duplex(1024 * 1024);
+    let mut server = Http2Session::new_server(server);
+    server.limits = Http2Limits { max_header_block_size: 4096, ..Http2Limits::default() };
+
+    let head = Http2Frame::create(Http2FrameType::Headers, 0, 1, None, Some(b"\x82\x84\x86"), None);
+    server.handle(Http2Frame::from_owned(head).unwrap()).await.unwrap();
+
+    let mut err = None;
+    for _ in 0..100 {
+        let cont = Http2Frame::create(Http2FrameType::Continuation, 0, 1, None, Some(&[0x40; 1000]), None);
+        if let Err(e) = server.handle(Http2Frame::from_owned(cont).unwrap()).await { err = Some(e); break }
+    }
+    assert_eq!(err.unwrap().goaway().unwrap().code, Http2ErrorCode::EnhanceYourCalm);
+    assert!(server.streams.get(&1).unwrap().head.len() <= 4096);
+
+    // a peer that keeps sending PINGs without reading the ACKs
+    let (server, _client) = tokio::io::duplex(32);
+    let mut server = Http2Session::new_server(server);
+    server.limits = Http2Limits { max_pending_pings: 4, ..Http2Limits::default() };
+
+    for round in 0..6 {
+        assert!(server.goaway_sent.lock().unwrap().is_none(), "{round}");
+        let ping = Http2Frame::create(Http2FrameType::Ping, 0, 0, None, Some(&[round; 8]), None);
+        let _ = tokio::time::timeout(std::time::Duration::from_millis(20), server.handle(Http2Frame::from_owned(ping).unwrap())).await;
+    }
+    assert_eq!(server.pending_pings.lock().unwrap().len(), 4);
+    assert!(server.goaway_sent.lock().unwrap().is_some());
+}
+
+#[test]
+fn http2_priority() {
+    assert_eq!(Http2Priority::from(b"u=5, i"), Http2Priority::new(5, true));
+    assert_eq!(Http2Priority::from(b"i=?0,u=0"), Http2Priority::new(0, false));
+    assert_eq!(Http2Priority::from(b"u=9, foo=bar"), Http2Priority::default());
+    assert_eq!(Http2Priority::from(&Http2Priority::new(1, true).to_vec()), Http2Priority::new(1, true));
+    assert_eq!(Http2Priority::from_update(&Http2Priority::new(6, false).to_update(7)), Some((7, Http2Priority::new(6, false))));
+
+    let mut sched = Http2Scheduler::default();
+    sched.push(1, Http2Priority::default());
+    sched.push(3, Http2Priority::new(0, true));
+    sched.push(5, Http2Priority::new(0, true));
+    assert_eq!(sched.next(), Some(3));
+
+    sched.remove(3);
+    sched.push(3, Http2Priority::new(0, true));
+    assert_eq!(sched.next(), Some(5));
+}
+
+#[tokio::test]
+async fn http2_fair_scheduling() {
+    use tokio::io::AsyncReadExt;
+
+    let (server, mut client) = tokio::io::duplex(1024);
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    for id in [1, 3] {
+        let mut block = Vec::new();
+        Encoder::new(0).encode_block(&mut block, &[(b":method", b"GET"), (b":path", b"/"), (b"priority", b"u=2, i")]).unwrap();
+        let head = Http2Frame::create(Http2FrameType::Headers, 0x5, id, None, Some(&block), None);
+        server.handle(Http2Frame::from_owned(head).unwrap()).await.unwrap();
+    }
+    assert_eq!(server.streams.get(&3).unwrap().priority, Http2Priority::new(2, true));
+
+    let reader = tokio::spawn(async move {
+        let mut order = Vec::new();
+        while order.len() < 4 {
+            let mut head = [0; 9];
+            client.read_exact(&mut head).await.unwrap();
+            let mut pay = vec![0; u32::from_be_bytes([0, head[0], head[1], head[2]]) as usize];
+            client.read_exact(&mut pay).await.unwrap();
+            if head[3] == 0 { order.push(u32::from_be_bytes([head[5], head[6], head[7], head[8]])) }
+        }
+        order
+    });
+
+    // two frames per stream, both fit in the initial windows
+    server.settings.lock().unwrap().max_frame_size = Some(16384);
+    let body = vec![0u8; 17000];
+    let (a, b) = tokio::join!(server.send_data(1, true, &body), server.send_data(3, true, &body));
+    a.unwrap();
+    b.unwrap();
+
+    // each round has one frame of either stream, which goes first depends on poll order
+    let order = reader.await.unwrap();
+    assert!(order[..2].contains(&1) && order[..2].contains(&3));
+    assert!(order[2..].contains(&1) && order[2..].contains(&3));
+}
+
+#[tokio::test]
+async fn http2_trailers() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, false, &[(b":method", b"POST"), (b":scheme", b"http"), (b":path", b"/")]).await.unwrap();
+    
+    let mut req = Http2Request::new(stream_id, client.clone()).unwrap();
+    req.sent_head = true;
+    req.write(b"ping").await.unwrap();
+    req.send_trailers(&[("checksum", "abc")]).await.unwrap();
+    assert!(req.send_trailers(&[("checksum", "abc")]).await.unwrap_err().is_stream_closed());
+
+    assert_eq!(server.next().await.unwrap(), Some(stream_id));
+    server.next().await.unwrap();
+    server.next().await.unwrap();
+
+    let mut res = Http2Socket::new(stream_id, server.clone()).unwrap();
+    let body = res.read_until_complete().await.unwrap().body.clone();
+    assert_eq!(body, b"ping");
+    assert_eq!(res.trailers.get("checksum").unwrap(), &["abc"]);
+    assert!(res.client.headers.get("checksum").is_none());
+
+    client.next().await.unwrap(); // window update
+    client.next().await.unwrap(); // window update
+
+    res.write(b"pong").await.unwrap();
+    res.send_trailers(&[("grpc-status", "0"), ("grpc-message", "ok")]).await.unwrap();
+
+    for _ in 0..3 { client.next().await.unwrap(); }
+
+    let response = req.read_until_complete().await.unwrap();
+    assert_eq!(response.code, 200);
+    assert_eq!(response.body, b"pong");
+    assert!(response.headers.get("grpc-status").is_none());
+    assert_eq!(req.trailers.get("grpc-status").unwrap(), &["0"]);
+    assert_eq!(req.trailers.get("grpc-message").unwrap(), &["ok"]);
+}
+
+#[tokio::test]
+async fn http2_interim_responses() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, true, &[(b":method", b"GET"), (b":scheme", b"http"), (b":path", b"/")]).await.unwrap();
+    let mut req = Http2Request::new(stream_id, client.clone()).unwrap();
+    req.sent_head = true;
+
+    assert_eq!(server.next().await.unwrap(), Some(stream_id));
+    let mut res = Http2Socket::new(stream_id, server.clone()).unwrap();
+    res.read_until_complete().await.unwrap();
+
+    assert!(res.send_interim(200, &[]).await.unwrap_err().is_invalid());
+    res.send_interim(100, &[]).await.unwrap();
+    res.send_interim(103, &[("link", "</style.css>; rel=preload")]).await.unwrap();
+    res.write(b"final").await.unwrap();
+    res.close(b"").await.unwrap();
+    assert!(res.send_interim(103, &[]).await.unwrap_err().is_headers_sent());
+
+    // the 1xx blocks are not trailers, the final headers still follow
+    let driver = client.clone();
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+
+    let response = req.read_until_complete().await.unwrap();
+    assert_eq!(response.code, 200);
+    assert_eq!(response.body, b"final");
+    assert!(response.headers.get("link").is_none());
+    assert_eq!(req.interim.len(), 2);
+    assert_eq!(req.interim[0].0, 100);
+    assert_eq!(req.interim[1], (103, [("link".to_owned(), vec!["</style.css>; rel=preload".to_owned()])].into()));
+}
+
+#[tokio::test]
+async fn http2_server_push() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, true, &[(b":method", b"GET"), (b":scheme", b"https"), (b":authority", b"example.com"), (b":path", b"/")]).await.unwrap();
+    let mut req = Http2Request::new(stream_id, client.clone()).unwrap();
+    req.sent_head = true;
+    req.sent = true;
+
+    assert_eq!(server.next().await.unwrap(), Some(stream_id));
+    let mut res = Http2Socket::new(stream_id, server.clone()).unwrap();
+    res.read_until_complete().await.unwrap();
+
+    let mut style = res.push(HttpMethod::Get, "/style.css", &[("accept", "text/css")]).await.unwrap();
+    let mut script = res.push(HttpMethod::Get, "/app.js", &[]).await.unwrap();
+    assert!(res.push(HttpMethod::Post, "/form", &[]).await.unwrap_err().is_invalid());
+    // without a host there is no :authority to promise
+    let host = res.client.host.take();
+    assert!(res.push(HttpMethod::Get, "/font.woff", &[]).await.unwrap_err().is_invalid());
+    res.client.host = host;
+    assert_eq!((style.stream_id, script.stream_id), (2, 4));
+    assert_eq!(style.client.path, "/style.css");
+    assert_eq!(style.client.headers.get("accept").unwrap(), &["text/css"]);
+
+    assert_eq!(client.next().await.unwrap(), Some(2));
+    assert_eq!(client.next().await.unwrap(), Some(4));
+    assert_eq!(req.promised(), vec![2, 4]);
+
+    // a promise still waiting for its CONTINUATION can not be accepted
+    client.streams.get_mut(&stream_id).unwrap().promising = Some(2);
+    assert!(req.accept_push(2).is_err());
+    client.streams.get_mut(&stream_id).unwrap().promising = None;
+
+    let mut pushed = req.accept_push(2).unwrap();
+    assert_eq!(pushed.path, "/style.css");
+    assert_eq!(pushed.authority, "example.com");
+    assert_eq!(pushed.headers.get("accept").unwrap(), &["text/css"]);
+    req.refuse_push(4).await.unwrap();
+    assert!(req.accept_push(stream_id).is_err());
+
+    server.next().await.unwrap();
+    assert!(server.streams.get(&4).unwrap().reset);
+    assert!(script.write(b"ignored").await.is_err());
+
+    style.close(b"body {}").await.unwrap();
+    res.close(b"").await.unwrap();
+    for _ in 0..4 { client.next().await.unwrap(); }
+
+    let response = pushed.read_until_complete().await.unwrap();
+    assert_eq!(response.body, b"body {}");
+    assert_eq!(req.read_until_complete().await.unwrap().code, 200);
+
+    // pushing on a finished stream or to a peer that disabled push is refused
+    assert!(res.push(HttpMethod::Get, "/late.js", &[]).await.unwrap_err().is_stream_closed());
+    server.settings.lock().unwrap().enable_push = Some(0);
+    assert!(server.open_push_stream().is_none());
+    assert!(client.open_push_stream().is_none());
+}
+
+#[tokio::test]
+async fn http2_extended_connect() {
+    let sett = Http2Settings::from(&Http2Settings { enable_connect_protocol: Some(1), ..Http2Settings::empty() }.to_vec());
+    assert_eq!(sett.enable_connect_protocol, Some(1));
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    assert!(Http2Request::websocket(client.clone(), "https", "localhost", "/chat", &[]).await.err().unwrap().is_not_accepted());
+
+    server.enable_connect_protocol().await.unwrap();
+    client.next().await.unwrap();
+    assert!(client.connect_protocol_enabled());
+
+    let driver = client.clone();
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+
+    let sess = server.clone();
+    tokio::spawn(async move {
+        while let Ok(opened) = sess.next().await {
+            let Some(stream_id) = opened else { continue };
+            let socket = Http2Socket::new(stream_id, sess.clone()).unwrap();
+
+            tokio::spawn(async move {
+                let Ok(ws) = socket.websocket().await else { return };
+                let frame = ws.read_frame().await.unwrap();
+                ws.send_text(&[b"echo: ".as_slice(), &frame.get_unmasked()].concat()).await.unwrap();
+            });
+        }
+    });
+
+    let ws = Http2Request::websocket(client.clone(), "http", "localhost", "/chat", &[("origin", "http://localhost")]).await.unwrap();
+    ws.send_text_masked(&[1, 2, 3, 4], b"hello").await.unwrap();
+
+    let frame = ws.read_frame().await.unwrap();
+    assert_eq!(frame.get_payload(), b"echo: hello");
+
+    // without sec-websocket-version the upgrade is refused
+    let mut request = Http2Request::open_extended(client.clone(), "websocket", "https", "localhost", "/chat", &[]).await.unwrap();
+    let response = request.read_until_complete().await.unwrap();
+    assert_eq!(response.code, 400);
+    assert_eq!(response.headers.get("sec-websocket-version").unwrap(), &["13"]);
+}
+
+#[tokio::test]
+async fn http2_connect_tunnel() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    let driver = client.clone();
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+
+    let sess = server.clone();
+    tokio::spawn(async move {
+        while let Ok(opened) = sess.next().await {
+            let Some(stream_id) = opened else { continue };
+            let socket = Http2Socket::new(stream_id, sess.clone()).unwrap();
+
+            tokio::spawn(async move {
+                let mut tunnel = socket.tunnel().await.unwrap();
+
+                // stands in for the TcpStream to the target, it echoes everything back
+                let (mut upstream, target) = tokio::io::duplex(4096);
+                tokio::spawn(async move {
+                    let (mut r, mut w) = tokio::io::split(target);
+                    tokio::io::copy(&mut r, &mut w).await.unwrap();
+                    w.shutdown().await.unwrap();
+                });
+
+                tokio::io::copy_bidirectional(&mut tunnel, &mut upstream).await.unwrap();
+            });
+        }
+    });
+
+    let tunnel = Http2Request::connect(client.clone(), "example.com:443", &[]).await.unwrap();
+    let (mut netr, mut netw) = tunnel.into_split();
+
+    // more than a whole stream window, only gets through when the reader hands it back
+    let payload: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+    let sent = payload.clone();
+    let writer = tokio::spawn(async move {
+        netw.write_all(&sent).await.unwrap();
+        netw.shutdown().await.unwrap();
+    });
+
+    let mut echoed = Vec::new();
+    netr.read_to_end(&mut echoed).await.unwrap();
+    writer.await.unwrap();
+    assert_eq!(echoed.len(), payload.len());
+    assert!(echoed == payload);
+}
+
+#[tokio::test]
+async fn http2_connect_reset() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    let driver = client.clone();
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+
+    // the server turns every request down with a reset instead of a response
+    let sess = server.clone();
+    tokio::spawn(async move {
+        while let Ok(opened) = sess.next().await {
+            let Some(stream_id) = opened else { continue };
+            sess.send_rst_stream(stream_id, Http2ErrorCode::RefusedStream).await.unwrap();
+        }
+    });
+
+    let connect = tokio::time::timeout(std::time::Duration::from_secs(2), Http2Request::connect(client.clone(), "example.com:443", &[])).await.unwrap();
+    assert_eq!(connect.err().unwrap().reset_code(), Some(Http2ErrorCode::RefusedStream));
+
+    // an extended CONNECT for a websocket fails the same way
+    server.enable_connect_protocol().await.unwrap();
+    tokio::time::timeout(std::time::Duration::from_secs(2), async {
+        while !client.connect_protocol_enabled() { tokio::time::sleep(std::time::Duration::from_millis(5)).await }
+    }).await.unwrap();
+    let websocket = tokio::time::timeout(std::time::Duration::from_secs(2), Http2Request::websocket(client.clone(), "https", "localhost", "/chat", &[])).await.unwrap();
+    assert_eq!(websocket.err().unwrap().reset_code(), Some(Http2ErrorCode::RefusedStream));
+}
+
+#[tokio::test]
+async fn http2_stream_small_window() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+    // acknowledged before the response comes in, the server handles frames in order
+    client.send_settings(Http2Settings { initial_window_size: Some(1000), ..Http2Settings::empty() }).await.unwrap();
+
+    let driver = client.clone();
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+
+    // the body only starts once the client reads through the stream
+    let ready = std::sync::Arc::new(tokio::sync::Notify::new());
+    let (sess, start) = (server.clone(), ready.clone());
+    tokio::spawn(async move {
+        while let Ok(opened) = sess.next().await {
+            let Some(stream_id) = opened else { continue };
+            let socket = Http2Socket::new(stream_id, sess.clone()).unwrap();
+            let start = start.clone();
+
+            tokio::spawn(async move {
+                let mut tunnel = socket.tunnel().await.unwrap();
+                start.notified().await;
+                let payload: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+                tunnel.write_all(&payload).await.unwrap();
+                tunnel.shutdown().await.unwrap();
+            });
+        }
+    });
+
+    // the window is far below the fixed threshold, the reader has to hand it back earlier
+    let mut tunnel = Http2Request::connect(client.clone(), "example.com:443", &[]).await.unwrap();
+    ready.notify_one();
+    let mut received = Vec::new();
+    tokio::time::timeout(std::time::Duration::from_secs(5), tunnel.read_to_end(&mut received)).await.unwrap().unwrap();
+    assert_eq!(received.len(), 10_000);
+}
+
+#[tokio::test]
+async fn http2_keepalive() {
+    let mut rtt = Http2Rtt::default();
+    rtt.sample(std::time::Duration::from_millis(100));
+    rtt.sample(std::time::Duration::from_millis(20));
+    assert_eq!(rtt.smoothed, Some(std::time::Duration::from_millis(90)));
+    assert_eq!(rtt.min, Some(std::time::Duration::from_millis(20)));
+    let payload = rtt.start().unwrap();
+    assert!(rtt.start().is_none());
+    rtt.received = 100_000;
+    rtt.ack(b"stranger");
+    assert!(rtt.outstanding.is_some());
+    rtt.ack(&payload);
+    assert_eq!(rtt.bdp, 100_000);
+
+    let keepalive = Http2Keepalive::new(std::time::Duration::from_millis(20), std::time::Duration::from_millis(500));
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let mut client = Http2Session::new_client(client);
+    client.keepalive = Some(keepalive);
+    let client = std::sync::Arc::new(client);
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    let (driver, sdriver) = (client.clone(), server.clone());
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+    let answering = tokio::spawn(async move { while sdriver.next().await.is_ok() {} });
+
+    let pinger = client.clone();
+    let keepalive_task = tokio::spawn(async move { pinger.keepalive().await });
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(client.smoothed_rtt().is_some());
+    assert!(!keepalive_task.is_finished());
+
+    // the peer stops answering, like a half-open connection behind a NAT
+    answering.abort();
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, false, &[(b":method", b"GET"), (b":scheme", b"http"), (b":path", b"/")]).await.unwrap();
+
+    let err = tokio::time::timeout(std::time::Duration::from_secs(5), keepalive_task).await.unwrap().unwrap().unwrap_err();
+    assert_eq!(err.io().unwrap().kind(), std::io::ErrorKind::TimedOut);
+    assert!(client.streams.get(&stream_id).unwrap().reset);
+    assert!(client.next().await.is_err());
+}
+
+// counts write calls that reach the socket
+struct CountingWriter<W> {
+    inner: W,
+    writes: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+impl<W: tokio::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for CountingWriter<W> {
+    fn poll_write(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>, buf: &[u8]) -> std::task::Poll<std::io::Result<usize>> {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        std::pin::Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+    fn poll_flush(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.inner).poll_flush(cx)
+    }
+    fn poll_shutdown(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[tokio::test]
+async fn http2_write_coalescing() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+
+    let writes = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let (netr, netw) = tokio::io::split(server);
+    let server = std::sync::Arc::new(Http2Session::with(netr, CountingWriter { inner: netw, writes: writes.clone() }, Mode::Server, true, Http2Settings::default()));
+
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, false, &[(b":method", b"POST"), (b":scheme", b"http"), (b":path", b"/")]).await.unwrap();
+    client.send_data(stream_id, false, b"hello").await.unwrap();
+    server.next().await.unwrap();
+
+    // both window updates in one write
+    server.next().await.unwrap();
+    assert_eq!(writes.load(Ordering::SeqCst), 1);
+    client.next().await.unwrap();
+    client.next().await.unwrap();
+
+    // buffered frames from several tasks wait for the next flush
+    let mut tasks = vec![];
+    for i in 0..4u8 {
+        let sess = server.clone();
+        tasks.push(tokio::spawn(async move { sess.write_frame_buffered(Http2FrameType::Ping, 0, 0, None, Some(&[i; 8]), None).await.unwrap() }));
+    }
+    for task in tasks { task.await.unwrap(); }
+    assert_eq!(writes.load(Ordering::SeqCst), 1);
+
+    server.flush().await.unwrap();
+    assert_eq!(writes.load(Ordering::SeqCst), 2);
+    assert!(server.output.lock().unwrap().spare.capacity() > 0);
+    server.flush().await.unwrap();
+    assert_eq!(writes.load(Ordering::SeqCst), 2);
+
+    for _ in 0..4 { client.next().await.unwrap(); }
+
+    // a response head goes out with the body that follows it
+    let mut socket = Http2Socket::new(stream_id, server.clone()).unwrap();
+    socket.close(b"world").await.unwrap();
+    assert_eq!(writes.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn http2_cancelled_flush() {
+    use tokio::io::AsyncReadExt;
+
+    // the pipe takes 16 bytes, the rest of the flush stays pending until it is dropped
+    let (mut peer, server) = tokio::io::duplex(16);
+    let server = Http2Session::new_server(server);
+
+    server.queue_raw(&[1; 100]);
+    assert!(tokio::time::timeout(std::time::Duration::from_millis(20), server.flush()).await.is_err());
+    assert_eq!(server.output.lock().unwrap().flushed, 16);
+    server.queue_raw(&[2; 10]);
+
+    let reader = tokio::spawn(async move {
+        let mut buf = vec![0; 110];
+        peer.read_exact(&mut buf).await.unwrap();
+        buf
+    });
+    server.flush().await.unwrap();
+
+    let mut expected = vec![1; 100];
+    expected.extend_from_slice(&[2; 10]);
+    assert_eq!(reader.await.unwrap(), expected);
+    assert_eq!(server.output.lock().unwrap().flushed, 110);
+}
+
+#[tokio::test]
+async fn http2_shared_read_buffer() {
+    assert!(Http2Frame::from_owned(vec![0, 0, 2, 0, 0x08, 0, 0, 0, 1, 5, 0]).is_none());
+    assert!(Http2Frame::from_owned(vec![0, 0, 4, 0, 0, 0, 0, 0, 1, 1]).is_none());
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    client.send_preface().await.unwrap();
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, false, &[(b":method", b"POST"), (b":scheme", b"http"), (b":path", b"/")]).await.unwrap();
+    client.send_data(stream_id, false, b"hello").await.unwrap();
+    client.send_data(stream_id, true, b"world").await.unwrap();
+
+    assert!(server.read_preface().await.unwrap());
+    for _ in 0..3 { server.next().await.unwrap(); }
+
+    {
+        // both payloads point into the same read buffer, nothing was copied on the way
+        let shard = server.streams.get(&stream_id).unwrap();
+        assert_eq!(shard.body.len(), 10);
+        assert_eq!(shard.body.chunks.len(), 2);
+        assert_eq!(unsafe { shard.body.chunks[0].as_ptr().add(5 + 9) }, shard.body.chunks[1].as_ptr());
+    }
+
+    let mut req = Http2Socket::new(stream_id, server.clone()).unwrap();
+    assert_eq!(req.read_until_complete().await.unwrap().body, b"helloworld");
+}
+
+#[tokio::test]
+async fn http2_padding() {
+    assert_eq!(Http2Padding::Block(64).padding_for(10), 53);
+    assert_eq!(Http2Padding::Block(256).padding_for(255), 0);
+    assert_eq!(Http2Padding::None.padding_for(10), 0);
+    assert!(Http2Padding::Random(16).padding_for(10) <= 16);
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let mut client = Http2Session::new_client(client);
+    client.padding = Http2Padding::Block(64);
+    let server = Http2Session::new_server(server);
+
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, false, &[(b":method", b"POST"), (b":scheme", b"http"), (b":path", b"/")]).await.unwrap();
+    client.send_data(stream_id, true, &[7; 100]).await.unwrap();
+
+    let head = server.read_frame().await.unwrap();
+    assert!(head.is_padded());
+    assert_eq!(head.length % 64, 0);
+    let data = server.read_frame().await.unwrap();
+    assert!(data.is_padded());
+    assert_eq!(data.length, 128);
+    assert_eq!(data.get_payload(), &[7; 100]);
+    // padding is flow controlled like the data itself
+    assert_eq!(*client.window.lock().unwrap(), 65535 - 128);
+    assert_eq!(client.streams.get(&stream_id).unwrap().window, 65535 - 128);
+
+    // through a tunnel the reader hands windows back itself, the padding must not get lost on the way
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut client, mut server) = (Http2Session::new_client(client), Http2Session::new_server(server));
+    client.padding = Http2Padding::Random(255);
+    server.padding = Http2Padding::Random(255);
+    let (client, server) = (std::sync::Arc::new(client), std::sync::Arc::new(server));
+
+    let driver = client.clone();
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+    let sess = server.clone();
+    tokio::spawn(async move {
+        while let Ok(opened) = sess.next().await {
+            let Some(stream_id) = opened else { continue };
+            let socket = Http2Socket::new(stream_id, sess.clone()).unwrap();
+            tokio::spawn(async move {
+                let (mut r, mut w) = tokio::io::split(socket.tunnel().await.unwrap());
+                tokio::io::copy(&mut r, &mut w).await.unwrap();
+                w.shutdown().await.unwrap();
+            });
+        }
+    });
+
+    let (mut netr, mut netw) = Http2Request::connect(client.clone(), "example.com:443", &[]).await.unwrap().into_split();
+    let payload: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+    let sent = payload.clone();
+    let writer = tokio::spawn(async move {
+        // small frames, so the padding adds up to more than a window
+        for chunk in sent.chunks(50) { netw.write_all(chunk).await.unwrap(); }
+        netw.shutdown().await.unwrap();
+    });
+
+    let mut echoed = Vec::new();
+    tokio::time::timeout(std::time::Duration::from_secs(10), netr.read_to_end(&mut echoed)).await.unwrap().unwrap();
+    writer.await.unwrap();
+    assert!(echoed == payload);
+}
+
+// remembers what it was offered, takes only frames of type 0xf0
+#[derive(Debug, Default)]
+struct RecordingExtension {
+    frames: std::sync::Mutex<Vec<(u8, Vec<u8>)>>,
+    settings: std::sync::Mutex<Vec<(u16, u32)>>,
+}
+impl Http2Extension for std::sync::Arc<RecordingExtension> {
+    fn handle_frame(&self, frame: &Http2Frame<'_>) -> crate::shared::LibResult<bool> {
+        let ftype: u8 = frame.ftype.into();
+        if ftype != 0xf0 { return Ok(false) }
+        self.frames.lock().unwrap().push((ftype, frame.get_payload().to_vec()));
+        Ok(true)
+    }
+    fn handle_setting(&self, id: u16, value: u32) {
+        self.settings.lock().unwrap().push((id, value));
+    }
+}
+
+#[tokio::test]
+async fn http2_extension_frames() {
+    let mut sett = Http2Settings::empty();
+    sett.max_frame_size = Some(16384);
+    sett.set_unknown(0x2a, 7);
+    sett.set_unknown(0x2a, 9);
+    let parsed = Http2Settings::from(&sett.to_vec());
+    assert_eq!(parsed.unknown.as_slice(), [(0x2a, 9)]);
+    // only a bounded number is kept, the settings stay Copy
+    for id in 0x30..0x40 { sett.set_unknown(id, 1) }
+    let copy = sett;
+    assert_eq!(copy.unknown.len, 8);
+    assert!(copy.unknown.truncated && !parsed.unknown.truncated);
+    assert_eq!(copy.get_unknown(0x2a), Some(9));
+    assert_eq!(copy.get_unknown(0x3f), None);
+    assert_eq!(parsed.get_unknown(0x2a), Some(9));
+    assert_eq!(parsed.max_frame_size, Some(16384));
+
+    let alt = Http2AltSvc { origin: b"https://example.com".to_vec(), value: b"h3=\":443\"".to_vec() };
+    assert_eq!(Http2AltSvc::from(&alt.to_vec()), Some(alt));
+    assert!(Http2AltSvc::from(&[0, 5, b'a']).is_none());
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+    let ext = std::sync::Arc::new(RecordingExtension::default());
+    client.add_extension(ext.clone());
+
+    let mut settings = Http2Settings::empty();
+    settings.set_unknown(0x2a, 1);
+    server.send_settings(settings).await.unwrap();
+    server.send_altsvc(0, "https://example.com", "h3=\":443\"").await.unwrap();
+    // an origin on a stream is invalid and dropped
+    server.send_altsvc(3, "https://example.com", "h3=\":443\"").await.unwrap();
+    server.send_origin(&["https://example.com", "https://cdn.example.com"]).await.unwrap();
+    server.send_extension_frame(0xf0, 0, 0, b"custom").await.unwrap();
+    // more than the settings keep, the session still has every one of them
+    let many = (0x30..0x40u16).flat_map(|id| [&id.to_be_bytes()[..], &5u32.to_be_bytes()].concat()).collect::<Vec<u8>>();
+    server.write_frame(Http2FrameType::Settings, 0, 0, None, Some(&many), None).await.unwrap();
+    for _ in 0..6 { client.next().await.unwrap(); }
+
+    assert_eq!(client.settings.lock().unwrap().get_unknown(0x2a), Some(1));
+    assert!(client.settings.lock().unwrap().unknown.truncated);
+    let all = client.peer_unknown_settings.lock().unwrap().clone();
+    assert_eq!((all.len(), all[0], all[16]), (17, (0x2a, 1), (0x3f, 5)));
+    assert_eq!(*ext.settings.lock().unwrap(), all);
+    assert_eq!(*ext.frames.lock().unwrap(), vec![(0xf0, b"custom".to_vec())]);
+    {
+        let alt_svc = client.alt_svc.lock().unwrap();
+        assert_eq!(alt_svc.len(), 1);
+        assert_eq!(alt_svc[0].1.value, b"h3=\":443\"");
+    }
+    assert!(client.origin_allowed("https://CDN.example.com"));
+    assert!(!client.origin_allowed("https://other.example.com"));
+
+    // no extension takes 0xf1, a strict session treats it like any other unknown frame
+    server.send_extension_frame(0xf1, 0, 0, b"").await.unwrap();
+    assert!(client.next().await.is_err());
+}
+
+#[test]
+fn grpc_framing() {
+    let mut reader = GrpcMessageReader::new();
+    let mut buf = encode_message(false, b"hello");
+    buf.append(&mut encode_message(true, b"world"));
+
+    let mut first = buf.split_off(7);
+    reader.push(&mut buf);
+    assert_eq!(reader.next_message().unwrap(), None);
+    reader.push(&mut first);
+    assert_eq!(reader.next_message().unwrap(), Some((false, b"hello".to_vec())));
+    assert_eq!(reader.next_message().unwrap(), Some((true, b"world".to_vec())));
+    assert_eq!(reader.next_message().unwrap(), None);
+
+    reader.max_message_size = 4;
+    reader.push(&mut encode_message(false, b"hello"));
+    assert_eq!(reader.next_message().unwrap_err().status, GrpcStatus::ResourceExhausted);
+
+    assert_eq!(parse_timeout("100m"), Some(std::time::Duration::from_millis(100)));
+    assert_eq!(parse_timeout("2H"), Some(std::time::Duration::from_secs(7200)));
+    assert_eq!(parse_timeout("123456789S"), None);
+    assert_eq!(parse_timeout("1x"), None);
+    assert_eq!(format_timeout(std::time::Duration::from_millis(1500)), "1500000u");
+    assert_eq!(format_timeout(std::time::Duration::from_secs(86400 * 365)), "31536000S");
+    assert_eq!(format_timeout(std::time::Duration::from_secs(86400 * 365 * 4)), "2102400M");
+
+    assert_eq!(encode_grpc_message("50% done\n"), "50%25 done%0A");
+    assert_eq!(decode_grpc_message("50%25 done%0A%"), "50% done\n%");
+}
+
+#[tokio::test]
+async fn grpc_unary() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let client = std::sync::Arc::new(Http2Session::new_client(client));
+    let server = std::sync::Arc::new(Http2Session::new_server(server));
+
+    let driver = client.clone();
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+
+    let sess = server.clone();
+    tokio::spawn(async move {
+        while let Ok(opened) = sess.next().await {
+            let Some(stream_id) = opened else { continue };
+            let mut grpc = GrpcSocket::new(Http2Socket::new(stream_id, sess.clone()).unwrap());
+            
+            tokio::spawn(async move {
+                if grpc.read_head().await.is_err() {
+                    assert!(grpc.finished);
+                    return
+                }
+                assert!(grpc.deadline.is_some());
+
+                if grpc.path() == "/echo.Echo/Say" {
+                    grpc.unary(async |req| Ok([b"echo: ".as_slice(), &req].concat())).await.unwrap();
+                }
+                else {
+                    grpc.finish(GrpcStatus::Unimplemented, "no such method").await.unwrap();
+                }
+            });
+        }
+    });
+
+    let timeout = Some(std::time::Duration::from_secs(10));
+    let mut call = GrpcRequest::open(client.clone(), "https", "localhost", "/echo.Echo/Say", &[("x-id", "1")], timeout).await.unwrap();
+    assert_eq!(call.unary(b"hi").await.unwrap(), b"echo: hi");
+    assert_eq!(call.status.unwrap().status, GrpcStatus::Ok);
+
+    let mut call = GrpcRequest::open(client.clone(), "http", "localhost", "/echo.Echo/Missing", &[], timeout).await.unwrap();
+    let err = call.unary(b"hi").await.unwrap_err();
+    assert_eq!(err.grpc().unwrap(), &GrpcError::new(GrpcStatus::Unimplemented, "no such method"));
+
+    // something that is not grpc gets a plain 415
+    let stream_id = client.open_stream().unwrap();
+    client.send_headers(stream_id, true, &[(b":method", b"POST"), (b":scheme", b"https"), (b":path", b"/echo.Echo/Say"), (b"content-type", b"text/plain")]).await.unwrap();
+    let mut req = Http2Request::new(stream_id, client.clone()).unwrap();
+    req.sent_head = true;
+    assert_eq!(req.read_until_complete().await.unwrap().code, 415);
+}
+
+
+#[test]
+fn http3_frames() {
+    // rfc9000 A.1
+    let vectors: &[(&[u8], u64)] = &[(&[0xc2, 0x19, 0x7c, 0x5e, 0xff, 0x14, 0xe8, 0x8c], 151288809941952652), (&[0x9d, 0x7f, 0x3e, 0x7d], 494878333), (&[0x7b, 0xbd], 15293), (&[0x25], 37), (&[0x40, 0x25], 37)];
+    for (raw, value) in vectors {
+        let mut pos = 0;
+        assert_eq!(read_varint(raw, &mut pos), Some(*value));
+        assert_eq!(pos, raw.len());
+    }
+    for value in [0, 63, 64, 16383, 16384, (1 << 30) - 1, 1 << 30, (1 << 62) - 1] {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, value);
+        assert_eq!(buf.len(), varint_len(value));
+        assert_eq!(read_varint(&buf, &mut 0), Some(value));
+    }
+    assert_eq!(read_varint(&[0x80, 0x00, 0x01], &mut 0), None);
+
+    let frame = Http3Frame::create(Http3FrameType::Headers, b"block");
+    assert_eq!(frame, [0x01, 0x05, b'b', b'l', b'o', b'c', b'k']);
+    let (parsed, used) = Http3Frame::from(&frame).unwrap();
+    assert_eq!((parsed.ftype, &parsed.payload[..], used), (Http3FrameType::Headers, &b"block"[..], 7));
+    assert!(Http3Frame::from(&frame[..6]).is_none());
+
+    // a frame split off a buffer that holds one and a half
+    let mut buf = bytes::BytesMut::from(&[frame.as_slice(), &frame[..3]].concat()[..]);
+    assert_eq!(Http3Frame::split_from(&mut buf, 16).unwrap().unwrap().payload, &b"block"[..]);
+    assert_eq!(Http3Frame::split_from(&mut buf, 16).unwrap(), None);
+    assert_eq!(buf.len(), 3);
+    assert_eq!(Http3Frame::split_from(&mut buf, 4), Err(Http3ErrorCode::ExcessiveLoad));
+
+    let goaway = Http3Frame::create_varint(Http3FrameType::Goaway, 400);
+    assert_eq!(Http3Frame::from(&goaway).unwrap().0.varint_payload(), Some(400));
+    assert_eq!(Http3Frame::from(&[0x07, 0x02, 0x01, 0x00]).unwrap().0.varint_payload(), None);
+
+    assert!(Http3FrameType::from(0x08).is_http2_only() && !Http3FrameType::from(0x21).is_http2_only());
+    assert_eq!(Into::<u64>::into(Http3FrameType::MaxPushId), 0x0d);
+    assert_eq!(Http3StreamType::from(0x02), Http3StreamType::QpackEncoder);
+    assert!(is_grease(0x21) && is_grease(0x1f * 7 + 0x21) && !is_grease(0x22) && is_grease(random_grease()));
+    assert_eq!(Http3ErrorCode::from(0x10b), Http3ErrorCode::RequestRejected);
+    assert_eq!(Http3ErrorCode::QpackDecompressionFailed.to_string(), "QPACK_DECOMPRESSION_FAILED");
+
+    let mut sett = Http3Settings::default();
+    sett.set_unknown(0x21, 7);
+    let parsed = Http3Settings::from(&sett.to_vec()).unwrap();
+    assert_eq!(parsed, sett);
+    assert_eq!(parsed.get_unknown(0x21), Some(7));
+
+    // repeated ids, http/2 only ids and a cut off value
+    assert_eq!(Http3Settings::from(&[0x01, 0x00, 0x01, 0x00]), Err(Http3ErrorCode::SettingsError));
+    assert_eq!(Http3Settings::from(&[0x04, 0x00]), Err(Http3ErrorCode::SettingsError));
+    assert_eq!(Http3Settings::from(&[0x01, 0x40]), Err(Http3ErrorCode::FrameError));
+}
+
+#[tokio::test]
+async fn http3_session() {
+    use tokio::io::AsyncWriteExt;
+
+    let (ctrans, strans) = MemoryTransport::pair(65536);
+    let client = std::sync::Arc::new(Http3Session::new_client(ctrans));
+    let server = std::sync::Arc::new(Http3Session::new_server(strans));
+    client.start().await.unwrap();
+    server.start().await.unwrap();
+
+    let driver = client.clone();
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+    let (opened, mut requests) = tokio::sync::mpsc::unbounded_channel();
+    let driver = server.clone();
+    tokio::spawn(async move {
+        while let Ok(id) = driver.next().await {
+            if let Some(id) = id { opened.send(id).unwrap() }
+        }
+    });
+
+    // with the server's settings in, the client's headers go into the dynamic table
+    let settings = client.wait_settings().await.unwrap();
+    assert_eq!(settings.qpack_max_table_capacity, Some(4096));
+    assert!(settings.unknown.iter().all(|(id, _)| is_grease(*id)));
+
+    let headers: &[(&[u8], &[u8])] = &[(b":method", b"POST"), (b":scheme", b"https"), (b":authority", b"example.com"), (b":path", b"/upload"), (b"x-trace", b"abc")];
+    for round in 0..2 {
+        let (mut crecv, mut csend) = client.open_request().await.unwrap();
+        let stream_id = Http3SendStream::stream_id(&csend);
+
+        let mut out = client.encode_headers(stream_id, headers).await.unwrap();
+        Http3Frame::create_into(&mut out, Http3FrameType::Data, b"hello");
+        Http3Frame::create_into(&mut out, Http3FrameType::Unknown(random_grease()), b"ignored");
+        csend.write_all(&out).await.unwrap();
+        csend.shutdown().await.unwrap();
+
+        assert_eq!(requests.recv().await.unwrap(), stream_id);
+        let (mut srecv, mut ssend) = server.take_request(stream_id).unwrap();
+        let mut buf = Http3ReadBuffer::new();
+
+        let frame = server.read_frame(&mut srecv, &mut buf).await.unwrap().unwrap();
+        assert_eq!(frame.ftype, Http3FrameType::Headers);
+        let decoded = server.decode_headers(stream_id, &frame.payload).await.unwrap();
+        assert_eq!(decoded, headers.iter().map(|(h, v)| (h.to_vec(), v.to_vec())).collect::<Vec<_>>());
+
+        assert_eq!(server.read_frame(&mut srecv, &mut buf).await.unwrap().unwrap().payload, &b"hello"[..]);
+        assert!(server.read_frame(&mut srecv, &mut buf).await.unwrap().unwrap().ftype.is_unknown());
+        assert!(server.read_frame(&mut srecv, &mut buf).await.unwrap().is_none());
+
+        let response = server.encode_headers(stream_id, &[(b":status", b"200"), (b"server", b"photon")]).await.unwrap();
+        ssend.write_all(&response).await.unwrap();
+        ssend.shutdown().await.unwrap();
+
+        let mut buf = Http3ReadBuffer::new();
+        let frame = client.read_frame(&mut crecv, &mut buf).await.unwrap().unwrap();
+        let decoded = client.decode_headers(stream_id, &frame.payload).await.unwrap();
+        assert_eq!(decoded[0], (b":status".to_vec(), b"200".to_vec()));
+        assert!(client.read_frame(&mut crecv, &mut buf).await.unwrap().is_none());
+
+        // the second round only refers to entries the first one inserted
+        if round == 1 { assert!(client.encoder.lock().unwrap().take_instructions().is_empty()) }
+    }
+    // :path is never indexed, :method and :scheme are static
+    assert_eq!(client.encoder.lock().unwrap().table.inserted, 2);
+
+    // a GOAWAY names the first request the server did not see, later requests are refused
+    server.send_goaway().await.unwrap();
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while client.get_goaway().is_none() { tokio::time::sleep(std::time::Duration::from_millis(5)).await }
+    }).await.unwrap();
+    assert_eq!(client.get_goaway(), Some(8));
+    assert!(client.open_request().await.unwrap_err().is_refused());
+}
+
+// raw bytes from a fake client against a server session, the connection error it closes with
+async fn http3_violation(streams: &[(Option<u64>, &[u8])], finish: bool) -> Option<Http3ErrorCode> {
+    use tokio::io::AsyncWriteExt;
+
+    let (peer, strans) = MemoryTransport::pair(65536);
+    let server = Http3Session::new_server(strans);
+    let mut sends = Vec::new();
+
+    for (stype, data) in streams {
+        let mut send = match stype {
+            Some(stype) => {
+                let mut send = peer.open_uni().await.unwrap();
+                let mut head = Vec::new();
+                write_varint(&mut head, *stype);
+                send.write_all(&head).await.unwrap();
+                send
+            },
+            None => peer.open_bi().await.unwrap().1,
+        };
+        send.write_all(data).await.unwrap();
+        if finish { send.shutdown().await.unwrap() }
+        sends.push(send);
+    }
+
+    let res = tokio::time::timeout(std::time::Duration::from_millis(200), async { loop { if let Err(err) = server.next().await { return err } } }).await;
+    match res {
+        Ok(err) => {
+            assert!(peer.accept_uni().await.unwrap_err().h3_code().is_some());
+            err.h3_code()
+        },
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn http3_control_errors() {
+    use tokio::io::AsyncWriteExt;
+
+    let settings = Http3Frame::create(Http3FrameType::Settings, &[]);
+    let with_settings = |frame: Vec<u8>| [settings.clone(), frame].concat();
+
+    assert_eq!(http3_violation(&[(Some(0), &settings)], false).await, None);
+    assert_eq!(http3_violation(&[(Some(0), &Http3Frame::create_varint(Http3FrameType::Goaway, 0))], false).await, Some(Http3ErrorCode::MissingSettings));
+    assert_eq!(http3_violation(&[(Some(0), &settings), (Some(0), &settings)], false).await, Some(Http3ErrorCode::StreamCreationError));
+    assert_eq!(http3_violation(&[(Some(0), &with_settings(settings.clone()))], false).await, Some(Http3ErrorCode::FrameUnexpected));
+    assert_eq!(http3_violation(&[(Some(0), &with_settings(Http3Frame::create(Http3FrameType::Data, b"x")))], false).await, Some(Http3ErrorCode::FrameUnexpected));
+    assert_eq!(http3_violation(&[(Some(0), &with_settings(Http3Frame::create(0x06u64, &[0; 8])))], false).await, Some(Http3ErrorCode::FrameUnexpected));
+    assert_eq!(http3_violation(&[(Some(0), &Http3Frame::create(Http3FrameType::Settings, &[0x05, 0x00]))], false).await, Some(Http3ErrorCode::SettingsError));
+    assert_eq!(http3_violation(&[(Some(0), &settings)], true).await, Some(Http3ErrorCode::ClosedCriticalStream));
+    assert_eq!(http3_violation(&[(Some(1), &[0x00])], false).await, Some(Http3ErrorCode::StreamCreationError));
+    assert_eq!(http3_violation(&[(Some(0), &[0x04, 0x80, 0xff, 0xff, 0xff])], false).await, Some(Http3ErrorCode::ExcessiveLoad));
+
+    // reserved frames and stream types are skipped
+    let grease = with_settings(Http3Frame::create(Http3FrameType::Unknown(0x21), b"grease"));
+    assert_eq!(http3_violation(&[(Some(0), &grease), (Some(0x21), b"anything")], false).await, None);
+
+    // MAX_PUSH_ID may not go down, GOAWAY ids from a client are push ids
+    let push = with_settings([Http3Frame::create_varint(Http3FrameType::MaxPushId, 5), Http3Frame::create_varint(Http3FrameType::MaxPushId, 4)].concat());
+    assert_eq!(http3_violation(&[(Some(0), &push)], false).await, Some(Http3ErrorCode::IdError));
+    assert_eq!(http3_violation(&[(Some(0), &with_settings(Http3Frame::create_varint(Http3FrameType::Goaway, 3)))], false).await, None);
+
+    // qpack instructions that do not fit the advertised table
+    let capacity = [0x3f, 0xe1, 0xff, 0x01];
+    assert_eq!(http3_violation(&[(Some(0), &settings), (Some(2), &capacity)], false).await, Some(Http3ErrorCode::QpackEncoderStreamError));
+    assert_eq!(http3_violation(&[(Some(0), &settings), (Some(3), &[0x81])], false).await, Some(Http3ErrorCode::QpackDecoderStreamError));
+
+    // a request stream is handed out, a section that can never be decoded closes the connection
+    let (peer, strans) = MemoryTransport::pair(65536);
+    let server = Http3Session::new_server(strans);
+    let (_, mut send) = peer.open_bi().await.unwrap();
+    send.write_all(&Http3Frame::create(Http3FrameType::Headers, &[0x00, 0x00, 0xff, 0x7f])).await.unwrap();
+    send.shutdown().await.unwrap();
+
+    let id = server.next().await.unwrap().unwrap();
+    let (mut recv, _) = server.take_request(id).unwrap();
+    let frame = server.read_frame(&mut recv, &mut Http3ReadBuffer::new()).await.unwrap().unwrap();
+    assert_eq!(server.decode_headers(id, &frame.payload).await.unwrap_err().h3_code(), Some(Http3ErrorCode::QpackDecompressionFailed));
+    assert_eq!(server.next().await.unwrap_err().h3_code(), Some(Http3ErrorCode::QpackDecompressionFailed));
+}
+
+#[tokio::test]
+async fn http3_stream_reads() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // a uni stream that never sends its type does not hold up the control stream
+    let (peer, strans) = MemoryTransport::pair(65536);
+    let mut settings = Http3Settings::default();
+    settings.max_field_section_size = Some(16);
+    let mut server = Http3Session::new(strans, Mode::Server, settings.clone());
+    server.max_frame_size = 64;
+    server.start().await.unwrap();
+
+    let _silent = peer.open_uni().await.unwrap();
+    let mut control = peer.open_uni().await.unwrap();
+    control.write_all(&[&[0x00][..], &Http3Frame::create(Http3FrameType::Settings, &[])].concat()).await.unwrap();
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while server.peer_settings.lock().unwrap().is_none() { server.next().await.unwrap(); }
+    }).await.unwrap();
+
+    // DATA over max_frame_size is handed out in pieces, HEADERS over it is refused
+    let (_, mut send) = peer.open_bi().await.unwrap();
+    let body = vec![7; 300];
+    send.write_all(&Http3Frame::create(Http3FrameType::Data, &body)).await.unwrap();
+    send.shutdown().await.unwrap();
+
+    let id = server.next().await.unwrap().unwrap();
+    let (mut recv, _) = server.take_request(id).unwrap();
+    let mut input = Http3ReadBuffer::new();
+    let mut read = Vec::new();
+    while let Some(frame) = server.read_frame(&mut recv, &mut input).await.unwrap() {
+        assert_eq!(frame.ftype, Http3FrameType::Data);
+        read.extend_from_slice(&frame.payload);
+    }
+    assert_eq!(read, body);
+
+    let (_, mut send) = peer.open_bi().await.unwrap();
+    send.write_all(&Http3Frame::create(Http3FrameType::Headers, &[0; 65])).await.unwrap();
+    let id = server.next().await.unwrap().unwrap();
+    let (mut recv, _) = server.take_request(id).unwrap();
+    assert_eq!(server.read_frame(&mut recv, &mut Http3ReadBuffer::new()).await.unwrap_err().h3_code(), Some(Http3ErrorCode::ExcessiveLoad));
+
+    // a section over max_field_section_size is cancelled on the decoder stream
+    let (peer, strans) = MemoryTransport::pair(65536);
+    let server = Http3Session::new(strans, Mode::Server, settings);
+    server.start().await.unwrap();
+    let mut encoder = QpackEncoder::new();
+    let block = encoder.encode_section(0, &[(b"x-long", b"a value that does not fit")]).unwrap();
+    assert_eq!(server.decode_headers(0, &block).await.unwrap_err().h3_code(), Some(Http3ErrorCode::ExcessiveLoad));
+
+    for _ in 0..2 { peer.accept_uni().await.unwrap(); }
+    let mut decoder = peer.accept_uni().await.unwrap();
+    let mut instructions = [0; 2];
+    tokio::time::timeout(std::time::Duration::from_secs(5), decoder.read_exact(&mut instructions)).await.unwrap().unwrap();
+    assert_eq!(instructions, [0x03, 0x40]);
+    assert!(server.get_error().is_none());
+}
+
+// written against the shared trait only, like a handler that does not care about the version
+async fn http3_echo_handler(mut socket: impl HttpSocket) {
+    let client = socket.read_until_complete().await.unwrap();
+    let body = [client.path.as_bytes(), b" ", &client.body].concat();
+
+    socket.set_status(201, String::new());
+    socket.set_header("X-Echo", "yes");
+    socket.close(&body).await.unwrap();
+}
+
+#[tokio::test]
+async fn http3_socket_request() {
+    use tokio::io::AsyncWriteExt;
+
+    let (ctrans, strans) = MemoryTransport::pair(65536);
+    let client = std::sync::Arc::new(Http3Session::new_client(ctrans));
+    let server = std::sync::Arc::new(Http3Session::new_server(strans));
+    client.start().await.unwrap();
+    server.start().await.unwrap();
+
+    let driver = client.clone();
+    tokio::spawn(async move { while driver.next().await.is_ok() {} });
+    let driver = server.clone();
+    tokio::spawn(async move {
+        while let Ok(id) = driver.next().await {
+            if let Some(id) = id {
+                let socket = PolyHttpSocket::<tokio::io::Empty, tokio::io::Sink, MemoryTransport>::Http3(Http3Socket::new(id, driver.clone()).unwrap());
+                assert_eq!(socket.get_type(), HttpType::Http3);
+                tokio::spawn(http3_echo_handler(socket));
+            }
+        }
+    });
+    client.wait_settings().await.unwrap();
+
+    for _ in 0..2 {
+        let mut request = Http3Request::open(client.clone()).await.unwrap();
+        HttpRequest::set_method(&mut request, HttpMethod::Post);
+        HttpRequest::set_path(&mut request, "/echo".to_owned());
+        HttpRequest::set_host(&mut request, "example.com".to_owned());
+        HttpRequest::write(&mut request, b"hello ").await.unwrap();
+        HttpRequest::send(&mut request, b"world").await.unwrap();
+
+        let response = request.read_until_complete().await.unwrap();
+        assert_eq!(response.code, 201);
+        assert_eq!(response.version, HttpVersion::Http3);
+        assert_eq!(response.headers.get("x-echo").unwrap(), &vec!["yes".to_owned()]);
+        assert_eq!(response.headers.get("content-length").unwrap(), &vec!["17".to_owned()]);
+        assert_eq!(response.body, b"/echo hello world");
+    }
+
+    // trailers end the message and unknown frames in between are skipped
+    let (ctrans, strans) = MemoryTransport::pair(65536);
+    let (client, server) = (std::sync::Arc::new(Http3Session::new_client(ctrans)), std::sync::Arc::new(Http3Session::new_server(strans)));
+    let mut request = Http3Request::open(client.clone()).await.unwrap();
+    request.path = "/trailers".to_owned();
+    request.write(b"part").await.unwrap();
+    request.send.write_all(&Http3Frame::create(Http3FrameType::Unknown(random_grease()), b"skip")).await.unwrap();
+    request.send_trailers(&[("grpc-status", "0")]).await.unwrap();
+    assert!(request.write(b"late").await.unwrap_err().is_stream_closed());
+
+    let id = server.next().await.unwrap().unwrap();
+    let mut socket = Http3Socket::new(id, server.clone()).unwrap();
+    assert!(Http3Socket::new(id, server.clone()).is_err());
+    let received = socket.read_until_complete().await.unwrap();
+    assert_eq!((received.method.clone(), received.path.as_str(), received.body.as_slice()), (HttpMethod::Get, "/trailers", &b"part"[..]));
+    // no host was set, so no :authority was sent
+    assert_eq!(received.host, None);
+    assert_eq!(socket.trailers.get("grpc-status").unwrap(), &vec!["0".to_owned()]);
+
+    // 1xx responses are skipped while waiting for the final one
+    let interim = server.encode_headers(id, &[(b":status", b"103"), (b"link", b"</style.css>")]).await.unwrap();
+    socket.send.write_all(&interim).await.unwrap();
+    socket.close(b"").await.unwrap();
+    let response = request.read_until_complete().await.unwrap();
+    assert_eq!((response.code, response.headers.contains_key("link"), response.body.len()), (200, false, 0));
+
+    // DATA before HEADERS is a connection error
+    let mut request = Http3Request::open(client.clone()).await.unwrap();
+    request.send.write_all(&Http3Frame::create(Http3FrameType::Data, b"early")).await.unwrap();
+    let id = server.next().await.unwrap().unwrap();
+    let mut socket = Http3Socket::new(id, server.clone()).unwrap();
+    assert_eq!(socket.read_client().await.unwrap_err().h3_code(), Some(Http3ErrorCode::FrameUnexpected));
+    assert_eq!(server.get_error(), Some(Http3ErrorCode::FrameUnexpected));
+}
+
+// seed inputs checked in under http/fuzz/corpus/<name>
+fn fuzz_corpus(name: &str) -> Vec<Vec<u8>> {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus").join(name);
+    let mut entries: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+    entries.sort();
+    entries.iter().map(|p| std::fs::read(p).unwrap()).collect()
+}
+// flips, overwrites, inserts, removes and truncates, seeded so every run sees the same inputs
+fn fuzz_mutate(rng: &mut rand::rngs::StdRng, input: &[u8]) -> Vec<u8> {
+    use rand::Rng;
+    let mut out = input.to_vec();
+
+    for _ in 0..rng.random_range(1..=4) {
+        let pos = if out.is_empty() { 0 } else { rng.random_range(0..out.len()) };
+        match rng.random_range(0..6) {
+            0 if !out.is_empty() => out[pos] ^= 1 << rng.random_range(0..8),
+            1 if !out.is_empty() => out[pos] = [0x00, 0x7f, 0x80, 0xff][rng.random_range(0..4)],
+            2 => out.insert(pos, rng.random()),
+            3 if !out.is_empty() => { out.remove(pos); },
+            4 => out.truncate(pos),
+            _ => out.extend_from_slice(&input[..rng.random_range(0..=input.len())]),
+        }
+    }
+    out
+}
+fn fuzz_inputs(name: &str, seed: u64) -> Vec<Vec<u8>> {
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let corpus = fuzz_corpus(name);
+    assert!(!corpus.is_empty());
+
+    let mut inputs = corpus.clone();
+    for input in &corpus {
+        for _ in 0..300 { inputs.push(fuzz_mutate(&mut rng, input)); }
+    }
+    inputs
+}
+
+#[test]
+fn fuzz_http2_frame() {
+    for input in fuzz_inputs("http2_frame", 1) { crate::fuzz::http2_frame(&input) }
+}
+#[test]
+fn fuzz_http2_settings() {
+    for input in fuzz_inputs("http2_settings", 2) { crate::fuzz::http2_settings(&input) }
+}
+#[test]
+fn fuzz_websocket_frame() {
+    for input in fuzz_inputs("websocket_frame", 3) { crate::fuzz::websocket_frame(&input) }
+}
+#[test]
+fn fuzz_hpack() {
+    for input in fuzz_inputs("hpack", 4) { crate::fuzz::hpack_block(&input) }
+}
+#[test]
+fn fuzz_huffman() {
+    for input in fuzz_inputs("huffman", 5) { crate::fuzz::huffman(&input) }
+}
+#[tokio::test]
+async fn fuzz_http1_request() {
+    for input in fuzz_inputs("http1_request", 6) {
+        tokio::time::timeout(std::time::Duration::from_secs(5), crate::fuzz::http1_request(&input)).await.unwrap();
+    }
+}
+
+#[test]
+fn property_roundtrips() {
+    use rand::{Rng, SeedableRng};
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    let bytes = |rng: &mut rand::rngs::StdRng, max: usize| -> Vec<u8> { (0..rng.random_range(0..=max)).map(|_| rng.random()).collect() };
+
+    for _ in 0..200 {
+        // Encoder to Decoder, with the dynamic table carried across blocks
+        let mut encoder = Encoder::new(256);
+        let mut decoder: Decoder<'static> = Decoder::new(256);
+        for _ in 0..3 {
+            let headers: Vec<(Vec<u8>, Vec<u8>)> = (0..rng.random_range(0..8)).map(|_| (bytes(&mut rng, 20), bytes(&mut rng, 60))).collect();
+            let list: Vec<(&[u8], &[u8])> = headers.iter().map(|(h, v)| (h.as_slice(), v.as_slice())).collect();
+            let mut block = Vec::new();
+            encoder.encode_block(&mut block, &list).unwrap();
+            assert_eq!(decoder.decode_all(&block).unwrap(), headers);
+        }
+
+        // Http2Frame::create to from
+        let payload = bytes(&mut rng, 300);
+        let padding = rng.random_bool(0.5).then(|| bytes(&mut rng, 255));
+        let priority = rng.random_bool(0.5).then(|| bytes(&mut rng, 5)).filter(|p| p.len() == 5);
+        let (ftype, stream_id) = (rng.random::<u8>(), rng.random::<u32>());
+        let frame = Http2Frame::from_owned(Http2Frame::create(ftype, 0, stream_id, priority.as_deref(), Some(&payload), padding.as_deref())).unwrap();
+        assert_eq!((frame.type_byte, frame.stream_id, frame.get_payload()), (ftype, stream_id, payload.as_slice()));
+        assert_eq!(frame.pad_len as usize, padding.map(|p| p.len()).unwrap_or(0));
+
+        // WebSocketFrame::create to from_owned, lengths around the 126 and 65536 boundaries
+        let len = [rng.random_range(0..200), rng.random_range(65530..65540)][rng.random_range(0..2)];
+        let payload: Vec<u8> = (0..len).map(|_| rng.random()).collect();
+        let mask: Option<[u8; 4]> = rng.random_bool(0.5).then(|| rng.random());
+        let (fin, rsv, opcode) = (rng.random(), rng.random_range(0..8), rng.random_range(0..16));
+        let frame = WebSocketFrame::from_owned(WebSocketFrame::create(fin, rsv, opcode, mask.as_ref().map(|m| m.as_slice()), &payload)).unwrap();
+        assert_eq!((frame.fin, frame.rsv, frame.opcode_byte, frame.masked), (fin, rsv, opcode, mask.is_some()));
+        assert_eq!(frame.get_unmasked(), payload);
+
+        // Http2Settings::to_vec to from
+        let mut sett = Http2Settings::empty();
+        sett.header_table_size = rng.random_bool(0.5).then(|| rng.random());
+        sett.initial_window_size = rng.random_bool(0.5).then(|| rng.random());
+        sett.enable_connect_protocol = rng.random_bool(0.5).then(|| rng.random_range(0..2));
+        for _ in 0..rng.random_range(0..4) { sett.set_unknown(rng.random_range(9..u16::MAX), rng.random()) }
+        let parsed = Http2Settings::from(&sett.to_vec());
+        assert_eq!((parsed.header_table_size, parsed.initial_window_size, parsed.enable_connect_protocol), (sett.header_table_size, sett.initial_window_size, sett.enable_connect_protocol));
+        assert_eq!(parsed.unknown, sett.unknown);
+    }
+}
@@ -98,13 +98,9 @@ impl WebSocketFrame{
         else {
             0..0
         };
-        let payload = // index .. source.len();
-        if len > 125 {
-            index .. (index + ext_len as usize)
-        }
-        else {
-            index .. (index + len as usize)
-        };
+        let payload_len = if len > 125 { usize::try_from(ext_len).ok()? } else { len as usize };
+        // the whole frame has to be in source, get_payload would panic otherwise
+        let payload = index .. index.checked_add(payload_len).filter(|&end| end <= source.len())?;
 
         Some(Self { 
             source,
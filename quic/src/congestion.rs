@@ -0,0 +1,439 @@
+use std::time::{Duration, Instant};
+
+use crate::recovery::RttEstimator;
+
+// https://datatracker.ietf.org/doc/html/rfc9002#section-7
+
+// 14.1, the smallest datagram every path has to carry
+pub const INITIAL_MSS: usize = 1200;
+
+// 7.2
+pub fn initial_window(mss: usize) -> usize {
+    (10 * mss).min(14720.max(2 * mss))
+}
+pub fn minimum_window(mss: usize) -> usize {
+    2 * mss
+}
+
+
+// decides how many bytes may be in flight, each connection owns its own
+pub trait Controller: Send {
+    // a packet that counts towards bytes in flight was sent
+    fn on_sent(&mut self, _now: Instant, _bytes: usize, _in_flight: usize) {}
+    // a packet sent at sent was acknowledged, app_limited when the window was not used up (7.8)
+    fn on_ack(&mut self, now: Instant, sent: Instant, bytes: usize, rtt: &RttEstimator, app_limited: bool);
+    // 7.3.2, packets were lost or ECN-CE marked, sent is when the latest of them was sent
+    fn on_congestion_event(&mut self, now: Instant, sent: Instant, persistent: bool, lost_bytes: usize);
+
+    fn window(&self) -> usize;
+    // bytes per second, None paces from the window as in 7.7
+    fn pacing_rate(&self) -> Option<u64> { None }
+    fn set_mss(&mut self, mss: usize);
+}
+
+
+// 7.3 and b, the controller of rfc9002
+#[derive(Debug, Clone)]
+pub struct NewReno {
+    pub mss: usize,
+    pub window: usize,
+    pub ssthresh: usize,
+    // packets sent before this do not change the window again (7.3.2)
+    pub recovery_start: Option<Instant>,
+    // acknowledged bytes not yet turned into window in congestion avoidance
+    acked: usize,
+}
+impl NewReno {
+    pub fn new(mss: usize) -> Self {
+        Self { mss, window: initial_window(mss), ssthresh: usize::MAX, recovery_start: None, acked: 0 }
+    }
+}
+impl Default for NewReno {
+    #[inline]
+    fn default() -> Self {
+        Self::new(INITIAL_MSS)
+    }
+}
+impl Controller for NewReno {
+    fn on_ack(&mut self, _now: Instant, sent: Instant, bytes: usize, _rtt: &RttEstimator, app_limited: bool) {
+        if app_limited || self.recovery_start.is_some_and(|r| sent <= r) { return }
+
+        if self.window < self.ssthresh {
+            self.window += bytes;
+            return;
+        }
+        // 7.3.3, a mss per window acknowledged
+        self.acked += bytes;
+        if self.acked >= self.window {
+            self.acked -= self.window;
+            self.window += self.mss;
+        }
+    }
+    fn on_congestion_event(&mut self, now: Instant, sent: Instant, persistent: bool, _lost_bytes: usize) {
+        if self.recovery_start.is_none_or(|r| sent > r) {
+            self.recovery_start = Some(now);
+            self.ssthresh = (self.window / 2).max(minimum_window(self.mss));
+            self.window = self.ssthresh;
+            self.acked = 0;
+        }
+        if persistent {
+            // 7.6.2 and b.8, back to the minimum and slow start, out of recovery
+            self.window = minimum_window(self.mss);
+            self.recovery_start = None;
+            self.acked = 0;
+        }
+    }
+
+    fn window(&self) -> usize {
+        self.window
+    }
+    fn set_mss(&mut self, mss: usize) {
+        self.mss = mss;
+        self.window = self.window.max(minimum_window(mss));
+    }
+}
+
+
+// https://datatracker.ietf.org/doc/html/rfc9438
+const CUBIC_C: f64 = 0.4;
+const CUBIC_BETA: f64 = 0.7;
+// 4.3, what makes the reno estimate grow as fast as reno on average
+const CUBIC_ALPHA: f64 = 3.0 * (1.0 - CUBIC_BETA) / (1.0 + CUBIC_BETA);
+
+#[derive(Debug, Clone)]
+pub struct Cubic {
+    pub mss: usize,
+    pub window: usize,
+    pub ssthresh: usize,
+    pub recovery_start: Option<Instant>,
+    // 4.1, the window before the last reduction, in bytes
+    pub w_max: f64,
+    // seconds the window takes to grow back to w_max
+    k: f64,
+    // when congestion avoidance started
+    epoch: Option<Instant>,
+    // 4.3, what reno would have by now
+    w_est: f64,
+}
+impl Cubic {
+    pub fn new(mss: usize) -> Self {
+        Self {
+            mss,
+            window: initial_window(mss),
+            ssthresh: usize::MAX,
+            recovery_start: None,
+            w_max: 0.0,
+            k: 0.0,
+            epoch: None,
+            w_est: 0.0,
+        }
+    }
+    // 4.2, the window t seconds into the epoch
+    fn w_cubic(&self, t: f64) -> f64 {
+        CUBIC_C * (t - self.k).powi(3) * self.mss as f64 + self.w_max
+    }
+}
+impl Default for Cubic {
+    #[inline]
+    fn default() -> Self {
+        Self::new(INITIAL_MSS)
+    }
+}
+impl Controller for Cubic {
+    fn on_ack(&mut self, now: Instant, sent: Instant, bytes: usize, rtt: &RttEstimator, app_limited: bool) {
+        if app_limited || self.recovery_start.is_some_and(|r| sent <= r) { return }
+
+        if self.window < self.ssthresh {
+            self.window += bytes;
+            return;
+        }
+
+        let window = self.window as f64;
+        let epoch = match self.epoch {
+            Some(epoch) => epoch,
+            None => {
+                // congestion avoidance without a loss before it starts from the current window
+                if self.w_max < window {
+                    self.w_max = window;
+                    self.k = 0.0;
+                }
+                self.w_est = window;
+                *self.epoch.insert(now)
+            },
+        };
+
+        let t = now.saturating_duration_since(epoch).as_secs_f64();
+        // 4.2, aim for where the curve is one rtt from now, growing by at most half a window
+        let target = self.w_cubic(t + rtt.smoothed.as_secs_f64()).clamp(window, 1.5 * window);
+        self.w_est += CUBIC_ALPHA * self.mss as f64 * bytes as f64 / window;
+
+        let grown = if self.w_cubic(t) < self.w_est { self.w_est }
+        else { window + (target - window) * bytes as f64 / window };
+        self.window = self.window.max(grown as usize);
+    }
+    fn on_congestion_event(&mut self, now: Instant, sent: Instant, persistent: bool, _lost_bytes: usize) {
+        if self.recovery_start.is_none_or(|r| sent > r) {
+            self.recovery_start = Some(now);
+            self.epoch = None;
+
+            let window = self.window as f64;
+            // 4.7, fast convergence gives up bandwidth sooner when the window keeps shrinking
+            self.w_max = if window < self.w_max { window * (1.0 + CUBIC_BETA) / 2.0 } else { window };
+            self.ssthresh = ((window * CUBIC_BETA) as usize).max(minimum_window(self.mss));
+            self.window = self.ssthresh;
+            self.k = ((self.w_max - self.window as f64).max(0.0) / (CUBIC_C * self.mss as f64)).cbrt();
+            self.w_est = self.window as f64;
+        }
+        // 4.8 and rfc9002 7.6.2, persistent congestion starts over from the minimum, out of recovery
+        if persistent {
+            self.window = minimum_window(self.mss);
+            self.recovery_start = None;
+            self.epoch = None;
+        }
+    }
+
+    fn window(&self) -> usize {
+        self.window
+    }
+    fn set_mss(&mut self, mss: usize) {
+        self.mss = mss;
+        self.window = self.window.max(minimum_window(mss));
+    }
+}
+
+
+// https://datatracker.ietf.org/doc/html/draft-cardwell-iccrg-bbr-congestion-control-02
+#[cfg(feature = "bbr")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BbrState {
+    Startup,
+    Drain,
+    ProbeBw,
+    ProbeRtt,
+}
+
+#[cfg(feature = "bbr")]
+const BBR_HIGH_GAIN: f64 = 2.885;
+#[cfg(feature = "bbr")]
+const BBR_PACING_CYCLE: [f64; 8] = [1.25, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+// rounds a bandwidth sample stays in the max filter
+#[cfg(feature = "bbr")]
+const BBR_BW_ROUNDS: u64 = 10;
+#[cfg(feature = "bbr")]
+const BBR_MIN_RTT_EXPIRY: Duration = Duration::from_secs(10);
+#[cfg(feature = "bbr")]
+const BBR_PROBE_RTT_TIME: Duration = Duration::from_millis(200);
+
+// a model based controller, it sends at the measured bottleneck bandwidth and mostly ignores loss
+#[cfg(feature = "bbr")]
+#[derive(Debug, Clone)]
+pub struct Bbr {
+    pub mss: usize,
+    pub state: BbrState,
+    // bytes per second, the max of the recent rounds
+    pub btl_bw: u64,
+    pub min_rtt: Option<Duration>,
+    pub pacing_gain: f64,
+    pub cwnd_gain: f64,
+    pub window: usize,
+
+    samples: Vec<(u64, u64)>,
+    min_rtt_stamp: Option<Instant>,
+    // a round ends with the ack of a packet sent after it started
+    round: u64,
+    round_start: Option<Instant>,
+    delivered: usize,
+    // startup ends once the bandwidth stops growing by a quarter for 3 rounds
+    full_bw: u64,
+    full_bw_rounds: u32,
+    cycle: usize,
+    cycle_stamp: Option<Instant>,
+    probe_rtt_done: Option<Instant>,
+    in_flight: usize,
+}
+#[cfg(feature = "bbr")]
+impl Bbr {
+    pub fn new(mss: usize) -> Self {
+        Self {
+            mss,
+            state: BbrState::Startup,
+            btl_bw: 0,
+            min_rtt: None,
+            pacing_gain: BBR_HIGH_GAIN,
+            cwnd_gain: BBR_HIGH_GAIN,
+            window: initial_window(mss),
+            samples: Vec::new(),
+            min_rtt_stamp: None,
+            round: 0,
+            round_start: None,
+            delivered: 0,
+            full_bw: 0,
+            full_bw_rounds: 0,
+            cycle: 0,
+            cycle_stamp: None,
+            probe_rtt_done: None,
+            in_flight: 0,
+        }
+    }
+
+    // bandwidth delay product in bytes, None before there is a model
+    pub fn bdp(&self) -> Option<usize> {
+        let min_rtt = self.min_rtt?;
+        if self.btl_bw == 0 { return None }
+        Some((self.btl_bw as f64 * min_rtt.as_secs_f64()) as usize)
+    }
+    fn set_state(&mut self, state: BbrState, now: Instant) {
+        self.state = state;
+        (self.pacing_gain, self.cwnd_gain) = match state {
+            BbrState::Startup => (BBR_HIGH_GAIN, BBR_HIGH_GAIN),
+            BbrState::Drain => (1.0 / BBR_HIGH_GAIN, BBR_HIGH_GAIN),
+            BbrState::ProbeBw => (BBR_PACING_CYCLE[self.cycle], 2.0),
+            BbrState::ProbeRtt => (1.0, 1.0),
+        };
+        self.cycle_stamp = Some(now);
+    }
+    fn end_round(&mut self, now: Instant) {
+        let Some(start) = self.round_start.replace(now) else { return };
+        let elapsed = now.saturating_duration_since(start).as_secs_f64();
+        if elapsed > 0.0 {
+            self.samples.push((self.round, (self.delivered as f64 / elapsed) as u64));
+        }
+        self.round += 1;
+        self.delivered = 0;
+
+        let round = self.round;
+        self.samples.retain(|(r, _)| r + BBR_BW_ROUNDS > round);
+        self.btl_bw = self.samples.iter().map(|(_, bw)| *bw).max().unwrap_or(0);
+
+        if self.state == BbrState::Startup {
+            if self.btl_bw >= self.full_bw + self.full_bw / 4 {
+                self.full_bw = self.btl_bw;
+                self.full_bw_rounds = 0;
+            }
+            else {
+                self.full_bw_rounds += 1;
+                if self.full_bw_rounds >= 3 { self.set_state(BbrState::Drain, now) }
+            }
+        }
+    }
+}
+#[cfg(feature = "bbr")]
+impl Default for Bbr {
+    #[inline]
+    fn default() -> Self {
+        Self::new(INITIAL_MSS)
+    }
+}
+#[cfg(feature = "bbr")]
+impl Controller for Bbr {
+    fn on_sent(&mut self, now: Instant, _bytes: usize, in_flight: usize) {
+        self.in_flight = in_flight;
+        self.round_start.get_or_insert(now);
+    }
+    fn on_ack(&mut self, now: Instant, sent: Instant, bytes: usize, rtt: &RttEstimator, app_limited: bool) {
+        self.in_flight = self.in_flight.saturating_sub(bytes);
+        self.delivered += bytes;
+
+        // a sample from an application limited round only says the bandwidth is at least this
+        if self.round_start.is_some_and(|start| sent >= start) && !(app_limited && self.delivered < self.bdp().unwrap_or(0)) {
+            self.end_round(now);
+        }
+
+        // the minimum is measured again every 10 seconds by draining the queue
+        let expired = self.min_rtt_stamp.is_some_and(|s| now.saturating_duration_since(s) > BBR_MIN_RTT_EXPIRY);
+        if self.min_rtt.is_none_or(|m| rtt.latest <= m) || expired {
+            self.min_rtt = Some(rtt.latest);
+            self.min_rtt_stamp = Some(now);
+        }
+        if expired && self.state != BbrState::ProbeRtt {
+            self.set_state(BbrState::ProbeRtt, now);
+            self.probe_rtt_done = Some(now + BBR_PROBE_RTT_TIME.max(rtt.latest));
+        }
+
+        match self.state {
+            BbrState::Drain if self.bdp().is_some_and(|bdp| self.in_flight <= bdp) => self.set_state(BbrState::ProbeBw, now),
+            BbrState::ProbeBw if let (Some(stamp), Some(min_rtt)) = (self.cycle_stamp, self.min_rtt) && now.saturating_duration_since(stamp) > min_rtt => {
+                self.cycle = (self.cycle + 1) % BBR_PACING_CYCLE.len();
+                self.set_state(BbrState::ProbeBw, now);
+            },
+            BbrState::ProbeRtt if self.probe_rtt_done.is_some_and(|done| now >= done) => {
+                let state = if self.full_bw_rounds >= 3 { BbrState::ProbeBw } else { BbrState::Startup };
+                self.set_state(state, now);
+            },
+            _ => (),
+        }
+
+        let floor = 4 * self.mss;
+        self.window = match (self.state, self.bdp()) {
+            (BbrState::ProbeRtt, _) => floor,
+            (_, Some(bdp)) => ((bdp as f64 * self.cwnd_gain) as usize).max(floor),
+            // no model yet, grow like slow start
+            (_, None) => self.window + bytes,
+        };
+    }
+    fn on_congestion_event(&mut self, _now: Instant, _sent: Instant, persistent: bool, _lost_bytes: usize) {
+        // loss is not a signal to bbr, a path that lost everything is
+        if persistent { self.window = minimum_window(self.mss) }
+    }
+
+    fn window(&self) -> usize {
+        self.window
+    }
+    fn pacing_rate(&self) -> Option<u64> {
+        if self.btl_bw == 0 { return None }
+        Some((self.btl_bw as f64 * self.pacing_gain) as u64)
+    }
+    fn set_mss(&mut self, mss: usize) {
+        self.mss = mss;
+    }
+}
+
+
+// 7.7, spreads packets over the rtt instead of sending the window at once
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    // bytes that may go out back to back
+    pub capacity: usize,
+    tokens: usize,
+    last: Option<Instant>,
+}
+impl Pacer {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, tokens: capacity, last: None }
+    }
+
+    // 7.7, N times the window per rtt so a window is not held back by timer granularity
+    pub fn rate(window: usize, srtt: Duration) -> u64 {
+        let srtt = srtt.max(Duration::from_micros(1));
+        (window as f64 * 1.25 / srtt.as_secs_f64()) as u64
+    }
+
+    // None when bytes can go now, otherwise when they can
+    pub fn delay(&mut self, now: Instant, bytes: usize, rate: u64) -> Option<Instant> {
+        self.refill(now, rate);
+        if self.tokens >= bytes.min(self.capacity) || rate == 0 { return None }
+
+        let missing = bytes.min(self.capacity) - self.tokens;
+        Some(now + Duration::from_secs_f64(missing as f64 / rate as f64))
+    }
+    pub fn on_sent(&mut self, now: Instant, bytes: usize, rate: u64) {
+        self.refill(now, rate);
+        self.tokens = self.tokens.saturating_sub(bytes);
+    }
+
+    fn refill(&mut self, now: Instant, rate: u64) {
+        if let Some(last) = self.last && now > last {
+            let earned = (now.saturating_duration_since(last).as_secs_f64() * rate as f64) as usize;
+            // the remainder of a byte is kept by not moving last
+            if earned == 0 { return }
+            self.tokens = (self.tokens + earned).min(self.capacity);
+        }
+        self.last = Some(now);
+    }
+}
+impl Default for Pacer {
+    #[inline]
+    fn default() -> Self {
+        Self::new(initial_window(INITIAL_MSS))
+    }
+}
@@ -0,0 +1,298 @@
+use std::{collections::BTreeMap, fmt::{Debug, Display}, ops::Deref};
+
+use bytes::{Buf, Bytes};
+
+// https://datatracker.ietf.org/doc/html/rfc9000
+
+pub const VERSION_1: u32 = 0x0000_0001;
+pub const MAX_VARINT: u64 = (1 << 62) - 1;
+// 14.1, a client pads its Initial packets to at least this
+pub const MIN_INITIAL_SIZE: usize = 1200;
+
+
+// 16, the two high bits of the first byte give the length
+pub fn varint_len(value: u64) -> usize {
+    if value < 1 << 6 { 1 }
+    else if value < 1 << 14 { 2 }
+    else if value < 1 << 30 { 4 }
+    else { 8 }
+}
+// values above MAX_VARINT can not be encoded, they are cut to 62 bits
+pub fn write_varint(buf: &mut Vec<u8>, value: u64) {
+    write_varint_with_len(buf, value, varint_len(value))
+}
+// a longer encoding than needed, for lengths that are filled in after the fact
+pub fn write_varint_with_len(buf: &mut Vec<u8>, value: u64, len: usize) {
+    match len {
+        1 => buf.push(value as u8),
+        2 => buf.extend_from_slice(&(value as u16 | 0x4000).to_be_bytes()),
+        4 => buf.extend_from_slice(&(value as u32 | 0x8000_0000).to_be_bytes()),
+        _ => buf.extend_from_slice(&(value & MAX_VARINT | 0xc000_0000_0000_0000).to_be_bytes()),
+    }
+}
+pub fn read_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let first = *buf.get(*pos)?;
+    let len = 1 << (first >> 6);
+    let bytes = buf.get(*pos..*pos + len)?;
+
+    let mut value = (first & 0x3f) as u64;
+    for b in &bytes[1..] {
+        value = (value << 8) | *b as u64;
+    }
+
+    *pos += len;
+    Some(value)
+}
+
+pub fn read_bytes<'b>(buf: &'b [u8], pos: &mut usize, len: usize) -> Option<&'b [u8]> {
+    let bytes = buf.get(*pos..pos.checked_add(len)?)?;
+    *pos += len;
+    Some(bytes)
+}
+pub fn read_array<const N: usize>(buf: &[u8], pos: &mut usize) -> Option<[u8; N]> {
+    read_bytes(buf, pos, N)?.try_into().ok()
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Client,
+    Server,
+}
+impl Side {
+    pub fn is_client(&self) -> bool { matches!(self, Self::Client) }
+    pub fn is_server(&self) -> bool { matches!(self, Self::Server) }
+    pub fn peer(&self) -> Self {
+        match self {
+            Self::Client => Self::Server,
+            Self::Server => Self::Client,
+        }
+    }
+}
+
+
+// 12.3, packet numbers and acknowledgements are kept apart per space
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Space {
+    Initial,
+    Handshake,
+    // 0-rtt and 1-rtt packets
+    Data,
+}
+impl Space {
+    pub const ALL: [Self; 3] = [Self::Initial, Self::Handshake, Self::Data];
+
+    pub fn index(&self) -> usize {
+        match self {
+            Self::Initial => 0,
+            Self::Handshake => 1,
+            Self::Data => 2,
+        }
+    }
+}
+
+
+// bytes of a stream that may arrive out of order and overlapping, handed out in order
+#[derive(Debug, Clone, Default)]
+pub struct Assembler {
+    // everything before this was handed out
+    pub offset: u64,
+    chunks: BTreeMap<u64, Bytes>,
+    buffered: usize,
+}
+impl Assembler {
+    pub fn insert(&mut self, offset: u64, mut data: Bytes) {
+        let end = offset + data.len() as u64;
+        if end <= self.offset || data.is_empty() { return }
+
+        let offset = if offset < self.offset {
+            data.advance((self.offset - offset) as usize);
+            self.offset
+        }
+        else { offset };
+
+        // of two chunks at the same offset the longer one covers more
+        if self.chunks.get(&offset).is_some_and(|c| c.len() >= data.len()) { return }
+        self.buffered += data.len();
+        if let Some(old) = self.chunks.insert(offset, data) { self.buffered -= old.len() }
+    }
+    // the next bytes in order, None while there is a gap
+    pub fn pop(&mut self) -> Option<Bytes> {
+        while let Some(entry) = self.chunks.first_entry() {
+            let start = *entry.key();
+            if start > self.offset { return None }
+
+            let mut data = entry.remove();
+            self.buffered -= data.len();
+            let end = start + data.len() as u64;
+            if end <= self.offset { continue }
+
+            data.advance((self.offset - start) as usize);
+            self.offset = end;
+            return Some(data);
+        }
+        None
+    }
+    // bytes held that could not be handed out yet, overlaps counted twice
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+
+// 5.1, 0 to 20 bytes chosen by the endpoint that receives packets with it
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ConnectionId {
+    len: u8,
+    bytes: [u8; ConnectionId::MAX_LEN],
+}
+impl ConnectionId {
+    pub const MAX_LEN: usize = 20;
+
+    // None when longer than MAX_LEN
+    pub fn new(id: &[u8]) -> Option<Self> {
+        if id.len() > Self::MAX_LEN { return None }
+
+        let mut bytes = [0; Self::MAX_LEN];
+        bytes[..id.len()].copy_from_slice(id);
+        Some(Self { len: id.len() as u8, bytes })
+    }
+
+    // a length byte followed by the id, as in long headers
+    pub fn from(buf: &[u8], pos: &mut usize) -> Option<Self> {
+        let len = *buf.get(*pos)? as usize;
+        if len > Self::MAX_LEN { return None }
+
+        let mut at = *pos + 1;
+        let id = Self::new(read_bytes(buf, &mut at, len)?)?;
+        *pos = at;
+        Some(id)
+    }
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        buf.push(self.len);
+        buf.extend_from_slice(self);
+    }
+}
+impl Deref for ConnectionId {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+impl Debug for ConnectionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ConnectionId(")?;
+        for b in self.iter() { write!(f, "{b:02x}")? }
+        write!(f, ")")
+    }
+}
+
+
+// 20.1, CONNECTION_CLOSE 0x1c carries these, 0x1d carries application codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuicErrorCode {
+    NoError,
+    InternalError,
+    ConnectionRefused,
+    FlowControlError,
+    StreamLimitError,
+    StreamStateError,
+    FinalSizeError,
+    FrameEncodingError,
+    TransportParameterError,
+    ConnectionIdLimitError,
+    ProtocolViolation,
+    InvalidToken,
+    ApplicationError,
+    CryptoBufferExceeded,
+    KeyUpdateError,
+    AeadLimitReached,
+    NoViablePath,
+    // a tls alert
+    Crypto(u8),
+
+    Unknown(u64),
+}
+impl From<u64> for QuicErrorCode {
+    fn from(value: u64) -> Self {
+        match value {
+            0x00 => Self::NoError,
+            0x01 => Self::InternalError,
+            0x02 => Self::ConnectionRefused,
+            0x03 => Self::FlowControlError,
+            0x04 => Self::StreamLimitError,
+            0x05 => Self::StreamStateError,
+            0x06 => Self::FinalSizeError,
+            0x07 => Self::FrameEncodingError,
+            0x08 => Self::TransportParameterError,
+            0x09 => Self::ConnectionIdLimitError,
+            0x0a => Self::ProtocolViolation,
+            0x0b => Self::InvalidToken,
+            0x0c => Self::ApplicationError,
+            0x0d => Self::CryptoBufferExceeded,
+            0x0e => Self::KeyUpdateError,
+            0x0f => Self::AeadLimitReached,
+            0x10 => Self::NoViablePath,
+            0x0100..=0x01ff => Self::Crypto(value as u8),
+
+            v => Self::Unknown(v),
+        }
+    }
+}
+impl From<QuicErrorCode> for u64 {
+    fn from(code: QuicErrorCode) -> Self {
+        match code {
+            QuicErrorCode::NoError => 0x00,
+            QuicErrorCode::InternalError => 0x01,
+            QuicErrorCode::ConnectionRefused => 0x02,
+            QuicErrorCode::FlowControlError => 0x03,
+            QuicErrorCode::StreamLimitError => 0x04,
+            QuicErrorCode::StreamStateError => 0x05,
+            QuicErrorCode::FinalSizeError => 0x06,
+            QuicErrorCode::FrameEncodingError => 0x07,
+            QuicErrorCode::TransportParameterError => 0x08,
+            QuicErrorCode::ConnectionIdLimitError => 0x09,
+            QuicErrorCode::ProtocolViolation => 0x0a,
+            QuicErrorCode::InvalidToken => 0x0b,
+            QuicErrorCode::ApplicationError => 0x0c,
+            QuicErrorCode::CryptoBufferExceeded => 0x0d,
+            QuicErrorCode::KeyUpdateError => 0x0e,
+            QuicErrorCode::AeadLimitReached => 0x0f,
+            QuicErrorCode::NoViablePath => 0x10,
+            QuicErrorCode::Crypto(alert) => 0x0100 | alert as u64,
+
+            QuicErrorCode::Unknown(v) => v,
+        }
+    }
+}
+impl Display for QuicErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoError => write!(f, "NO_ERROR"),
+            Self::InternalError => write!(f, "INTERNAL_ERROR"),
+            Self::ConnectionRefused => write!(f, "CONNECTION_REFUSED"),
+            Self::FlowControlError => write!(f, "FLOW_CONTROL_ERROR"),
+            Self::StreamLimitError => write!(f, "STREAM_LIMIT_ERROR"),
+            Self::StreamStateError => write!(f, "STREAM_STATE_ERROR"),
+            Self::FinalSizeError => write!(f, "FINAL_SIZE_ERROR"),
+            Self::FrameEncodingError => write!(f, "FRAME_ENCODING_ERROR"),
+            Self::TransportParameterError => write!(f, "TRANSPORT_PARAMETER_ERROR"),
+            Self::ConnectionIdLimitError => write!(f, "CONNECTION_ID_LIMIT_ERROR"),
+            Self::ProtocolViolation => write!(f, "PROTOCOL_VIOLATION"),
+            Self::InvalidToken => write!(f, "INVALID_TOKEN"),
+            Self::ApplicationError => write!(f, "APPLICATION_ERROR"),
+            Self::CryptoBufferExceeded => write!(f, "CRYPTO_BUFFER_EXCEEDED"),
+            Self::KeyUpdateError => write!(f, "KEY_UPDATE_ERROR"),
+            Self::AeadLimitReached => write!(f, "AEAD_LIMIT_REACHED"),
+            Self::NoViablePath => write!(f, "NO_VIABLE_PATH"),
+            Self::Crypto(alert) => write!(f, "CRYPTO_ERROR(0x{alert:02x})"),
+
+            Self::Unknown(v) => write!(f, "UNKNOWN(0x{v:x})"),
+        }
+    }
+}
+impl std::error::Error for QuicErrorCode {}
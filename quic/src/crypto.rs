@@ -0,0 +1,359 @@
+use std::{mem, ops::Range, sync::Arc};
+
+use bytes::Bytes;
+use rustls::{
+    CipherSuite, ClientConfig, RootCertStore, ServerConfig,
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    quic::{ClientConnection, Connection, DirectionalKeys, HeaderProtectionKey, KeyChange, Keys, PacketKey, PacketKeySet, Secrets, ServerConnection, Suite, Version},
+};
+
+use crate::{PROVIDER, core::{Assembler, ConnectionId, QuicErrorCode, Side, Space}, frame::Frame, packet::{Header, PacketType, decode_packet_number}, params::TransportParameters};
+
+// https://datatracker.ietf.org/doc/html/rfc9001
+
+// 5.4.2, the sample starts this far past the start of the packet number, as if it was 4 bytes long
+const SAMPLE_OFFSET: usize = 4;
+// 7.5, at least 4096 bytes have to be buffered, anything far beyond is CRYPTO_BUFFER_EXCEEDED
+pub const MAX_CRYPTO_BUFFER: usize = 1 << 16;
+
+
+fn tls_side(side: Side) -> rustls::Side {
+    match side {
+        Side::Client => rustls::Side::Client,
+        Side::Server => rustls::Side::Server,
+    }
+}
+
+// 5.2, initial packets always use AES-128-GCM whatever gets negotiated later
+pub fn initial_suite() -> Option<Suite> {
+    PROVIDER.cipher_suites.iter()
+        .find(|s| s.suite() == CipherSuite::TLS13_AES_128_GCM_SHA256)?
+        .tls13()?
+        .quic_suite()
+}
+// 5.2, derived from the destination connection id of the first Initial the client sends
+pub fn initial_keys(dcid: &ConnectionId, side: Side) -> Option<Keys> {
+    Some(initial_suite()?.keys(dcid, tls_side(side), Version::V1))
+}
+
+// quic needs tls 1.3 (4.2) and alpn (8.1)
+pub fn client_config(roots: RootCertStore, alpn: Vec<Vec<u8>>) -> Result<ClientConfig, rustls::Error> {
+    let mut conf = ClientConfig::builder_with_provider(PROVIDER.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    conf.alpn_protocols = alpn;
+    Ok(conf)
+}
+pub fn server_config(certs: Vec<CertificateDer<'static>>, key: PrivateKeyDer<'static>, alpn: Vec<Vec<u8>>) -> Result<ServerConfig, rustls::Error> {
+    let mut conf = ServerConfig::builder_with_provider(PROVIDER.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    conf.alpn_protocols = alpn;
+    Ok(conf)
+}
+
+
+// 5.3 and 5.4.1, appends the protected packet to buf and returns its length
+// pn_len comes from packet_number_len, payloads too short to be sampled are padded with PADDING frames
+pub fn seal(header_key: &dyn HeaderProtectionKey, packet_key: &dyn PacketKey, header: &Header, pn: u64, pn_len: usize, payload: &[u8], buf: &mut Vec<u8>) -> Result<usize, QuicErrorCode> {
+    let tag_len = packet_key.tag_len();
+    let padding = (SAMPLE_OFFSET + header_key.sample_len()).saturating_sub(pn_len + payload.len() + tag_len);
+
+    let start = buf.len();
+    let pn_offset = start + header.write(buf, pn, pn_len, payload.len() + padding + tag_len);
+    let payload_offset = pn_offset + pn_len;
+    buf.extend_from_slice(payload);
+    buf.resize(buf.len() + padding, 0);
+
+    // the header up to and including the packet number is the associated data
+    let (head, body) = buf[start..].split_at_mut(payload_offset - start);
+    let tag = packet_key.encrypt_in_place(pn, head, body).map_err(|_| QuicErrorCode::InternalError)?;
+    buf.extend_from_slice(tag.as_ref());
+
+    let (head, rest) = buf[start..].split_at_mut(pn_offset - start + SAMPLE_OFFSET);
+    let (first, head) = head.split_first_mut().ok_or(QuicErrorCode::InternalError)?;
+    let pn_start = pn_offset - start - 1;
+    header_key.encrypt_in_place(&rest[..header_key.sample_len()], first, &mut head[pn_start..pn_start + pn_len])
+        .map_err(|_| QuicErrorCode::InternalError)?;
+
+    Ok(buf.len() - start)
+}
+
+// 5.4.1, removes header protection in place and returns the full packet number
+// packet starts at the first byte of the header, header.first holds the unprotected bits afterwards
+// the reserved bits are only checked once the payload was authenticated (17.2)
+pub fn unprotect(header_key: &dyn HeaderProtectionKey, header: &mut Header, packet: &mut [u8], largest: Option<u64>) -> Option<u64> {
+    let sample_offset = header.pn_offset + SAMPLE_OFFSET;
+    let sample_len = header_key.sample_len();
+    // 5.4.2, a packet too short to sample is discarded
+    if header.pn_offset + header.length < sample_offset + sample_len { return None }
+
+    let (head, rest) = packet.split_at_mut(sample_offset);
+    let sample = rest.get(..sample_len)?;
+    let (first, head) = head.split_first_mut()?;
+    // all four bytes that may be the packet number are passed, only the real length is unmasked
+    header_key.decrypt_in_place(sample, first, &mut head[header.pn_offset - 1..]).ok()?;
+
+    header.first = *first;
+    let truncated = header.packet_number(packet)?;
+    Some(decode_packet_number(largest, truncated, header.pn_len()))
+}
+// 5.3, decrypts the payload in place, returns where the plaintext is in packet
+pub fn decrypt(packet_key: &dyn PacketKey, header: &Header, pn: u64, packet: &mut [u8]) -> Option<Range<usize>> {
+    let payload_offset = header.pn_offset + header.pn_len();
+    let end = header.pn_offset + header.length;
+    let (head, body) = packet.get_mut(..end)?.split_at_mut(payload_offset);
+
+    let len = packet_key.decrypt_in_place(pn, head, body).ok()?.len();
+    Some(payload_offset..payload_offset + len)
+}
+// None means the packet is discarded
+pub fn open(keys: &DirectionalKeys, header: &mut Header, packet: &mut [u8], largest: Option<u64>) -> Option<(u64, Range<usize>)> {
+    let pn = unprotect(&*keys.header, header, packet, largest)?;
+    Some((pn, decrypt(&*keys.packet, header, pn, packet)?))
+}
+
+
+// 6, the 1-rtt packet keys change with each key update, the header protection keys stay
+pub struct OneRttKeys {
+    pub local_header: Box<dyn HeaderProtectionKey>,
+    pub remote_header: Box<dyn HeaderProtectionKey>,
+    pub local: Box<dyn PacketKey>,
+    pub remote: Box<dyn PacketKey>,
+    pub key_phase: bool,
+
+    next: PacketKeySet,
+    secrets: Secrets,
+    // 6.5, the old remote key, for packets reordered around the update
+    previous: Option<Box<dyn PacketKey>>,
+    // the lowest packet numbers sent and received with the current keys
+    first_sent: Option<u64>,
+    first_received: Option<u64>,
+    // 6.1, a packet sent with the current keys was acknowledged
+    confirmed: bool,
+}
+impl OneRttKeys {
+    // secrets is what came with the keys in KeyChange::OneRtt, it is already one update ahead
+    pub fn new(keys: Keys, mut secrets: Secrets) -> Self {
+        let Keys { local, remote } = keys;
+        Self {
+            local_header: local.header,
+            remote_header: remote.header,
+            local: local.packet,
+            remote: remote.packet,
+            key_phase: false,
+            next: secrets.next_packet_keys(),
+            secrets,
+            previous: None,
+            first_sent: None,
+            first_received: None,
+            confirmed: false,
+        }
+    }
+
+    // the key phase bit of header is set to the current one
+    pub fn seal(&mut self, header: &Header, pn: u64, pn_len: usize, payload: &[u8], buf: &mut Vec<u8>) -> Result<usize, QuicErrorCode> {
+        let mut header = header.clone();
+        header.first = header.first & !0x04 | if self.key_phase { 0x04 } else { 0 };
+
+        let len = seal(&*self.local_header, &*self.local, &header, pn, pn_len, payload, buf)?;
+        self.first_sent.get_or_insert(pn);
+        Ok(len)
+    }
+    // 6.2 and 6.3, a key phase that is not the current one is either from before the last update or starts the next one
+    pub fn open(&mut self, header: &mut Header, packet: &mut [u8], largest: Option<u64>) -> Option<(u64, Range<usize>)> {
+        let pn = unprotect(&*self.remote_header, header, packet, largest)?;
+
+        if header.key_phase() == self.key_phase {
+            let range = decrypt(&*self.remote, header, pn, packet)?;
+            self.first_received = Some(self.first_received.map_or(pn, |f| f.min(pn)));
+            return Some((pn, range));
+        }
+
+        if let Some(previous) = &self.previous && self.first_received.is_none_or(|f| pn < f) {
+            return Some((pn, decrypt(&**previous, header, pn, packet)?));
+        }
+
+        // only an authenticated packet can make us update
+        let range = decrypt(&*self.next.remote, header, pn, packet)?;
+        self.rotate();
+        self.first_received = Some(pn);
+        Some((pn, range))
+    }
+
+    // 6.1, initiates a key update, which is only allowed once the current keys are confirmed
+    pub fn update(&mut self) -> bool {
+        if !self.confirmed { return false }
+        self.rotate();
+        true
+    }
+    // call with every acknowledged 1-rtt packet number
+    pub fn on_acked(&mut self, pn: u64) {
+        if self.first_sent.is_some_and(|f| pn >= f) { self.confirmed = true }
+    }
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed
+    }
+    // 6.5, some time after an update, 3 PTO is recommended
+    pub fn drop_previous(&mut self) {
+        self.previous = None;
+    }
+
+    fn rotate(&mut self) {
+        let next = mem::replace(&mut self.next, self.secrets.next_packet_keys());
+        self.local = next.local;
+        self.previous = Some(mem::replace(&mut self.remote, next.remote));
+        self.key_phase = !self.key_phase;
+        self.first_sent = None;
+        self.first_received = None;
+        self.confirmed = false;
+    }
+}
+
+
+// 4, the handshake runs over CRYPTO frames in the Initial, Handshake and 1-rtt spaces
+pub struct Tls {
+    pub side: Side,
+    pub conn: Connection,
+
+    // None before the keys are known and once the space is discarded (4.9)
+    pub initial: Option<Keys>,
+    pub handshake: Option<Keys>,
+    pub zero_rtt: Option<DirectionalKeys>,
+    pub one_rtt: Option<OneRttKeys>,
+
+    // where the next handshake bytes from rustls belong
+    write_space: Space,
+    // taken out of rustls early for the keys that come with them
+    pending: Vec<(Space, Frame)>,
+    sent: [u64; 3],
+    received: [Assembler; 3],
+}
+impl Tls {
+    // dcid is the destination connection id of the first Initial, params should carry initial_source_connection_id
+    pub fn client(config: Arc<ClientConfig>, server_name: ServerName<'static>, dcid: &ConnectionId, params: &TransportParameters) -> Result<Self, rustls::Error> {
+        let conn = ClientConnection::new(config, Version::V1, server_name, params.to_vec())?;
+        Self::new(Side::Client, conn.into(), dcid)
+    }
+    // dcid is the one the client chose, params should carry original_destination_connection_id
+    pub fn server(config: Arc<ServerConfig>, dcid: &ConnectionId, params: &TransportParameters) -> Result<Self, rustls::Error> {
+        let conn = ServerConnection::new(config, Version::V1, params.to_vec())?;
+        Self::new(Side::Server, conn.into(), dcid)
+    }
+    fn new(side: Side, conn: Connection, dcid: &ConnectionId) -> Result<Self, rustls::Error> {
+        let initial = initial_keys(dcid, side).ok_or(rustls::Error::General("no AES-128-GCM suite for initial packets".into()))?;
+        Ok(Self {
+            side,
+            zero_rtt: conn.zero_rtt_keys(),
+            conn,
+            initial: Some(initial),
+            handshake: None,
+            one_rtt: None,
+            write_space: Space::Initial,
+            pending: Vec::new(),
+            sent: [0; 3],
+            received: Default::default(),
+        })
+    }
+
+    // the data of a CRYPTO frame received in space
+    pub fn read_crypto(&mut self, space: Space, offset: u64, data: Bytes) -> Result<(), QuicErrorCode> {
+        let received = &mut self.received[space.index()];
+        received.insert(offset, data);
+        if received.buffered() > MAX_CRYPTO_BUFFER { return Err(QuicErrorCode::CryptoBufferExceeded) }
+
+        while let Some(data) = received.pop() {
+            // 4.8, tls alerts become CRYPTO_ERROR
+            self.conn.read_hs(&data).map_err(|_| match self.conn.alert() {
+                Some(alert) => QuicErrorCode::Crypto(alert.into()),
+                None => QuicErrorCode::ProtocolViolation,
+            })?;
+        }
+
+        if self.zero_rtt.is_none() && self.side.is_server() { self.zero_rtt = self.conn.zero_rtt_keys() }
+        // the keys for what the peer sends next only come out together with our answer
+        self.poll_tls();
+        Ok(())
+    }
+    // the CRYPTO frames to send and the space of each
+    pub fn write_crypto(&mut self) -> Vec<(Space, Frame)> {
+        self.poll_tls();
+        mem::take(&mut self.pending)
+    }
+    // keys are installed as rustls hands them out
+    fn poll_tls(&mut self) {
+        loop {
+            let mut buf = Vec::new();
+            let change = self.conn.write_hs(&mut buf);
+
+            if !buf.is_empty() {
+                let sent = &mut self.sent[self.write_space.index()];
+                let offset = *sent;
+                *sent += buf.len() as u64;
+                self.pending.push((self.write_space, Frame::Crypto { offset, data: buf.into() }));
+            }
+
+            match change {
+                Some(KeyChange::Handshake { keys }) => {
+                    self.handshake = Some(keys);
+                    self.write_space = Space::Handshake;
+                },
+                Some(KeyChange::OneRtt { keys, next }) => {
+                    self.one_rtt = Some(OneRttKeys::new(keys, next));
+                    self.write_space = Space::Data;
+                },
+                None => break,
+            }
+        }
+    }
+
+    // chooses the keys by packet type
+    pub fn seal(&mut self, header: &Header, pn: u64, pn_len: usize, payload: &[u8], buf: &mut Vec<u8>) -> Result<usize, QuicErrorCode> {
+        let keys = match header.ptype {
+            PacketType::Initial => self.initial.as_ref().map(|k| &k.local),
+            PacketType::Handshake => self.handshake.as_ref().map(|k| &k.local),
+            PacketType::ZeroRtt if self.side.is_client() => self.zero_rtt.as_ref(),
+            PacketType::OneRtt => {
+                let keys = self.one_rtt.as_mut().ok_or(QuicErrorCode::InternalError)?;
+                return keys.seal(header, pn, pn_len, payload, buf);
+            },
+            _ => None,
+        };
+        let keys = keys.ok_or(QuicErrorCode::InternalError)?;
+        seal(&*keys.header, &*keys.packet, header, pn, pn_len, payload, buf)
+    }
+    // None when the packet can not be decrypted, yet or at all
+    pub fn open(&mut self, header: &mut Header, packet: &mut [u8], largest: Option<u64>) -> Option<(u64, Range<usize>)> {
+        let keys = match header.ptype {
+            PacketType::Initial => &self.initial.as_ref()?.remote,
+            PacketType::Handshake => &self.handshake.as_ref()?.remote,
+            PacketType::ZeroRtt if self.side.is_server() => self.zero_rtt.as_ref()?,
+            PacketType::OneRtt => return self.one_rtt.as_mut()?.open(header, packet, largest),
+            _ => return None,
+        };
+        open(keys, header, packet, largest)
+    }
+
+    // 4.9, keys of a space are thrown away once the next space is in use
+    pub fn discard(&mut self, space: Space) {
+        match space {
+            Space::Initial => self.initial = None,
+            Space::Handshake => self.handshake = None,
+            // 4.9.3, 0-rtt keys are not needed once 1-rtt keys are
+            Space::Data => self.zero_rtt = None,
+        }
+    }
+
+    pub fn is_handshaking(&self) -> bool {
+        self.conn.is_handshaking()
+    }
+    pub fn alpn(&self) -> Option<&[u8]> {
+        self.conn.alpn_protocol()
+    }
+    // 8.2, available before the handshake completes but only trustworthy after
+    pub fn peer_params(&self) -> Option<Result<TransportParameters, QuicErrorCode>> {
+        self.conn.quic_transport_parameters().map(|p| TransportParameters::from(p, self.side.peer()))
+    }
+}
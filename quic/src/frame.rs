@@ -0,0 +1,290 @@
+use std::ops::RangeInclusive;
+
+use bytes::Bytes;
+
+use crate::{core::{ConnectionId, MAX_VARINT, QuicErrorCode, read_array, read_bytes, read_varint, write_varint}, packet::PacketType};
+
+// 4.6, stream counts can not reach past what a stream id can hold
+pub const MAX_STREAMS: u64 = 1 << 60;
+
+
+// 19.3.2
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EcnCounts {
+    pub ect0: u64,
+    pub ect1: u64,
+    pub ce: u64,
+}
+
+// 19
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    // a run of padding bytes
+    Padding(usize),
+    Ping,
+    Ack {
+        // in microseconds after scaling by the ack_delay_exponent, as sent
+        delay: u64,
+        // acknowledged packet numbers, largest range first and none overlapping
+        ranges: Vec<RangeInclusive<u64>>,
+        ecn: Option<EcnCounts>,
+    },
+    ResetStream { stream_id: u64, error_code: u64, final_size: u64 },
+    StopSending { stream_id: u64, error_code: u64 },
+    Crypto { offset: u64, data: Bytes },
+    NewToken(Bytes),
+    Stream { stream_id: u64, offset: u64, fin: bool, data: Bytes },
+    MaxData(u64),
+    MaxStreamData { stream_id: u64, max: u64 },
+    MaxStreams { bidi: bool, max: u64 },
+    DataBlocked(u64),
+    StreamDataBlocked { stream_id: u64, limit: u64 },
+    StreamsBlocked { bidi: bool, limit: u64 },
+    NewConnectionId { sequence: u64, retire_prior_to: u64, cid: ConnectionId, reset_token: [u8; 16] },
+    RetireConnectionId(u64),
+    PathChallenge([u8; 8]),
+    PathResponse([u8; 8]),
+    // frame_type is None for the application variant 0x1d
+    ConnectionClose { error_code: u64, frame_type: Option<u64>, reason: Bytes },
+    HandshakeDone,
+}
+impl Frame {
+    pub fn frame_type(&self) -> u64 {
+        match self {
+            Self::Padding(_) => 0x00,
+            Self::Ping => 0x01,
+            Self::Ack { ecn, .. } => if ecn.is_some() { 0x03 } else { 0x02 },
+            Self::ResetStream { .. } => 0x04,
+            Self::StopSending { .. } => 0x05,
+            Self::Crypto { .. } => 0x06,
+            Self::NewToken(_) => 0x07,
+            Self::Stream { offset, fin, .. } => 0x08 | if *offset > 0 { 0x04 } else { 0 } | 0x02 | if *fin { 0x01 } else { 0 },
+            Self::MaxData(_) => 0x10,
+            Self::MaxStreamData { .. } => 0x11,
+            Self::MaxStreams { bidi, .. } => if *bidi { 0x12 } else { 0x13 },
+            Self::DataBlocked(_) => 0x14,
+            Self::StreamDataBlocked { .. } => 0x15,
+            Self::StreamsBlocked { bidi, .. } => if *bidi { 0x16 } else { 0x17 },
+            Self::NewConnectionId { .. } => 0x18,
+            Self::RetireConnectionId(_) => 0x19,
+            Self::PathChallenge(_) => 0x1a,
+            Self::PathResponse(_) => 0x1b,
+            Self::ConnectionClose { frame_type, .. } => if frame_type.is_some() { 0x1c } else { 0x1d },
+            Self::HandshakeDone => 0x1e,
+        }
+    }
+
+    // 13.2.1, packets with only these do not get acknowledged right away
+    pub fn is_ack_eliciting(&self) -> bool {
+        !matches!(self, Self::Padding(_) | Self::Ack { .. } | Self::ConnectionClose { .. })
+    }
+    // 12.4 table 3
+    pub fn allowed_in(&self, ptype: PacketType) -> bool {
+        match ptype {
+            PacketType::Initial | PacketType::Handshake => {
+                matches!(self, Self::Padding(_) | Self::Ping | Self::Ack { .. } | Self::Crypto { .. } | Self::ConnectionClose { frame_type: Some(_), .. })
+            },
+            PacketType::ZeroRtt => {
+                !matches!(self, Self::Ack { .. } | Self::Crypto { .. } | Self::NewToken(_) | Self::PathResponse(_) | Self::RetireConnectionId(_) | Self::HandshakeDone)
+            },
+            PacketType::OneRtt => true,
+            PacketType::Retry => false,
+        }
+    }
+
+    // a whole packet payload, any error is a connection error of that code
+    pub fn parse_all(payload: &Bytes) -> Result<Vec<Self>, QuicErrorCode> {
+        let mut frames = Vec::new();
+        let mut pos = 0;
+
+        while pos < payload.len() {
+            frames.push(Self::from(payload, &mut pos)?);
+        }
+        // 12.4, a packet has to carry at least one frame
+        if frames.is_empty() { Err(QuicErrorCode::ProtocolViolation) }
+        else { Ok(frames) }
+    }
+
+    pub fn from(buf: &Bytes, pos: &mut usize) -> Result<Self, QuicErrorCode> {
+        let err = QuicErrorCode::FrameEncodingError;
+        let varint = |pos: &mut usize| read_varint(buf, pos).ok_or(err);
+        let slice = |pos: &mut usize, len: u64| {
+            let start = *pos;
+            read_bytes(buf, pos, usize::try_from(len).map_err(|_| err)?).ok_or(err)?;
+            Ok::<_, QuicErrorCode>(buf.slice(start..*pos))
+        };
+
+        let ftype = varint(pos)?;
+
+        let frame = match ftype {
+            0x00 => {
+                let start = *pos - 1;
+                while buf.get(*pos) == Some(&0) { *pos += 1 }
+                Self::Padding(*pos - start)
+            },
+            0x01 => Self::Ping,
+            0x02 | 0x03 => {
+                let largest = varint(pos)?;
+                let delay = varint(pos)?;
+                let count = varint(pos)?;
+                let first = varint(pos)?;
+
+                let mut smallest = largest.checked_sub(first).ok_or(err)?;
+                // every range takes at least two bytes, more than that can not be in the packet
+                let mut ranges = Vec::with_capacity((count as usize).min(buf.len() / 2) + 1);
+                ranges.push(smallest..=largest);
+
+                for _ in 0..count {
+                    let gap = varint(pos)?;
+                    let len = varint(pos)?;
+
+                    let end = smallest.checked_sub(gap + 2).ok_or(err)?;
+                    smallest = end.checked_sub(len).ok_or(err)?;
+                    ranges.push(smallest..=end);
+                }
+
+                let ecn = if ftype == 0x03 {
+                    Some(EcnCounts { ect0: varint(pos)?, ect1: varint(pos)?, ce: varint(pos)? })
+                }
+                else { None };
+
+                Self::Ack { delay, ranges, ecn }
+            },
+            0x04 => Self::ResetStream { stream_id: varint(pos)?, error_code: varint(pos)?, final_size: varint(pos)? },
+            0x05 => Self::StopSending { stream_id: varint(pos)?, error_code: varint(pos)? },
+            0x06 => {
+                let offset = varint(pos)?;
+                let len = varint(pos)?;
+                if offset + len > MAX_VARINT { return Err(err) }
+                Self::Crypto { offset, data: slice(pos, len)? }
+            },
+            0x07 => {
+                let len = varint(pos)?;
+                // 19.7, an empty token is a FRAME_ENCODING_ERROR
+                if len == 0 { return Err(err) }
+                Self::NewToken(slice(pos, len)?)
+            },
+            0x08..=0x0f => {
+                let stream_id = varint(pos)?;
+                let offset = if ftype & 0x04 != 0 { varint(pos)? } else { 0 };
+                // without the length bit the data runs to the end of the packet
+                let len = if ftype & 0x02 != 0 { varint(pos)? } else { (buf.len() - *pos) as u64 };
+
+                // 19.8, the final size has to fit in a varint
+                if offset + len > MAX_VARINT { return Err(err) }
+                Self::Stream { stream_id, offset, fin: ftype & 0x01 != 0, data: slice(pos, len)? }
+            },
+            0x10 => Self::MaxData(varint(pos)?),
+            0x11 => Self::MaxStreamData { stream_id: varint(pos)?, max: varint(pos)? },
+            0x12 | 0x13 => {
+                let max = varint(pos)?;
+                if max > MAX_STREAMS { return Err(err) }
+                Self::MaxStreams { bidi: ftype == 0x12, max }
+            },
+            0x14 => Self::DataBlocked(varint(pos)?),
+            0x15 => Self::StreamDataBlocked { stream_id: varint(pos)?, limit: varint(pos)? },
+            0x16 | 0x17 => {
+                let limit = varint(pos)?;
+                if limit > MAX_STREAMS { return Err(err) }
+                Self::StreamsBlocked { bidi: ftype == 0x16, limit }
+            },
+            0x18 => {
+                let sequence = varint(pos)?;
+                let retire_prior_to = varint(pos)?;
+                let cid = ConnectionId::from(buf, pos).ok_or(err)?;
+                let reset_token = read_array(buf, pos).ok_or(err)?;
+
+                // 19.15, ids of 1 to 20 bytes and nothing retired past the new one
+                if cid.is_empty() || retire_prior_to > sequence { return Err(err) }
+                Self::NewConnectionId { sequence, retire_prior_to, cid, reset_token }
+            },
+            0x19 => Self::RetireConnectionId(varint(pos)?),
+            0x1a => Self::PathChallenge(read_array(buf, pos).ok_or(err)?),
+            0x1b => Self::PathResponse(read_array(buf, pos).ok_or(err)?),
+            0x1c | 0x1d => {
+                let error_code = varint(pos)?;
+                let frame_type = if ftype == 0x1c { Some(varint(pos)?) } else { None };
+                let len = varint(pos)?;
+                Self::ConnectionClose { error_code, frame_type, reason: slice(pos, len)? }
+            },
+            0x1e => Self::HandshakeDone,
+            // 12.4, unknown frame types
+            _ => return Err(err),
+        };
+        Ok(frame)
+    }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        if let Self::Padding(len) = self {
+            buf.resize(buf.len() + len, 0);
+            return;
+        }
+        write_varint(buf, self.frame_type());
+
+        match self {
+            Self::Padding(_) | Self::Ping | Self::HandshakeDone => (),
+            // there is always at least the range with the largest packet number
+            Self::Ack { delay, ranges, ecn } => {
+                let first = &ranges[0];
+
+                write_varint(buf, *first.end());
+                write_varint(buf, *delay);
+                write_varint(buf, ranges.len() as u64 - 1);
+                write_varint(buf, first.end() - first.start());
+
+                for pair in ranges.windows(2) {
+                    write_varint(buf, pair[0].start() - pair[1].end() - 2);
+                    write_varint(buf, pair[1].end() - pair[1].start());
+                }
+                if let Some(ecn) = ecn {
+                    write_varint(buf, ecn.ect0);
+                    write_varint(buf, ecn.ect1);
+                    write_varint(buf, ecn.ce);
+                }
+            },
+            Self::ResetStream { stream_id, error_code, final_size } => {
+                write_varint(buf, *stream_id);
+                write_varint(buf, *error_code);
+                write_varint(buf, *final_size);
+            },
+            Self::StopSending { stream_id, error_code } => {
+                write_varint(buf, *stream_id);
+                write_varint(buf, *error_code);
+            },
+            Self::Crypto { offset, data } => {
+                write_varint(buf, *offset);
+                write_varint(buf, data.len() as u64);
+                buf.extend_from_slice(data);
+            },
+            Self::NewToken(token) => {
+                write_varint(buf, token.len() as u64);
+                buf.extend_from_slice(token);
+            },
+            Self::Stream { stream_id, offset, data, .. } => {
+                write_varint(buf, *stream_id);
+                if *offset > 0 { write_varint(buf, *offset) }
+                write_varint(buf, data.len() as u64);
+                buf.extend_from_slice(data);
+            },
+            Self::MaxData(max) | Self::DataBlocked(max) | Self::RetireConnectionId(max) => write_varint(buf, *max),
+            Self::MaxStreamData { stream_id, max: value } | Self::StreamDataBlocked { stream_id, limit: value } => {
+                write_varint(buf, *stream_id);
+                write_varint(buf, *value);
+            },
+            Self::MaxStreams { max: value, .. } | Self::StreamsBlocked { limit: value, .. } => write_varint(buf, *value),
+            Self::NewConnectionId { sequence, retire_prior_to, cid, reset_token } => {
+                write_varint(buf, *sequence);
+                write_varint(buf, *retire_prior_to);
+                cid.write(buf);
+                buf.extend_from_slice(reset_token);
+            },
+            Self::PathChallenge(data) | Self::PathResponse(data) => buf.extend_from_slice(data),
+            Self::ConnectionClose { error_code, frame_type, reason } => {
+                write_varint(buf, *error_code);
+                if let Some(frame_type) = frame_type { write_varint(buf, *frame_type) }
+                write_varint(buf, reason.len() as u64);
+                buf.extend_from_slice(reason);
+            },
+        }
+    }
+}
@@ -1 +1,24 @@
+use std::sync::{Arc, LazyLock};
+
+use rustls::crypto::CryptoProvider;
+
 pub mod tests;
+
+pub mod core;
+pub mod packet;
+pub mod frame;
+pub mod params;
+pub mod crypto;
+pub mod recovery;
+pub mod congestion;
+pub mod stream;
+
+
+#[cfg(feature = "aws-lc-rs")]
+pub static PROVIDER: LazyLock<Arc<CryptoProvider>> = LazyLock::new(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()));
+#[cfg(feature = "ring")]
+pub static PROVIDER: LazyLock<Arc<CryptoProvider>> = LazyLock::new(|| Arc::new(rustls::crypto::ring::default_provider()));
+#[cfg(not(any(feature = "ring", feature = "aws-lc-rs")))]
+pub static PROVIDER: LazyLock<Arc<CryptoProvider>> = LazyLock::new(|| rustls::crypto::CryptoProvider::get_default().unwrap().clone());
+#[cfg(not(any(feature = "ring", feature = "aws-lc-rs")))]
+compile_error!("You must enable either feature \"ring\" or \"aws-lc-rs\"");
@@ -0,0 +1,290 @@
+use bytes::Bytes;
+
+use crate::core::{ConnectionId, Space, VERSION_1, read_array, read_bytes, read_varint, write_varint};
+
+
+// 17.2, the two type bits of a long header and the short header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketType {
+    Initial,
+    ZeroRtt,
+    Handshake,
+    Retry,
+    OneRtt,
+}
+impl PacketType {
+    pub fn is_long(&self) -> bool {
+        !matches!(self, Self::OneRtt)
+    }
+    fn long_bits(&self) -> u8 {
+        match self {
+            Self::Initial => 0x00,
+            Self::ZeroRtt => 0x10,
+            Self::Handshake => 0x20,
+            Self::Retry | Self::OneRtt => 0x30,
+        }
+    }
+    // Retry carries no frames, it is counted with the application data
+    pub fn space(&self) -> Space {
+        match self {
+            Self::Initial => Space::Initial,
+            Self::Handshake => Space::Handshake,
+            Self::ZeroRtt | Self::OneRtt | Self::Retry => Space::Data,
+        }
+    }
+}
+
+
+// a packet header up to the packet number, which like the low bits of the first byte stays protected until rfc9001 5.4 is undone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub ptype: PacketType,
+    pub first: u8,
+    // None for short headers
+    pub version: Option<u32>,
+    pub dcid: ConnectionId,
+    pub scid: ConnectionId,
+    // Initial packets only
+    pub token: Bytes,
+    // bytes from the packet number to the end of the packet
+    pub length: usize,
+    pub pn_offset: usize,
+}
+impl Header {
+    pub fn initial(dcid: ConnectionId, scid: ConnectionId, token: Bytes) -> Self {
+        Self { token, ..Self::long(PacketType::Initial, VERSION_1, dcid, scid) }
+    }
+    pub fn long(ptype: PacketType, version: u32, dcid: ConnectionId, scid: ConnectionId) -> Self {
+        Self {
+            ptype,
+            first: 0xc0 | ptype.long_bits(),
+            version: Some(version),
+            dcid, scid,
+            token: Bytes::new(),
+            length: 0,
+            pn_offset: 0,
+        }
+    }
+    pub fn short(dcid: ConnectionId, spin: bool, key_phase: bool) -> Self {
+        Self {
+            ptype: PacketType::OneRtt,
+            first: 0x40 | if spin { 0x20 } else { 0 } | if key_phase { 0x04 } else { 0 },
+            version: None,
+            dcid,
+            scid: ConnectionId::default(),
+            token: Bytes::new(),
+            length: 0,
+            pn_offset: 0,
+        }
+    }
+
+    // the fields below are only meaningful once header protection is removed
+    pub fn pn_len(&self) -> usize {
+        (self.first & 0x03) as usize + 1
+    }
+    pub fn key_phase(&self) -> bool {
+        !self.ptype.is_long() && self.first & 0x04 != 0
+    }
+    pub fn spin(&self) -> bool {
+        !self.ptype.is_long() && self.first & 0x20 != 0
+    }
+    // 17.2 and 17.3.1, non-zero reserved bits are a PROTOCOL_VIOLATION
+    pub fn reserved_bits_valid(&self) -> bool {
+        self.first & if self.ptype.is_long() { 0x0c } else { 0x18 } == 0
+    }
+    // the truncated packet number in the unprotected packet
+    pub fn packet_number(&self, packet: &[u8]) -> Option<u64> {
+        let mut pos = self.pn_offset;
+        let bytes = read_bytes(packet, &mut pos, self.pn_len())?;
+        Some(bytes.iter().fold(0, |pn, b| (pn << 8) | *b as u64))
+    }
+
+    // payload_len counts everything after the packet number, the aead tag included, returns the packet number offset
+    pub fn write(&self, buf: &mut Vec<u8>, pn: u64, pn_len: usize, payload_len: usize) -> usize {
+        let start = buf.len();
+        buf.push(self.first & !0x03 | (pn_len as u8 - 1));
+
+        if let Some(version) = self.version {
+            buf.extend_from_slice(&version.to_be_bytes());
+            self.dcid.write(buf);
+            self.scid.write(buf);
+            if self.ptype == PacketType::Initial {
+                write_varint(buf, self.token.len() as u64);
+                buf.extend_from_slice(&self.token);
+            }
+            write_varint(buf, (pn_len + payload_len) as u64);
+        }
+        else {
+            buf.extend_from_slice(&self.dcid);
+        }
+
+        let pn_offset = buf.len() - start;
+        buf.extend_from_slice(&pn.to_be_bytes()[8 - pn_len..]);
+        pn_offset
+    }
+}
+
+
+// what the first packet in a datagram is, before anything is decrypted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    Protected(Header),
+    VersionNegotiation(VersionNegotiation),
+    Retry(Retry),
+    // 6, a long header with a version we do not speak, answered with Version Negotiation
+    Unsupported(u32, ConnectionId, ConnectionId),
+}
+impl Packet {
+    // (packet, bytes used), a datagram can carry several long header packets (12.2)
+    // short headers do not say how long their connection id is, local_cid_len is the length we hand out
+    // None means the packet is discarded
+    pub fn from(buf: &Bytes, local_cid_len: usize) -> Option<(Self, usize)> {
+        let first = *buf.first()?;
+        let mut pos = 1;
+
+        if first & 0x80 == 0 {
+            // 17.3.1, the fixed bit
+            if first & 0x40 == 0 { return None }
+            let dcid = ConnectionId::new(read_bytes(buf, &mut pos, local_cid_len)?)?;
+            if buf.len() == pos { return None }
+
+            let header = Header {
+                ptype: PacketType::OneRtt,
+                first,
+                version: None,
+                dcid,
+                scid: ConnectionId::default(),
+                token: Bytes::new(),
+                length: buf.len() - pos,
+                pn_offset: pos,
+            };
+            return Some((Self::Protected(header), buf.len()));
+        }
+
+        let version = u32::from_be_bytes(read_array(buf, &mut pos)?);
+        let dcid = ConnectionId::from(buf, &mut pos)?;
+        let scid = ConnectionId::from(buf, &mut pos)?;
+
+        if version == 0 {
+            let mut versions = Vec::new();
+            while let Some(version) = read_array(buf, &mut pos) { versions.push(u32::from_be_bytes(version)) }
+            if pos != buf.len() { return None }
+
+            return Some((Self::VersionNegotiation(VersionNegotiation { dcid, scid, versions }), buf.len()));
+        }
+        if version != VERSION_1 {
+            return Some((Self::Unsupported(version, dcid, scid), buf.len()));
+        }
+        if first & 0x40 == 0 { return None }
+
+        let ptype = match first & 0x30 {
+            0x00 => PacketType::Initial,
+            0x10 => PacketType::ZeroRtt,
+            0x20 => PacketType::Handshake,
+            _ => {
+                let token_len = buf.len().checked_sub(pos + 16)?;
+                let token = buf.slice(pos..pos + token_len);
+                pos += token_len;
+                let integrity_tag = read_array(buf, &mut pos)?;
+
+                return Some((Self::Retry(Retry { version, dcid, scid, token, integrity_tag }), buf.len()));
+            },
+        };
+
+        let token = if ptype == PacketType::Initial {
+            let len = read_varint(buf, &mut pos)? as usize;
+            let start = pos;
+            read_bytes(buf, &mut pos, len)?;
+            buf.slice(start..pos)
+        }
+        else { Bytes::new() };
+
+        let length = read_varint(buf, &mut pos)? as usize;
+        // the packet number alone takes at least one byte
+        if length == 0 || length > buf.len() - pos { return None }
+
+        let header = Header { ptype, first, version: Some(version), dcid, scid, token, length, pn_offset: pos };
+        Some((Self::Protected(header), pos + length))
+    }
+}
+
+
+// 17.2.1, a server's answer to a version it does not support, never protected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionNegotiation {
+    pub dcid: ConnectionId,
+    pub scid: ConnectionId,
+    pub versions: Vec<u32>,
+}
+impl VersionNegotiation {
+    // the connection ids are the ones of the client's packet, swapped
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        // the unused bits are arbitrary, 0x40 keeps quic multiplexable with other protocols
+        buf.push(0xc0);
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        self.dcid.write(buf);
+        self.scid.write(buf);
+        for version in &self.versions {
+            buf.extend_from_slice(&version.to_be_bytes());
+        }
+    }
+}
+
+// 17.2.5
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retry {
+    pub version: u32,
+    pub dcid: ConnectionId,
+    pub scid: ConnectionId,
+    pub token: Bytes,
+    pub integrity_tag: [u8; 16],
+}
+impl Retry {
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        self.write_without_tag(buf);
+        buf.extend_from_slice(&self.integrity_tag);
+    }
+    fn write_without_tag(&self, buf: &mut Vec<u8>) {
+        buf.push(0xc0 | PacketType::Retry.long_bits());
+        buf.extend_from_slice(&self.version.to_be_bytes());
+        self.dcid.write(buf);
+        self.scid.write(buf);
+        buf.extend_from_slice(&self.token);
+    }
+    // rfc9001 5.8, what the integrity tag is computed over
+    pub fn pseudo_packet(&self, original_dcid: &ConnectionId) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64 + self.token.len());
+        original_dcid.write(&mut buf);
+        self.write_without_tag(&mut buf);
+        buf
+    }
+}
+
+
+// a.2, enough bytes to cover twice the packets in flight
+pub fn packet_number_len(full: u64, largest_acked: Option<u64>) -> usize {
+    let unacked = match largest_acked {
+        Some(largest) => full - largest,
+        None => full + 1,
+    };
+    let bits = 64 - unacked.leading_zeros() as usize + 1;
+    bits.div_ceil(8).clamp(1, 4)
+}
+// a.3, the candidate closest to the next expected packet number
+pub fn decode_packet_number(largest: Option<u64>, truncated: u64, pn_len: usize) -> u64 {
+    let expected = largest.map_or(0, |l| l + 1);
+    let win = 1u64 << (pn_len * 8);
+    let hwin = win / 2;
+    let mask = win - 1;
+
+    let candidate = (expected & !mask) | truncated;
+    if candidate + hwin <= expected && candidate < (1 << 62) - win {
+        candidate + win
+    }
+    else if candidate > expected + hwin && candidate >= win {
+        candidate - win
+    }
+    else {
+        candidate
+    }
+}
@@ -0,0 +1,243 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+
+use bytes::Bytes;
+
+use crate::{core::{ConnectionId, QuicErrorCode, Side, read_array, read_bytes, read_varint, write_varint}, frame::MAX_STREAMS};
+
+// 18.2, what an absent parameter means
+pub const DEFAULT_MAX_UDP_PAYLOAD_SIZE: u64 = 65527;
+pub const DEFAULT_ACK_DELAY_EXPONENT: u64 = 3;
+pub const DEFAULT_MAX_ACK_DELAY: u64 = 25;
+pub const DEFAULT_ACTIVE_CONNECTION_ID_LIMIT: u64 = 2;
+
+
+// 18.2, a server's address to migrate to after the handshake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreferredAddress {
+    pub ipv4: Option<SocketAddrV4>,
+    pub ipv6: Option<SocketAddrV6>,
+    pub cid: ConnectionId,
+    pub reset_token: [u8; 16],
+}
+impl PreferredAddress {
+    pub fn from(buf: &[u8]) -> Option<Self> {
+        let mut pos = 0;
+        let ip4: [u8; 4] = read_array(buf, &mut pos)?;
+        let port4 = u16::from_be_bytes(read_array(buf, &mut pos)?);
+        let ip6: [u8; 16] = read_array(buf, &mut pos)?;
+        let port6 = u16::from_be_bytes(read_array(buf, &mut pos)?);
+        let cid = ConnectionId::from(buf, &mut pos)?;
+        let reset_token = read_array(buf, &mut pos)?;
+
+        if pos != buf.len() { return None }
+
+        // an all zero address and port means the family is not offered
+        Some(Self {
+            ipv4: (ip4 != [0; 4] || port4 != 0).then(|| SocketAddrV4::new(Ipv4Addr::from(ip4), port4)),
+            ipv6: (ip6 != [0; 16] || port6 != 0).then(|| SocketAddrV6::new(Ipv6Addr::from(ip6), port6, 0, 0)),
+            cid, reset_token,
+        })
+    }
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        let ipv4 = self.ipv4.unwrap_or(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
+        let ipv6 = self.ipv6.unwrap_or(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0));
+
+        buf.extend_from_slice(&ipv4.ip().octets());
+        buf.extend_from_slice(&ipv4.port().to_be_bytes());
+        buf.extend_from_slice(&ipv6.ip().octets());
+        buf.extend_from_slice(&ipv6.port().to_be_bytes());
+        self.cid.write(buf);
+        buf.extend_from_slice(&self.reset_token);
+    }
+}
+
+
+// 18, carried in the quic_transport_parameters tls extension, None is the default of 18.2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportParameters {
+    pub original_destination_connection_id: Option<ConnectionId>,
+    // milliseconds
+    pub max_idle_timeout: Option<u64>,
+    pub stateless_reset_token: Option<[u8; 16]>,
+    pub max_udp_payload_size: Option<u64>,
+    pub initial_max_data: Option<u64>,
+    pub initial_max_stream_data_bidi_local: Option<u64>,
+    pub initial_max_stream_data_bidi_remote: Option<u64>,
+    pub initial_max_stream_data_uni: Option<u64>,
+    pub initial_max_streams_bidi: Option<u64>,
+    pub initial_max_streams_uni: Option<u64>,
+    pub ack_delay_exponent: Option<u64>,
+    // milliseconds
+    pub max_ack_delay: Option<u64>,
+    pub disable_active_migration: bool,
+    pub preferred_address: Option<PreferredAddress>,
+    pub active_connection_id_limit: Option<u64>,
+    pub initial_source_connection_id: Option<ConnectionId>,
+    pub retry_source_connection_id: Option<ConnectionId>,
+
+    pub unknown: Vec<(u64, Bytes)>,
+}
+impl TransportParameters {
+    pub const fn empty() -> Self {
+        Self {
+            original_destination_connection_id: None,
+            max_idle_timeout: None,
+            stateless_reset_token: None,
+            max_udp_payload_size: None,
+            initial_max_data: None,
+            initial_max_stream_data_bidi_local: None,
+            initial_max_stream_data_bidi_remote: None,
+            initial_max_stream_data_uni: None,
+            initial_max_streams_bidi: None,
+            initial_max_streams_uni: None,
+            ack_delay_exponent: None,
+            max_ack_delay: None,
+            disable_active_migration: false,
+            preferred_address: None,
+            active_connection_id_limit: None,
+            initial_source_connection_id: None,
+            retry_source_connection_id: None,
+            unknown: Vec::new(),
+        }
+    }
+    // everything defaults to zero, which allows no streams and no data, so these are what we announce
+    pub const fn default() -> Self {
+        Self {
+            original_destination_connection_id: None,
+            max_idle_timeout: Some(30_000),
+            stateless_reset_token: None,
+            max_udp_payload_size: None,
+            initial_max_data: Some(1 << 24),
+            initial_max_stream_data_bidi_local: Some(1 << 20),
+            initial_max_stream_data_bidi_remote: Some(1 << 20),
+            initial_max_stream_data_uni: Some(1 << 20),
+            initial_max_streams_bidi: Some(100),
+            initial_max_streams_uni: Some(100),
+            ack_delay_exponent: None,
+            max_ack_delay: None,
+            disable_active_migration: false,
+            preferred_address: None,
+            active_connection_id_limit: Some(4),
+            initial_source_connection_id: None,
+            retry_source_connection_id: None,
+            unknown: Vec::new(),
+        }
+    }
+
+    pub fn from(buf: &[u8], sender: Side) -> Result<Self, QuicErrorCode> {
+        let err = QuicErrorCode::TransportParameterError;
+        let mut params = Self::empty();
+        let mut seen = Vec::new();
+        let mut pos = 0;
+
+        while pos < buf.len() {
+            let id = read_varint(buf, &mut pos).ok_or(err)?;
+            let len = read_varint(buf, &mut pos).ok_or(err)?;
+            let value = read_bytes(buf, &mut pos, usize::try_from(len).map_err(|_| err)?).ok_or(err)?;
+
+            // 7.4, no parameter twice
+            if seen.contains(&id) { return Err(err) }
+            seen.push(id);
+
+            // 18.2, only servers send these
+            if sender.is_client() && matches!(id, 0x00 | 0x02 | 0x0d | 0x10) { return Err(err) }
+
+            let int = || {
+                let mut at = 0;
+                let int = read_varint(value, &mut at).ok_or(err)?;
+                if at == value.len() { Ok(int) } else { Err(err) }
+            };
+            let bounded = |valid: fn(u64) -> bool| int().and_then(|v| if valid(v) { Ok(Some(v)) } else { Err(err) });
+            let cid = || ConnectionId::new(value).ok_or(err);
+
+            match id {
+                0x00 => params.original_destination_connection_id = Some(cid()?),
+                0x01 => params.max_idle_timeout = Some(int()?),
+                0x02 => params.stateless_reset_token = Some(value.try_into().map_err(|_| err)?),
+                0x03 => params.max_udp_payload_size = bounded(|v| v >= 1200)?,
+                0x04 => params.initial_max_data = Some(int()?),
+                0x05 => params.initial_max_stream_data_bidi_local = Some(int()?),
+                0x06 => params.initial_max_stream_data_bidi_remote = Some(int()?),
+                0x07 => params.initial_max_stream_data_uni = Some(int()?),
+                0x08 => params.initial_max_streams_bidi = bounded(|v| v <= MAX_STREAMS)?,
+                0x09 => params.initial_max_streams_uni = bounded(|v| v <= MAX_STREAMS)?,
+                0x0a => params.ack_delay_exponent = bounded(|v| v <= 20)?,
+                0x0b => params.max_ack_delay = bounded(|v| v < 1 << 14)?,
+                0x0c => {
+                    if !value.is_empty() { return Err(err) }
+                    params.disable_active_migration = true;
+                },
+                0x0d => {
+                    let address = PreferredAddress::from(value).ok_or(err)?;
+                    if address.cid.is_empty() { return Err(err) }
+                    params.preferred_address = Some(address);
+                },
+                0x0e => params.active_connection_id_limit = bounded(|v| v >= 2)?,
+                0x0f => params.initial_source_connection_id = Some(cid()?),
+                0x10 => params.retry_source_connection_id = Some(cid()?),
+                // 18.1, unknown and reserved ones are ignored
+                _ => params.unknown.push((id, Bytes::copy_from_slice(value))),
+            }
+        }
+
+        Ok(params)
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(128);
+
+        let write = |buf: &mut Vec<u8>, id: u64, value: &[u8]| {
+            write_varint(buf, id);
+            write_varint(buf, value.len() as u64);
+            buf.extend_from_slice(value);
+        };
+        let int = |buf: &mut Vec<u8>, id: u64, value: Option<u64>| {
+            if let Some(value) = value {
+                let mut int = Vec::with_capacity(8);
+                write_varint(&mut int, value);
+                write(buf, id, &int);
+            }
+        };
+
+        if let Some(cid) = &self.original_destination_connection_id { write(&mut buf, 0x00, cid) }
+        int(&mut buf, 0x01, self.max_idle_timeout);
+        if let Some(token) = &self.stateless_reset_token { write(&mut buf, 0x02, token) }
+        int(&mut buf, 0x03, self.max_udp_payload_size);
+        int(&mut buf, 0x04, self.initial_max_data);
+        int(&mut buf, 0x05, self.initial_max_stream_data_bidi_local);
+        int(&mut buf, 0x06, self.initial_max_stream_data_bidi_remote);
+        int(&mut buf, 0x07, self.initial_max_stream_data_uni);
+        int(&mut buf, 0x08, self.initial_max_streams_bidi);
+        int(&mut buf, 0x09, self.initial_max_streams_uni);
+        int(&mut buf, 0x0a, self.ack_delay_exponent);
+        int(&mut buf, 0x0b, self.max_ack_delay);
+        if self.disable_active_migration { write(&mut buf, 0x0c, &[]) }
+        if let Some(address) = &self.preferred_address {
+            let mut value = Vec::with_capacity(64);
+            address.write(&mut value);
+            write(&mut buf, 0x0d, &value);
+        }
+        int(&mut buf, 0x0e, self.active_connection_id_limit);
+        if let Some(cid) = &self.initial_source_connection_id { write(&mut buf, 0x0f, cid) }
+        if let Some(cid) = &self.retry_source_connection_id { write(&mut buf, 0x10, cid) }
+
+        for (id, value) in &self.unknown {
+            write(&mut buf, *id, value);
+        }
+        buf
+    }
+
+    pub fn get_unknown(&self, id: u64) -> Option<&Bytes> {
+        self.unknown.iter().find(|(i, _)| *i == id).map(|(_, v)| v)
+    }
+    pub fn set_unknown(&mut self, id: u64, value: Bytes) {
+        if let Some((_, v)) = self.unknown.iter_mut().find(|(i, _)| *i == id) { *v = value }
+        else { self.unknown.push((id, value)) }
+    }
+}
+impl Default for TransportParameters {
+    #[inline]
+    fn default() -> Self {
+        Self::default()
+    }
+}
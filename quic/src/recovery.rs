@@ -0,0 +1,387 @@
+use std::{collections::BTreeMap, ops::RangeInclusive, time::{Duration, Instant}};
+
+use crate::{congestion::{Controller, INITIAL_MSS, NewReno, Pacer, initial_window}, core::{QuicErrorCode, Side, Space}, frame::Frame, params::DEFAULT_MAX_ACK_DELAY};
+
+// https://datatracker.ietf.org/doc/html/rfc9002
+
+// 6.1.1, packets this far below an acknowledged one are lost
+pub const PACKET_THRESHOLD: u64 = 3;
+// 6.1.2, no timer is shorter than this
+pub const GRANULARITY: Duration = Duration::from_millis(1);
+// 6.2.2, before there is a sample
+pub const INITIAL_RTT: Duration = Duration::from_millis(333);
+// 7.6.1
+pub const PERSISTENT_CONGESTION_THRESHOLD: u32 = 3;
+
+
+// 5
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RttEstimator {
+    pub latest: Duration,
+    pub smoothed: Duration,
+    pub var: Duration,
+    pub min: Duration,
+    // when the first sample was taken (7.6.2)
+    pub first_sample: Option<Instant>,
+}
+impl RttEstimator {
+    pub const fn new() -> Self {
+        Self {
+            latest: INITIAL_RTT,
+            smoothed: INITIAL_RTT,
+            var: Duration::from_micros(INITIAL_RTT.as_micros() as u64 / 2),
+            min: INITIAL_RTT,
+            first_sample: None,
+        }
+    }
+
+    // 5.3, max_ack_delay only bounds the ack delay once the handshake is confirmed
+    pub fn update(&mut self, now: Instant, sample: Duration, ack_delay: Duration, max_ack_delay: Duration, handshake_confirmed: bool) {
+        self.latest = sample;
+        if self.first_sample.is_none() {
+            self.first_sample = Some(now);
+            self.min = sample;
+            self.smoothed = sample;
+            self.var = sample / 2;
+            return;
+        }
+
+        self.min = self.min.min(sample);
+        let ack_delay = if handshake_confirmed { ack_delay.min(max_ack_delay) } else { ack_delay };
+        // 5.3, the delay is never taken off below min_rtt
+        let adjusted = if sample >= self.min + ack_delay { sample - ack_delay } else { sample };
+
+        self.var = (self.var * 3 + self.smoothed.abs_diff(adjusted)) / 4;
+        self.smoothed = (self.smoothed * 7 + adjusted) / 8;
+    }
+
+    // 6.2.1, without max_ack_delay and backoff
+    pub fn pto_base(&self) -> Duration {
+        self.smoothed + (self.var * 4).max(GRANULARITY)
+    }
+    // 6.1.2, 9/8 of the larger of the smoothed and the latest rtt
+    pub fn loss_delay(&self) -> Duration {
+        (self.smoothed.max(self.latest) * 9 / 8).max(GRANULARITY)
+    }
+}
+impl Default for RttEstimator {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+// a.1.1, what is remembered of each packet until it is acknowledged or lost
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentPacket {
+    pub pn: u64,
+    pub time_sent: Instant,
+    // bytes in the datagram this packet took
+    pub size: usize,
+    pub ack_eliciting: bool,
+    // counts towards bytes in flight, ack-eliciting or padded (2)
+    pub in_flight: bool,
+    // what is sent again should the packet be lost
+    pub frames: Vec<Frame>,
+}
+impl SentPacket {
+    pub fn new(pn: u64, time_sent: Instant, size: usize, frames: Vec<Frame>) -> Self {
+        let ack_eliciting = frames.iter().any(|f| f.is_ack_eliciting());
+        let in_flight = ack_eliciting || frames.iter().any(|f| matches!(f, Frame::Padding(_)));
+        Self { pn, time_sent, size, ack_eliciting, in_flight, frames }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct PacketSpace {
+    sent: BTreeMap<u64, SentPacket>,
+    largest_sent: Option<u64>,
+    largest_acked: Option<u64>,
+    last_ack_eliciting: Option<Instant>,
+    ack_eliciting_in_flight: usize,
+    // 6.1.2, when the earliest packet not yet lost by time will be
+    loss_time: Option<Instant>,
+    discarded: bool,
+}
+
+// what an ACK frame changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AckOutcome {
+    pub acked: Vec<SentPacket>,
+    pub lost: Vec<SentPacket>,
+}
+
+// a.9, what the loss detection timer asks for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Timeout {
+    Lost(Space, Vec<SentPacket>),
+    // 6.2.4, send one or two ack-eliciting packets in the space
+    Probe(Space),
+}
+
+
+// loss detection and congestion control of one connection, driven by whoever owns the connection
+pub struct Recovery {
+    pub side: Side,
+    pub rtt: RttEstimator,
+    pub controller: Box<dyn Controller>,
+    pub pacer: Pacer,
+    pub bytes_in_flight: usize,
+    pub pto_count: u32,
+    // the peer's max_ack_delay transport parameter
+    pub max_ack_delay: Duration,
+    pub handshake_confirmed: bool,
+    // 8.1, a client is only sure of this once the server acknowledged a Handshake packet
+    pub peer_validated: bool,
+    // 7.8, set while there is less to send than the window allows
+    pub app_limited: bool,
+
+    spaces: [PacketSpace; 3],
+    timer: Option<Instant>,
+}
+impl Recovery {
+    pub fn new(side: Side, controller: Box<dyn Controller>) -> Self {
+        Self {
+            side,
+            rtt: RttEstimator::new(),
+            pacer: Pacer::new(controller.window()),
+            controller,
+            bytes_in_flight: 0,
+            pto_count: 0,
+            max_ack_delay: Duration::from_millis(DEFAULT_MAX_ACK_DELAY),
+            handshake_confirmed: false,
+            // a server has nothing to prove
+            peer_validated: side.is_server(),
+            app_limited: false,
+            spaces: Default::default(),
+            timer: None,
+        }
+    }
+    pub fn with_new_reno(side: Side) -> Self {
+        Self::new(side, Box::new(NewReno::new(INITIAL_MSS)))
+    }
+    // the new controller starts from its own initial window
+    pub fn set_controller(&mut self, controller: Box<dyn Controller>) {
+        self.controller = controller;
+    }
+
+    pub fn largest_acked(&self, space: Space) -> Option<u64> {
+        self.spaces[space.index()].largest_acked
+    }
+    // bytes the window has room for
+    pub fn window_available(&self) -> usize {
+        self.controller.window().saturating_sub(self.bytes_in_flight)
+    }
+    // 7.7, None when a packet of bytes may be sent now
+    pub fn pacing_delay(&mut self, now: Instant, bytes: usize) -> Option<Instant> {
+        let rate = self.pacing_rate();
+        self.pacer.capacity = self.controller.window().min(initial_window(INITIAL_MSS));
+        self.pacer.delay(now, bytes, rate)
+    }
+    fn pacing_rate(&self) -> u64 {
+        self.controller.pacing_rate().unwrap_or_else(|| Pacer::rate(self.controller.window(), self.rtt.smoothed))
+    }
+    // when on_timeout should be called
+    pub fn timeout(&self) -> Option<Instant> {
+        self.timer
+    }
+
+    // a.5
+    pub fn on_packet_sent(&mut self, space: Space, packet: SentPacket) {
+        let now = packet.time_sent;
+        let rate = self.pacing_rate();
+        let sp = &mut self.spaces[space.index()];
+        sp.largest_sent = Some(packet.pn);
+
+        if packet.in_flight {
+            if packet.ack_eliciting {
+                sp.last_ack_eliciting = Some(now);
+                sp.ack_eliciting_in_flight += 1;
+            }
+            self.bytes_in_flight += packet.size;
+            self.controller.on_sent(now, packet.size, self.bytes_in_flight);
+            self.pacer.on_sent(now, packet.size, rate);
+        }
+        sp.sent.insert(packet.pn, packet);
+        self.set_timer(now);
+    }
+
+    // a.7, ranges as in Frame::Ack, the largest first, ack_delay already scaled by the exponent
+    pub fn on_ack_received(&mut self, space: Space, ranges: &[RangeInclusive<u64>], ack_delay: Duration, now: Instant) -> Result<AckOutcome, QuicErrorCode> {
+        let sp = &mut self.spaces[space.index()];
+        let largest = *ranges.first().ok_or(QuicErrorCode::FrameEncodingError)?.end();
+        // 13.1, acknowledging a packet that was never sent
+        if sp.largest_sent.is_none_or(|sent| largest > sent) { return Err(QuicErrorCode::ProtocolViolation) }
+        sp.largest_acked = Some(sp.largest_acked.map_or(largest, |l| l.max(largest)));
+
+        let mut acked = Vec::new();
+        for range in ranges.iter().rev() {
+            let pns: Vec<u64> = sp.sent.range(range.clone()).map(|(pn, _)| *pn).collect();
+            acked.extend(pns.iter().filter_map(|pn| sp.sent.remove(pn)));
+        }
+        if acked.is_empty() { return Ok(AckOutcome::default()) }
+
+        // 5.1, only the largest acknowledged gives a sample, and only when something newly acked elicited the ACK
+        if let Some(newest) = acked.iter().find(|p| p.pn == largest) && acked.iter().any(|p| p.ack_eliciting) {
+            // ack delay is 0 for Initial and Handshake packets (5.3)
+            let ack_delay = if space == Space::Data { ack_delay } else { Duration::ZERO };
+            self.rtt.update(now, now.saturating_duration_since(newest.time_sent), ack_delay, self.max_ack_delay, self.handshake_confirmed);
+        }
+
+        let lost = self.detect_lost(space, now);
+        self.on_lost(&lost, now);
+
+        let sp = &mut self.spaces[space.index()];
+        for packet in acked.iter().filter(|p| p.in_flight) {
+            if packet.ack_eliciting { sp.ack_eliciting_in_flight -= 1 }
+            self.bytes_in_flight -= packet.size;
+            self.controller.on_ack(now, packet.time_sent, packet.size, &self.rtt, self.app_limited);
+        }
+
+        // 8.1, a server that processed our Handshake packet knows our address
+        if self.side.is_client() && space == Space::Handshake { self.peer_validated = true }
+        // a.7, the backoff only resets when the peer can answer
+        if self.peer_validated { self.pto_count = 0 }
+
+        self.set_timer(now);
+        Ok(AckOutcome { acked, lost })
+    }
+
+    // a.9, call once timeout has passed
+    pub fn on_timeout(&mut self, now: Instant) -> Option<Timeout> {
+        if self.timer.is_none_or(|t| t > now) { return None }
+
+        if let Some((_, space)) = self.loss_time() {
+            let lost = self.detect_lost(space, now);
+            self.on_lost(&lost, now);
+            self.set_timer(now);
+            return Some(Timeout::Lost(space, lost));
+        }
+
+        let space = match self.pto_time() {
+            Some((_, space)) => space,
+            // a.9, a client keeps the server's amplification limit from deadlocking the handshake
+            None if !self.peer_validated => self.client_probe_space(),
+            None => {
+                self.timer = None;
+                return None;
+            },
+        };
+        self.pto_count += 1;
+        self.set_timer(now);
+        Some(Timeout::Probe(space))
+    }
+
+    // 6.4, nothing of a space is tracked once its keys are gone
+    pub fn discard(&mut self, space: Space, now: Instant) {
+        let sp = &mut self.spaces[space.index()];
+        let in_flight: usize = sp.sent.values().filter(|p| p.in_flight).map(|p| p.size).sum();
+        self.bytes_in_flight -= in_flight;
+        *sp = PacketSpace { discarded: true, ..Default::default() };
+        self.pto_count = 0;
+        self.set_timer(now);
+    }
+
+    // 6.2.1
+    pub fn pto(&self, space: Space) -> Duration {
+        let backoff = 1u32 << self.pto_count.min(16);
+        let max_ack_delay = if space == Space::Data { self.max_ack_delay } else { Duration::ZERO };
+        (self.rtt.pto_base() + max_ack_delay) * backoff
+    }
+
+    // a.10
+    fn detect_lost(&mut self, space: Space, now: Instant) -> Vec<SentPacket> {
+        let loss_delay = self.rtt.loss_delay();
+        let sp = &mut self.spaces[space.index()];
+        sp.loss_time = None;
+        let Some(largest) = sp.largest_acked else { return Vec::new() };
+        let lost_send_time = now.checked_sub(loss_delay);
+
+        let mut lost = Vec::new();
+        for (pn, packet) in sp.sent.range(..=largest) {
+            if lost_send_time.is_some_and(|t| packet.time_sent <= t) || largest >= pn + PACKET_THRESHOLD {
+                lost.push(*pn);
+            }
+            else {
+                let time = packet.time_sent + loss_delay;
+                sp.loss_time = Some(sp.loss_time.map_or(time, |l| l.min(time)));
+            }
+        }
+
+        let lost: Vec<SentPacket> = lost.iter().filter_map(|pn| sp.sent.remove(pn)).collect();
+        for packet in lost.iter().filter(|p| p.in_flight) {
+            if packet.ack_eliciting { sp.ack_eliciting_in_flight -= 1 }
+            self.bytes_in_flight -= packet.size;
+        }
+        lost
+    }
+    // a.10, one congestion event for everything lost at once
+    fn on_lost(&mut self, lost: &[SentPacket], now: Instant) {
+        let Some(latest) = lost.iter().filter(|p| p.in_flight).map(|p| p.time_sent).max() else { return };
+        let lost_bytes = lost.iter().filter(|p| p.in_flight).map(|p| p.size).sum();
+
+        let persistent = self.is_persistent_congestion(lost);
+        self.controller.on_congestion_event(now, latest, persistent, lost_bytes);
+        // 5.2, the old minimum might not hold for the path anymore
+        if persistent { self.rtt.min = self.rtt.latest }
+    }
+    // 7.6.2, ack-eliciting packets lost over a long enough time with nothing in between acknowledged
+    fn is_persistent_congestion(&self, lost: &[SentPacket]) -> bool {
+        let Some(first_sample) = self.rtt.first_sample else { return false };
+        let duration = (self.rtt.pto_base() + self.max_ack_delay) * PERSISTENT_CONGESTION_THRESHOLD;
+
+        let mut start: Option<Instant> = None;
+        let mut previous: Option<u64> = None;
+        for packet in lost {
+            // a gap in packet numbers is a packet that was acknowledged or lost before
+            if packet.time_sent <= first_sample || previous.is_some_and(|p| packet.pn != p + 1) { start = None }
+            previous = Some(packet.pn);
+            if packet.time_sent <= first_sample || !packet.ack_eliciting { continue }
+
+            match start {
+                Some(start) if packet.time_sent.saturating_duration_since(start) > duration => return true,
+                Some(_) => (),
+                None => start = Some(packet.time_sent),
+            }
+        }
+        false
+    }
+
+    fn loss_time(&self) -> Option<(Instant, Space)> {
+        Space::ALL.into_iter()
+            .filter_map(|space| Some((self.spaces[space.index()].loss_time?, space)))
+            .min_by_key(|(time, _)| *time)
+    }
+    // a.8, the earliest probe timeout of the spaces with ack-eliciting packets in flight
+    fn pto_time(&self) -> Option<(Instant, Space)> {
+        Space::ALL.into_iter()
+            .filter(|space| {
+                let sp = &self.spaces[space.index()];
+                // application data is not probed before the handshake is confirmed
+                !sp.discarded && sp.ack_eliciting_in_flight > 0 && (*space != Space::Data || self.handshake_confirmed)
+            })
+            .filter_map(|space| Some((self.spaces[space.index()].last_ack_eliciting? + self.pto(space), space)))
+            .min_by_key(|(time, _)| *time)
+    }
+    fn client_probe_space(&self) -> Space {
+        if self.spaces[Space::Initial.index()].discarded { Space::Handshake } else { Space::Initial }
+    }
+    // a.8
+    fn set_timer(&mut self, now: Instant) {
+        if let Some((time, _)) = self.loss_time() {
+            self.timer = Some(time);
+            return;
+        }
+
+        let in_flight = self.spaces.iter().any(|sp| sp.ack_eliciting_in_flight > 0);
+        self.timer = if !in_flight && !self.peer_validated {
+            // a.8, the client arms the timer from now while it has nothing in flight
+            let space = self.client_probe_space();
+            Some(now + self.pto(space))
+        }
+        else {
+            self.pto_time().map(|(time, _)| time)
+        };
+    }
+}
@@ -0,0 +1,738 @@
+use std::{collections::{HashMap, VecDeque}, future::poll_fn, io, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll, Waker}};
+
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{core::{MAX_VARINT, QuicErrorCode, Side, varint_len}, frame::{Frame, MAX_STREAMS}, params::TransportParameters};
+
+// https://datatracker.ietf.org/doc/html/rfc9000#section-2
+
+// bytes a stream holds from writes before they are handed to write_frames
+pub const MAX_SEND_BUFFER: usize = 1 << 16;
+
+
+// 2.1, the lowest bit is the initiator and the second one the direction
+pub fn stream_id(initiator: Side, bidi: bool, index: u64) -> u64 {
+    index << 2 | if bidi { 0 } else { 0x02 } | if initiator.is_server() { 0x01 } else { 0 }
+}
+pub fn stream_initiator(id: u64) -> Side {
+    if id & 0x01 == 0 { Side::Client } else { Side::Server }
+}
+pub fn stream_is_bidi(id: u64) -> bool {
+    id & 0x02 == 0
+}
+pub fn stream_index(id: u64) -> u64 {
+    id >> 2
+}
+
+
+// 3.1, the sending part of a stream
+#[derive(Debug, Default)]
+struct SendState {
+    // the peer's MAX_STREAM_DATA
+    max: u64,
+    // what was handed out in STREAM frames so far
+    offset: u64,
+    buffer: BytesMut,
+    fin: bool,
+    fin_sent: bool,
+    // the limit a STREAM_DATA_BLOCKED went out for
+    blocked: Option<u64>,
+    // set by reset() or in answer to STOP_SENDING, the RESET_STREAM went out with it
+    reset: Option<u64>,
+    stopped: Option<u64>,
+    waker: Option<Waker>,
+}
+impl SendState {
+    fn is_done(&self) -> bool {
+        self.reset.is_some() || self.fin_sent
+    }
+    fn end(&self) -> u64 {
+        self.offset + self.buffer.len() as u64
+    }
+}
+
+// 3.2, the receiving part of a stream
+#[derive(Debug, Default)]
+struct RecvState {
+    // the MAX_STREAM_DATA given to the peer and the distance kept from what was read
+    max: u64,
+    window: u64,
+    // the largest offset seen
+    received: u64,
+    final_size: Option<u64>,
+    assembler: crate::core::Assembler,
+    // popped from the assembler and not read yet
+    ready: Bytes,
+    read: u64,
+    reset: Option<u64>,
+    // STOP_SENDING went out, whatever arrives after is thrown away
+    stopped: Option<u64>,
+    waker: Option<Waker>,
+}
+impl RecvState {
+    fn is_done(&self) -> bool {
+        self.reset.is_some() || self.final_size.is_some_and(|f| f == self.read || self.stopped.is_some())
+    }
+}
+
+#[derive(Debug)]
+struct StreamState {
+    send: Option<SendState>,
+    recv: Option<RecvState>,
+    // no QuicStream points at it anymore
+    dropped: bool,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    // streams opened by us and the peer's MAX_STREAMS
+    opened: u64,
+    max: u64,
+    blocked: Option<u64>,
+    // streams opened by the peer and the MAX_STREAMS given to it
+    remote: u64,
+    remote_max: u64,
+    remote_max_sent: u64,
+    incoming: VecDeque<u64>,
+    open_waker: Option<Waker>,
+    accept_waker: Option<Waker>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    side: Side,
+    local: TransportParameters,
+    peer: TransportParameters,
+    streams: HashMap<u64, StreamState>,
+    bidi: Counts,
+    uni: Counts,
+
+    // 4.1, connection wide limits in both directions
+    max_data: u64,
+    data_sent: u64,
+    data_blocked: Option<u64>,
+    local_max_data: u64,
+    local_max_data_sent: u64,
+    data_window: u64,
+    data_received: u64,
+    data_read: u64,
+
+    // control frames waiting for write_frames, and the streams with data to send
+    queue: VecDeque<Frame>,
+    writable: VecDeque<u64>,
+    driver: Option<Waker>,
+    error: Option<QuicErrorCode>,
+}
+impl Inner {
+    fn counts(&mut self, bidi: bool) -> &mut Counts {
+        if bidi { &mut self.bidi } else { &mut self.uni }
+    }
+    fn wake_driver(&mut self) {
+        if let Some(waker) = self.driver.take() { waker.wake() }
+    }
+    fn has_frames(&self) -> bool {
+        !self.queue.is_empty() || !self.writable.is_empty()
+            || self.local_max_data != self.local_max_data_sent
+            || self.bidi.remote_max != self.bidi.remote_max_sent || self.uni.remote_max != self.uni.remote_max_sent
+    }
+    fn schedule(&mut self, id: u64) {
+        if !self.writable.contains(&id) { self.writable.push_back(id) }
+        self.wake_driver();
+    }
+
+    fn create(&mut self, id: u64) {
+        let local = stream_initiator(id) == self.side;
+        let bidi = stream_is_bidi(id);
+
+        // 18.2, which of the initial limits applies depends on who opened the stream
+        let (send_max, recv_max) = match (local, bidi) {
+            (true, true) => (self.peer.initial_max_stream_data_bidi_remote, self.local.initial_max_stream_data_bidi_local),
+            (false, true) => (self.peer.initial_max_stream_data_bidi_local, self.local.initial_max_stream_data_bidi_remote),
+            (true, false) => (self.peer.initial_max_stream_data_uni, None),
+            (false, false) => (None, self.local.initial_max_stream_data_uni),
+        };
+
+        let send = (bidi || local).then(|| SendState { max: send_max.unwrap_or(0), ..Default::default() });
+        let recv = (bidi || !local).then(|| {
+            let window = recv_max.unwrap_or(0);
+            RecvState { max: window, window, ..Default::default() }
+        });
+        self.streams.insert(id, StreamState { send, recv, dropped: false });
+    }
+
+    // the state of a stream the peer sent a frame for, None when it is gone already
+    fn remote(&mut self, id: u64, sending: bool) -> Result<Option<&mut StreamState>, QuicErrorCode> {
+        let local = stream_initiator(id) == self.side;
+        let bidi = stream_is_bidi(id);
+        // 19.8 and 19.5, a receive only stream can not be sent on, a send only one can not be stopped
+        if !bidi && local == sending { return Err(QuicErrorCode::StreamStateError) }
+
+        let index = stream_index(id);
+        if local {
+            if index >= self.counts(bidi).opened { return Err(QuicErrorCode::StreamStateError) }
+        }
+        else if index >= self.counts(bidi).remote {
+            // 4.6 and 3.2, opening a stream opens all lower ones of the same type
+            if index >= self.counts(bidi).remote_max { return Err(QuicErrorCode::StreamLimitError) }
+
+            let peer = self.side.peer();
+            for index in self.counts(bidi).remote..=index {
+                let id = stream_id(peer, bidi, index);
+                self.create(id);
+                self.counts(bidi).incoming.push_back(id);
+            }
+            let counts = self.counts(bidi);
+            counts.remote = index + 1;
+            if let Some(waker) = counts.accept_waker.take() { waker.wake() }
+        }
+        Ok(self.streams.get_mut(&id))
+    }
+
+    // 4.5, data past the end and a changed end are both errors
+    fn on_received(&mut self, id: u64, end: u64, fin: bool) -> Result<(), QuicErrorCode> {
+        let Some(recv) = self.streams.get_mut(&id).and_then(|s| s.recv.as_mut()) else { return Ok(()) };
+
+        if let Some(final_size) = recv.final_size && (end > final_size || fin && end != final_size) { return Err(QuicErrorCode::FinalSizeError) }
+        if fin && recv.received > end { return Err(QuicErrorCode::FinalSizeError) }
+        if end > recv.max { return Err(QuicErrorCode::FlowControlError) }
+
+        if fin { recv.final_size = Some(end) }
+        if end > recv.received {
+            self.data_received += end - recv.received;
+            recv.received = end;
+        }
+        if self.data_received > self.local_max_data { return Err(QuicErrorCode::FlowControlError) }
+        Ok(())
+    }
+
+    // 4.2, new credit once half the window was used
+    fn on_read(&mut self, id: u64, bytes: u64) {
+        self.data_read += bytes;
+        if self.local_max_data - self.data_read < self.data_window / 2 {
+            self.local_max_data = self.data_read + self.data_window;
+            self.wake_driver();
+        }
+
+        let Some(recv) = self.streams.get_mut(&id).and_then(|s| s.recv.as_mut()) else { return };
+        if recv.final_size.is_none() && recv.max - recv.read < recv.window / 2 {
+            recv.max = recv.read + recv.window;
+            self.queue.push_back(Frame::MaxStreamData { stream_id: id, max: recv.max });
+            self.wake_driver();
+        }
+    }
+
+    // bytes that will never be read still give their credit back
+    fn discard_recv(&mut self, id: u64) {
+        let Some(recv) = self.streams.get_mut(&id).and_then(|s| s.recv.as_mut()) else { return };
+        let end = recv.final_size.unwrap_or(recv.received);
+        let unread = end - recv.read;
+
+        recv.read = end;
+        recv.ready.clear();
+        recv.assembler = Default::default();
+        recv.assembler.offset = end;
+        if let Some(waker) = recv.waker.take() { waker.wake() }
+        self.on_read(id, unread);
+    }
+
+    fn reset(&mut self, id: u64, code: u64) {
+        let Some(send) = self.streams.get_mut(&id).and_then(|s| s.send.as_mut()) else { return };
+        if send.is_done() { return }
+
+        send.reset = Some(code);
+        send.buffer.clear();
+        if let Some(waker) = send.waker.take() { waker.wake() }
+
+        let final_size = send.offset;
+        self.queue.push_back(Frame::ResetStream { stream_id: id, error_code: code, final_size });
+        self.wake_driver();
+        self.collect(id);
+    }
+    fn stop(&mut self, id: u64, code: u64) {
+        let Some(recv) = self.streams.get_mut(&id).and_then(|s| s.recv.as_mut()) else { return };
+        if recv.is_done() || recv.stopped.is_some() { return }
+
+        recv.stopped = Some(code);
+        self.queue.push_back(Frame::StopSending { stream_id: id, error_code: code });
+        self.discard_recv(id);
+        self.wake_driver();
+        self.collect(id);
+    }
+
+    // 2.4, a stream is forgotten once both parts are done and nobody holds it
+    fn collect(&mut self, id: u64) {
+        let Some(stream) = self.streams.get(&id) else { return };
+        if !stream.dropped || stream.send.as_ref().is_some_and(|s| !s.is_done()) || stream.recv.as_ref().is_some_and(|r| !r.is_done()) { return }
+
+        self.streams.remove(&id);
+        // 4.6, the peer may open one more in its place
+        if stream_initiator(id) != self.side {
+            self.counts(stream_is_bidi(id)).remote_max += 1;
+            self.wake_driver();
+        }
+    }
+
+    fn error(&self) -> io::Error {
+        io::Error::new(io::ErrorKind::ConnectionAborted, format!("{}", self.error.unwrap_or(QuicErrorCode::NoError)))
+    }
+}
+
+
+// the streams of one connection, the caller moves frames between it and the packets
+// clones share the same streams
+#[derive(Debug, Clone)]
+pub struct Streams {
+    inner: Arc<Mutex<Inner>>,
+}
+impl Streams {
+    // local are the parameters we sent and limit the peer, peer are the ones it sent and limit us
+    pub fn new(side: Side, local: &TransportParameters, peer: &TransportParameters) -> Self {
+        let bidi = Counts {
+            max: peer.initial_max_streams_bidi.unwrap_or(0),
+            remote_max: local.initial_max_streams_bidi.unwrap_or(0),
+            remote_max_sent: local.initial_max_streams_bidi.unwrap_or(0),
+            ..Default::default()
+        };
+        let uni = Counts {
+            max: peer.initial_max_streams_uni.unwrap_or(0),
+            remote_max: local.initial_max_streams_uni.unwrap_or(0),
+            remote_max_sent: local.initial_max_streams_uni.unwrap_or(0),
+            ..Default::default()
+        };
+        let local_max_data = local.initial_max_data.unwrap_or(0);
+
+        let inner = Inner {
+            side,
+            local: local.clone(),
+            peer: peer.clone(),
+            streams: HashMap::new(),
+            bidi,
+            uni,
+            max_data: peer.initial_max_data.unwrap_or(0),
+            data_sent: 0,
+            data_blocked: None,
+            local_max_data,
+            local_max_data_sent: local_max_data,
+            data_window: local_max_data,
+            data_received: 0,
+            data_read: 0,
+            queue: VecDeque::new(),
+            writable: VecDeque::new(),
+            driver: None,
+            error: None,
+        };
+        Self { inner: Arc::new(Mutex::new(inner)) }
+    }
+
+    // ready once the peer's MAX_STREAMS allows another stream, a STREAMS_BLOCKED goes out while waiting
+    pub fn poll_open(&self, cx: &mut Context<'_>, bidi: bool) -> Poll<Result<QuicStream, QuicErrorCode>> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(error) = inner.error { return Poll::Ready(Err(error)) }
+
+        let side = inner.side;
+        let counts = inner.counts(bidi);
+        if counts.opened >= counts.max {
+            counts.open_waker = Some(cx.waker().clone());
+            if counts.blocked != Some(counts.max) {
+                counts.blocked = Some(counts.max);
+                let limit = counts.max;
+                inner.queue.push_back(Frame::StreamsBlocked { bidi, limit });
+                inner.wake_driver();
+            }
+            return Poll::Pending;
+        }
+
+        let id = stream_id(side, bidi, counts.opened);
+        counts.opened += 1;
+        inner.create(id);
+        Poll::Ready(Ok(QuicStream { id, inner: self.inner.clone() }))
+    }
+    pub async fn open(&self, bidi: bool) -> Result<QuicStream, QuicErrorCode> {
+        poll_fn(|cx| self.poll_open(cx, bidi)).await
+    }
+
+    // streams the peer opened, in order of their ids
+    pub fn poll_accept(&self, cx: &mut Context<'_>, bidi: bool) -> Poll<Result<QuicStream, QuicErrorCode>> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(error) = inner.error { return Poll::Ready(Err(error)) }
+
+        let counts = inner.counts(bidi);
+        match counts.incoming.pop_front() {
+            Some(id) => Poll::Ready(Ok(QuicStream { id, inner: self.inner.clone() })),
+            None => {
+                counts.accept_waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+    pub async fn accept(&self, bidi: bool) -> Result<QuicStream, QuicErrorCode> {
+        poll_fn(|cx| self.poll_accept(cx, bidi)).await
+    }
+
+    // takes the stream related frames of a packet, the others are left to the caller
+    pub fn on_frame(&self, frame: Frame) -> Result<(), QuicErrorCode> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.error.is_some() { return Ok(()) }
+
+        match frame {
+            Frame::Stream { stream_id: id, offset, fin, data } => {
+                let end = offset + data.len() as u64;
+                if end > MAX_VARINT { return Err(QuicErrorCode::FrameEncodingError) }
+                if inner.remote(id, true)?.is_none() { return Ok(()) }
+                inner.on_received(id, end, fin)?;
+
+                let Some(recv) = inner.streams.get_mut(&id).and_then(|s| s.recv.as_mut()) else { return Ok(()) };
+                if recv.reset.is_some() { return Ok(()) }
+                if recv.stopped.is_some() {
+                    // nothing will read it, the credit goes back right away
+                    inner.discard_recv(id);
+                    inner.collect(id);
+                    return Ok(());
+                }
+
+                recv.assembler.insert(offset, data);
+                if let Some(waker) = recv.waker.take() { waker.wake() }
+            },
+            Frame::ResetStream { stream_id: id, error_code, final_size } => {
+                if inner.remote(id, true)?.is_none() { return Ok(()) }
+                inner.on_received(id, final_size, true)?;
+
+                let Some(recv) = inner.streams.get_mut(&id).and_then(|s| s.recv.as_mut()) else { return Ok(()) };
+                if recv.reset.is_some() { return Ok(()) }
+                recv.reset = Some(error_code);
+                inner.discard_recv(id);
+                inner.collect(id);
+            },
+            Frame::StopSending { stream_id: id, error_code } => {
+                let Some(stream) = inner.remote(id, false)? else { return Ok(()) };
+                let Some(send) = stream.send.as_mut() else { return Ok(()) };
+
+                // 3.5, answered with a RESET_STREAM carrying the same code
+                send.stopped = Some(error_code);
+                if let Some(waker) = send.waker.take() { waker.wake() }
+                inner.reset(id, error_code);
+            },
+            Frame::MaxData(max) if max > inner.max_data => {
+                inner.max_data = max;
+                let blocked = inner.streams.iter().filter(|(_, s)| s.send.as_ref().is_some_and(|s| !s.buffer.is_empty())).map(|(id, _)| *id).collect::<Vec<_>>();
+                for id in blocked { inner.schedule(id) }
+            },
+            Frame::MaxStreamData { stream_id: id, max } => {
+                let Some(stream) = inner.remote(id, false)? else { return Ok(()) };
+                let Some(send) = stream.send.as_mut() else { return Ok(()) };
+
+                if max > send.max {
+                    send.max = max;
+                    if !send.buffer.is_empty() { inner.schedule(id) }
+                }
+            },
+            Frame::MaxStreams { bidi, max } => {
+                if max > MAX_STREAMS { return Err(QuicErrorCode::FrameEncodingError) }
+
+                let counts = inner.counts(bidi);
+                if max > counts.max {
+                    counts.max = max;
+                    if let Some(waker) = counts.open_waker.take() { waker.wake() }
+                }
+            },
+            Frame::StreamsBlocked { limit, .. } if limit > MAX_STREAMS => return Err(QuicErrorCode::FrameEncodingError),
+            // limits are raised as data is read, the blocked frames change nothing
+            _ => {},
+        }
+        Ok(())
+    }
+
+    // frames to send, stream data takes at most limit bytes of frames and the control frames come first
+    pub fn write_frames(&self, limit: usize) -> Vec<Frame> {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        let mut budget = limit;
+        let mut frames = Vec::new();
+
+        // 13.3, data sent again takes from the same budget, what does not fit waits for the next packet
+        let mut held = VecDeque::new();
+        for frame in std::mem::take(&mut inner.queue) {
+            let Frame::Stream { stream_id: id, offset, fin, mut data } = frame else { frames.push(frame); continue };
+
+            let overhead = 1 + varint_len(id) + varint_len(offset) + varint_len(data.len() as u64);
+            let len = data.len().min(budget.saturating_sub(overhead));
+            if budget < overhead || (len == 0 && !data.is_empty()) {
+                held.push_back(Frame::Stream { stream_id: id, offset, fin, data });
+                continue;
+            }
+
+            if len < data.len() {
+                let rest = data.split_off(len);
+                held.push_back(Frame::Stream { stream_id: id, offset: offset + len as u64, fin, data: rest });
+                frames.push(Frame::Stream { stream_id: id, offset, fin: false, data });
+            }
+            else { frames.push(Frame::Stream { stream_id: id, offset, fin, data }) }
+            budget -= overhead + len;
+        }
+        inner.queue = held;
+
+        if inner.local_max_data != inner.local_max_data_sent {
+            inner.local_max_data_sent = inner.local_max_data;
+            frames.push(Frame::MaxData(inner.local_max_data));
+        }
+        for bidi in [true, false] {
+            let counts = inner.counts(bidi);
+            if counts.remote_max != counts.remote_max_sent {
+                counts.remote_max_sent = counts.remote_max;
+                frames.push(Frame::MaxStreams { bidi, max: counts.remote_max });
+            }
+        }
+
+        let mut retry = VecDeque::new();
+        while let Some(id) = inner.writable.pop_front() {
+            let connection = inner.max_data - inner.data_sent;
+            let Some(send) = inner.streams.get_mut(&id).and_then(|s| s.send.as_mut()) else { continue };
+            if send.is_done() { continue }
+
+            // 19.8, type, id, offset and length ahead of the data
+            let overhead = 1 + varint_len(id) + varint_len(send.offset) + varint_len(send.end());
+            let credit = (send.max - send.offset).min(connection);
+            let len = (send.buffer.len() as u64).min(credit).min(budget.saturating_sub(overhead) as u64) as usize;
+            let fin = send.fin && len == send.buffer.len();
+
+            if len == 0 && !fin {
+                // 4.1, the limit that held it back is told to the peer once
+                if credit == 0 && !send.buffer.is_empty() {
+                    if send.offset == send.max {
+                        if send.blocked != Some(send.max) {
+                            send.blocked = Some(send.max);
+                            frames.push(Frame::StreamDataBlocked { stream_id: id, limit: send.max });
+                        }
+                    }
+                    else if inner.data_blocked != Some(inner.max_data) {
+                        inner.data_blocked = Some(inner.max_data);
+                        frames.push(Frame::DataBlocked(inner.max_data));
+                    }
+                }
+                else if budget > overhead { continue }
+                else { retry.push_back(id) }
+                continue;
+            }
+
+            let data = send.buffer.split_to(len).freeze();
+            let offset = send.offset;
+            send.offset += len as u64;
+            send.fin_sent = fin;
+            if let Some(waker) = send.waker.take() { waker.wake() }
+            let more = !send.buffer.is_empty();
+
+            inner.data_sent += len as u64;
+            budget = budget.saturating_sub(overhead + len);
+            frames.push(Frame::Stream { stream_id: id, offset, fin, data });
+
+            // round robin, a stream with more goes to the back
+            if more { inner.writable.push_back(id) }
+            if fin { inner.collect(id) }
+            if budget <= overhead { break }
+        }
+        for id in retry.into_iter().rev() { inner.writable.push_front(id) }
+
+        frames
+    }
+    // ready when write_frames has something to give
+    pub fn poll_frames(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.has_frames() || inner.error.is_some() { return Poll::Ready(()) }
+
+        inner.driver = Some(cx.waker().clone());
+        Poll::Pending
+    }
+    pub async fn frames(&self) {
+        poll_fn(|cx| self.poll_frames(cx)).await
+    }
+
+    // 13.3, what was in a lost packet goes out again if it still matters
+    pub fn on_lost(&self, frame: Frame) {
+        let mut inner = self.inner.lock().unwrap();
+        match &frame {
+            Frame::Stream { stream_id: id, .. } => {
+                if inner.streams.get(id).and_then(|s| s.send.as_ref()).is_some_and(|s| s.reset.is_some()) { return }
+            },
+            Frame::MaxStreamData { stream_id: id, .. } => {
+                let Some(recv) = inner.streams.get(id).and_then(|s| s.recv.as_ref()) else { return };
+                if recv.final_size.is_some() { return }
+                let max = recv.max;
+                inner.queue.push_back(Frame::MaxStreamData { stream_id: *id, max });
+                inner.wake_driver();
+                return;
+            },
+            Frame::MaxData(_) => {
+                inner.local_max_data_sent = 0;
+                inner.wake_driver();
+                return;
+            },
+            Frame::MaxStreams { bidi, .. } => {
+                inner.counts(*bidi).remote_max_sent = 0;
+                inner.wake_driver();
+                return;
+            },
+            Frame::ResetStream { .. } | Frame::StopSending { .. } => {},
+            _ => return,
+        }
+        inner.queue.push_back(frame);
+        inner.wake_driver();
+    }
+
+    // fails every stream and every waiting open and accept
+    pub fn close(&self, error: QuicErrorCode) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.error.is_some() { return }
+        inner.error = Some(error);
+
+        for stream in inner.streams.values_mut() {
+            if let Some(waker) = stream.send.as_mut().and_then(|s| s.waker.take()) { waker.wake() }
+            if let Some(waker) = stream.recv.as_mut().and_then(|r| r.waker.take()) { waker.wake() }
+        }
+        for bidi in [true, false] {
+            let counts = inner.counts(bidi);
+            if let Some(waker) = counts.open_waker.take() { waker.wake() }
+            if let Some(waker) = counts.accept_waker.take() { waker.wake() }
+        }
+        inner.wake_driver();
+    }
+
+    // streams opened by either side that were not forgotten yet
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().streams.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+
+// one stream, bidirectional or one half of a unidirectional one
+// reading a send only stream or writing a receive only one fails
+// dropping it finishes what was written and stops what was not read
+#[derive(Debug)]
+pub struct QuicStream {
+    id: u64,
+    inner: Arc<Mutex<Inner>>,
+}
+impl QuicStream {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    pub fn is_bidi(&self) -> bool {
+        stream_is_bidi(self.id)
+    }
+
+    // 3.1, abandons the sending part, RESET_STREAM
+    pub fn reset(&mut self, code: u64) {
+        self.inner.lock().unwrap().reset(self.id, code);
+    }
+    // 3.5, asks the peer to stop sending, STOP_SENDING
+    pub fn stop(&mut self, code: u64) {
+        self.inner.lock().unwrap().stop(self.id, code);
+    }
+    // the code of a RESET_STREAM the peer sent
+    pub fn reset_code(&self) -> Option<u64> {
+        self.inner.lock().unwrap().streams.get(&self.id).and_then(|s| s.recv.as_ref()).and_then(|r| r.reset)
+    }
+    // the code of a STOP_SENDING the peer sent
+    pub fn stopped_code(&self) -> Option<u64> {
+        self.inner.lock().unwrap().streams.get(&self.id).and_then(|s| s.send.as_ref()).and_then(|s| s.stopped)
+    }
+}
+impl AsyncRead for QuicStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.error.is_some() { return Poll::Ready(Err(inner.error())) }
+
+        let Some(recv) = inner.streams.get_mut(&self.id).and_then(|s| s.recv.as_mut()) else {
+            return Poll::Ready(Err(io::ErrorKind::Unsupported.into()))
+        };
+        if recv.reset.is_some() { return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into())) }
+        if recv.stopped.is_some() { return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())) }
+
+        let mut read = 0;
+        while buf.remaining() > 0 {
+            if recv.ready.is_empty() {
+                match recv.assembler.pop() {
+                    Some(data) => recv.ready = data,
+                    None => break,
+                }
+            }
+            let len = recv.ready.len().min(buf.remaining());
+            buf.put_slice(&recv.ready.split_to(len));
+            read += len;
+        }
+        recv.read += read as u64;
+
+        if read > 0 {
+            inner.on_read(self.id, read as u64);
+            return Poll::Ready(Ok(()));
+        }
+        // the end of the stream reads as nothing
+        if recv.final_size == Some(recv.read) {
+            inner.collect(self.id);
+            return Poll::Ready(Ok(()));
+        }
+
+        recv.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+impl AsyncWrite for QuicStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.error.is_some() { return Poll::Ready(Err(inner.error())) }
+
+        let Some(send) = inner.streams.get_mut(&self.id).and_then(|s| s.send.as_mut()) else {
+            return Poll::Ready(Err(io::ErrorKind::Unsupported.into()))
+        };
+        if send.reset.is_some() || send.fin { return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())) }
+        if buf.is_empty() { return Poll::Ready(Ok(0)) }
+
+        let len = buf.len().min(MAX_SEND_BUFFER - send.buffer.len());
+        if len == 0 {
+            send.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        send.buffer.extend_from_slice(&buf[..len]);
+        inner.schedule(self.id);
+        Poll::Ready(Ok(len))
+    }
+    // written bytes belong to the connection already, like a tcp socket
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let inner = self.inner.lock().unwrap();
+        if inner.error.is_some() { return Poll::Ready(Err(inner.error())) }
+        Poll::Ready(Ok(()))
+    }
+    // sends the FIN after what was written
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.error.is_some() { return Poll::Ready(Err(inner.error())) }
+
+        let Some(send) = inner.streams.get_mut(&self.id).and_then(|s| s.send.as_mut()) else {
+            return Poll::Ready(Err(io::ErrorKind::Unsupported.into()))
+        };
+        if send.reset.is_some() { return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())) }
+        if !send.fin {
+            send.fin = true;
+            inner.schedule(self.id);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+impl Drop for QuicStream {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock().unwrap();
+        let id = self.id;
+        let Some(stream) = inner.streams.get_mut(&id) else { return };
+        stream.dropped = true;
+
+        let finish = stream.send.as_mut().is_some_and(|s| if s.fin || s.reset.is_some() { false } else { s.fin = true; true });
+        if finish { inner.schedule(id) }
+        // 3.5, STOP_SENDING with 0 for a stream nobody will read anymore
+        inner.stop(id, 0);
+        inner.collect(id);
+    }
+}